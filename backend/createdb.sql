@@ -1,9 +1,24 @@
 DROP SCHEMA public CASCADE;
 CREATE SCHEMA public;
 
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
 CREATE TYPE user_status AS ENUM ('active', 'deleted');
-CREATE TYPE machine_status AS ENUM ('available', 'rented', 'maintenance', 'reserved');
-CREATE TYPE rental_status AS ENUM ('active', 'pending_payment', 'completed', 'cancelled', 'failed');
+CREATE TYPE machine_status AS ENUM ('available', 'rented', 'maintenance', 'reserved', 'decommissioned', 'in_transit');
+CREATE TYPE rental_status AS ENUM ('active', 'pending_payment', 'completed', 'cancelled', 'failed', 'retired');
+CREATE TYPE spec_data_type AS ENUM ('numeric', 'enum', 'bool');
+CREATE TYPE tax_condition AS ENUM ('consumidor_final', 'responsable_inscripto', 'monotributista', 'exento');
+CREATE TYPE invoice_kind AS ENUM ('invoice', 'credit_note');
+CREATE TYPE extension_status AS ENUM ('pending_payment', 'paid', 'failed');
+CREATE TYPE charge_kind AS ENUM ('late_return', 'damage', 'delivery');
+CREATE TYPE inspection_kind AS ENUM ('check_out', 'check_in');
+CREATE TYPE charge_status AS ENUM ('pending', 'paid', 'waived');
+CREATE TYPE deposit_status AS ENUM ('held', 'released', 'partially_kept', 'kept');
+CREATE TYPE waitlist_status AS ENUM ('waiting', 'notified', 'booked', 'expired', 'cancelled');
+CREATE TYPE delivery_slot AS ENUM ('morning', 'afternoon');
+CREATE TYPE delivery_status AS ENUM ('scheduled', 'delivered', 'collected', 'cancelled');
+CREATE TYPE transfer_status AS ENUM ('requested', 'in_transit', 'received', 'cancelled');
+CREATE TYPE calendar_feed_kind AS ENUM ('client', 'unit', 'branch');
 
 CREATE TABLE users (
     id SERIAL PRIMARY KEY CHECK (id != 0), --get_questions endpoint relies on this check
@@ -21,7 +36,10 @@ CREATE TABLE user_info (
     id INTEGER PRIMARY KEY REFERENCES users(id),
     birthdate date NOT NULL,
     id_card varchar(30) UNIQUE NOT NULL,
-    phone varchar(50) NULL
+    phone varchar(50) NULL,
+    tax_id varchar(11) NULL CHECK (tax_id ~ '^[0-9]{11}$'), --CUIT/CUIL without dashes
+    tax_condition tax_condition NOT NULL DEFAULT 'consumidor_final',
+    CHECK (tax_condition = 'consumidor_final' OR tax_id IS NOT NULL)
 );
 
 CREATE TABLE codes_2fa (
@@ -43,8 +61,16 @@ CREATE TABLE machinery_models (
     year INTEGER NOT NULL CHECK (year >= 1900),
     policy TEXT NOT NULL,
     description TEXT NOT NULL,
-    price REAL NOT NULL,
+    price NUMERIC(12,2) NOT NULL CHECK (price >= 0),
     image varchar(64) NOT NULL,
+    decommissioned_at DATE NULL,
+    decommission_reason TEXT NULL,
+    min_rental_days INTEGER NOT NULL DEFAULT 7 CHECK (min_rental_days >= 1),
+    max_rental_days INTEGER NULL CHECK (max_rental_days >= min_rental_days),
+    turnaround_days INTEGER NOT NULL DEFAULT 7 CHECK (turnaround_days >= 0),
+    min_lead_days INTEGER NULL CHECK (min_lead_days >= 0),
+    max_lead_days INTEGER NULL CHECK (max_lead_days >= COALESCE(min_lead_days, 0)),
+    deposit NUMERIC(12,2) NOT NULL DEFAULT 0 CHECK (deposit >= 0),
     UNIQUE (name, brand, model, year)
 );
 
@@ -63,7 +89,9 @@ CREATE TABLE machinery_units (
     status machine_status NOT NULL,
     assigned_at TIMESTAMP NOT NULL DEFAULT NOW(),
     model_id INTEGER NOT NULL REFERENCES machinery_models(id),
-    location_id INTEGER NOT NULL REFERENCES locations(id)
+    location_id INTEGER NOT NULL REFERENCES locations(id),
+    decommissioned_at DATE NULL,
+    decommission_reason TEXT NULL
 );
 
 CREATE TABLE machinery_location_history (
@@ -74,9 +102,34 @@ CREATE TABLE machinery_location_history (
     PRIMARY KEY (unit_id, location_id, assigned_at)
 );
 
+-- Planned moves of a unit to another branch. The location history is completed on reception
+CREATE TABLE unit_transfers (
+    id SERIAL PRIMARY KEY,
+    unit_id INTEGER NOT NULL REFERENCES machinery_units(id),
+    from_location_id INTEGER NOT NULL REFERENCES locations(id),
+    to_location_id INTEGER NOT NULL REFERENCES locations(id),
+    departure_date DATE NOT NULL,
+    expected_arrival_date DATE NOT NULL CHECK (expected_arrival_date >= departure_date),
+    status transfer_status NOT NULL DEFAULT 'requested',
+    notes TEXT NULL,
+    requested_by INTEGER NOT NULL REFERENCES users(id),
+    requested_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    shipped_by INTEGER NULL REFERENCES users(id),
+    shipped_at TIMESTAMP NULL,
+    received_by INTEGER NULL REFERENCES users(id),
+    received_at TIMESTAMP NULL,
+    cancelled_by INTEGER NULL REFERENCES users(id),
+    cancelled_at TIMESTAMP NULL,
+    CHECK (from_location_id != to_location_id)
+);
+
+CREATE UNIQUE INDEX unit_transfers_open_idx ON unit_transfers (unit_id)
+WHERE status IN ('requested', 'in_transit'); --one open transfer per unit
+
 CREATE TABLE categories (
     id SERIAL PRIMARY KEY,
-    name TEXT NOT NULL UNIQUE CHECK (name = LOWER(name))
+    name TEXT NOT NULL UNIQUE CHECK (name = LOWER(name)),
+    parent_id INTEGER NULL REFERENCES categories(id) CHECK (parent_id != id)
 );
 
 CREATE TABLE machinery_categories (
@@ -85,6 +138,76 @@ CREATE TABLE machinery_categories (
     PRIMARY KEY (model_id, category_id)
 );
 
+CREATE TABLE spec_definitions (
+    id SERIAL PRIMARY KEY,
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    key VARCHAR(50) NOT NULL UNIQUE CHECK (key ~ '^[a-z][a-z0-9_]*$'), --used as filter name in the catalog
+    name VARCHAR(100) NOT NULL,
+    unit VARCHAR(20) NULL,
+    data_type spec_data_type NOT NULL,
+    allowed_values TEXT[] NULL CHECK ((data_type = 'enum') = (allowed_values IS NOT NULL))
+);
+
+CREATE TABLE model_specs (
+    model_id INTEGER NOT NULL REFERENCES machinery_models(id) ON DELETE CASCADE,
+    spec_id INTEGER NOT NULL REFERENCES spec_definitions(id) ON DELETE CASCADE,
+    numeric_value DOUBLE PRECISION NULL,
+    text_value TEXT NULL,
+    bool_value BOOLEAN NULL,
+    PRIMARY KEY (model_id, spec_id)
+);
+
+CREATE TABLE duration_discounts (
+    id SERIAL PRIMARY KEY,
+    model_id INTEGER NULL REFERENCES machinery_models(id) ON DELETE CASCADE, --NULL applies to every model
+    min_days INTEGER NOT NULL CHECK (min_days > 0),
+    discount_percentage NUMERIC(5,2) NOT NULL CHECK (discount_percentage > 0 AND discount_percentage < 100),
+    UNIQUE NULLS NOT DISTINCT (model_id, min_days)
+);
+
+CREATE TABLE seasonal_rates (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    model_id INTEGER NULL REFERENCES machinery_models(id) ON DELETE CASCADE, --NULL applies to every model
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL CHECK (end_date >= start_date),
+    multiplier NUMERIC(6,4) NOT NULL CHECK (multiplier > 0)
+);
+
+CREATE TABLE tax_rates (
+    id SERIAL PRIMARY KEY,
+    model_id INTEGER NULL REFERENCES machinery_models(id) ON DELETE CASCADE, --NULL applies to every model
+    name TEXT NOT NULL,
+    percentage NUMERIC(5,2) NOT NULL CHECK (percentage >= 0 AND percentage < 100),
+    UNIQUE NULLS NOT DISTINCT (model_id)
+);
+
+CREATE TABLE cancellation_policies (
+    id SERIAL PRIMARY KEY,
+    model_id INTEGER NOT NULL REFERENCES machinery_models(id),
+    version INTEGER NOT NULL CHECK (version >= 1), --a new version is added on every change, rentals keep the one they were booked with
+    staff_refund_percentage NUMERIC(5,2) NOT NULL CHECK (staff_refund_percentage >= 0 AND staff_refund_percentage <= 100), --applies when staff cancels, whatever the notice
+    created_by INTEGER NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (model_id, version)
+);
+
+CREATE TABLE cancellation_policy_tiers (
+    policy_id INTEGER NOT NULL REFERENCES cancellation_policies(id) ON DELETE CASCADE,
+    min_notice_days INTEGER NOT NULL CHECK (min_notice_days >= 0), --days left until the start of the rental
+    refund_percentage NUMERIC(5,2) NOT NULL CHECK (refund_percentage >= 0 AND refund_percentage <= 100),
+    PRIMARY KEY (policy_id, min_notice_days)
+);
+
+-- Several rentals booked and paid together. Each rental of the order is one of its lines
+CREATE TABLE orders (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    payment_id TEXT NULL,
+    paid_at TIMESTAMP NULL
+);
+
 CREATE TABLE rentals (
     id SERIAL PRIMARY KEY,
     user_id INTEGER NOT NULL REFERENCES users(id),
@@ -96,12 +219,219 @@ CREATE TABLE rentals (
     machine_id INTEGER NOT NULL REFERENCES machinery_units(id),
     start_date DATE NOT NULL,
     end_date DATE NOT NULL,
-    total_price REAL NOT NULL,
+    total_price NUMERIC(12,2) NOT NULL CHECK (total_price >= 0),
     status rental_status NOT NULL,
     created_at TIMESTAMP NOT NULL DEFAULT NOW(),
     updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
     payment_id TEXT NULL,
-    notes TEXT NULL
+    payment_deadline TIMESTAMP NULL, --A pending_payment rental stops holding its dates after it. NULL never expires
+    notes TEXT NULL,
+    cancellation_policy_id INTEGER NULL REFERENCES cancellation_policies(id), --NULL when the model had no policy, refunds in full
+    refund_amount NUMERIC(12,2) NULL CHECK (refund_amount >= 0), --set when the rental is cancelled, or paid after it lost its dates
+    deposit_amount NUMERIC(12,2) NOT NULL DEFAULT 0 CHECK (deposit_amount >= 0),
+    deposit_status deposit_status NULL, --NULL until the deposit is paid, or when there is none
+    deposit_kept NUMERIC(12,2) NULL CHECK (deposit_kept >= 0 AND deposit_kept <= deposit_amount), --applied to the rental's charges
+    deposit_settled_at TIMESTAMP NULL,
+    order_id INTEGER NULL REFERENCES orders(id), --NULL when booked on its own
+    -- Rentals holding a unit can't overlap. Pending ones are left out: their hold lapses at
+    -- payment_deadline, which a constraint can't follow. They and the turnaround days after
+    -- each rental are guarded by lock_unit_period instead
+    CONSTRAINT rentals_no_overlap EXCLUDE USING gist (
+        machine_id WITH =,
+        daterange(start_date, end_date, '[]') WITH &&
+    ) WHERE (status IN ('active', 'retired'))
+);
+
+CREATE INDEX rentals_order_id_idx ON rentals (order_id);
+
+CREATE TABLE rental_status_history (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id) ON DELETE CASCADE,
+    from_status rental_status NULL, --NULL when the rental was created
+    to_status rental_status NOT NULL,
+    actor_id INTEGER NULL REFERENCES users(id), --NULL when no user made the change
+    reason TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX rental_status_history_rental_id_idx ON rental_status_history (rental_id);
+
+CREATE TABLE rental_extensions (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id) ON DELETE CASCADE,
+    previous_end_date DATE NOT NULL,
+    new_end_date DATE NOT NULL CHECK (new_end_date > previous_end_date),
+    price NUMERIC(12,2) NOT NULL CHECK (price >= 0),
+    status extension_status NOT NULL,
+    payment_id TEXT NULL,
+    payment_deadline TIMESTAMP NULL, --The extra days are held until it while the payment is pending
+    rental_employee_id INTEGER NULL REFERENCES users(id), --Staff member who granted it in person
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    paid_at TIMESTAMP NULL,
+    refund_amount NUMERIC(12,2) NULL CHECK (refund_amount >= 0) --set when it's paid after it lost its days
+);
+
+CREATE INDEX rental_extensions_rental_id_idx ON rental_extensions (rental_id);
+
+-- Clients waiting for a unit of a model at a location for some dates. When one frees up the
+-- first entry it fits is notified and the unit is held for it until priority_until
+CREATE TABLE waitlist_entries (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    model_id INTEGER NOT NULL REFERENCES machinery_models(id),
+    location_id INTEGER NOT NULL REFERENCES locations(id),
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL CHECK (end_date > start_date),
+    status waitlist_status NOT NULL DEFAULT 'waiting',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    unit_id INTEGER NULL REFERENCES machinery_units(id),
+    notified_at TIMESTAMP NULL,
+    priority_until TIMESTAMP NULL,
+    CHECK (status != 'notified' OR (unit_id IS NOT NULL AND priority_until IS NOT NULL))
+);
+
+CREATE INDEX waitlist_entries_model_id_idx ON waitlist_entries (model_id, status);
+
+-- Periods that keep a unit busy: the rentals that hold their dates, the extra days of
+-- extensions waiting for payment and the units held for notified waitlist entries
+CREATE VIEW unit_bookings AS
+SELECT r.id AS rental_id, r.machine_id, r.start_date, r.end_date
+FROM rentals r
+WHERE r.status IN ('active', 'retired')
+OR (r.status = 'pending_payment' AND COALESCE(r.payment_deadline > NOW(), TRUE))
+UNION ALL
+SELECT r.id, r.machine_id, r.end_date, e.new_end_date
+FROM rental_extensions e
+INNER JOIN rentals r ON e.rental_id = r.id
+WHERE e.status = 'pending_payment' AND e.payment_deadline > NOW()
+AND r.status IN ('active', 'retired')
+UNION ALL
+SELECT NULL, w.unit_id, w.start_date, w.end_date
+FROM waitlist_entries w
+WHERE w.status = 'notified' AND w.priority_until > NOW()
+UNION ALL
+SELECT NULL, t.unit_id, t.departure_date, GREATEST(t.expected_arrival_date, CURRENT_DATE)
+FROM unit_transfers t
+WHERE t.status IN ('requested', 'in_transit');
+
+CREATE TABLE charges (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id),
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    kind charge_kind NOT NULL,
+    description TEXT NOT NULL,
+    amount NUMERIC(12,2) NOT NULL CHECK (amount > 0),
+    status charge_status NOT NULL DEFAULT 'pending',
+    due_date DATE NOT NULL, --a pending charge past it blocks new rentals of the client
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    payment_id TEXT NULL,
+    paid_at TIMESTAMP NULL,
+    collected_by INTEGER NULL REFERENCES users(id), --employee who took the payment at the counter
+    waived_by INTEGER NULL REFERENCES users(id),
+    waived_at TIMESTAMP NULL,
+    waive_reason TEXT NULL,
+    deposit_applied NUMERIC(12,2) NOT NULL DEFAULT 0 CHECK (deposit_applied >= 0 AND deposit_applied <= amount), --part kept from the rental's deposit
+    CHECK ((status = 'paid') = (paid_at IS NOT NULL)),
+    CHECK ((status = 'waived') = (waive_reason IS NOT NULL))
+);
+
+CREATE INDEX charges_user_id_idx ON charges (user_id);
+
+CREATE TABLE delivery_rates (
+    location_id INTEGER PRIMARY KEY REFERENCES locations(id), --branches without rates don't deliver
+    base_fee NUMERIC(12,2) NOT NULL CHECK (base_fee >= 0),
+    fee_per_km NUMERIC(12,2) NOT NULL CHECK (fee_per_km >= 0),
+    max_distance_km NUMERIC(8,2) NOT NULL CHECK (max_distance_km > 0),
+    slot_capacity INTEGER NOT NULL CHECK (slot_capacity > 0), --trips per slot and day
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE deliveries (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER UNIQUE NOT NULL REFERENCES rentals(id),
+    address TEXT NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    distance_km NUMERIC(8,2) NOT NULL,
+    fee NUMERIC(12,2) NOT NULL,
+    delivery_slot delivery_slot NOT NULL, --on the start date of the rental
+    pickup_slot delivery_slot NULL, --on the end date, NULL when the client returns the machine
+    status delivery_status NOT NULL DEFAULT 'scheduled',
+    charge_id INTEGER NULL REFERENCES charges(id), --NULL when the fee is zero
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP NULL,
+    delivered_by INTEGER NULL REFERENCES users(id),
+    collected_at TIMESTAMP NULL,
+    collected_by INTEGER NULL REFERENCES users(id),
+    CHECK (status != 'collected' OR pickup_slot IS NOT NULL)
+);
+
+CREATE TABLE inspections (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id),
+    kind inspection_kind NOT NULL, --check_out at retirement, check_in at return
+    employee_id INTEGER NOT NULL REFERENCES users(id),
+    hour_meter NUMERIC(10,1) NULL CHECK (hour_meter >= 0),
+    notes TEXT NULL,
+    has_damage BOOLEAN NOT NULL DEFAULT FALSE, --some checklist item failed
+    damage_charge_id INTEGER NULL REFERENCES charges(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (rental_id, kind)
+);
+
+CREATE TABLE inspection_items (
+    inspection_id INTEGER NOT NULL REFERENCES inspections(id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    ok BOOLEAN NOT NULL,
+    notes TEXT NULL,
+    PRIMARY KEY (inspection_id, item)
+);
+
+CREATE TABLE inspection_photos (
+    inspection_id INTEGER NOT NULL REFERENCES inspections(id) ON DELETE CASCADE,
+    name TEXT NOT NULL, --stored with the machine images
+    PRIMARY KEY (inspection_id, name)
+);
+
+CREATE TABLE invoice_sequences (
+    kind invoice_kind NOT NULL,
+    letter CHAR(1) NOT NULL,
+    point_of_sale INTEGER NOT NULL,
+    last_number INTEGER NOT NULL,
+    PRIMARY KEY (kind, letter, point_of_sale)
+);
+
+CREATE TABLE invoices (
+    id SERIAL PRIMARY KEY,
+    kind invoice_kind NOT NULL,
+    letter CHAR(1) NOT NULL CHECK (letter IN ('A', 'B')),
+    point_of_sale INTEGER NOT NULL,
+    number INTEGER NOT NULL,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id),
+    related_invoice_id INTEGER NULL UNIQUE REFERENCES invoices(id), --invoice voided by this credit note
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    billing_name TEXT NOT NULL,
+    billing_id_card varchar(30) NOT NULL,
+    billing_tax_id varchar(11) NULL,
+    billing_tax_condition tax_condition NOT NULL,
+    net_amount NUMERIC(12,2) NOT NULL,
+    tax_amount NUMERIC(12,2) NOT NULL,
+    total_amount NUMERIC(12,2) NOT NULL,
+    issued_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (kind, letter, point_of_sale, number),
+    CHECK ((kind = 'credit_note') = (related_invoice_id IS NOT NULL))
+);
+
+CREATE TABLE invoice_lines (
+    id SERIAL PRIMARY KEY,
+    invoice_id INTEGER NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    description TEXT NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    unit_price NUMERIC(12,2) NOT NULL, --without taxes
+    net_amount NUMERIC(12,2) NOT NULL,
+    tax_percentage NUMERIC(5,2) NOT NULL,
+    tax_amount NUMERIC(12,2) NOT NULL,
+    total_amount NUMERIC(12,2) NOT NULL
 );
 
 CREATE TABLE model_extra_images (
@@ -155,3 +485,16 @@ CREATE TABLE service_reviews (
     content varchar(256) NULL,
     created_at TIMESTAMP NOT NULL DEFAULT NOW()
 );
+
+-- iCalendar feeds. Their URLs carry a signed token with the feed id, revoking the feed disables it
+CREATE TABLE calendar_feeds (
+    id SERIAL PRIMARY KEY,
+    kind calendar_feed_kind NOT NULL,
+    subject_id INTEGER NOT NULL, --the client, unit or location of the feed, by its kind
+    created_by INTEGER NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP NULL
+);
+
+CREATE UNIQUE INDEX calendar_feeds_active_idx ON calendar_feeds (kind, subject_id)
+WHERE revoked_at IS NULL; --one feed per client, unit or branch until it's revoked
@@ -0,0 +1,16 @@
+-- Adds the 'decommissioned' unit status and the decommission date and reason of models and units.
+
+-- A new enum value can't be used in the transaction that adds it.
+ALTER TYPE machine_status ADD VALUE IF NOT EXISTS 'decommissioned';
+
+BEGIN;
+
+ALTER TABLE machinery_models
+    ADD COLUMN decommissioned_at DATE NULL,
+    ADD COLUMN decommission_reason TEXT NULL;
+
+ALTER TABLE machinery_units
+    ADD COLUMN decommissioned_at DATE NULL,
+    ADD COLUMN decommission_reason TEXT NULL;
+
+COMMIT;
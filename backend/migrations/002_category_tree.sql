@@ -0,0 +1,8 @@
+-- Lets categories hang from a parent category. Existing categories stay at the top level.
+
+BEGIN;
+
+ALTER TABLE categories
+    ADD COLUMN parent_id INTEGER NULL REFERENCES categories(id) CHECK (parent_id != id);
+
+COMMIT;
@@ -0,0 +1,26 @@
+-- Typed technical specs defined per category and their values for each model.
+
+BEGIN;
+
+CREATE TYPE spec_data_type AS ENUM ('numeric', 'enum', 'bool');
+
+CREATE TABLE spec_definitions (
+    id SERIAL PRIMARY KEY,
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    key VARCHAR(50) NOT NULL UNIQUE CHECK (key ~ '^[a-z][a-z0-9_]*$'), --used as filter name in the catalog
+    name VARCHAR(100) NOT NULL,
+    unit VARCHAR(20) NULL,
+    data_type spec_data_type NOT NULL,
+    allowed_values TEXT[] NULL CHECK ((data_type = 'enum') = (allowed_values IS NOT NULL))
+);
+
+CREATE TABLE model_specs (
+    model_id INTEGER NOT NULL REFERENCES machinery_models(id) ON DELETE CASCADE,
+    spec_id INTEGER NOT NULL REFERENCES spec_definitions(id) ON DELETE CASCADE,
+    numeric_value DOUBLE PRECISION NULL,
+    text_value TEXT NULL,
+    bool_value BOOLEAN NULL,
+    PRIMARY KEY (model_id, spec_id)
+);
+
+COMMIT;
@@ -0,0 +1,23 @@
+-- Duration discounts and seasonal rates applied by the server when pricing a rental.
+-- Their amounts become NUMERIC in 005_decimal_money.sql.
+
+BEGIN;
+
+CREATE TABLE duration_discounts (
+    id SERIAL PRIMARY KEY,
+    model_id INTEGER NULL REFERENCES machinery_models(id) ON DELETE CASCADE, --NULL applies to every model
+    min_days INTEGER NOT NULL CHECK (min_days > 0),
+    discount_percentage REAL NOT NULL CHECK (discount_percentage > 0 AND discount_percentage < 100),
+    UNIQUE NULLS NOT DISTINCT (model_id, min_days)
+);
+
+CREATE TABLE seasonal_rates (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    model_id INTEGER NULL REFERENCES machinery_models(id) ON DELETE CASCADE, --NULL applies to every model
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL CHECK (end_date >= start_date),
+    multiplier REAL NOT NULL CHECK (multiplier > 0)
+);
+
+COMMIT;
@@ -0,0 +1,25 @@
+-- Converts money columns from REAL to NUMERIC, rounding existing values to cents.
+-- Only needed for databases created before this change; createdb.sql already uses NUMERIC.
+
+BEGIN;
+
+ALTER TABLE machinery_models
+    ALTER COLUMN price TYPE NUMERIC(12,2) USING ROUND(price::numeric, 2),
+    ADD CHECK (price >= 0);
+
+ALTER TABLE rentals
+    ALTER COLUMN total_price TYPE NUMERIC(12,2) USING ROUND(total_price::numeric, 2),
+    ADD CHECK (total_price >= 0);
+
+-- The checks are added again so that they compare decimals instead of floats
+ALTER TABLE duration_discounts
+    DROP CONSTRAINT duration_discounts_discount_percentage_check,
+    ALTER COLUMN discount_percentage TYPE NUMERIC(5,2) USING ROUND(discount_percentage::numeric, 2),
+    ADD CHECK (discount_percentage > 0 AND discount_percentage < 100);
+
+ALTER TABLE seasonal_rates
+    DROP CONSTRAINT seasonal_rates_multiplier_check,
+    ALTER COLUMN multiplier TYPE NUMERIC(6,4) USING ROUND(multiplier::numeric, 4),
+    ADD CHECK (multiplier > 0);
+
+COMMIT;
@@ -0,0 +1,64 @@
+-- Adds tax rates, client billing data and invoices.
+
+BEGIN;
+
+CREATE TYPE tax_condition AS ENUM ('consumidor_final', 'responsable_inscripto', 'monotributista', 'exento');
+CREATE TYPE invoice_kind AS ENUM ('invoice', 'credit_note');
+
+ALTER TABLE user_info
+    ADD COLUMN tax_id varchar(11) NULL CHECK (tax_id ~ '^[0-9]{11}$'),
+    ADD COLUMN tax_condition tax_condition NOT NULL DEFAULT 'consumidor_final',
+    ADD CHECK (tax_condition = 'consumidor_final' OR tax_id IS NOT NULL);
+
+CREATE TABLE tax_rates (
+    id SERIAL PRIMARY KEY,
+    model_id INTEGER NULL REFERENCES machinery_models(id) ON DELETE CASCADE, --NULL applies to every model
+    name TEXT NOT NULL,
+    percentage NUMERIC(5,2) NOT NULL CHECK (percentage >= 0 AND percentage < 100),
+    UNIQUE NULLS NOT DISTINCT (model_id)
+);
+
+CREATE TABLE invoice_sequences (
+    kind invoice_kind NOT NULL,
+    letter CHAR(1) NOT NULL,
+    point_of_sale INTEGER NOT NULL,
+    last_number INTEGER NOT NULL,
+    PRIMARY KEY (kind, letter, point_of_sale)
+);
+
+CREATE TABLE invoices (
+    id SERIAL PRIMARY KEY,
+    kind invoice_kind NOT NULL,
+    letter CHAR(1) NOT NULL CHECK (letter IN ('A', 'B')),
+    point_of_sale INTEGER NOT NULL,
+    number INTEGER NOT NULL,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id),
+    related_invoice_id INTEGER NULL UNIQUE REFERENCES invoices(id), --invoice voided by this credit note
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    billing_name TEXT NOT NULL,
+    billing_id_card varchar(30) NOT NULL,
+    billing_tax_id varchar(11) NULL,
+    billing_tax_condition tax_condition NOT NULL,
+    net_amount NUMERIC(12,2) NOT NULL,
+    tax_amount NUMERIC(12,2) NOT NULL,
+    total_amount NUMERIC(12,2) NOT NULL,
+    issued_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (kind, letter, point_of_sale, number),
+    CHECK ((kind = 'credit_note') = (related_invoice_id IS NOT NULL))
+);
+
+CREATE TABLE invoice_lines (
+    id SERIAL PRIMARY KEY,
+    invoice_id INTEGER NOT NULL REFERENCES invoices(id) ON DELETE CASCADE,
+    description TEXT NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    unit_price NUMERIC(12,2) NOT NULL, --without taxes
+    net_amount NUMERIC(12,2) NOT NULL,
+    tax_percentage NUMERIC(5,2) NOT NULL,
+    tax_amount NUMERIC(12,2) NOT NULL,
+    total_amount NUMERIC(12,2) NOT NULL
+);
+
+INSERT INTO tax_rates (model_id, name, percentage) VALUES (NULL, 'IVA 21%', 21);
+
+COMMIT;
@@ -0,0 +1,29 @@
+-- Adds the 'retired' rental status and the history of status changes.
+
+-- A new enum value can't be used in the transaction that adds it.
+ALTER TYPE rental_status ADD VALUE IF NOT EXISTS 'retired';
+
+BEGIN;
+
+UPDATE rentals
+SET status = 'retired'
+WHERE status = 'active' AND retirement_date IS NOT NULL AND return_date IS NULL;
+
+CREATE TABLE rental_status_history (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id) ON DELETE CASCADE,
+    from_status rental_status NULL, --NULL when the rental was created
+    to_status rental_status NOT NULL,
+    actor_id INTEGER NULL REFERENCES users(id), --NULL when no user made the change
+    reason TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX rental_status_history_rental_id_idx ON rental_status_history (rental_id);
+
+-- Earlier changes weren't recorded, so every rental starts its timeline in its current status
+INSERT INTO rental_status_history (rental_id, from_status, to_status, reason, created_at)
+SELECT id, NULL, status, 'Estado previo al registro del historial', updated_at
+FROM rentals;
+
+COMMIT;
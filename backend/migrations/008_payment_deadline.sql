@@ -0,0 +1,12 @@
+-- Pending payments only hold the rental dates until a deadline.
+
+BEGIN;
+
+ALTER TABLE rentals ADD COLUMN payment_deadline TIMESTAMP NULL;
+
+-- Existing holds get the default window of 30 minutes, so the ones already abandoned are released
+UPDATE rentals
+SET payment_deadline = created_at + INTERVAL '30 minutes'
+WHERE status = 'pending_payment';
+
+COMMIT;
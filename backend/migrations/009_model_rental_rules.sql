@@ -0,0 +1,13 @@
+-- Rental length, maintenance time between rentals and booking lead time are set per model.
+-- Existing models keep the previous fixed rules: at least 7 days and a week of maintenance.
+
+BEGIN;
+
+ALTER TABLE machinery_models
+    ADD COLUMN min_rental_days INTEGER NOT NULL DEFAULT 7 CHECK (min_rental_days >= 1),
+    ADD COLUMN max_rental_days INTEGER NULL CHECK (max_rental_days >= min_rental_days),
+    ADD COLUMN turnaround_days INTEGER NOT NULL DEFAULT 7 CHECK (turnaround_days >= 0),
+    ADD COLUMN min_lead_days INTEGER NULL CHECK (min_lead_days >= 0),
+    ADD COLUMN max_lead_days INTEGER NULL CHECK (max_lead_days >= COALESCE(min_lead_days, 0));
+
+COMMIT;
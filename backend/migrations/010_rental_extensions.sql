@@ -0,0 +1,38 @@
+-- Active rentals can be extended. Pending extensions hold the extra days like pending rentals,
+-- so availability checks read the bookings of a unit from the unit_bookings view.
+
+BEGIN;
+
+CREATE TYPE extension_status AS ENUM ('pending_payment', 'paid', 'failed');
+
+CREATE TABLE rental_extensions (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id) ON DELETE CASCADE,
+    previous_end_date DATE NOT NULL,
+    new_end_date DATE NOT NULL CHECK (new_end_date > previous_end_date),
+    price NUMERIC(12,2) NOT NULL CHECK (price >= 0),
+    status extension_status NOT NULL,
+    payment_id TEXT NULL,
+    payment_deadline TIMESTAMP NULL, --The extra days are held until it while the payment is pending
+    rental_employee_id INTEGER NULL REFERENCES users(id), --Staff member who granted it in person
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    paid_at TIMESTAMP NULL
+);
+
+CREATE INDEX rental_extensions_rental_id_idx ON rental_extensions (rental_id);
+
+-- Periods that keep a unit busy: the rentals that hold their dates and the extra days of
+-- extensions waiting for payment
+CREATE VIEW unit_bookings AS
+SELECT r.id AS rental_id, r.machine_id, r.start_date, r.end_date
+FROM rentals r
+WHERE r.status IN ('active', 'retired')
+OR (r.status = 'pending_payment' AND COALESCE(r.payment_deadline > NOW(), TRUE))
+UNION ALL
+SELECT r.id, r.machine_id, r.end_date, e.new_end_date
+FROM rental_extensions e
+INNER JOIN rentals r ON e.rental_id = r.id
+WHERE e.status = 'pending_payment' AND e.payment_deadline > NOW()
+AND r.status IN ('active', 'retired');
+
+COMMIT;
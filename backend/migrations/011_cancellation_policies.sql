@@ -0,0 +1,27 @@
+-- Machine-readable cancellation policies. Each change adds a new version and rentals keep the
+-- version that applied when they were booked. Existing rentals have no policy and refund in full.
+
+BEGIN;
+
+CREATE TABLE cancellation_policies (
+    id SERIAL PRIMARY KEY,
+    model_id INTEGER NOT NULL REFERENCES machinery_models(id),
+    version INTEGER NOT NULL CHECK (version >= 1),
+    staff_refund_percentage NUMERIC(5,2) NOT NULL CHECK (staff_refund_percentage >= 0 AND staff_refund_percentage <= 100),
+    created_by INTEGER NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (model_id, version)
+);
+
+CREATE TABLE cancellation_policy_tiers (
+    policy_id INTEGER NOT NULL REFERENCES cancellation_policies(id) ON DELETE CASCADE,
+    min_notice_days INTEGER NOT NULL CHECK (min_notice_days >= 0),
+    refund_percentage NUMERIC(5,2) NOT NULL CHECK (refund_percentage >= 0 AND refund_percentage <= 100),
+    PRIMARY KEY (policy_id, min_notice_days)
+);
+
+ALTER TABLE rentals
+    ADD COLUMN cancellation_policy_id INTEGER NULL REFERENCES cancellation_policies(id),
+    ADD COLUMN refund_amount NUMERIC(12,2) NULL CHECK (refund_amount >= 0);
+
+COMMIT;
@@ -0,0 +1,31 @@
+-- Ledger of what clients owe besides the rental price, starting with late return fines.
+-- Fines of past returns were never stored, so the ledger starts empty.
+
+BEGIN;
+
+CREATE TYPE charge_kind AS ENUM ('late_return');
+CREATE TYPE charge_status AS ENUM ('pending', 'paid', 'waived');
+
+CREATE TABLE charges (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id),
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    kind charge_kind NOT NULL,
+    description TEXT NOT NULL,
+    amount NUMERIC(12,2) NOT NULL CHECK (amount > 0),
+    status charge_status NOT NULL DEFAULT 'pending',
+    due_date DATE NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    payment_id TEXT NULL,
+    paid_at TIMESTAMP NULL,
+    collected_by INTEGER NULL REFERENCES users(id),
+    waived_by INTEGER NULL REFERENCES users(id),
+    waived_at TIMESTAMP NULL,
+    waive_reason TEXT NULL,
+    CHECK ((status = 'paid') = (paid_at IS NOT NULL)),
+    CHECK ((status = 'waived') = (waive_reason IS NOT NULL))
+);
+
+CREATE INDEX charges_user_id_idx ON charges (user_id);
+
+COMMIT;
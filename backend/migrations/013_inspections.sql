@@ -0,0 +1,37 @@
+-- Condition inspections when a machine leaves (check_out) and comes back (check_in), with
+-- checklist, hour meter and photos. Damage found at return can be charged to the rental.
+
+ALTER TYPE charge_kind ADD VALUE IF NOT EXISTS 'damage';
+
+BEGIN;
+
+CREATE TYPE inspection_kind AS ENUM ('check_out', 'check_in');
+
+CREATE TABLE inspections (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER NOT NULL REFERENCES rentals(id),
+    kind inspection_kind NOT NULL,
+    employee_id INTEGER NOT NULL REFERENCES users(id),
+    hour_meter NUMERIC(10,1) NULL CHECK (hour_meter >= 0),
+    notes TEXT NULL,
+    has_damage BOOLEAN NOT NULL DEFAULT FALSE,
+    damage_charge_id INTEGER NULL REFERENCES charges(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (rental_id, kind)
+);
+
+CREATE TABLE inspection_items (
+    inspection_id INTEGER NOT NULL REFERENCES inspections(id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    ok BOOLEAN NOT NULL,
+    notes TEXT NULL,
+    PRIMARY KEY (inspection_id, item)
+);
+
+CREATE TABLE inspection_photos (
+    inspection_id INTEGER NOT NULL REFERENCES inspections(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    PRIMARY KEY (inspection_id, name)
+);
+
+COMMIT;
@@ -0,0 +1,20 @@
+-- Security deposits of each model, held by the rentals and settled against their charges at
+-- return. Existing models and rentals have no deposit.
+
+BEGIN;
+
+CREATE TYPE deposit_status AS ENUM ('held', 'released', 'partially_kept', 'kept');
+
+ALTER TABLE machinery_models
+    ADD COLUMN deposit NUMERIC(12,2) NOT NULL DEFAULT 0 CHECK (deposit >= 0);
+
+ALTER TABLE rentals
+    ADD COLUMN deposit_amount NUMERIC(12,2) NOT NULL DEFAULT 0 CHECK (deposit_amount >= 0),
+    ADD COLUMN deposit_status deposit_status NULL,
+    ADD COLUMN deposit_kept NUMERIC(12,2) NULL CHECK (deposit_kept >= 0 AND deposit_kept <= deposit_amount),
+    ADD COLUMN deposit_settled_at TIMESTAMP NULL;
+
+ALTER TABLE charges
+    ADD COLUMN deposit_applied NUMERIC(12,2) NOT NULL DEFAULT 0 CHECK (deposit_applied >= 0 AND deposit_applied <= amount);
+
+COMMIT;
@@ -0,0 +1,41 @@
+-- Waitlist of clients for a model, location and dates. The unit held for a notified entry
+-- keeps its dates busy until the priority window ends.
+
+BEGIN;
+
+CREATE TYPE waitlist_status AS ENUM ('waiting', 'notified', 'booked', 'expired', 'cancelled');
+
+CREATE TABLE waitlist_entries (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    model_id INTEGER NOT NULL REFERENCES machinery_models(id),
+    location_id INTEGER NOT NULL REFERENCES locations(id),
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL CHECK (end_date > start_date),
+    status waitlist_status NOT NULL DEFAULT 'waiting',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    unit_id INTEGER NULL REFERENCES machinery_units(id),
+    notified_at TIMESTAMP NULL,
+    priority_until TIMESTAMP NULL,
+    CHECK (status != 'notified' OR (unit_id IS NOT NULL AND priority_until IS NOT NULL))
+);
+
+CREATE INDEX waitlist_entries_model_id_idx ON waitlist_entries (model_id, status);
+
+CREATE OR REPLACE VIEW unit_bookings AS
+SELECT r.id AS rental_id, r.machine_id, r.start_date, r.end_date
+FROM rentals r
+WHERE r.status IN ('active', 'retired')
+OR (r.status = 'pending_payment' AND COALESCE(r.payment_deadline > NOW(), TRUE))
+UNION ALL
+SELECT r.id, r.machine_id, r.end_date, e.new_end_date
+FROM rental_extensions e
+INNER JOIN rentals r ON e.rental_id = r.id
+WHERE e.status = 'pending_payment' AND e.payment_deadline > NOW()
+AND r.status IN ('active', 'retired')
+UNION ALL
+SELECT NULL, w.unit_id, w.start_date, w.end_date
+FROM waitlist_entries w
+WHERE w.status = 'notified' AND w.priority_until > NOW();
+
+COMMIT;
@@ -0,0 +1,18 @@
+-- Orders grouping several rentals booked and paid together. Existing rentals belong to none.
+
+BEGIN;
+
+CREATE TABLE orders (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    payment_id TEXT NULL,
+    paid_at TIMESTAMP NULL
+);
+
+ALTER TABLE rentals
+    ADD COLUMN order_id INTEGER NULL REFERENCES orders(id);
+
+CREATE INDEX rentals_order_id_idx ON rentals (order_id);
+
+COMMIT;
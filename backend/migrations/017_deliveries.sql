@@ -0,0 +1,40 @@
+-- Delivery of the machine to the client's job site and pickup at the end of the rental, with
+-- the rates of each branch. The fee is billed as a charge of the rental.
+
+ALTER TYPE charge_kind ADD VALUE IF NOT EXISTS 'delivery';
+
+BEGIN;
+
+CREATE TYPE delivery_slot AS ENUM ('morning', 'afternoon');
+CREATE TYPE delivery_status AS ENUM ('scheduled', 'delivered', 'collected', 'cancelled');
+
+CREATE TABLE delivery_rates (
+    location_id INTEGER PRIMARY KEY REFERENCES locations(id),
+    base_fee NUMERIC(12,2) NOT NULL CHECK (base_fee >= 0),
+    fee_per_km NUMERIC(12,2) NOT NULL CHECK (fee_per_km >= 0),
+    max_distance_km NUMERIC(8,2) NOT NULL CHECK (max_distance_km > 0),
+    slot_capacity INTEGER NOT NULL CHECK (slot_capacity > 0),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE deliveries (
+    id SERIAL PRIMARY KEY,
+    rental_id INTEGER UNIQUE NOT NULL REFERENCES rentals(id),
+    address TEXT NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    distance_km NUMERIC(8,2) NOT NULL,
+    fee NUMERIC(12,2) NOT NULL,
+    delivery_slot delivery_slot NOT NULL,
+    pickup_slot delivery_slot NULL,
+    status delivery_status NOT NULL DEFAULT 'scheduled',
+    charge_id INTEGER NULL REFERENCES charges(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP NULL,
+    delivered_by INTEGER NULL REFERENCES users(id),
+    collected_at TIMESTAMP NULL,
+    collected_by INTEGER NULL REFERENCES users(id),
+    CHECK (status != 'collected' OR pickup_slot IS NOT NULL)
+);
+
+COMMIT;
@@ -0,0 +1,53 @@
+-- Transfers of units between branches. Open transfers keep the unit from being booked from its
+-- departure until it's received.
+
+ALTER TYPE machine_status ADD VALUE IF NOT EXISTS 'in_transit';
+
+BEGIN;
+
+CREATE TYPE transfer_status AS ENUM ('requested', 'in_transit', 'received', 'cancelled');
+
+CREATE TABLE unit_transfers (
+    id SERIAL PRIMARY KEY,
+    unit_id INTEGER NOT NULL REFERENCES machinery_units(id),
+    from_location_id INTEGER NOT NULL REFERENCES locations(id),
+    to_location_id INTEGER NOT NULL REFERENCES locations(id),
+    departure_date DATE NOT NULL,
+    expected_arrival_date DATE NOT NULL CHECK (expected_arrival_date >= departure_date),
+    status transfer_status NOT NULL DEFAULT 'requested',
+    notes TEXT NULL,
+    requested_by INTEGER NOT NULL REFERENCES users(id),
+    requested_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    shipped_by INTEGER NULL REFERENCES users(id),
+    shipped_at TIMESTAMP NULL,
+    received_by INTEGER NULL REFERENCES users(id),
+    received_at TIMESTAMP NULL,
+    cancelled_by INTEGER NULL REFERENCES users(id),
+    cancelled_at TIMESTAMP NULL,
+    CHECK (from_location_id != to_location_id)
+);
+
+CREATE UNIQUE INDEX unit_transfers_open_idx ON unit_transfers (unit_id)
+WHERE status IN ('requested', 'in_transit');
+
+CREATE OR REPLACE VIEW unit_bookings AS
+SELECT r.id AS rental_id, r.machine_id, r.start_date, r.end_date
+FROM rentals r
+WHERE r.status IN ('active', 'retired')
+OR (r.status = 'pending_payment' AND COALESCE(r.payment_deadline > NOW(), TRUE))
+UNION ALL
+SELECT r.id, r.machine_id, r.end_date, e.new_end_date
+FROM rental_extensions e
+INNER JOIN rentals r ON e.rental_id = r.id
+WHERE e.status = 'pending_payment' AND e.payment_deadline > NOW()
+AND r.status IN ('active', 'retired')
+UNION ALL
+SELECT NULL, w.unit_id, w.start_date, w.end_date
+FROM waitlist_entries w
+WHERE w.status = 'notified' AND w.priority_until > NOW()
+UNION ALL
+SELECT NULL, t.unit_id, t.departure_date, GREATEST(t.expected_arrival_date, CURRENT_DATE)
+FROM unit_transfers t
+WHERE t.status IN ('requested', 'in_transit');
+
+COMMIT;
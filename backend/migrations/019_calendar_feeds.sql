@@ -0,0 +1,20 @@
+-- Signed and revocable iCalendar feeds of a client's rentals, a unit's schedule or a branch's
+-- agenda of retirements and returns.
+
+BEGIN;
+
+CREATE TYPE calendar_feed_kind AS ENUM ('client', 'unit', 'branch');
+
+CREATE TABLE calendar_feeds (
+    id SERIAL PRIMARY KEY,
+    kind calendar_feed_kind NOT NULL,
+    subject_id INTEGER NOT NULL, --the client, unit or location of the feed, by its kind
+    created_by INTEGER NOT NULL REFERENCES users(id),
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP NULL
+);
+
+CREATE UNIQUE INDEX calendar_feeds_active_idx ON calendar_feeds (kind, subject_id)
+WHERE revoked_at IS NULL; --one feed per client, unit or branch until it's revoked
+
+COMMIT;
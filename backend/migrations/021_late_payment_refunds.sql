@@ -0,0 +1,8 @@
+-- Extensions paid after their extra days went to another booking keep the payment on record
+-- and the amount to refund, as rentals already do in refund_amount.
+
+BEGIN;
+
+ALTER TABLE rental_extensions ADD COLUMN refund_amount NUMERIC(12,2) NULL CHECK (refund_amount >= 0);
+
+COMMIT;
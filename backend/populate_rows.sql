@@ -68,6 +68,10 @@ INSERT INTO locations (latitude, longitude, street, number, city) VALUES
 (-32.944242, -60.650538, 'Av. Pellegrini', '2345', 'Rosario'),
 (-31.420083, -64.188776, 'Bv. San Juan', '789', 'Córdoba');
 
+INSERT INTO delivery_rates (location_id, base_fee, fee_per_km, max_distance_km, slot_capacity) VALUES
+(1, 15000.00, 900.00, 50.00, 3),
+(2, 12000.00, 800.00, 40.00, 2);
+
 -- Insert sample data into the machinery_units table
 INSERT INTO machinery_units (serial_number, status, assigned_at, model_id, location_id) VALUES
 -- Modelo 1 (Caterpillar)
@@ -174,7 +178,7 @@ VALUES (
 
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status,
     retirement_employee_id, retirement_date, payment_id, created_at) VALUES
-(4, 6, NOW() - INTERVAL '3 days', NOW() + INTERVAL '8 days', 1000.00, 'active', 2, NOW() - INTERVAL '3 days', 'PAY_00003', DATE_TRUNC('year', NOW()));
+(4, 6, NOW() - INTERVAL '3 days', NOW() + INTERVAL '8 days', 1000.00, 'retired', 2, NOW() - INTERVAL '3 days', 'PAY_00003', DATE_TRUNC('year', NOW()));
 
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status, created_at) VALUES
 (4, 9, NOW(), NOW() + INTERVAL '13 days', 1000.00, 'active', DATE_TRUNC('year', NOW())),
@@ -207,14 +211,14 @@ INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, sta
 
 (4, 2, NOW() - INTERVAL '10 days', NOW() + INTERVAL '3 days', 1000.00, 'completed', DATE_TRUNC('year', NOW())),
 
-(4, 2, NOW() - INTERVAL '10 days', NOW(), 1000.00, 'active', DATE_TRUNC('year', NOW())),
+(4, 10, NOW() - INTERVAL '10 days', NOW(), 1000.00, 'active', DATE_TRUNC('year', NOW())),
 
 -- To test cancel rental start date in the past
 (9, 15, NOW() - INTERVAL '40 days', NOW() + INTERVAL '2 days', 1000.00, 'pending_payment', DATE_TRUNC('year', NOW()));
 
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status,
     retirement_employee_id, retirement_date, payment_id, created_at) VALUES
-(4, 6, NOW() - INTERVAL '3 days', NOW() + INTERVAL '8 days', 1000.00, 'active', 2, NOW() - INTERVAL '3 days', 'PAY_00003', DATE_TRUNC('year', NOW()));
+(4, 14, NOW() - INTERVAL '3 days', NOW() + INTERVAL '8 days', 1000.00, 'retired', 2, NOW() - INTERVAL '3 days', 'PAY_00003', DATE_TRUNC('year', NOW()));
 
 -- To test validate dates
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status, created_at) VALUES
@@ -247,59 +251,59 @@ INSERT INTO unit_history_events (unit_id, description, previous_status, new_stat
 (6, 'Cambio de aceite', 'maintenance', 'available'),
 (6, NULL, 'available', 'maintenance');
 
--- Stats by month test
+-- Stats by month test. Completed, since active rentals of a unit can't share their dates
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, created_at, status) VALUES
 -- FEB Current year
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'active'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,2,1,0,0,0),'completed'),
 -- DEC Current year
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'active'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,12,1,0,0,0),'completed'),
 -- MAR 2024
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'active'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,3,1,0,0,0),'completed'),
 -- APR 2024
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'active'),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'active');
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'completed'),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,4,1,0,0,0),'completed');
 
 -- Stats by employee test
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, created_at, status, rental_employee_id) VALUES
 -- AUG Current year user22
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'active',22),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'active',22),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'active',22),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'active',22),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'completed',22),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'completed',22),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'completed',22),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'completed',22),
 -- AUG 2024 user22
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active',22),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active',22),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed',22),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed',22),
 -- AUG Current year user23
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'active',23),
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'active',23),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'completed',23),
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(EXTRACT(YEAR FROM NOW())::INT,8,1,0,0,0),'completed',23),
 -- AUG 2024 user23
-(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active',23);
+(10,20,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed',23);
 
 -- Stats by category test
 INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, created_at, status) VALUES
 -- 2024 obras urbanas
-(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active'),
-(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active'),
-(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active'),
-(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active'),
+(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed'),
+(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed'),
+(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed'),
+(10,17,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed'),
 -- 2024 construccion pesada
-(10,19,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active'),
-(10,19,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'active'),
+(10,19,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed'),
+(10,19,NOW(),NOW(),1000.00,MAKE_TIMESTAMP(2024,8,1,0,0,0),'completed'),
 
 -- Test new machine review - user21 - unit20 - model 8
 (21,20,NOW(),NOW(),1000.00,DATE_TRUNC('year', NOW()),'active'), -- id 59
@@ -320,3 +324,159 @@ INSERT INTO machine_reviews (model_id, rental_id, user_id, rating, content, crea
 (1, 3, 3, 3, 'r3', NOW() - INTERVAL '3 seconds'),
 (1, 4, 4, 4, 'r4', NOW() - INTERVAL '4 seconds'),
 (1, 5, 5, 5, 'r5', NOW() - INTERVAL '5 seconds');
+
+-- Decommission tests
+INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image) VALUES
+('testdecommission', 'model9', 'EC-B', 2023, 'Reembolso total disponible si se cancela con suficiente antelación.', 'Grúa de gran altura', 200000.00, 'imagecode'), -- id 9
+('testdecommissionmodel', 'model10', 'EC-B', 2023, 'Reembolso total disponible si se cancela con suficiente antelación.', 'Grúa de gran altura', 200000.00, 'imagecode'); -- id 10
+
+INSERT INTO machinery_units (serial_number, status, assigned_at, model_id, location_id) VALUES
+('DEC-001', 'available', NOW() - INTERVAL '5 days', 9, 1), -- id 21
+('DEC-002', 'available', NOW() - INTERVAL '5 days', 9, 1), -- id 22
+('DEC-003', 'available', NOW() - INTERVAL '5 days', 9, 1), -- id 23
+('DECM-001', 'available', NOW() - INTERVAL '5 days', 10, 2); -- id 24
+
+INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status, created_at) VALUES
+(9, 22, NOW() + INTERVAL '20 days', NOW() + INTERVAL '27 days', 1000.00, 'active', MAKE_TIMESTAMP(2023,1,1,0,0,0)), -- id 61
+(9, 24, NOW() + INTERVAL '20 days', NOW() + INTERVAL '27 days', 1000.00, 'pending_payment', MAKE_TIMESTAMP(2023,1,1,0,0,0)); -- id 62
+
+-- Category administration tests
+INSERT INTO categories (name, parent_id) VALUES
+('excavadoras', NULL), -- id 6
+('miniexcavadora', 6), -- id 7
+('mini excavadora', NULL); -- id 8
+
+INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image) VALUES
+('testcategoriesparent', 'model11', 'EC-B', 2023, 'No se realizan reembolsos por cancelaciones.', 'Modelo de prueba', 100000.00, 'imagecode'), -- id 11
+('testcategorieschild', 'model12', 'EC-B', 2023, 'No se realizan reembolsos por cancelaciones.', 'Modelo de prueba', 100000.00, 'imagecode'), -- id 12
+('testcategoriesmisspelled', 'model13', 'EC-B', 2023, 'No se realizan reembolsos por cancelaciones.', 'Modelo de prueba', 100000.00, 'imagecode'); -- id 13
+
+INSERT INTO machinery_units (serial_number, status, assigned_at, model_id, location_id) VALUES
+('CATG-001', 'available', NOW() - INTERVAL '5 days', 11, 1), -- id 25
+('CATG-002', 'available', NOW() - INTERVAL '5 days', 12, 1), -- id 26
+('CATG-003', 'available', NOW() - INTERVAL '5 days', 13, 1); -- id 27
+
+INSERT INTO machinery_categories (model_id, category_id) VALUES
+(11, 6),
+(12, 7),
+(13, 8);
+
+-- Model specs tests
+INSERT INTO spec_definitions (category_id, key, name, unit, data_type, allowed_values) VALUES
+(6, 'weight_kg', 'Peso operativo', 'kg', 'numeric', NULL), -- id 1
+(6, 'engine_power_hp', 'Potencia del motor', 'HP', 'numeric', NULL), -- id 2
+(6, 'track_type', 'Tren de rodaje', NULL, 'enum', ARRAY['orugas', 'neumaticos']), -- id 3
+(6, 'has_cabin', 'Cabina cerrada', NULL, 'bool', NULL); -- id 4
+
+INSERT INTO model_specs (model_id, spec_id, numeric_value, text_value, bool_value) VALUES
+(11, 1, 20000, NULL, NULL),
+(11, 2, 150, NULL, NULL),
+(11, 3, NULL, 'orugas', NULL),
+(11, 4, NULL, NULL, TRUE),
+(12, 1, 3500, NULL, NULL),
+(12, 2, 25, NULL, NULL),
+(12, 3, NULL, 'neumaticos', NULL),
+(12, 4, NULL, NULL, FALSE);
+
+-- Pricing tests
+INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image) VALUES
+('testpricing', 'model14', 'PRC-1', 2023, 'No se realizan reembolsos por cancelaciones.', 'Modelo de prueba', 1000.00, 'imagecode'); -- id 14
+
+INSERT INTO machinery_units (serial_number, status, assigned_at, model_id, location_id) VALUES
+('PRC-001', 'available', NOW() - INTERVAL '5 days', 14, 1); -- id 28
+
+INSERT INTO duration_discounts (model_id, min_days, discount_percentage) VALUES
+(14, 7, 5), -- id 1
+(14, 30, 15); -- id 2
+
+INSERT INTO seasonal_rates (name, model_id, start_date, end_date, multiplier) VALUES
+('Temporada alta', 14, CURRENT_DATE + 100, CURRENT_DATE + 109, 1.5); -- id 1
+
+-- Invoicing tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('invoices@example.com', 'Ines', 'Factura', 'nopasswordforyou', '123', 2, 'active'); -- id 25
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(25, '1990-03-15', 'ID250250', NULL);
+
+INSERT INTO tax_rates (model_id, name, percentage) VALUES
+(NULL, 'IVA 21%', 21); -- id 1
+
+-- Rental extension tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('extensions@example.com', 'Elena', 'Prorroga', 'nopasswordforyou', '123', 2, 'active'); -- id 26
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(26, '1988-07-02', 'ID260260', NULL);
+
+-- Cancellation policy tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('cancellations@example.com', 'Carla', 'Reintegro', 'nopasswordforyou', '123', 2, 'active'); -- id 27
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(27, '1992-11-20', 'ID270270', NULL);
+
+-- Charges tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('charges@example.com', 'Carlos', 'Multa', 'nopasswordforyou', '123', 2, 'active'); -- id 28
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(28, '1985-04-09', 'ID280280', NULL);
+
+-- Inspection tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('inspections@example.com', 'Ivan', 'Inspeccion', 'nopasswordforyou', '123', 2, 'active'); -- id 29
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(29, '1990-02-14', 'ID290290', NULL);
+
+-- Deposit tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('deposits@example.com', 'Diego', 'Garantia', 'nopasswordforyou', '123', 2, 'active'); -- id 30
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(30, '1983-09-30', 'ID300300', NULL);
+
+-- Waitlist tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('waitlist@example.com', 'Wanda', 'Espera', 'nopasswordforyou', '123', 2, 'active'), -- id 31
+('waitlist2@example.com', 'Walter', 'Turno', 'nopasswordforyou', '123', 2, 'active'); -- id 32
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(31, '1992-04-18', 'ID310310', NULL),
+(32, '1987-12-03', 'ID320320', NULL);
+
+-- Order tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('orders@example.com', 'Oscar', 'Pedido', 'nopasswordforyou', '123', 2, 'active'); -- id 33
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(33, '1979-08-21', 'ID330330', NULL);
+
+-- Delivery tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('deliveries@example.com', 'Daniel', 'Envio', 'nopasswordforyou', '123', 2, 'active'); -- id 34
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(34, '1988-06-12', 'ID340340', '1145678901');
+
+-- Transfer tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('transfers@example.com', 'Tomas', 'Traslado', 'nopasswordforyou', '123', 2, 'active'); -- id 35
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(35, '1991-03-27', 'ID350350', NULL);
+
+-- Rental document tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('documents@example.com', 'Diana', 'Contrato', 'nopasswordforyou', '123', 2, 'active'); -- id 36
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(36, '1984-10-05', 'ID360360', '1156789012');
+
+-- Calendar feed tests
+INSERT INTO users (email, name, surname, psw_hash, salt, role, status) VALUES
+('calendar@example.com', 'Carla', 'Agenda', 'nopasswordforyou', '123', 2, 'active'); -- id 37
+
+INSERT INTO user_info (id, birthdate, id_card, phone) VALUES
+(37, '1993-01-14', 'ID370370', '1167890123');
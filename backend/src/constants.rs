@@ -1,5 +1,27 @@
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
 pub const ACCESS_EXPIRATION_MINUTES: i64 = 15;
 pub const REFRESH_EXPIRATION_DAYS: i64 = 7;
 pub const CHANGE_PSW_CODE_EXP_MINS: i32 = 15;
-pub const LATE_RETURN_FINE: f32 = 0.1;
+pub const QUOTE_EXPIRATION_MINUTES: i64 = 15;
+pub const QUOTE_TOKEN_AUDIENCE: &str = "quote";
+pub const DEFAULT_PAYMENT_HOLD_MINUTES: i64 = 30;
+pub const PAYMENT_HOLD_SWEEP_SECONDS: u64 = 60;
+pub const DEFAULT_WAITLIST_PRIORITY_MINUTES: i64 = 60;
+pub const LATE_RETURN_FINE: Decimal = dec!(0.1);
+pub const CHARGE_DUE_DAYS: i64 = 10;
 pub const INTERNAL_PAYMENT_ID_PREFIX: u32 = 60000;
+pub const INVOICE_POINT_OF_SALE: i32 = 1;
+pub const COMPANY_NAME: &str = "Bob el Alquilador";
+pub const COMPANY_TAX_ID: &str = "30-71234567-1";
+pub const LOCAL_MEDIA_ROOT: &str = "media";
+pub const MACHINE_IMAGES_PREFIX: &str = "machines/";
+pub const STAGED_IMAGES_DIR: &str = "media/staging";
+pub const STAGED_IMAGE_MAX_AGE_MINUTES: u64 = 60;
+pub const MAX_EXTRA_IMAGES: usize = 10;
+pub const MAX_ORDER_LINES: u64 = 10;
+pub const MAX_IMAGE_UPLOAD_BYTES: usize = 8 * 1024 * 1024;
+pub const CALENDAR_FEED_PAST_DAYS: i32 = 90;
+pub const CALENDAR_UID_DOMAIN: &str = "bob-el-alquilador";
+pub const CALENDAR_FEED_TOKEN_AUDIENCE: &str = "calendar_feed";
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 pub enum RunningEnv {
     Production,
@@ -35,6 +35,56 @@ pub enum UnitStatusEvents {
     Maintenance,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FutureRentalsAction {
+    Reassign,
+    Cancel,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecDataType {
+    Numeric,
+    Enum,
+    Bool,
+}
+
+impl fmt::Display for SpecDataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SpecDataType::Numeric => "numeric",
+            SpecDataType::Enum => "enum",
+            SpecDataType::Bool => "bool",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecOperator {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl fmt::Display for SpecOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            SpecOperator::Eq => "=",
+            SpecOperator::NotEq => "!=",
+            SpecOperator::Lt => "<",
+            SpecOperator::LtEq => "<=",
+            SpecOperator::Gt => ">",
+            SpecOperator::GtEq => ">=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StatType {
@@ -74,3 +124,268 @@ pub enum ReviewOrder{
     LessRating,
     Recent,
 }
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxCondition {
+    ConsumidorFinal,
+    ResponsableInscripto,
+    Monotributista,
+    Exento,
+}
+
+impl fmt::Display for TaxCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TaxCondition::ConsumidorFinal => "consumidor_final",
+            TaxCondition::ResponsableInscripto => "responsable_inscripto",
+            TaxCondition::Monotributista => "monotributista",
+            TaxCondition::Exento => "exento",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceKind {
+    Invoice,
+    CreditNote,
+}
+
+impl fmt::Display for InvoiceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            InvoiceKind::Invoice => "invoice",
+            InvoiceKind::CreditNote => "credit_note",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageVariant {
+    Thumbnail,
+    Card,
+    Full,
+}
+
+impl ImageVariant {
+    pub const ALL: [ImageVariant; 3] = [
+        ImageVariant::Thumbnail,
+        ImageVariant::Card,
+        ImageVariant::Full,
+    ];
+
+    /// Full images keep the bare name they had before variants existed, so old URLs still work.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            ImageVariant::Thumbnail => "_thumb",
+            ImageVariant::Card => "_card",
+            ImageVariant::Full => "",
+        }
+    }
+
+    /// Longest side in pixels. Smaller images are never upscaled.
+    pub fn max_dimension(&self) -> u32 {
+        match self {
+            ImageVariant::Thumbnail => 200,
+            ImageVariant::Card => 600,
+            ImageVariant::Full => 1600,
+        }
+    }
+
+    pub fn file_name(&self, name: &str) -> String {
+        format!("{}{}.webp", name, self.suffix())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RentalStatus {
+    PendingPayment,
+    Active,
+    Retired,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl fmt::Display for RentalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RentalStatus::PendingPayment => "pending_payment",
+            RentalStatus::Active => "active",
+            RentalStatus::Retired => "retired",
+            RentalStatus::Completed => "completed",
+            RentalStatus::Cancelled => "cancelled",
+            RentalStatus::Failed => "failed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for RentalStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending_payment" => Ok(RentalStatus::PendingPayment),
+            "active" => Ok(RentalStatus::Active),
+            "retired" => Ok(RentalStatus::Retired),
+            "completed" => Ok(RentalStatus::Completed),
+            "cancelled" => Ok(RentalStatus::Cancelled),
+            "failed" => Ok(RentalStatus::Failed),
+            _ => Err(format!("Unknown rental status '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChargeStatus {
+    Pending,
+    Paid,
+    Waived,
+}
+
+impl fmt::Display for ChargeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ChargeStatus::Pending => "pending",
+            ChargeStatus::Paid => "paid",
+            ChargeStatus::Waived => "waived",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InspectionKind {
+    /// When the machine is retired by the client.
+    CheckOut,
+    /// When the machine is returned.
+    CheckIn,
+}
+
+impl fmt::Display for InspectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            InspectionKind::CheckOut => "check_out",
+            InspectionKind::CheckIn => "check_in",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DepositStatus {
+    /// Paid with the rental and not settled yet.
+    Held,
+    Released,
+    PartiallyKept,
+    Kept,
+}
+
+impl fmt::Display for DepositStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DepositStatus::Held => "held",
+            DepositStatus::Released => "released",
+            DepositStatus::PartiallyKept => "partially_kept",
+            DepositStatus::Kept => "kept",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliverySlot {
+    Morning,
+    Afternoon,
+}
+
+impl fmt::Display for DeliverySlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DeliverySlot::Morning => "morning",
+            DeliverySlot::Afternoon => "afternoon",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    Requested,
+    InTransit,
+    Received,
+    Cancelled,
+}
+
+impl fmt::Display for TransferStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TransferStatus::Requested => "requested",
+            TransferStatus::InTransit => "in_transit",
+            TransferStatus::Received => "received",
+            TransferStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarFeedKind {
+    Client,
+    Unit,
+    Branch,
+}
+
+impl fmt::Display for CalendarFeedKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            CalendarFeedKind::Client => "client",
+            CalendarFeedKind::Unit => "unit",
+            CalendarFeedKind::Branch => "branch",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Who moves a rental from one status to another. Background jobs act as the system.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RentalActor {
+    Admin(i32),
+    Employee(i32),
+    Client(i32),
+    System,
+}
+
+impl RentalActor {
+    pub fn new(user_id: i32, role: i16) -> Self {
+        match role {
+            0 => RentalActor::Admin(user_id),
+            1 => RentalActor::Employee(user_id),
+            _ => RentalActor::Client(user_id),
+        }
+    }
+
+    pub fn user_id(&self) -> Option<i32> {
+        match self {
+            RentalActor::Admin(id) | RentalActor::Employee(id) | RentalActor::Client(id) => {
+                Some(*id)
+            }
+            RentalActor::System => None,
+        }
+    }
+
+    pub fn is_staff(&self) -> bool {
+        matches!(self, RentalActor::Admin(_) | RentalActor::Employee(_))
+    }
+}
@@ -1,14 +1,46 @@
+use crate::constants::MAX_ORDER_LINES;
 use crate::custom_types::enums::UnitStatusEvents;
+use crate::helpers::{
+    images::{machine_image_key, machine_image_url},
+    media::MediaStore,
+};
 
 use super::enums::*;
-use axum::Json;
 use chrono::{NaiveDate, NaiveDateTime};
 use deadpool_postgres::Pool;
-use reqwest::StatusCode;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::collections::HashMap;
 use std::sync::Arc;
-use validator::Validate;
+use validator::{Validate, ValidationError};
+
+fn validate_non_negative_amount(amount: &Decimal) -> Result<(), ValidationError> {
+    if *amount < Decimal::ZERO {
+        return Err(ValidationError::new("negative_amount"));
+    }
+    Ok(())
+}
+
+fn validate_positive_amount(amount: &Decimal) -> Result<(), ValidationError> {
+    if *amount <= Decimal::ZERO {
+        return Err(ValidationError::new("non_positive_amount"));
+    }
+    Ok(())
+}
+
+fn validate_percentage(percentage: &Decimal) -> Result<(), ValidationError> {
+    if *percentage <= Decimal::ZERO || *percentage >= Decimal::ONE_HUNDRED {
+        return Err(ValidationError::new("invalid_percentage"));
+    }
+    Ok(())
+}
+
+fn validate_tax_percentage(percentage: &Decimal) -> Result<(), ValidationError> {
+    if *percentage < Decimal::ZERO || *percentage >= Decimal::ONE_HUNDRED {
+        return Err(ValidationError::new("invalid_percentage"));
+    }
+    Ok(())
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
@@ -48,6 +80,8 @@ pub struct UserInfo {
     pub birthdate: NaiveDate,
     pub id_card: String,
     pub phone: Option<String>,
+    pub tax_id: Option<String>,
+    pub tax_condition: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -110,6 +144,7 @@ pub struct Claims {
 #[derive(Clone)]
 pub struct AppState {
     pub pool: Arc<Pool>,
+    pub media: Arc<dyn MediaStore>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -128,6 +163,13 @@ pub struct ChangePhone {
     pub phone: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeBillingInfo {
+    pub access: String,
+    pub tax_id: Option<String>,
+    pub tax_condition: TaxCondition,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteEmployee {
     pub access: String,
@@ -155,10 +197,12 @@ pub struct CatalogParams {
     pub order_dir: Option<OrderDirection>,
     #[serde(default, rename = "category")]
     pub categories: Vec<String>,
-    #[validate(range(min = 0.0))]
-    pub min_price: Option<f32>,
-    #[validate(range(min = 0.0))]
-    pub max_price: Option<f32>,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub min_price: Option<Decimal>,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub max_price: Option<Decimal>,
+    #[serde(default, rename = "spec")]
+    pub specs: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -170,10 +214,43 @@ pub struct MachineModel {
     pub year: i32,
     pub policy: String,
     pub description: String,
-    pub price: f32,
+    pub price: Decimal,
+    /// Security deposit paid with each rental and settled at return.
+    pub deposit: Decimal,
     pub categories: Vec<Category>,
     pub main_image: String, //base64 encoded string
     pub extra_images: Vec<String>,
+    pub main_image_urls: ImageUrls,
+    pub extra_images_urls: Vec<ImageUrls>,
+    pub decommissioned_at: Option<NaiveDate>,
+    pub decommission_reason: Option<String>,
+    pub specs: Vec<ModelSpec>,
+    pub rental_rules: RentalRules,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageUrls {
+    pub thumbnail: String,
+    pub card: String,
+    pub full: String,
+}
+
+impl ImageUrls {
+    pub fn new(media: &dyn MediaStore, name: &str) -> Self {
+        let url = |variant: ImageVariant| media.url(&machine_image_key(name, variant));
+
+        ImageUrls {
+            thumbnail: url(ImageVariant::Thumbnail),
+            card: url(ImageVariant::Card),
+            full: url(ImageVariant::Full),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ImageSweepSummary {
+    pub removed_files: usize,
+    pub generated_variants: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -183,7 +260,10 @@ pub struct MyRentalInfo {
     pub retirement_date: Option<NaiveDate>,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
-    pub total_price: f32,
+    pub total_price: Decimal,
+    pub refund_amount: Option<Decimal>,
+    pub deposit_amount: Decimal,
+    pub deposit_status: Option<String>,
     pub status: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
@@ -201,25 +281,149 @@ pub struct MyRentalInfo {
     pub percentage_per_late_day: Option<String>,
     pub has_service_review: bool,
     pub has_machine_review: bool,
+    pub extensions: Vec<RentalExtension>,
+    /// Order the rental was booked in, if any.
+    pub order_id: Option<i32>,
 }
 
-impl MachineModel {
-    pub fn build_from_row(
-        row: &tokio_postgres::Row,
-    ) -> Result<Self, (StatusCode, Json<serde_json::Value>)> {
-        let nginx_url = match env::var("NGINX_URL") {
-            Ok(url) => url,
-            Err(_) => {
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({
-                        "error": "NGINX_URL environment variable is not set. Cannot get machine image."
-                    })),
-                ))
-            }
-        };
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RentalExtension {
+    pub id: i32,
+    pub rental_id: i32,
+    pub previous_end_date: NaiveDate,
+    pub new_end_date: NaiveDate,
+    pub price: Decimal,
+    pub status: String,
+    pub payment_deadline: Option<NaiveDateTime>,
+    pub rental_employee_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub paid_at: Option<NaiveDateTime>,
+    pub refund_amount: Option<Decimal>,
+}
+
+impl RentalExtension {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        RentalExtension {
+            id: row.get("id"),
+            rental_id: row.get("rental_id"),
+            previous_end_date: row.get("previous_end_date"),
+            new_end_date: row.get("new_end_date"),
+            price: row.get("price"),
+            status: row.get("status"),
+            payment_deadline: row.get("payment_deadline"),
+            rental_employee_id: row.get("rental_employee_id"),
+            created_at: row.get("created_at"),
+            paid_at: row.get("paid_at"),
+            refund_amount: row.get("refund_amount"),
+        }
+    }
+}
+
+/// Something a client owes besides the rental price, such as a late return fine.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Charge {
+    pub id: i32,
+    pub rental_id: i32,
+    pub user_id: i32,
+    pub kind: String,
+    pub description: String,
+    pub amount: Decimal,
+    pub status: String,
+    pub due_date: NaiveDate,
+    pub created_at: NaiveDateTime,
+    pub payment_id: Option<String>,
+    pub paid_at: Option<NaiveDateTime>,
+    pub collected_by: Option<i32>,
+    pub waived_by: Option<i32>,
+    pub waived_at: Option<NaiveDateTime>,
+    pub waive_reason: Option<String>,
+    /// Part of the amount kept from the rental's deposit. The client owes the rest.
+    pub deposit_applied: Decimal,
+}
+
+impl Charge {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        Charge {
+            id: row.get("id"),
+            rental_id: row.get("rental_id"),
+            user_id: row.get("user_id"),
+            kind: row.get("kind"),
+            description: row.get("description"),
+            amount: row.get("amount"),
+            status: row.get("status"),
+            due_date: row.get("due_date"),
+            created_at: row.get("created_at"),
+            payment_id: row.get("payment_id"),
+            paid_at: row.get("paid_at"),
+            collected_by: row.get("collected_by"),
+            waived_by: row.get("waived_by"),
+            waived_at: row.get("waived_at"),
+            waive_reason: row.get("waive_reason"),
+            deposit_applied: row.get("deposit_applied"),
+        }
+    }
+}
+
+/// How the deposit of a rental was settled against its charges.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DepositStatement {
+    pub rental_id: i32,
+    pub deposit_amount: Decimal,
+    pub status: Option<String>,
+    pub kept: Decimal,
+    pub released: Decimal,
+    pub settled_at: Option<NaiveDateTime>,
+    pub applications: Vec<DepositApplication>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DepositApplication {
+    pub charge_id: i32,
+    pub kind: String,
+    pub description: String,
+    pub amount: Decimal,
+    pub applied: Decimal,
+    /// What the client still owes for the charge.
+    pub outstanding: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChargeIdAndToken {
+    pub charge_id: i32,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct WaiveCharge {
+    pub charge_id: i32,
+    #[validate(length(min = 1))]
+    pub reason: String,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetCharges {
+    pub user_id: Option<i32>,
+    pub status: Option<ChargeStatus>,
+    pub access: String,
+}
 
-        let machine = MachineModel {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RequestExtension {
+    pub rental_id: i32,
+    pub new_end_date: NaiveDate,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtensionIdAndToken {
+    pub extension_id: i32,
+    pub access: String,
+}
+
+impl MachineModel {
+    pub fn build_from_row(row: &tokio_postgres::Row, media: &dyn MediaStore) -> Self {
+        MachineModel {
             id: row.get("id"),
             name: row.get("name"),
             brand: row.get("brand"),
@@ -228,225 +432,1021 @@ impl MachineModel {
             policy: row.get("policy"),
             description: row.get("description"),
             price: row.get("price"),
+            deposit: row.get("deposit"),
             categories: Vec::new(),
-            main_image: format!(
-                "{}/media/machines/{}.webp",
-                nginx_url,
-                row.get::<_, String>("image")
-            ),
+            main_image: machine_image_url(media, row.get("image")),
             extra_images: Vec::new(),
-        };
-
-        Ok(machine)
+            main_image_urls: ImageUrls::new(media, row.get("image")),
+            extra_images_urls: Vec::new(),
+            decommissioned_at: row.get("decommissioned_at"),
+            decommission_reason: row.get("decommission_reason"),
+            specs: Vec::new(),
+            rental_rules: RentalRules::build_from_row(row),
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct Category {
-    pub id: i32,
-    pub name: String,
+/// How a model can be rented. Lead times are counted in days from today and are only
+/// enforced when set. The defaults match the ones of the `machinery_models` columns.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_rental_rules"))]
+pub struct RentalRules {
+    #[validate(range(min = 1))]
+    pub min_rental_days: i32,
+    pub max_rental_days: Option<i32>,
+    /// Days the unit spends in maintenance after each rental.
+    #[validate(range(min = 0))]
+    pub turnaround_days: i32,
+    #[validate(range(min = 0))]
+    pub min_lead_days: Option<i32>,
+    pub max_lead_days: Option<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Location {
-    pub id: i32,
-    pub latitude: f64,
-    pub longitude: f64,
-    pub street: String,
-    pub number: String,
-    pub city: String,
+impl Default for RentalRules {
+    fn default() -> Self {
+        RentalRules {
+            min_rental_days: 7,
+            max_rental_days: None,
+            turnaround_days: 7,
+            min_lead_days: None,
+            max_lead_days: None,
+        }
+    }
 }
 
-impl Location {
+impl RentalRules {
     pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
-        Location {
-            id: row.get("id"),
-            latitude: row.get("latitude"),
-            longitude: row.get("longitude"),
-            street: row.get("street"),
-            number: row.get("number"),
-            city: row.get("city"),
+        RentalRules {
+            min_rental_days: row.get("min_rental_days"),
+            max_rental_days: row.get("max_rental_days"),
+            turnaround_days: row.get("turnaround_days"),
+            min_lead_days: row.get("min_lead_days"),
+            max_lead_days: row.get("max_lead_days"),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ModelAndLocation {
+fn validate_rental_rules(rules: &RentalRules) -> Result<(), ValidationError> {
+    if rules
+        .max_rental_days
+        .is_some_and(|max| max < rules.min_rental_days)
+    {
+        return Err(ValidationError::new("max_rental_days_below_min"));
+    }
+    if rules
+        .max_lead_days
+        .is_some_and(|max| max < rules.min_lead_days.unwrap_or(0))
+    {
+        return Err(ValidationError::new("max_lead_days_below_min"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateModelRentalRules {
+    pub access: String,
     pub model_id: i32,
-    pub location_id: i32,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub rules: RentalRules,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UnitDatesInfo {
-    machines_info: Vec<UnitAndDates>,
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateModelDeposit {
+    pub access: String,
+    pub model_id: i32,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub deposit: Decimal,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UnitAndDates {
-    pub unit_id: i32,
-    pub periods: Vec<DateRange>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefundTier {
+    /// Days left until the start of the rental needed for this tier to apply.
+    pub min_notice_days: i32,
+    pub refund_percentage: Decimal,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DateRange {
-    pub start_date: NaiveDate,
-    pub end_date: NaiveDate,
+/// A version of a model's cancellation policy. Rentals keep the version they were booked with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancellationPolicy {
+    pub id: i32,
+    pub model_id: i32,
+    pub version: i32,
+    pub staff_refund_percentage: Decimal,
+    pub tiers: Vec<RefundTier>,
+    pub created_at: NaiveDateTime,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Validate)]
-pub struct NewRental {
-    pub machine_id: i32,
-    pub start_date: NaiveDate,
-    pub end_date: NaiveDate,
-    #[validate(range(min = 0.0))]
-    pub total_price: f32,
-    pub access: String,
+impl CancellationPolicy {
+    /// Cancellations made by staff refund `staff_refund_percentage` whatever the notice. The
+    /// rest get the tier with the most notice days that were given, or nothing.
+    pub fn refund_percentage(&self, notice_days: i64, staff_initiated: bool) -> Decimal {
+        if staff_initiated {
+            return self.staff_refund_percentage;
+        }
+
+        self.tiers
+            .iter()
+            .filter(|tier| i64::from(tier.min_notice_days) <= notice_days)
+            .max_by_key(|tier| tier.min_notice_days)
+            .map_or(Decimal::ZERO, |tier| tier.refund_percentage)
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct NewModel {
-    pub name: String,
-    pub brand: String,
-    pub model: String,
-    pub year: i32,
-    pub policy: String,
-    pub description: String,
-    pub price: f32,
-    pub categories: Vec<String>,
-    pub extra_images: Vec<String>, //base64 encoded strings
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_cancellation_policy"))]
+pub struct UpdateCancellationPolicy {
     pub access: String,
-    pub image: String, //base64 encoded strings
+    pub model_id: i32,
+    pub staff_refund_percentage: Decimal,
+    pub tiers: Vec<RefundTier>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct NewUnit {
-    pub serial_number: String,
-    pub model_id: i32,
-    pub location_id: i32,
-    pub access: String,
+fn validate_cancellation_policy(policy: &UpdateCancellationPolicy) -> Result<(), ValidationError> {
+    let valid_percentage =
+        |percentage: Decimal| percentage >= Decimal::ZERO && percentage <= Decimal::ONE_HUNDRED;
+
+    if !valid_percentage(policy.staff_refund_percentage)
+        || policy
+            .tiers
+            .iter()
+            .any(|tier| !valid_percentage(tier.refund_percentage))
+    {
+        return Err(ValidationError::new("refund_percentage_out_of_range"));
+    }
+
+    let mut notice_days: Vec<i32> = policy.tiers.iter().map(|t| t.min_notice_days).collect();
+    notice_days.sort_unstable();
+    notice_days.dedup();
+
+    if notice_days.len() != policy.tiers.len() || notice_days.first().is_some_and(|d| *d < 0) {
+        return Err(ValidationError::new("invalid_notice_days"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CheckPayment {
-    pub payment_id: String,
-    pub status: PaymentStatus,
+pub struct CategoryInfo {
+    pub id: i32,
+    pub name: String,
+    pub parent_id: Option<i32>,
+    pub model_count: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct RentalIdAndToken {
-    pub rental_id: i32,
+pub struct NewCategory {
     pub access: String,
+    pub name: String,
+    pub parent_id: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct RentalInfo {
+pub struct UpdateCategory {
+    pub access: String,
     pub id: i32,
-    pub machine_brand: String,
-    pub machine_model: String,
-    pub machine_name: String,
-    pub start_date: NaiveDate,
-    pub end_date: NaiveDate,
-    pub city: String,
-    pub street: String,
-    pub number: String,
-    pub payment_id: String,
+    pub name: String,
+    pub parent_id: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct LoadRetirement {
+pub struct MergeCategories {
     pub access: String,
-    pub rental_id: i32,
+    pub source_id: i32,
+    pub target_id: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct LoadReturn {
+pub struct DeleteCategory {
     pub access: String,
-    pub rental_id: i32,
-    pub location_id: i32,
+    pub id: i32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CancelRentalInfo {
+pub struct SpecDefinition {
+    pub id: i32,
+    pub category_id: i32,
+    pub key: String,
+    pub name: String,
+    pub unit: Option<String>,
+    pub data_type: String,
+    pub allowed_values: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewSpecDefinition {
     pub access: String,
-    pub rental_id: i32,
-    pub reason: Option<String>,
+    pub category_id: i32,
+    pub key: String,
+    pub name: String,
+    pub unit: Option<String>,
+    pub data_type: SpecDataType,
+    pub allowed_values: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct GetRentalQueryParams {
-    pub id: Option<i32>, // Rental ID
+pub struct ModelSpec {
+    pub key: String,
+    pub name: String,
+    pub unit: Option<String>,
+    pub value: serde_json::Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct NewQuestion {
+pub struct UpdateModelSpecs {
     pub access: String,
     pub model_id: i32,
-    pub content: String,
+    pub specs: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct NewAnswer {
-    pub access: String,
-    pub question_id: i32,
-    pub content: String,
+#[derive(Debug)]
+pub struct SpecFilter {
+    pub key: String,
+    pub operator: SpecOperator,
+    pub value: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct VoteQuestion {
+pub struct RentalQuoteRequest {
     pub access: String,
-    pub question_id: i32,
-    pub upvote: bool,
+    pub machine_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct SerialNumber {
-    pub serial_number: String,
+pub struct QuoteLine {
+    pub description: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub days: i64,
+    pub daily_price: Decimal,
+    pub multiplier: Decimal,
+    pub amount: Decimal,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct GetMachineUnit {
+pub struct PriceQuote {
+    pub machine_id: i32,
     pub model_id: i32,
-    pub name: String,
-    pub brand: String,
-    pub model: String,
-    pub year: i32,
-    pub image: String, //base64 encoded string
-    pub unit_id: i32,
-    pub serial_number: String,
-    pub status: String,
-    pub location_id: i32,
-    pub city: String,
-    pub street: String,
-    pub number: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub days: i64,
+    pub base_daily_price: Decimal,
+    pub lines: Vec<QuoteLine>,
+    pub subtotal: Decimal,
+    pub discount_percentage: Decimal,
+    pub discount: Decimal,
+    pub total_price: Decimal,
+    pub deposit: Decimal,
+    /// Rental price plus deposit, which are paid together.
+    pub amount_due: Decimal,
 }
 
-impl GetMachineUnit {
-    pub fn build_from_row(
-        row: &tokio_postgres::Row,
-    ) -> Result<Self, (StatusCode, Json<serde_json::Value>)> {
-        let nginx_url = match env::var("NGINX_URL") {
-            Ok(url) => url,
-            Err(_) => {
-                return Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({
-                        "error": "NGINX_URL environment variable is not set. Cannot get machine image."
-                    })),
-                ))
-            }
-        };
+/// What a new rental is charged: its price and the deposit paid along with it.
+#[derive(Debug, Clone, Copy)]
+pub struct RentalPrice {
+    pub total_price: Decimal,
+    pub deposit: Decimal,
+}
 
-        Ok(GetMachineUnit {
-            model_id: row.get("model_id"),
-            name: row.get("name"),
-            brand: row.get("brand"),
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuoteClaims {
+    pub aud: String, //Tells quote tokens apart from the other tokens signed with the same key
+    pub user_id: i32, //Client the quote was given to
+    pub machine_id: i32,
+    pub model_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub total_price: Decimal,
+    pub deposit: Decimal,
+    pub exp: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DurationDiscount {
+    pub id: i32,
+    pub model_id: Option<i32>,
+    pub min_days: i32,
+    pub discount_percentage: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct NewDurationDiscount {
+    pub access: String,
+    pub model_id: Option<i32>,
+    #[validate(range(min = 1))]
+    pub min_days: i32,
+    #[validate(custom(function = "validate_percentage"))]
+    pub discount_percentage: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeasonalRate {
+    pub id: i32,
+    pub name: String,
+    pub model_id: Option<i32>,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub multiplier: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct NewSeasonalRate {
+    pub access: String,
+    #[validate(length(min = 1))]
+    pub name: String,
+    pub model_id: Option<i32>,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    #[validate(custom(function = "validate_positive_amount"))]
+    pub multiplier: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePricingRule {
+    pub access: String,
+    pub id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateBasePrice {
+    pub access: String,
+    pub model_id: i32,
+    #[validate(custom(function = "validate_positive_amount"))]
+    pub price: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaxRate {
+    pub id: i32,
+    pub model_id: Option<i32>,
+    pub name: String,
+    pub percentage: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct NewTaxRate {
+    pub access: String,
+    pub model_id: Option<i32>,
+    #[validate(length(min = 1))]
+    pub name: String,
+    #[validate(custom(function = "validate_tax_percentage"))]
+    pub percentage: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteTaxRate {
+    pub access: String,
+    pub id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetInvoices {
+    pub access: String,
+    pub rental_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceLine {
+    pub description: String,
+    pub quantity: i32,
+    pub unit_price: Decimal,
+    pub net_amount: Decimal,
+    pub tax_percentage: Decimal,
+    pub tax_amount: Decimal,
+    pub total_amount: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Invoice {
+    pub id: i32,
+    pub kind: String,
+    pub letter: String,
+    pub number: String,
+    pub rental_id: i32,
+    pub related_invoice_id: Option<i32>,
+    pub related_invoice_number: Option<String>,
+    pub user_id: i32,
+    pub billing_name: String,
+    pub billing_id_card: String,
+    pub billing_tax_id: Option<String>,
+    pub billing_tax_condition: String,
+    pub net_amount: Decimal,
+    pub tax_amount: Decimal,
+    pub total_amount: Decimal,
+    pub issued_at: NaiveDateTime,
+    pub lines: Vec<InvoiceLine>,
+}
+
+/// Data printed on the contract and the payment receipt of a rental.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RentalDocument {
+    pub rental_id: i32,
+    pub user_id: i32,
+    pub status: String,
+    pub client_name: String,
+    pub client_email: String,
+    pub client_id_card: Option<String>,
+    pub client_phone: Option<String>,
+    pub model_name: String,
+    pub brand: String,
+    pub model: String,
+    pub year: i32,
+    pub serial_number: String,
+    pub branch_address: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub total_price: Decimal,
+    pub extensions_price: Decimal,
+    pub delivery_fee: Option<Decimal>,
+    pub deposit_amount: Decimal,
+    pub policy: String,
+    pub payment_id: Option<String>,
+    pub paid_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub id: i32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub street: String,
+    pub number: String,
+    pub city: String,
+}
+
+impl Location {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        Location {
+            id: row.get("id"),
+            latitude: row.get("latitude"),
+            longitude: row.get("longitude"),
+            street: row.get("street"),
+            number: row.get("number"),
+            city: row.get("city"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelAndLocation {
+    pub model_id: i32,
+    pub location_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitDatesInfo {
+    machines_info: Vec<UnitAndDates>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitAndDates {
+    pub unit_id: i32,
+    pub periods: Vec<DateRange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DateRange {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Validate)]
+pub struct NewRental {
+    pub machine_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub total_price: Option<Decimal>,
+    pub quote_token: Option<String>,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct NewModelRental {
+    pub model_id: i32,
+    pub location_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub total_price: Option<Decimal>,
+    pub quote_token: Option<String>,
+    pub access: String,
+}
+
+/// An order books several units in one go, each line for its own dates. A line names either a
+/// unit or a model and location for a unit to be assigned.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct NewOrder {
+    #[validate(length(min = 1, max = MAX_ORDER_LINES), nested)]
+    pub lines: Vec<NewOrderLine>,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct NewOrderLine {
+    pub machine_id: Option<i32>,
+    pub model_id: Option<i32>,
+    pub location_id: Option<i32>,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub total_price: Option<Decimal>,
+    pub quote_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderIdAndToken {
+    pub order_id: i32,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Order {
+    pub id: i32,
+    pub user_id: i32,
+    /// pending_payment, paid, failed or cancelled, worked out from the payment and the lines.
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub payment_id: Option<String>,
+    pub paid_at: Option<NaiveDateTime>,
+    pub payment_deadline: Option<NaiveDateTime>,
+    /// Sum of the lines that weren't cancelled or failed.
+    pub total_price: Decimal,
+    pub deposit: Decimal,
+    pub lines: Vec<OrderLine>,
+}
+
+/// A rental of an order.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub rental_id: i32,
+    pub machine_id: i32,
+    pub model_id: i32,
+    pub model_name: String,
+    pub model_brand: String,
+    pub model_model: String,
+    pub location_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub status: String,
+    pub total_price: Decimal,
+    pub deposit_amount: Decimal,
+    pub refund_amount: Option<Decimal>,
+}
+
+impl OrderLine {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        OrderLine {
+            rental_id: row.get("rental_id"),
+            machine_id: row.get("machine_id"),
+            model_id: row.get("model_id"),
+            model_name: row.get("model_name"),
+            model_brand: row.get("model_brand"),
+            model_model: row.get("model_model"),
+            location_id: row.get("location_id"),
+            start_date: row.get("start_date"),
+            end_date: row.get("end_date"),
+            status: row.get("status"),
+            total_price: row.get("total_price"),
+            deposit_amount: row.get("deposit_amount"),
+            refund_amount: row.get("refund_amount"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JoinWaitlist {
+    pub model_id: i32,
+    pub location_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistEntryIdAndToken {
+    pub entry_id: i32,
+    pub access: String,
+}
+
+/// A client waiting for a unit of a model at a location for some dates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WaitlistEntry {
+    pub id: i32,
+    pub model_id: i32,
+    pub location_id: i32,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    /// Place in the queue of the model at the location, only while the entry is waiting.
+    pub position: Option<i64>,
+    /// Unit held for the client once notified, until `priority_until`.
+    pub unit_id: Option<i32>,
+    pub notified_at: Option<NaiveDateTime>,
+    pub priority_until: Option<NaiveDateTime>,
+}
+
+impl WaitlistEntry {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        WaitlistEntry {
+            id: row.get("id"),
+            model_id: row.get("model_id"),
+            location_id: row.get("location_id"),
+            start_date: row.get("start_date"),
+            end_date: row.get("end_date"),
+            status: row.get("status"),
+            created_at: row.get("created_at"),
+            position: row.get("position"),
+            unit_id: row.get("unit_id"),
+            notified_at: row.get("notified_at"),
+            priority_until: row.get("priority_until"),
+        }
+    }
+}
+
+/// Delivery service of a branch. The fee of each trip is `base_fee` plus `fee_per_km` for every
+/// km between the branch and the job site.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct DeliveryRates {
+    pub location_id: i32,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub base_fee: Decimal,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub fee_per_km: Decimal,
+    #[validate(custom(function = "validate_positive_amount"))]
+    pub max_distance_km: Decimal,
+    #[validate(range(min = 1))]
+    pub slot_capacity: i32, // trips per slot and day
+}
+
+impl DeliveryRates {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        DeliveryRates {
+            location_id: row.get("location_id"),
+            base_fee: row.get("base_fee"),
+            fee_per_km: row.get("fee_per_km"),
+            max_distance_km: row.get("max_distance_km"),
+            slot_capacity: row.get("slot_capacity"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateDeliveryRates {
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub rates: DeliveryRates,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct DeliveryQuoteQuery {
+    pub machine_id: i32,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: f64,
+    #[serde(default)]
+    pub pickup: bool,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RequestDelivery {
+    pub rental_id: i32,
+    #[validate(length(min = 1))]
+    pub address: String,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: f64,
+    pub delivery_slot: DeliverySlot,
+    pub pickup_slot: Option<DeliverySlot>, // None when the client returns the machine
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliveryScheduleQuery {
+    pub location_id: i32,
+    pub date: NaiveDate,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliverySlotAvailability {
+    pub slot: DeliverySlot,
+    pub capacity: i32,
+    pub booked: i64,
+}
+
+/// Delivery of a rental to the client's job site, and its pickup when requested.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: i32,
+    pub rental_id: i32,
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_km: Decimal,
+    pub fee: Decimal,
+    pub delivery_slot: String,
+    pub pickup_slot: Option<String>,
+    pub status: String,
+    pub charge_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub delivered_by: Option<i32>,
+    pub collected_at: Option<NaiveDateTime>,
+    pub collected_by: Option<i32>,
+}
+
+impl Delivery {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        Delivery {
+            id: row.get("id"),
+            rental_id: row.get("rental_id"),
+            address: row.get("address"),
+            latitude: row.get("latitude"),
+            longitude: row.get("longitude"),
+            distance_km: row.get("distance_km"),
+            fee: row.get("fee"),
+            delivery_slot: row.get("delivery_slot"),
+            pickup_slot: row.get("pickup_slot"),
+            status: row.get("status"),
+            charge_id: row.get("charge_id"),
+            created_at: row.get("created_at"),
+            delivered_at: row.get("delivered_at"),
+            delivered_by: row.get("delivered_by"),
+            collected_at: row.get("collected_at"),
+            collected_by: row.get("collected_by"),
+        }
+    }
+}
+
+/// A trip of the daily dispatch list of a branch, either taking a machine to a job site or
+/// bringing it back.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DispatchStop {
+    pub rental_id: i32,
+    pub kind: String, // "delivery" or "pickup"
+    pub slot: String,
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub status: String,
+    pub client_name: String,
+    pub client_phone: Option<String>,
+    pub serial_number: String,
+    pub model_name: String,
+    pub model_brand: String,
+    pub model_model: String,
+}
+
+impl DispatchStop {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        DispatchStop {
+            rental_id: row.get("rental_id"),
+            kind: row.get("kind"),
+            slot: row.get("slot"),
+            address: row.get("address"),
+            latitude: row.get("latitude"),
+            longitude: row.get("longitude"),
+            status: row.get("status"),
+            client_name: row.get("client_name"),
+            client_phone: row.get("client_phone"),
+            serial_number: row.get("serial_number"),
+            model_name: row.get("model_name"),
+            model_brand: row.get("model_brand"),
+            model_model: row.get("model_model"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewModelInfo {
+    pub name: String,
+    pub brand: String,
+    pub model: String,
+    pub year: i32,
+    pub policy: String,
+    pub description: String,
+    pub price: Decimal,
+    #[serde(default)]
+    pub deposit: Decimal,
+    pub categories: Vec<String>,
+    #[serde(default)]
+    pub specs: HashMap<String, serde_json::Value>, //spec key -> value
+    pub access: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewModel {
+    #[serde(flatten)]
+    pub info: NewModelInfo,
+    pub extra_images: Vec<String>, //base64 encoded strings
+    pub image: String,             //base64 encoded strings
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewUnit {
+    pub serial_number: String,
+    pub model_id: i32,
+    pub location_id: i32,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckPayment {
+    pub payment_id: String,
+    pub status: PaymentStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RentalIdAndToken {
+    pub rental_id: i32,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RentalInfo {
+    pub id: i32,
+    pub machine_brand: String,
+    pub machine_model: String,
+    pub machine_name: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub city: String,
+    pub street: String,
+    pub number: String,
+    pub payment_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoadRetirement {
+    pub access: String,
+    pub rental_id: i32,
+    pub inspection: Option<InspectionInput>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoadReturn {
+    pub access: String,
+    pub rental_id: i32,
+    pub location_id: i32,
+    pub inspection: Option<InspectionInput>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ChecklistItem {
+    #[validate(length(min = 1))]
+    pub item: String,
+    pub ok: bool,
+    pub notes: Option<String>,
+}
+
+/// Condition of the machine recorded by the employee who hands it over or takes it back.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct InspectionInput {
+    #[validate(nested)]
+    pub items: Vec<ChecklistItem>,
+    pub notes: Option<String>,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub hour_meter: Option<Decimal>,
+    /// Base64 encoded photos.
+    #[serde(default)]
+    pub photos: Vec<String>,
+    /// Amount charged to the client for the damage found. Only taken at return.
+    #[validate(custom(function = "validate_positive_amount"))]
+    pub damage_charge: Option<Decimal>,
+}
+
+impl InspectionInput {
+    pub fn has_damage(&self) -> bool {
+        self.items.iter().any(|item| !item.ok)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Inspection {
+    pub id: i32,
+    pub rental_id: i32,
+    pub kind: String,
+    pub employee_id: i32,
+    pub hour_meter: Option<Decimal>,
+    pub notes: Option<String>,
+    pub has_damage: bool,
+    pub damage_charge_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub items: Vec<ChecklistItem>,
+    pub photos: Vec<ImageUrls>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelRentalInfo {
+    pub access: String,
+    pub rental_id: i32,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetRentalQueryParams {
+    pub id: Option<i32>, // Rental ID
+    pub order_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RentalStatusChange {
+    pub from_status: Option<RentalStatus>,
+    pub to_status: RentalStatus,
+    pub actor_id: Option<i32>, // None when no user made the change
+    pub actor_name: Option<String>,
+    pub actor_role: Option<i16>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl RentalStatusChange {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        let parse = |status: String| {
+            status
+                .parse()
+                .expect("rental_status values must match RentalStatus")
+        };
+
+        RentalStatusChange {
+            from_status: row.get::<_, Option<String>>("from_status").map(parse),
+            to_status: parse(row.get("to_status")),
+            actor_id: row.get("actor_id"),
+            actor_name: row.get("actor_name"),
+            actor_role: row.get("actor_role"),
+            reason: row.get("reason"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewQuestion {
+    pub access: String,
+    pub model_id: i32,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewAnswer {
+    pub access: String,
+    pub question_id: i32,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VoteQuestion {
+    pub access: String,
+    pub question_id: i32,
+    pub upvote: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SerialNumber {
+    pub serial_number: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetMachineUnit {
+    pub model_id: i32,
+    pub name: String,
+    pub brand: String,
+    pub model: String,
+    pub year: i32,
+    pub image: String, //base64 encoded string
+    pub unit_id: i32,
+    pub serial_number: String,
+    pub status: String,
+    pub location_id: i32,
+    pub city: String,
+    pub street: String,
+    pub number: String,
+    pub decommissioned_at: Option<NaiveDate>,
+    pub decommission_reason: Option<String>,
+}
+
+impl GetMachineUnit {
+    pub fn build_from_row(row: &tokio_postgres::Row, media: &dyn MediaStore) -> Self {
+        GetMachineUnit {
+            model_id: row.get("model_id"),
+            name: row.get("name"),
+            brand: row.get("brand"),
             model: row.get("model"),
             year: row.get("year"),
-            image: format!(
-                "{}/media/machines/{}.webp",
-                nginx_url,
-                row.get::<_, String>("image")
-            ),
+            image: machine_image_url(media, row.get("image")),
             unit_id: row.get("unit_id"),
             serial_number: row.get("serial_number"),
             status: row.get::<_, String>("status"),
@@ -454,7 +1454,9 @@ impl GetMachineUnit {
             city: row.get("city"),
             street: row.get("street"),
             number: row.get("number"),
-        })
+            decommissioned_at: row.get("decommissioned_at"),
+            decommission_reason: row.get("decommission_reason"),
+        }
     }
 }
 
@@ -527,6 +1529,176 @@ pub struct UpdateUnitHistoryInfo {
     pub new_status: UnitStatusEvents,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewTransfer {
+    pub unit_id: i32,
+    pub to_location_id: i32,
+    pub departure_date: NaiveDate,
+    pub expected_arrival_date: NaiveDate,
+    pub notes: Option<String>,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferIdAndToken {
+    pub transfer_id: i32,
+    pub access: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetTransfers {
+    /// Transfers still requested or in transit when missing.
+    pub status: Option<TransferStatus>,
+    /// Transfers leaving or arriving at the location.
+    pub location_id: Option<i32>,
+    pub access: String,
+}
+
+/// Move of a unit from one branch to another.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitTransfer {
+    pub id: i32,
+    pub unit_id: i32,
+    pub serial_number: String,
+    pub model_id: i32,
+    pub model_name: String,
+    pub from_location_id: i32,
+    pub from_city: Option<String>,
+    pub to_location_id: i32,
+    pub to_city: Option<String>,
+    pub departure_date: NaiveDate,
+    pub expected_arrival_date: NaiveDate,
+    pub status: String,
+    pub notes: Option<String>,
+    pub requested_by: i32,
+    pub requested_at: NaiveDateTime,
+    pub shipped_by: Option<i32>,
+    pub shipped_at: Option<NaiveDateTime>,
+    pub received_by: Option<i32>,
+    pub received_at: Option<NaiveDateTime>,
+    pub cancelled_by: Option<i32>,
+    pub cancelled_at: Option<NaiveDateTime>,
+}
+
+impl UnitTransfer {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        UnitTransfer {
+            id: row.get("id"),
+            unit_id: row.get("unit_id"),
+            serial_number: row.get("serial_number"),
+            model_id: row.get("model_id"),
+            model_name: row.get("model_name"),
+            from_location_id: row.get("from_location_id"),
+            from_city: row.get("from_city"),
+            to_location_id: row.get("to_location_id"),
+            to_city: row.get("to_city"),
+            departure_date: row.get("departure_date"),
+            expected_arrival_date: row.get("expected_arrival_date"),
+            status: row.get("status"),
+            notes: row.get("notes"),
+            requested_by: row.get("requested_by"),
+            requested_at: row.get("requested_at"),
+            shipped_by: row.get("shipped_by"),
+            shipped_at: row.get("shipped_at"),
+            received_by: row.get("received_by"),
+            received_at: row.get("received_at"),
+            cancelled_by: row.get("cancelled_by"),
+            cancelled_at: row.get("cancelled_at"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewCalendarFeed {
+    pub access: String,
+    pub kind: CalendarFeedKind,
+    pub subject_id: Option<i32>, //Unit or location, clients always get the feed of their rentals
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CalendarFeedIdAndToken {
+    pub access: String,
+    pub feed_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CalendarFeedClaims {
+    pub aud: String, //Tells feed tokens apart from the other tokens signed with the same key
+    pub feed_id: i32,
+    pub kind: String,
+    pub subject_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CalendarFeed {
+    pub id: i32,
+    pub kind: String,
+    pub subject_id: i32,
+    pub created_by: i32,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub url: Option<String>, //Only for feeds that haven't been revoked
+}
+
+impl CalendarFeed {
+    pub fn build_from_row(row: &tokio_postgres::Row) -> Self {
+        CalendarFeed {
+            id: row.get("id"),
+            kind: row.get("kind"),
+            subject_id: row.get("subject_id"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+            revoked_at: row.get("revoked_at"),
+            url: None,
+        }
+    }
+}
+
+/// An event of an iCalendar feed. All day events end on the day after their last one.
+#[derive(Debug)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    pub location: Option<String>,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub all_day: bool,
+    pub status: &'static str,
+    pub sequence: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecommissionUnit {
+    pub access: String,
+    pub unit_id: i32,
+    pub reason: String,
+    pub date: Option<NaiveDate>,
+    pub future_rentals: Option<FutureRentalsAction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecommissionModel {
+    pub access: String,
+    pub model_id: i32,
+    pub reason: String,
+    pub date: Option<NaiveDate>,
+    pub future_rentals: Option<FutureRentalsAction>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReassignedRental {
+    pub rental_id: i32,
+    pub previous_unit_id: i32,
+    pub new_unit_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DecommissionSummary {
+    pub reassigned_rentals: Vec<ReassignedRental>,
+    pub cancelled_rentals: Vec<i32>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifyClient {
     pub access: String,
@@ -558,10 +1730,27 @@ pub struct GetStats {
     pub order: Option<StatOrder>,
 }
 
+/// Rentals are counted as integers, while income keeps exact decimals.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StatValue {
+    Count(i64),
+    Amount(Decimal),
+}
+
+impl From<StatValue> for Decimal {
+    fn from(value: StatValue) -> Self {
+        match value {
+            StatValue::Count(count) => Decimal::from(count),
+            StatValue::Amount(amount) => amount,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NameValue {
     pub name: String,
-    pub value: f64,
+    pub value: StatValue,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -600,8 +1789,9 @@ pub struct NewInPersonRental {
     pub user_id: i32,
     pub start_date: NaiveDate,
     pub end_date: NaiveDate,
-    #[validate(range(min = 0.0))]
-    pub total_price: f32,
+    #[validate(custom(function = "validate_non_negative_amount"))]
+    pub total_price: Option<Decimal>,
+    pub quote_token: Option<String>,
     pub access: String,
 }
 
@@ -1,6 +1,7 @@
 use crate::constants::CHANGE_PSW_CODE_EXP_MINS;
+use crate::custom_types::enums::TaxCondition;
 use crate::custom_types::structs::*;
-use crate::helpers::auth::*;
+use crate::helpers::{auth::*, invoices::is_valid_tax_id};
 use axum::{
     extract::State,
     http::{header, HeaderMap, HeaderValue, StatusCode},
@@ -203,7 +204,11 @@ pub async fn login(State(state): State<AppState>, Json(payload): Json<LoginReque
     //Clients(2) and Employees(1) have user_info
     let user_info = if pub_user.role != 0 {
         let row = match client
-            .query_one("SELECT * FROM user_info WHERE id = $1;", &[&pub_user.id])
+            .query_one(
+                "SELECT id, birthdate, id_card, phone, tax_id, tax_condition::TEXT
+                FROM user_info WHERE id = $1;",
+                &[&pub_user.id],
+            )
             .await
         {
             Ok(r) => r,
@@ -220,6 +225,8 @@ pub async fn login(State(state): State<AppState>, Json(payload): Json<LoginReque
             birthdate: row.get("birthdate"),
             id_card: row.get("id_card"),
             phone: row.get("phone"),
+            tax_id: row.get("tax_id"),
+            tax_condition: row.get("tax_condition"),
         })
     } else {
         None
@@ -1090,3 +1097,74 @@ pub async fn change_phone(
         }
     };
 }
+
+pub async fn change_billing_info(
+    State(state): State<AppState>,
+    Json(payload): Json<ChangeBillingInfo>,
+) -> Response {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            )
+                .into_response()
+        }
+    }
+    .claims;
+
+    let tax_id = payload
+        .tax_id
+        .map(|id| id.replace(['-', ' '], ""))
+        .filter(|id| !id.is_empty());
+
+    let tax_id_is_valid = match &tax_id {
+        Some(id) => is_valid_tax_id(id),
+        None => payload.tax_condition == TaxCondition::ConsumidorFinal,
+    };
+
+    if !tax_id_is_valid {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid tax ID"})),
+        )
+            .into_response();
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            )
+                .into_response()
+        }
+    };
+
+    match client
+        .execute(
+            "UPDATE user_info SET tax_id = $1, tax_condition = $2::TEXT::tax_condition
+            WHERE id = $3;",
+            &[&tax_id, &payload.tax_condition.to_string(), &claims.user_id],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The user has no billing information"})),
+        )
+            .into_response(),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Billing information changed successfully"})),
+        )
+            .into_response(),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to change the billing information"})),
+        )
+            .into_response(),
+    }
+}
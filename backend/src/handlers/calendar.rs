@@ -0,0 +1,259 @@
+use crate::custom_types::{enums::CalendarFeedKind, structs::*};
+use crate::helpers::{auth::validate_jwt, calendar::*};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+fn internal_error() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+    )
+}
+
+fn feed_not_found() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"message": "No se ha encontrado el calendario"})),
+    )
+}
+
+/// Clients get the feed of their own rentals and staff the ones of units and branches. A
+/// subject only has one feed at a time, requesting it again returns the same URL.
+pub async fn new_calendar_feed(
+    State(state): State<AppState>,
+    Json(payload): Json<NewCalendarFeed>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+    let claims = token.claims;
+
+    let subject_id = match (payload.kind, claims.role) {
+        (CalendarFeedKind::Client, 2) => claims.user_id,
+        (CalendarFeedKind::Client, _) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "Client feeds can only be requested by the client"})),
+            );
+        }
+        (_, 2) => {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({"message": "No tiene permisos para acceder a este calendario"})),
+            );
+        }
+        (_, _) => match payload.subject_id {
+            Some(subject_id) => subject_id,
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "The unit or location of the feed is missing"})),
+                );
+            }
+        },
+    };
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let subject_query = match payload.kind {
+        CalendarFeedKind::Client => "SELECT 1 FROM users WHERE id = $1;",
+        CalendarFeedKind::Unit => "SELECT 1 FROM machinery_units WHERE id = $1;",
+        CalendarFeedKind::Branch => "SELECT 1 FROM locations WHERE id = $1;",
+    };
+
+    match client.query_opt(subject_query, &[&subject_id]).await {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": format!("There is no {} with that id", payload.kind)})),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    let existing = client
+        .query_opt(
+            "SELECT id FROM calendar_feeds
+            WHERE kind = $1::TEXT::calendar_feed_kind AND subject_id = $2 AND revoked_at IS NULL;",
+            &[&payload.kind.to_string(), &subject_id],
+        )
+        .await;
+
+    let (status, feed_row) = match existing {
+        Ok(Some(row)) => (StatusCode::OK, row),
+        Ok(None) => match client
+            .query_one(
+                "INSERT INTO calendar_feeds (kind, subject_id, created_by)
+                VALUES ($1::TEXT::calendar_feed_kind, $2, $3)
+                RETURNING id;",
+                &[&payload.kind.to_string(), &subject_id, &claims.user_id],
+            )
+            .await
+        {
+            Ok(row) => (StatusCode::CREATED, row),
+            Err(_) => return internal_error(),
+        },
+        Err(_) => return internal_error(),
+    };
+
+    match get_calendar_feed(&client, feed_row.get("id")).await {
+        Ok(Some(feed)) => match with_feed_url(feed) {
+            Ok(feed) => (status, Json(json!({"feed": feed}))),
+            Err(_) => internal_error(),
+        },
+        _ => internal_error(),
+    }
+}
+
+/// Feeds that haven't been revoked, the client's own one or every unit and branch feed.
+pub async fn get_calendar_feeds(
+    State(state): State<AppState>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+    let claims = token.claims;
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let rows = if claims.role == 2 {
+        client
+            .query(
+                "SELECT id, kind::TEXT, subject_id, created_by, created_at, revoked_at
+                FROM calendar_feeds
+                WHERE kind = 'client' AND subject_id = $1 AND revoked_at IS NULL;",
+                &[&claims.user_id],
+            )
+            .await
+    } else {
+        client
+            .query(
+                "SELECT id, kind::TEXT, subject_id, created_by, created_at, revoked_at
+                FROM calendar_feeds
+                WHERE kind != 'client' AND revoked_at IS NULL
+                ORDER BY kind, subject_id;",
+                &[],
+            )
+            .await
+    };
+
+    let Ok(rows) = rows else {
+        return internal_error();
+    };
+
+    let feeds: Result<Vec<CalendarFeed>, ()> = rows
+        .iter()
+        .map(|row| with_feed_url(CalendarFeed::build_from_row(row)))
+        .collect();
+
+    match feeds {
+        Ok(feeds) => (StatusCode::OK, Json(json!({"feeds": feeds}))),
+        Err(_) => internal_error(),
+    }
+}
+
+/// Revoking a feed disables its URL for good, the next request gets a new one.
+pub async fn revoke_calendar_feed(
+    State(state): State<AppState>,
+    Json(payload): Json<CalendarFeedIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+    let claims = token.claims;
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let feed = match get_calendar_feed(&client, payload.feed_id).await {
+        Ok(feed) => feed,
+        Err(_) => return internal_error(),
+    };
+
+    // Clients can't tell apart feeds of other users from missing ones
+    let allowed = |feed: &CalendarFeed| {
+        let is_client_feed = feed.kind == CalendarFeedKind::Client.to_string();
+
+        match claims.role {
+            2 => is_client_feed && feed.subject_id == claims.user_id,
+            _ => !is_client_feed,
+        }
+    };
+
+    match feed {
+        Some(feed) if allowed(&feed) && feed.revoked_at.is_none() => (),
+        _ => return feed_not_found(),
+    }
+
+    match client
+        .execute(
+            "UPDATE calendar_feeds SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL;",
+            &[&payload.feed_id],
+        )
+        .await
+    {
+        Ok(1) => (
+            StatusCode::OK,
+            Json(json!({"message": "El calendario ha sido revocado"})),
+        ),
+        Ok(_) => feed_not_found(),
+        Err(_) => internal_error(),
+    }
+}
+
+/// The iCalendar feed behind a signed URL. It's built on every request, so calendar apps get
+/// changes of status when they refresh it.
+pub async fn get_calendar(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    let Some(claims) = validate_feed_token(&token) else {
+        return feed_not_found().into_response();
+    };
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error().into_response();
+    };
+
+    let feed = match get_calendar_feed(&client, claims.feed_id).await {
+        Ok(Some(feed))
+            if feed.revoked_at.is_none()
+                && feed.kind == claims.kind
+                && feed.subject_id == claims.subject_id =>
+        {
+            feed
+        }
+        Ok(_) => return feed_not_found().into_response(),
+        Err(_) => return internal_error().into_response(),
+    };
+
+    let Ok(calendar) = render_feed(&client, &feed).await else {
+        return internal_error().into_response();
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/calendar; charset=utf-8"),
+    );
+
+    (StatusCode::OK, headers, calendar).into_response()
+}
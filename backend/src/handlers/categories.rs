@@ -0,0 +1,414 @@
+use crate::custom_types::structs::*;
+use crate::helpers::machinery_mgmt::*;
+use axum::{extract::State, http::StatusCode, Json};
+use serde_json::json;
+use tokio_postgres::error::SqlState;
+
+pub async fn get_categories(
+    State(state): State<AppState>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let categories_query = "
+        SELECT c.id, c.name, c.parent_id, COUNT(mc.model_id) AS model_count
+        FROM categories c
+        LEFT JOIN machinery_categories mc ON c.id = mc.category_id
+        GROUP BY c.id
+        ORDER BY c.name ASC;
+    ";
+
+    match client.query(categories_query, &[]).await {
+        Ok(rows) => {
+            let categories: Vec<CategoryInfo> = rows
+                .iter()
+                .map(|row| CategoryInfo {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    parent_id: row.get("parent_id"),
+                    model_count: row.get("model_count"),
+                })
+                .collect();
+
+            (StatusCode::OK, Json(json!({"categories": categories})))
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se produjo un error al obtener las categorías"})),
+        ),
+    }
+}
+
+pub async fn new_category(
+    State(state): State<AppState>,
+    Json(payload): Json<NewCategory>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let name = payload.name.trim().to_lowercase();
+
+    if name.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "El nombre de la categoría no puede estar vacío"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query_one(
+            "INSERT INTO categories (name, parent_id) VALUES ($1, $2) RETURNING id;",
+            &[&name, &payload.parent_id],
+        )
+        .await
+    {
+        Ok(row) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "message": "La categoría se ha creado correctamente",
+                "id": row.get::<_, i32>("id"),
+            })),
+        ),
+        Err(e) => category_db_error(e),
+    }
+}
+
+pub async fn update_category(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateCategory>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let name = payload.name.trim().to_lowercase();
+
+    if name.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "El nombre de la categoría no puede estar vacío"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    // A category cannot hang from itself or from one of its own subcategories
+    if let Some(parent_id) = payload.parent_id {
+        let descendants_query = "
+            WITH RECURSIVE tree AS (
+                SELECT id FROM categories WHERE id = $1
+                UNION
+                SELECT c.id FROM categories c INNER JOIN tree t ON c.parent_id = t.id
+            )
+            SELECT 1 FROM tree WHERE id = $2;
+        ";
+
+        match client
+            .query_opt(descendants_query, &[&payload.id, &parent_id])
+            .await
+        {
+            Ok(None) => (),
+            Ok(Some(_)) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "message": "La categoría no puede ubicarse dentro de sí misma o de una de sus subcategorías"
+                    })),
+                );
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"message": "Se produjo un error al actualizar la categoría"})),
+                );
+            }
+        }
+    }
+
+    match client
+        .execute(
+            "UPDATE categories SET name = $1, parent_id = $2 WHERE id = $3;",
+            &[&name, &payload.parent_id, &payload.id],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "La categoría indicada no existe"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "La categoría se ha actualizado correctamente"})),
+        ),
+        Err(e) => category_db_error(e),
+    }
+}
+
+pub async fn merge_categories(
+    State(state): State<AppState>,
+    Json(payload): Json<MergeCategories>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.source_id == payload.target_id {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Una categoría no puede fusionarse consigo misma"})),
+        );
+    }
+
+    let mut client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let transaction = match client.transaction().await {
+        Ok(t) => t,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to create a DB transaction"})),
+            );
+        }
+    };
+
+    let internal_error = (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se produjo un error al fusionar las categorías"})),
+    );
+
+    let rows = match transaction
+        .query(
+            "SELECT id FROM categories WHERE id IN ($1, $2) FOR UPDATE;",
+            &[&payload.source_id, &payload.target_id],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return internal_error,
+    };
+
+    if rows.len() != 2 {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "La categoría indicada no existe"})),
+        );
+    }
+
+    // The subcategories of the source move to the target, so the target can't be one of them
+    let ancestors_query = "
+        WITH RECURSIVE ancestors AS (
+            SELECT parent_id AS id FROM categories WHERE id = $1
+            UNION
+            SELECT c.parent_id FROM categories c INNER JOIN ancestors a ON c.id = a.id
+        )
+        SELECT 1 FROM ancestors WHERE id = $2;
+    ";
+
+    match transaction
+        .query_opt(ancestors_query, &[&payload.target_id, &payload.source_id])
+        .await
+    {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "message": "Una categoría no puede fusionarse con una de sus subcategorías"
+                })),
+            );
+        }
+        Err(_) => return internal_error,
+    }
+
+    // Models already linked to both categories keep a single link
+    if transaction
+        .execute(
+            "INSERT INTO machinery_categories (model_id, category_id)
+            SELECT model_id, $2 FROM machinery_categories WHERE category_id = $1
+            ON CONFLICT DO NOTHING;",
+            &[&payload.source_id, &payload.target_id],
+        )
+        .await
+        .is_err()
+    {
+        return internal_error;
+    }
+
+    if transaction
+        .execute(
+            "DELETE FROM machinery_categories WHERE category_id = $1;",
+            &[&payload.source_id],
+        )
+        .await
+        .is_err()
+    {
+        return internal_error;
+    }
+
+    if transaction
+        .execute(
+            "UPDATE spec_definitions SET category_id = $2 WHERE category_id = $1;",
+            &[&payload.source_id, &payload.target_id],
+        )
+        .await
+        .is_err()
+    {
+        return internal_error;
+    }
+
+    if transaction
+        .execute(
+            "UPDATE categories SET parent_id = $2 WHERE parent_id = $1;",
+            &[&payload.source_id, &payload.target_id],
+        )
+        .await
+        .is_err()
+    {
+        return internal_error;
+    }
+
+    if transaction
+        .execute(
+            "DELETE FROM categories WHERE id = $1;",
+            &[&payload.source_id],
+        )
+        .await
+        .is_err()
+    {
+        return internal_error;
+    }
+
+    if transaction.commit().await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to commit transaction"})),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "Las categorías se han fusionado correctamente"})),
+    )
+}
+
+pub async fn delete_category(
+    State(state): State<AppState>,
+    Json(payload): Json<DeleteCategory>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let delete_query = "
+        DELETE FROM categories c
+        WHERE c.id = $1
+        AND NOT EXISTS (SELECT 1 FROM machinery_categories mc WHERE mc.category_id = c.id)
+        AND NOT EXISTS (SELECT 1 FROM categories sub WHERE sub.parent_id = c.id);
+    ";
+
+    match client.execute(delete_query, &[&payload.id]).await {
+        Ok(1) => (
+            StatusCode::OK,
+            Json(json!({"message": "La categoría se ha eliminado correctamente"})),
+        ),
+        Ok(_) => {
+            match client
+                .query_opt("SELECT id FROM categories WHERE id = $1;", &[&payload.id])
+                .await
+            {
+                Ok(Some(_)) => (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "message": "La categoría está asignada a modelos o tiene subcategorías y no puede eliminarse"
+                    })),
+                ),
+                Ok(None) => (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"message": "La categoría indicada no existe"})),
+                ),
+                Err(_) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"message": "Se produjo un error al eliminar la categoría"})),
+                ),
+            }
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se produjo un error al eliminar la categoría"})),
+        ),
+    }
+}
+
+fn category_db_error(e: tokio_postgres::Error) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(db_err) = e.as_db_error() {
+        match db_err.code() {
+            &SqlState::UNIQUE_VIOLATION => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "Ya existe una categoría con ese nombre"})),
+                );
+            }
+            &SqlState::FOREIGN_KEY_VIOLATION | &SqlState::CHECK_VIOLATION => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "La categoría padre indicada no es válida"})),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se produjo un error al guardar la categoría"})),
+    )
+}
@@ -0,0 +1,274 @@
+use crate::constants::INTERNAL_PAYMENT_ID_PREFIX;
+use crate::custom_types::{enums::*, structs::*};
+use crate::helpers::{
+    auth::validate_jwt,
+    charges::*,
+    machinery_mgmt::{get_claims_from_token, validate_admin, validate_client},
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use rust_decimal::Decimal;
+use serde_json::json;
+use validator::Validate;
+
+pub async fn list_charges(
+    State(state): State<AppState>,
+    Json(payload): Json<GetCharges>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    // Clients only see their own charges, staff can look up any client
+    let user_id = if claims.role == 2 {
+        Some(claims.user_id)
+    } else {
+        payload.user_id
+    };
+
+    match get_charges(&client, user_id, payload.status).await {
+        Ok(charges) => {
+            let pending_amount: Decimal = charges
+                .iter()
+                .filter(|charge| charge.status == ChargeStatus::Pending.to_string())
+                .map(|charge| charge.amount - charge.deposit_applied)
+                .sum();
+
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "charges": charges,
+                    "pending_amount": pending_amount,
+                })),
+            )
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error al obtener los cargos"})),
+        ),
+    }
+}
+
+pub async fn check_charge_payment(
+    State(state): State<AppState>,
+    Query(query_params): Query<CheckPayment>,
+    Json(payload): Json<ChargeIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    match query_params.status {
+        PaymentStatus::Pending => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"message": "El pago no ha sido aprobado ni rechazado"})),
+            );
+        }
+        PaymentStatus::Rejected => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "message": "Ha ocurrido un error en el pago. El cargo sigue pendiente.",
+                })),
+            );
+        }
+        PaymentStatus::Approved => {}
+    }
+
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+        )
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    match transaction
+        .query_opt(
+            "SELECT id FROM charges
+            WHERE id = $1 AND user_id = $2 AND status = 'pending'
+            FOR UPDATE;",
+            &[&payload.charge_id, &claims.user_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado el cargo pendiente de pago"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    if settle_charge(
+        &transaction,
+        payload.charge_id,
+        &query_params.payment_id,
+        None,
+    )
+    .await
+    .is_err()
+        || transaction.commit().await.is_err()
+    {
+        return internal_error();
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "El cargo ha sido pagado"})),
+    )
+}
+
+pub async fn collect_charge(
+    State(state): State<AppState>,
+    Json(payload): Json<ChargeIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if token.claims.role == 2 {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "message": "Solo empleados y administradores pueden acceder a esta funcionalidad",
+            })),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let payment_id = format!(
+        "C{}",
+        INTERNAL_PAYMENT_ID_PREFIX + (payload.charge_id as u32)
+    );
+
+    match settle_charge(
+        &client,
+        payload.charge_id,
+        &payment_id,
+        Some(token.claims.user_id),
+    )
+    .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "No se ha encontrado el cargo pendiente de pago"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "message": "El cargo ha sido cobrado",
+                "payment_id": payment_id,
+            })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+        ),
+    }
+}
+
+pub async fn waive_charge(
+    State(state): State<AppState>,
+    Json(payload): Json<WaiveCharge>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "A reason is required to waive a charge"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .execute(
+            "UPDATE charges
+            SET status = 'waived', waived_by = $2, waived_at = NOW(), waive_reason = $3
+            WHERE id = $1 AND status = 'pending';",
+            &[&payload.charge_id, &claims.user_id, &payload.reason],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The charge does not exist or is not pending"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Charge waived successfully"})),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to waive the charge"})),
+        ),
+    }
+}
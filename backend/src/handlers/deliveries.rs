@@ -0,0 +1,560 @@
+use crate::custom_types::structs::*;
+use crate::helpers::{
+    auth::validate_jwt,
+    charges::record_delivery_charge,
+    deliveries::*,
+    machinery_mgmt::{get_claims_from_token, validate_admin, validate_client},
+};
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{Local, NaiveDate};
+use rust_decimal::Decimal;
+use serde_json::json;
+use validator::Validate;
+
+fn internal_error() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+    )
+}
+
+fn validate_staff(access_token: &str) -> Result<i32, (StatusCode, Json<serde_json::Value>)> {
+    let Some(token) = validate_jwt(access_token) else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        ));
+    };
+
+    if token.claims.role == 2 {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "message": "Solo empleados y administradores pueden acceder a esta funcionalidad",
+            })),
+        ));
+    }
+
+    Ok(token.claims.user_id)
+}
+
+/// Distance from the branch to the job site and fee of the delivery, or the response to send
+/// when the branch can't deliver there.
+fn quote_delivery(
+    rates: Option<DeliveryRates>,
+    branch: (Option<f64>, Option<f64>),
+    site: (f64, f64),
+    pickup: bool,
+) -> Result<(DeliveryRates, Decimal, Decimal), (StatusCode, Json<serde_json::Value>)> {
+    let (Some(rates), (Some(latitude), Some(longitude))) = (rates, branch) else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "La sucursal de la máquina no realiza envíos a obra"})),
+        ));
+    };
+
+    let distance = distance_km((latitude, longitude), site);
+
+    if distance > rates.max_distance_km {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": format!(
+                    "La obra está a {} km de la sucursal. Solo se realizan envíos hasta {} km",
+                    distance, rates.max_distance_km
+                ),
+            })),
+        ));
+    }
+
+    let fee = delivery_fee(&rates, distance, pickup);
+
+    Ok((rates, distance, fee))
+}
+
+pub async fn get_delivery_quote(
+    State(state): State<AppState>,
+    Json(payload): Json<DeliveryQuoteQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Las coordenadas de la obra no son válidas"})),
+        );
+    }
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let row = match client
+        .query_opt(
+            "SELECT l.id, l.latitude, l.longitude
+            FROM machinery_units mu
+            INNER JOIN locations l ON l.id = mu.location_id
+            WHERE mu.id = $1;",
+            &[&payload.machine_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    let Ok(rates) = get_delivery_rates(&client, row.get("id")).await else {
+        return internal_error();
+    };
+
+    match quote_delivery(
+        rates,
+        (row.get("latitude"), row.get("longitude")),
+        (payload.latitude, payload.longitude),
+        payload.pickup,
+    ) {
+        Ok((rates, distance, fee)) => (
+            StatusCode::OK,
+            Json(json!({
+                "location_id": rates.location_id,
+                "distance_km": distance,
+                "max_distance_km": rates.max_distance_km,
+                "pickup": payload.pickup,
+                "fee": fee,
+            })),
+        ),
+        Err(error_response) => error_response,
+    }
+}
+
+pub async fn request_delivery(
+    State(state): State<AppState>,
+    Json(payload): Json<RequestDelivery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "La dirección o las coordenadas de la obra no son válidas"})),
+        );
+    }
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    // Locking the branch keeps two requests from taking the last trip of a slot
+    let rental = match transaction
+        .query_opt(
+            "SELECT r.start_date, r.end_date, l.id AS location_id, l.latitude, l.longitude
+            FROM rentals r
+            INNER JOIN machinery_units mu ON mu.id = r.machine_id
+            INNER JOIN locations l ON l.id = mu.location_id
+            WHERE r.id = $1 AND r.user_id = $2 AND r.status = 'active'
+            FOR UPDATE OF r, l;",
+            &[&payload.rental_id, &claims.user_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "message": "No se ha encontrado un alquiler pago pendiente de retiro",
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    let start_date: NaiveDate = rental.get("start_date");
+    let end_date: NaiveDate = rental.get("end_date");
+    let location_id: i32 = rental.get("location_id");
+
+    if start_date <= Local::now().date_naive() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": "El envío a obra debe solicitarse al menos un día antes del inicio del alquiler",
+            })),
+        );
+    }
+
+    match get_delivery(&transaction, payload.rental_id).await {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"message": "El alquiler ya tiene un envío a obra"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    let Ok(rates) = get_delivery_rates(&transaction, location_id).await else {
+        return internal_error();
+    };
+
+    let (rates, distance, fee) = match quote_delivery(
+        rates,
+        (rental.get("latitude"), rental.get("longitude")),
+        (payload.latitude, payload.longitude),
+        payload.pickup_slot.is_some(),
+    ) {
+        Ok(quote) => quote,
+        Err(error_response) => return error_response,
+    };
+
+    let mut trips = vec![(start_date, payload.delivery_slot)];
+    if let Some(pickup_slot) = payload.pickup_slot {
+        trips.push((end_date, pickup_slot));
+    }
+
+    for (date, slot) in trips {
+        match booked_trips(&transaction, location_id, date, slot).await {
+            Ok(booked) if booked < rates.slot_capacity as i64 => (),
+            Ok(_) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({
+                        "message": format!(
+                            "No quedan turnos de envío disponibles para el {}",
+                            date.format("%d/%m/%Y")
+                        ),
+                        "date": date,
+                        "slot": slot,
+                    })),
+                );
+            }
+            Err(_) => return internal_error(),
+        }
+    }
+
+    let Ok(charge_id) = record_delivery_charge(
+        &transaction,
+        payload.rental_id,
+        claims.user_id,
+        fee,
+        start_date,
+    )
+    .await
+    else {
+        return internal_error();
+    };
+
+    if transaction
+        .execute(
+            "INSERT INTO deliveries (rental_id, address, latitude, longitude, distance_km, fee,
+            delivery_slot, pickup_slot, charge_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7::TEXT::delivery_slot, $8::TEXT::delivery_slot, $9);",
+            &[
+                &payload.rental_id,
+                &payload.address,
+                &payload.latitude,
+                &payload.longitude,
+                &distance,
+                &fee,
+                &payload.delivery_slot.to_string(),
+                &payload.pickup_slot.map(|slot| slot.to_string()),
+                &charge_id,
+            ],
+        )
+        .await
+        .is_err()
+    {
+        return internal_error();
+    }
+
+    let Ok(Some(delivery)) = get_delivery(&transaction, payload.rental_id).await else {
+        return internal_error();
+    };
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "message": "El envío a obra ha sido programado. Recuerde abonar el cargo del envío antes del inicio del alquiler",
+            "delivery": delivery,
+        })),
+    )
+}
+
+pub async fn get_delivery_slots(
+    State(state): State<AppState>,
+    Json(payload): Json<DeliveryScheduleQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if validate_jwt(&payload.access).is_none() {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    }
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let rates = match get_delivery_rates(&client, payload.location_id).await {
+        Ok(Some(rates)) => rates,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "La sucursal no realiza envíos a obra"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    match get_slot_availability(&client, &rates, payload.date).await {
+        Ok(slots) => (
+            StatusCode::OK,
+            Json(json!({
+                "location_id": payload.location_id,
+                "date": payload.date,
+                "slots": slots,
+            })),
+        ),
+        Err(_) => internal_error(),
+    }
+}
+
+pub async fn get_dispatch_list(
+    State(state): State<AppState>,
+    Json(payload): Json<DeliveryScheduleQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Err(invalid_response) = validate_staff(&payload.access) {
+        return invalid_response;
+    }
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let rows = match client
+        .query(
+            "SELECT * FROM (
+                SELECT r.id AS rental_id, 'delivery' AS kind, d.delivery_slot::TEXT AS slot,
+                d.address, d.latitude, d.longitude, d.status::TEXT,
+                u.name || ' ' || u.surname AS client_name, ui.phone AS client_phone,
+                mu.serial_number, mm.name AS model_name, mm.brand AS model_brand,
+                mm.model AS model_model
+                FROM deliveries d
+                INNER JOIN rentals r ON r.id = d.rental_id
+                INNER JOIN machinery_units mu ON mu.id = r.machine_id
+                INNER JOIN machinery_models mm ON mm.id = mu.model_id
+                INNER JOIN users u ON u.id = r.user_id
+                LEFT JOIN user_info ui ON ui.id = u.id
+                WHERE mu.location_id = $1 AND r.start_date = $2 AND d.status != 'cancelled'
+                UNION ALL
+                SELECT r.id, 'pickup', d.pickup_slot::TEXT,
+                d.address, d.latitude, d.longitude, d.status::TEXT,
+                u.name || ' ' || u.surname, ui.phone,
+                mu.serial_number, mm.name, mm.brand, mm.model
+                FROM deliveries d
+                INNER JOIN rentals r ON r.id = d.rental_id
+                INNER JOIN machinery_units mu ON mu.id = r.machine_id
+                INNER JOIN machinery_models mm ON mm.id = mu.model_id
+                INNER JOIN users u ON u.id = r.user_id
+                LEFT JOIN user_info ui ON ui.id = u.id
+                WHERE mu.location_id = $1 AND r.end_date = $2 AND d.pickup_slot IS NOT NULL
+                AND d.status != 'cancelled'
+            ) stops
+            ORDER BY slot::delivery_slot, kind, rental_id;",
+            &[&payload.location_id, &payload.date],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to get the dispatch list"})),
+            );
+        }
+    };
+
+    let stops: Vec<DispatchStop> = rows.iter().map(DispatchStop::build_from_row).collect();
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "location_id": payload.location_id,
+            "date": payload.date,
+            "stops": stops,
+        })),
+    )
+}
+
+/// Records that the machine of a retired rental reached the job site.
+pub async fn mark_delivered(
+    State(state): State<AppState>,
+    Json(payload): Json<RentalIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let employee_id = match validate_staff(&payload.access) {
+        Ok(user_id) => user_id,
+        Err(invalid_response) => return invalid_response,
+    };
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    // The machine leaves the branch through the retirement of the rental
+    match client
+        .execute(
+            "UPDATE deliveries d
+            SET status = 'delivered', delivered_at = NOW(), delivered_by = $2
+            FROM rentals r
+            WHERE d.rental_id = $1 AND r.id = d.rental_id
+            AND d.status = 'scheduled' AND r.status = 'retired';",
+            &[&payload.rental_id, &employee_id],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": "There is no scheduled delivery of a retired rental with that id",
+            })),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Delivery recorded successfully"})),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to record the delivery"})),
+        ),
+    }
+}
+
+/// Records that the machine was picked up at the job site. It's returned at the branch as usual.
+pub async fn mark_collected(
+    State(state): State<AppState>,
+    Json(payload): Json<RentalIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let employee_id = match validate_staff(&payload.access) {
+        Ok(user_id) => user_id,
+        Err(invalid_response) => return invalid_response,
+    };
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    match client
+        .execute(
+            "UPDATE deliveries d
+            SET status = 'collected', collected_at = NOW(), collected_by = $2
+            FROM rentals r
+            WHERE d.rental_id = $1 AND r.id = d.rental_id
+            AND d.status = 'delivered' AND d.pickup_slot IS NOT NULL AND r.status = 'retired';",
+            &[&payload.rental_id, &employee_id],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": "There is no delivered machine to pick up for a rental with that id",
+            })),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Pickup recorded successfully"})),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to record the pickup"})),
+        ),
+    }
+}
+
+pub async fn update_delivery_rates(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateDeliveryRates>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": "Fees can't be negative, and the maximum distance and the slot capacity must be positive",
+            })),
+        );
+    }
+
+    let Ok(client) = state.pool.get().await else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to connect to the DB"})),
+        );
+    };
+
+    let rates = &payload.rates;
+
+    // Deliveries already scheduled keep the fee they were quoted
+    match client
+        .execute(
+            "INSERT INTO delivery_rates (location_id, base_fee, fee_per_km, max_distance_km, slot_capacity)
+            SELECT id, $2, $3, $4, $5 FROM locations WHERE id = $1
+            ON CONFLICT (location_id) DO UPDATE
+            SET base_fee = EXCLUDED.base_fee, fee_per_km = EXCLUDED.fee_per_km,
+            max_distance_km = EXCLUDED.max_distance_km, slot_capacity = EXCLUDED.slot_capacity,
+            updated_at = NOW();",
+            &[
+                &rates.location_id,
+                &rates.base_fee,
+                &rates.fee_per_km,
+                &rates.max_distance_km,
+                &rates.slot_capacity,
+            ],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The location does not exist"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "message": "Delivery rates updated successfully",
+                "rates": rates,
+            })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to update the delivery rates"})),
+        ),
+    }
+}
@@ -0,0 +1,122 @@
+use crate::custom_types::{enums::RentalStatus, structs::*};
+use crate::helpers::{auth::validate_jwt, documents::*};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+pub async fn get_rental_contract(
+    State(state): State<AppState>,
+    Path(rental_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> Response {
+    let document = match load_rental_document(state, rental_id, &payload.access).await {
+        Ok(document) => document,
+        Err(error_response) => return error_response.into_response(),
+    };
+
+    // Rentals that were never paid have no contract
+    if document.status == RentalStatus::PendingPayment.to_string()
+        || document.status == RentalStatus::Failed.to_string()
+    {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"message": "El alquiler no ha sido pagado"})),
+        )
+            .into_response();
+    }
+
+    match render_contract_pdf(&document) {
+        Ok(pdf) => pdf_response(&contract_file_name(rental_id), pdf),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error al generar el contrato"})),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn get_rental_receipt(
+    State(state): State<AppState>,
+    Path(rental_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> Response {
+    let document = match load_rental_document(state, rental_id, &payload.access).await {
+        Ok(document) => document,
+        Err(error_response) => return error_response.into_response(),
+    };
+
+    if document.payment_id.is_none() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"message": "El alquiler no ha sido pagado"})),
+        )
+            .into_response();
+    }
+
+    match render_receipt_pdf(&document) {
+        Ok(pdf) => pdf_response(&receipt_file_name(rental_id), pdf),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error al generar el recibo"})),
+        )
+            .into_response(),
+    }
+}
+
+async fn load_rental_document(
+    state: AppState,
+    rental_id: i32,
+    access: &str,
+) -> Result<RentalDocument, (StatusCode, Json<serde_json::Value>)> {
+    let claims = match validate_jwt(access) {
+        Some(data) => data,
+        None => {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            ));
+        }
+    }
+    .claims;
+
+    let client = state.pool.get().await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to connect to the DB"})),
+        )
+    })?;
+
+    let document = get_rental_document(&client, rental_id).await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error al obtener el alquiler"})),
+        )
+    })?;
+
+    match document {
+        // Clients only get the documents of their own rentals
+        Some(document) if claims.role != 2 || document.user_id == claims.user_id => Ok(document),
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "El alquiler no se ha encontrado"})),
+        )),
+    }
+}
+
+fn pdf_response(file_name: &str, pdf: Vec<u8>) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/pdf"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name)).unwrap(),
+    );
+
+    (StatusCode::OK, headers, pdf).into_response()
+}
@@ -0,0 +1,322 @@
+use crate::custom_types::structs::*;
+use crate::helpers::{auth::validate_jwt, invoices::*, machinery_mgmt::validate_admin};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tokio_postgres::error::SqlState;
+use validator::Validate;
+
+pub async fn get_invoices(
+    State(state): State<AppState>,
+    Json(payload): Json<GetInvoices>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    // Clients only see their own invoices, staff sees every one
+    let user_id = (claims.role == 2).then_some(claims.user_id);
+
+    match find_invoices(&client, None, user_id, payload.rental_id).await {
+        Ok(invoices) => (StatusCode::OK, Json(json!({"invoices": invoices}))),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error al obtener las facturas"})),
+        ),
+    }
+}
+
+pub async fn get_invoice(
+    State(state): State<AppState>,
+    Path(invoice_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> Response {
+    match load_invoice(state, invoice_id, &payload.access).await {
+        Ok(invoice) => {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&format!(
+                    "attachment; filename=\"{}.json\"",
+                    invoice_file_name(&invoice)
+                ))
+                .unwrap(),
+            );
+
+            (StatusCode::OK, headers, Json(json!(invoice))).into_response()
+        }
+        Err(error_response) => error_response.into_response(),
+    }
+}
+
+pub async fn get_invoice_pdf(
+    State(state): State<AppState>,
+    Path(invoice_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> Response {
+    let invoice = match load_invoice(state, invoice_id, &payload.access).await {
+        Ok(invoice) => invoice,
+        Err(error_response) => return error_response.into_response(),
+    };
+
+    let Ok(pdf) = render_invoice_pdf(&invoice) else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error al generar el PDF de la factura"})),
+        )
+            .into_response();
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/pdf"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            "attachment; filename=\"{}.pdf\"",
+            invoice_file_name(&invoice)
+        ))
+        .unwrap(),
+    );
+
+    (StatusCode::OK, headers, pdf).into_response()
+}
+
+pub async fn get_tax_rates(
+    State(state): State<AppState>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query(
+            "SELECT * FROM tax_rates ORDER BY model_id NULLS FIRST;",
+            &[],
+        )
+        .await
+    {
+        Ok(rows) => {
+            let tax_rates: Vec<TaxRate> = rows
+                .iter()
+                .map(|row| TaxRate {
+                    id: row.get("id"),
+                    model_id: row.get("model_id"),
+                    name: row.get("name"),
+                    percentage: row.get("percentage"),
+                })
+                .collect();
+
+            (StatusCode::OK, Json(json!({"tax_rates": tax_rates})))
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to get the tax rates"})),
+        ),
+    }
+}
+
+/// Creates the tax rate of a model (or the default one when no model is given),
+/// replacing the current one if there is any.
+pub async fn set_tax_rate(
+    State(state): State<AppState>,
+    Json(payload): Json<NewTaxRate>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid tax rate"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query_one(
+            "INSERT INTO tax_rates (model_id, name, percentage) VALUES ($1, $2, $3)
+            ON CONFLICT (model_id) DO UPDATE SET name = $2, percentage = $3
+            RETURNING id;",
+            &[&payload.model_id, &payload.name.trim(), &payload.percentage],
+        )
+        .await
+    {
+        Ok(row) => (
+            StatusCode::OK,
+            Json(json!({
+                "message": "Tax rate saved successfully",
+                "id": row.get::<_, i32>("id"),
+            })),
+        ),
+        Err(e) => match e.as_db_error().map(|db_err| db_err.code()) {
+            Some(&SqlState::FOREIGN_KEY_VIOLATION) => (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "The model does not exist"})),
+            ),
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to save the tax rate"})),
+            ),
+        },
+    }
+}
+
+pub async fn delete_tax_rate(
+    State(state): State<AppState>,
+    Json(payload): Json<DeleteTaxRate>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let tax_rate = match client
+        .query_opt(
+            "SELECT model_id FROM tax_rates WHERE id = $1;",
+            &[&payload.id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "The tax rate does not exist"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to delete the tax rate"})),
+            );
+        }
+    };
+
+    if tax_rate.get::<_, Option<i32>>("model_id").is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The default tax rate cannot be deleted"})),
+        );
+    }
+
+    match client
+        .execute("DELETE FROM tax_rates WHERE id = $1;", &[&payload.id])
+        .await
+    {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Tax rate deleted successfully"})),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to delete the tax rate"})),
+        ),
+    }
+}
+
+async fn load_invoice(
+    state: AppState,
+    invoice_id: i32,
+    access: &str,
+) -> Result<Invoice, (StatusCode, Json<serde_json::Value>)> {
+    let claims = match validate_jwt(access) {
+        Some(data) => data,
+        None => {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            ));
+        }
+    }
+    .claims;
+
+    let client = state.pool.get().await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to connect to the DB"})),
+        )
+    })?;
+
+    let invoice = find_invoices(&client, Some(invoice_id), None, None)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Se ha producido un error al obtener la factura"})),
+            )
+        })?
+        .pop();
+
+    match invoice {
+        // Clients can't tell apart invoices of other users from missing ones
+        Some(invoice) if claims.role != 2 || invoice.user_id == claims.user_id => Ok(invoice),
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "No se ha encontrado la factura"})),
+        )),
+    }
+}
+
+fn invoice_file_name(invoice: &Invoice) -> String {
+    let prefix = if invoice.related_invoice_id.is_some() {
+        "nota-de-credito"
+    } else {
+        "factura"
+    };
+
+    format!("{}-{}-{}", prefix, invoice.letter, invoice.number)
+}
@@ -1,9 +1,25 @@
 use crate::constants::INTERNAL_PAYMENT_ID_PREFIX;
 use crate::constants::LATE_RETURN_FINE;
+use crate::constants::{MAX_EXTRA_IMAGES, MAX_IMAGE_UPLOAD_BYTES};
 use crate::custom_types::enums::*;
 use crate::custom_types::structs::*;
-use crate::helpers::{auth::*, machinery_mgmt::*};
+use crate::helpers::{
+    auth::*,
+    charges::*,
+    deliveries::get_delivery,
+    deposits::*,
+    documents::rental_attachments,
+    images::{machine_image_url, sniff_image_format, StagedImages},
+    inspections::*,
+    invoices::*,
+    machinery_mgmt::*,
+    pricing::*,
+    rentals::*,
+    specs::*,
+    waitlist::*,
+};
 use axum::{
+    extract::multipart::{Multipart, MultipartError},
     extract::Path,
     extract::State,
     http::StatusCode,
@@ -11,13 +27,11 @@ use axum::{
     Json,
 };
 use axum_extra::extract::Query;
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine;
 use chrono::Duration;
 use chrono::{Local, NaiveDate, NaiveDateTime};
-use image::ImageFormat;
+use rust_decimal::Decimal;
 use serde_json::json;
-use std::{env, fs::File, io::BufWriter, path::PathBuf, collections::HashSet};
+use std::collections::HashSet;
 use tokio_postgres::{error::SqlState, types::ToSql};
 use validator::Validate;
 
@@ -44,7 +58,6 @@ pub async fn explore_catalog(
         let limit = page_size;
 
         let mut where_clauses: Vec<String> = Vec::new();
-        let mut join_clauses: Vec<String> = Vec::new();
         let mut params: Vec<Box<dyn ToSql + Sync + Send>> = Vec::new();
         let mut param_idx = 1;
 
@@ -94,9 +107,6 @@ pub async fn explore_catalog(
         let categories = &query_params.categories;
 
         if !categories.is_empty() {
-            join_clauses
-                .push("INNER JOIN machinery_categories mc ON mm.id = mc.model_id".to_string());
-            join_clauses.push("INNER JOIN categories c ON mc.category_id = c.id".to_string());
             let category_placeholders: Vec<String> = categories
                 .iter()
                 .map(|_| {
@@ -106,13 +116,95 @@ pub async fn explore_catalog(
                 })
                 .collect();
 
-            where_clauses.push(format!("c.name IN ({})", category_placeholders.join(", ")));
+            // A parent category also matches the models of all its subcategories
+            where_clauses.push(format!(
+                "mm.id IN (SELECT mc.model_id FROM machinery_categories mc
+                WHERE mc.category_id IN (
+                    WITH RECURSIVE category_tree AS (
+                        SELECT id FROM categories WHERE name IN ({})
+                        UNION
+                        SELECT c.id FROM categories c
+                        INNER JOIN category_tree ct ON c.parent_id = ct.id
+                    )
+                    SELECT id FROM category_tree))",
+                category_placeholders.join(", ")
+            ));
 
             for cat_name in categories {
                 params.push(Box::new(cat_name.clone()));
             }
         }
 
+        for spec_filter in &query_params.specs {
+            let invalid_filter = (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "message": format!("Filtro de especificación inválido: {}", spec_filter),
+                })),
+            );
+
+            let Some(filter) = parse_spec_filter(spec_filter) else {
+                return invalid_filter;
+            };
+
+            let definition = match client
+                .query_opt(
+                    "SELECT id, data_type::TEXT FROM spec_definitions WHERE key = $1;",
+                    &[&filter.key],
+                )
+                .await
+            {
+                Ok(Some(row)) => row,
+                Ok(None) => return invalid_filter,
+                Err(_) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({
+                            "message": "Se ha producido un error interno en el servidor",
+                        })),
+                    );
+                }
+            };
+
+            let is_equality = matches!(filter.operator, SpecOperator::Eq | SpecOperator::NotEq);
+
+            // Ranges only make sense for numeric specs
+            let column = match definition.get::<_, String>("data_type").as_str() {
+                "numeric" => match filter.value.parse::<f64>() {
+                    Ok(value) => {
+                        params.push(Box::new(value));
+                        "numeric_value"
+                    }
+                    Err(_) => return invalid_filter,
+                },
+                "enum" if is_equality => {
+                    params.push(Box::new(filter.value.clone()));
+                    "text_value"
+                }
+                "bool" if is_equality => match filter.value.parse::<bool>() {
+                    Ok(value) => {
+                        params.push(Box::new(value));
+                        "bool_value"
+                    }
+                    Err(_) => return invalid_filter,
+                },
+                _ => return invalid_filter,
+            };
+
+            params.push(Box::new(definition.get::<_, i32>("id")));
+
+            where_clauses.push(format!(
+                "EXISTS (SELECT 1 FROM model_specs ms
+                WHERE ms.model_id = mm.id AND ms.spec_id = ${} AND ms.{} {} ${})",
+                param_idx + 1,
+                column,
+                filter.operator,
+                param_idx
+            ));
+
+            param_idx += 2;
+        }
+
         let where_clause = if where_clauses.is_empty() {
             "".to_string()
         } else {
@@ -146,29 +238,20 @@ pub async fn explore_catalog(
         let limit_param_idx = param_idx;
         let offset_param_idx = param_idx + 1;
 
-        let all_joins = join_clauses.join(" ");
-
-        let exists_clause = "EXISTS
+        let exists_clause = "mm.decommissioned_at IS NULL AND EXISTS
         (SELECT * FROM machinery_units mu2 
-        WHERE mu2.model_id = mm.id)";
+        WHERE mu2.model_id = mm.id AND mu2.status != 'decommissioned')";
 
         let select_query = format!(
-            "SELECT DISTINCT mm.* FROM machinery_models mm 
-            {} WHERE {} {} {} LIMIT ${} OFFSET ${};",
-            all_joins, exists_clause, where_clause, order_clause, limit_param_idx, offset_param_idx
+            "SELECT mm.* FROM machinery_models mm 
+            WHERE {} {} {} LIMIT ${} OFFSET ${};",
+            exists_clause, where_clause, order_clause, limit_param_idx, offset_param_idx
         );
 
-        let count_query = if categories.is_empty() {
-            format!(
-                "SELECT COUNT(*) FROM machinery_models mm WHERE {} {};",
-                exists_clause, where_clause
-            )
-        } else {
-            format!(
-                "SELECT COUNT(DISTINCT mm.id) FROM machinery_models mm {} WHERE {} {};",
-                all_joins, exists_clause, where_clause
-            )
-        };
+        let count_query = format!(
+            "SELECT COUNT(*) FROM machinery_models mm WHERE {} {};",
+            exists_clause, where_clause
+        );
 
         let all_params_slice: Vec<&(dyn ToSql + Sync + Send)> =
             params.iter().map(|p| p.as_ref()).collect();
@@ -193,21 +276,11 @@ pub async fn explore_catalog(
 
                 match client.query(&select_query, select_params.as_slice()).await {
                     Ok(machinery_rows) => {
-                        let result_machinery_list: Result<
-                            Vec<MachineModel>,
-                            (StatusCode, Json<serde_json::Value>),
-                        > = machinery_rows
+                        let mut machinery_list: Vec<MachineModel> = machinery_rows
                             .iter()
-                            .map(|row| MachineModel::build_from_row(row))
+                            .map(|row| MachineModel::build_from_row(row, state.media.as_ref()))
                             .collect();
 
-                        let mut machinery_list = match result_machinery_list {
-                            Ok(list) => list,
-                            Err((status, json)) => {
-                                return (status, json);
-                            }
-                        };
-
                         let all_categories: Vec<String>;
 
                         if let Ok(all_categories_rows) = client
@@ -290,30 +363,12 @@ pub async fn select_machine(
     Path(machine_id): Path<i32>,
 ) -> (StatusCode, Json<serde_json::Value>) {
     if let Ok(client) = state.pool.get().await {
-        let nginx_url = match env::var("NGINX_URL") {
-            Ok(url) => url,
-            Err(_) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({
-                        "message": "NGINX_URL environment variable is not set. Cannot get machine image.",
-                    })),
-                );
-            }
-        };
-
-        let machine_query = "SELECT * FROM machinery_models WHERE id = $1;";
+        let machine_query =
+            "SELECT * FROM machinery_models WHERE id = $1 AND decommissioned_at IS NULL;";
 
         match client.query_one(machine_query, &[&machine_id]).await {
             Ok(machine_row) => {
-                let result_machine = MachineModel::build_from_row(&machine_row);
-
-                let mut machine = match result_machine {
-                    Ok(m) => m,
-                    Err((status, json)) => {
-                        return (status, json);
-                    }
-                };
+                let mut machine = MachineModel::build_from_row(&machine_row, state.media.as_ref());
 
                 let extra_images_query = "
                     SELECT name FROM model_extra_images WHERE id = $1;";
@@ -322,14 +377,12 @@ pub async fn select_machine(
                     client.query(extra_images_query, &[&machine.id]).await
                 {
                     machine.extra_images = extra_images_rows
-                        .into_iter()
-                        .map(|row| {
-                            format!(
-                                "{}/media/machines/{}.webp",
-                                nginx_url,
-                                row.get::<_, String>("name")
-                            )
-                        })
+                        .iter()
+                        .map(|row| machine_image_url(state.media.as_ref(), row.get("name")))
+                        .collect();
+                    machine.extra_images_urls = extra_images_rows
+                        .iter()
+                        .map(|row| ImageUrls::new(state.media.as_ref(), row.get("name")))
                         .collect();
                 } else {
                     return (
@@ -364,6 +417,38 @@ pub async fn select_machine(
                     );
                 }
 
+                let specs_query = "
+                    SELECT sd.key, sd.name, sd.unit, sd.data_type::TEXT,
+                    ms.numeric_value, ms.text_value, ms.bool_value
+                    FROM model_specs ms
+                    INNER JOIN spec_definitions sd ON ms.spec_id = sd.id
+                    WHERE ms.model_id = $1
+                    ORDER BY sd.id;
+                ";
+
+                if let Ok(spec_rows) = client.query(specs_query, &[&machine.id]).await {
+                    machine.specs = spec_rows
+                        .into_iter()
+                        .map(|row| ModelSpec {
+                            value: match row.get::<_, String>("data_type").as_str() {
+                                "numeric" => json!(row.get::<_, Option<f64>>("numeric_value")),
+                                "enum" => json!(row.get::<_, Option<String>>("text_value")),
+                                _ => json!(row.get::<_, Option<bool>>("bool_value")),
+                            },
+                            key: row.get("key"),
+                            name: row.get("name"),
+                            unit: row.get("unit"),
+                        })
+                        .collect();
+                } else {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({
+                            "message": "Se ha producido un error interno al intentar obtener las especificaciones",
+                        })),
+                    );
+                }
+
                 return (
                     StatusCode::OK,
                     Json(json!({
@@ -422,7 +507,7 @@ pub async fn get_machine_locations(
             SELECT DISTINCT l.* FROM machinery_models mm
             INNER JOIN machinery_units mu ON mm.id = mu.model_id 
             INNER JOIN locations l ON mu.location_id = l.id
-            WHERE mm.id = $1;
+            WHERE mm.id = $1 AND mu.status != 'decommissioned';
         ";
 
         if let Ok(location_rows) = client.query(locations_query, &[&machine_id]).await {
@@ -472,7 +557,7 @@ pub async fn get_units_unavailable_dates(
             FROM machinery_units mu
             INNER JOIN machinery_models mm ON mu.model_id = mm.id
             INNER JOIN locations l ON mu.location_id = l.id
-            WHERE mm.id = $1 AND mu.location_id = $2;
+            WHERE mm.id = $1 AND mu.location_id = $2 AND mu.status != 'decommissioned';
         ";
 
         let model_id = query_params.model_id;
@@ -494,9 +579,11 @@ pub async fn get_units_unavailable_dates(
             let unit_ids: Vec<i32> = machine_units.iter().map(|row| row.get(0)).collect();
 
             let unavailable_dates_query = "
-                SELECT start_date, (end_date + INTERVAL '7 days')::date AS end_date
-                FROM rentals r 
-                WHERE r.status IN ('active', 'pending_payment') AND (machine_id = $1);
+                SELECT b.start_date, b.end_date + mm.turnaround_days AS end_date
+                FROM unit_bookings b
+                INNER JOIN machinery_units mu ON b.machine_id = mu.id
+                INNER JOIN machinery_models mm ON mu.model_id = mm.id
+                WHERE b.machine_id = $1;
             ";
 
             let mut machines_info: Vec<UnitAndDates> = Vec::new();
@@ -545,11 +632,8 @@ pub async fn get_units_unavailable_dates(
     );
 }
 
-pub async fn new_model(
-    State(state): State<AppState>,
-    Json(mut payload): Json<NewModel>,
-) -> Response {
-    if payload.extra_images.len() > 10 {
+pub async fn new_model(State(state): State<AppState>, Json(payload): Json<NewModel>) -> Response {
+    if payload.extra_images.len() > MAX_EXTRA_IMAGES {
         return (
             StatusCode::BAD_REQUEST,
             Json(json!({"message": "Cannot upload more than 10 images"})),
@@ -557,22 +641,233 @@ pub async fn new_model(
             .into_response();
     }
 
-    let claims = match validate_jwt(&payload.access) {
+    if let Some(error_response) = validate_model_uploader(&payload.info.access) {
+        return error_response;
+    }
+
+    let mut staged_images = StagedImages::default();
+    let mut image_names = Vec::new();
+    for b64 in std::iter::once(&payload.image).chain(&payload.extra_images) {
+        match staged_images.stage(b64) {
+            Ok(name) => image_names.push(name),
+            Err(error_response) => return error_response.into_response(),
+        }
+    }
+
+    let main_image = image_names.remove(0);
+    save_new_model(&state, payload.info, staged_images, main_image, image_names).await
+}
+
+/// Multipart version of `new_model`. The `data` part holds the model as JSON and must come
+/// first, so nothing is read from unauthorized requests. It's followed by an `image` file part
+/// and up to 10 `extra_images` ones, each streamed up to `MAX_IMAGE_UPLOAD_BYTES`.
+pub async fn new_model_multipart(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Response {
+    let mut staged_images = StagedImages::default();
+
+    match read_new_model_parts(&mut multipart, &mut staged_images).await {
+        Ok((info, main_image, extra_images)) => {
+            save_new_model(&state, info, staged_images, main_image, extra_images).await
+        }
+        Err(error_response) => {
+            // Skip what's left of the body so the client gets the error instead of a reset connection
+            while let Ok(Some(_)) = multipart.next_field().await {}
+            error_response
+        }
+    }
+}
+
+async fn read_new_model_parts(
+    multipart: &mut Multipart,
+    staged_images: &mut StagedImages,
+) -> Result<(NewModelInfo, String, Vec<String>), Response> {
+    let multipart_error =
+        |e: MultipartError| (e.status(), Json(json!({"message": e.body_text()}))).into_response();
+
+    let mut info: Option<NewModelInfo> = None;
+    let mut main_image: Option<String> = None;
+    let mut extra_images: Vec<String> = Vec::new();
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Err(multipart_error(e)),
+        };
+
+        let field_name = field.name().unwrap_or_default().to_string();
+
+        if field_name == "data" {
+            let data = match field.text().await {
+                Ok(data) => data,
+                Err(e) => return Err(multipart_error(e)),
+            };
+
+            let Ok(data) = serde_json::from_str::<NewModelInfo>(&data) else {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "Invalid model data"})),
+                )
+                    .into_response());
+            };
+
+            if let Some(error_response) = validate_model_uploader(&data.access) {
+                return Err(error_response);
+            }
+
+            info = Some(data);
+            continue;
+        }
+
+        if field_name != "image" && field_name != "extra_images" {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": format!("Unexpected field '{}'", field_name)})),
+            )
+                .into_response());
+        }
+
+        if info.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "The data part must be sent before the images"})),
+            )
+                .into_response());
+        }
+
+        if field_name == "image" && main_image.is_some() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "Only one main image can be uploaded"})),
+            )
+                .into_response());
+        }
+
+        if field_name == "extra_images" && extra_images.len() == MAX_EXTRA_IMAGES {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "Cannot upload more than 10 images"})),
+            )
+                .into_response());
+        }
+
+        let file_name = field.file_name().unwrap_or(&field_name).to_string();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut sniffed = false;
+        loop {
+            let chunk = match field.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => return Err(multipart_error(e)),
+            };
+
+            if bytes.len() + chunk.len() > MAX_IMAGE_UPLOAD_BYTES {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Json(json!({"message": format!(
+                        "The file '{}' is larger than {} MB",
+                        file_name,
+                        MAX_IMAGE_UPLOAD_BYTES / (1024 * 1024)
+                    )})),
+                )
+                    .into_response());
+            }
+            bytes.extend_from_slice(&chunk);
+
+            // Stop reading files that aren't images as soon as their magic bytes arrived
+            if !sniffed && bytes.len() >= 16 {
+                if sniff_image_format(&bytes).is_none() {
+                    break;
+                }
+                sniffed = true;
+            }
+        }
+
+        if sniff_image_format(&bytes).is_none() {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                Json(json!({"message": format!(
+                    "The file '{}' is not a PNG, JPEG or WebP image",
+                    file_name
+                )})),
+            )
+                .into_response());
+        }
+
+        let name = match staged_images.stage_file(&file_name, &bytes) {
+            Ok(name) => name,
+            Err(error_response) => return Err(error_response.into_response()),
+        };
+
+        if field_name == "image" {
+            main_image = Some(name);
+        } else {
+            extra_images.push(name);
+        }
+    }
+
+    let Some(info) = info else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The data part is required"})),
+        )
+            .into_response());
+    };
+
+    let Some(main_image) = main_image else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The main image is required"})),
+        )
+            .into_response());
+    };
+
+    Ok((info, main_image, extra_images))
+}
+
+fn validate_model_uploader(access: &str) -> Option<Response> {
+    let claims = match validate_jwt(access) {
         Some(data) => data,
         None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(json!({"message": "Invalid access token"})),
+            return Some(
+                (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({"message": "Invalid access token"})),
+                )
+                    .into_response(),
             )
-                .into_response()
         }
     }
     .claims;
 
     if claims.role != 0 {
+        return Some(
+            (
+                StatusCode::FORBIDDEN,
+                Json(json!({"message": "Not enough permissions"})),
+            )
+                .into_response(),
+        );
+    }
+
+    None
+}
+
+/// Saves a model whose images are already staged, publishing them once it's committed.
+async fn save_new_model(
+    state: &AppState,
+    info: NewModelInfo,
+    staged_images: StagedImages,
+    main_image: String,
+    extra_images: Vec<String>,
+) -> Response {
+    if info.deposit < Decimal::ZERO {
         return (
-            StatusCode::FORBIDDEN,
-            Json(json!({"message": "Not enough permissions"})),
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The deposit can't be negative"})),
         )
             .into_response();
     }
@@ -602,16 +897,17 @@ pub async fn new_model(
     let row = match transaction
         .query_one(
             "INSERT INTO machinery_models
-        (name, brand, model, year, policy, description, price, image)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, 'tempvalue') RETURNING id;",
+        (name, brand, model, year, policy, description, price, deposit, image)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'tempvalue') RETURNING id;",
             &[
-                &payload.name,
-                &payload.brand,
-                &payload.model,
-                &payload.year,
-                &payload.policy,
-                &payload.description,
-                &payload.price,
+                &info.name,
+                &info.brand,
+                &info.model,
+                &info.year,
+                &info.policy,
+                &info.description,
+                &info.price,
+                &info.deposit,
             ],
         )
         .await
@@ -619,7 +915,8 @@ pub async fn new_model(
         Ok(r) => r,
         Err(e) => {
             if let Some(db_err) = e.as_db_error() {
-                if db_err.code() == &SqlState::UNIQUE_VIOLATION && db_err.message().contains("brand_model_year")
+                if db_err.code() == &SqlState::UNIQUE_VIOLATION
+                    && db_err.message().contains("brand_model_year")
                 {
                     return (
                         StatusCode::BAD_REQUEST,
@@ -632,14 +929,14 @@ pub async fn new_model(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"message": "Failed to execute transaction"})),
             )
-                .into_response()
+                .into_response();
         }
     };
 
     let model_id: i32 = row.get("id");
 
     //Link the model with the categories
-    for cat_name in payload.categories.iter().map(|c| c.to_lowercase()) {
+    for cat_name in info.categories.iter().map(|c| c.to_lowercase()) {
         //Get or create category
         let Ok(row) = transaction
             .query_one(
@@ -681,82 +978,49 @@ pub async fn new_model(
         };
     }
 
-    payload.extra_images.insert(0, payload.image); //So that we can process image as another extra_image
-    let mut first: bool = true;
-    for b64 in &payload.extra_images {
-        let Ok(bytes) = STANDARD.decode(b64) else {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"message": "Invalid base64 in images"})),
-            )
-                .into_response();
-        };
-
-        // Decode into a DynamicImage
-        let Ok(image) = image::load_from_memory(&bytes) else {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({"message": "Failed to decode image"})),
-            )
-                .into_response();
-        };
+    if let Err(error_response) = save_model_specs(&transaction, model_id, &info.specs).await {
+        return error_response.into_response();
+    }
 
-        // Generate 64-character filename
-        let name_without_extension = generate_random_string(64);
-        let filename = format!("{}.webp", name_without_extension);
+    if transaction
+        .execute(
+            "UPDATE machinery_models SET image = $1 WHERE id = $2;",
+            &[&main_image, &model_id],
+        )
+        .await
+        .is_err()
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message":"Failed to save the images"})),
+        )
+            .into_response();
+    }
 
-        // Save image as JPG
-        let filepath = PathBuf::from(format!("media/machines/{}", filename));
-        let Ok(file) = File::create(&filepath) else {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"message": "Failed to save the images"})),
+    for name in &extra_images {
+        if transaction
+            .execute(
+                "INSERT INTO model_extra_images (name, id) VALUES ($1, $2)",
+                &[name, &model_id],
             )
-                .into_response();
-        };
-        let mut writer = BufWriter::new(file);
-        if image.write_to(&mut writer, ImageFormat::WebP).is_err() {
+            .await
+            .is_err()
+        {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"message":"Failed to save the images"})),
             )
                 .into_response();
         }
+    }
 
-        //image should be stored with the model
-        if first {
-            if transaction
-                .execute(
-                    "UPDATE machinery_models SET image = $1 WHERE id = $2;",
-                    &[&name_without_extension, &model_id],
-                )
-                .await
-                .is_err()
-            {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"message":"Failed to save the images"})),
-                )
-                    .into_response();
-            }
-            first = false;
-        } else {
-            // Store only filename
-            if transaction
-                .execute(
-                    "INSERT INTO model_extra_images (name, id) VALUES ($1, $2)",
-                    &[&name_without_extension, &model_id],
-                )
-                .await
-                .is_err()
-            {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"message":"Failed to save the images"})),
-                )
-                    .into_response();
-            }
-        }
+    // Published before committing, so the model never points at images that failed to upload
+    if staged_images.promote(state.media.as_ref()).await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to save the images"})),
+        )
+            .into_response();
     }
 
     match transaction.commit().await {
@@ -804,27 +1068,33 @@ pub async fn new_rental(
         );
     }
 
-    if let Ok(client) = state.pool.get().await {
+    if let Ok(mut client) = state.pool.get().await {
         let machine_id = payload.machine_id;
         let user_id = token_claims.user_id;
         let start_date = payload.start_date;
         let end_date = payload.end_date;
-        let total_price = payload.total_price;
 
-        let unavailable_dates_query = "
-            SELECT start_date, (end_date + INTERVAL '7 days')::date AS end_date
-            FROM rentals r 
-            WHERE r.status IN ('active', 'pending_payment') AND (machine_id = $1);
-        ";
+        match has_overdue_charges(&client, user_id, Local::now().date_naive()).await {
+            Ok(false) => {}
+            Ok(true) => return overdue_charges_response(),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({
+                        "message": "Se ha producido un error interno en el servidor",
+                    })),
+                );
+            }
+        }
 
-        let unavailable_dates = match client.query(unavailable_dates_query, &[&machine_id]).await {
-            Ok(rows) => rows
-                .iter()
-                .map(|row| DateRange {
-                    start_date: row.get("start_date"),
-                    end_date: row.get("end_date"),
-                })
-                .collect::<Vec<DateRange>>(),
+        let rules = match get_rental_rules(&client, machine_id).await {
+            Ok(Some(rules)) => rules,
+            Ok(None) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+                );
+            }
             Err(_) => {
                 return (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -835,73 +1105,80 @@ pub async fn new_rental(
             }
         };
 
-        let end_date_with_maintenance_period = end_date + Duration::days(7);
-
-        let is_overlap = unavailable_dates.iter().any(|period| {
-            date_is_overlap(
-                start_date,
-                end_date_with_maintenance_period,
-                period.start_date,
-                period.end_date,
-            )
-        });
-
-        let duration_days = (end_date - start_date).num_days();
+        if let Err(message) =
+            check_rental_period(&rules, start_date, end_date, Local::now().date_naive())
+        {
+            return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+        }
 
-        if end_date < start_date || duration_days < 7 {
+        let Ok(transaction) = client.transaction().await else {
             return (
-                StatusCode::BAD_REQUEST,
+                StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
-                    "message": "El período indicado no es válido.
-                        Debe ser al menos 7 días y la fecha de fin no puede ser anterior a la de inicio.",
+                    "message": "Se ha producido un error interno en el servidor",
                 })),
             );
-        }
+        };
 
-        if is_overlap {
-            return (
-                StatusCode::CONFLICT,
+        // Frees the unit the waitlist may be holding for the client
+        let Ok(claimed_hold) =
+            claim_waitlist_hold_of_unit(&transaction, user_id, machine_id, start_date, end_date)
+                .await
+        else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
-                    "message": "Las fechas de inicio y fin se superponen con un alquiler existente,
-                        considerando el período de mantenimiento planificado",
+                    "message": "Se ha producido un error interno en el servidor",
                 })),
             );
-        }
-
-        let price_query = "
-            SELECT price FROM machinery_models mm 
-            INNER JOIN machinery_units mu 
-            ON mm.id = mu.model_id
-            WHERE mu.id = $1;
-        ";
+        };
 
-        let price_row = match client.query_one(price_query, &[&machine_id]).await {
-            Ok(row) => row,
+        match lock_unit_period(&transaction, machine_id, start_date, end_date).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({
+                        "message": "Las fechas de inicio y fin se superponen con un alquiler existente,
+                        considerando el período de mantenimiento planificado",
+                    })),
+                );
+            }
             Err(_) => {
                 return (
-                    StatusCode::NOT_FOUND,
+                    StatusCode::INTERNAL_SERVER_ERROR,
                     Json(json!({
-                        "message": "No se ha encontrado la máquina solicitada",
+                        "message": "Se ha producido un error interno en el servidor",
                     })),
                 );
             }
-        };
-
-        let machine_price: f32 = price_row.get("price");
-        let number_of_days = (end_date - start_date).num_days() as f32;
-
-        if total_price != number_of_days * machine_price {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({
-                    "message": "El precio total no es correcto",
-                })),
-            );
         }
 
+        let RentalPrice {
+            total_price,
+            deposit,
+        } = match resolve_rental_price(
+            &transaction,
+            &[user_id],
+            machine_id,
+            start_date,
+            end_date,
+            &payload.quote_token,
+            payload.total_price,
+        )
+        .await
+        {
+            Ok(price) => price,
+            Err(error_response) => return error_response,
+        };
+
         let user_query = "SELECT * FROM users WHERE id = $1;";
 
-        if let Err(_) = client.query_one(user_query, &[&user_id]).await {
+        if transaction
+            .query_one(user_query, &[&user_id])
+            .await
+            .is_err()
+        {
             return (
                 StatusCode::NOT_FOUND,
                 Json(json!({
@@ -910,25 +1187,46 @@ pub async fn new_rental(
             );
         };
 
-        let insert_query = "
-            INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
-            VALUES ($1, $2, $3, $4, $5, 'pending_payment')
-            RETURNING id;
-        ";
+        let payment_hold_seconds = payment_hold().num_seconds() as i32;
 
-        if let Ok(rent_row) = client
-            .query_one(
-                insert_query,
-                &[&user_id, &machine_id, &start_date, &end_date, &total_price],
-            )
-            .await
+        if let Ok((rental_id, payment_deadline)) = create_pending_rental(
+            &transaction,
+            user_id,
+            machine_id,
+            start_date,
+            end_date,
+            RentalPrice {
+                total_price,
+                deposit,
+            },
+            payment_hold_seconds,
+        )
+        .await
         {
-            let rental_id: i32 = rent_row.get(0);
+            if transaction.commit().await.is_err() {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({
+                        "message": "Se ha producido un error interno en el servidor",
+                    })),
+                );
+            }
+
+            // The client booked another unit than the one held for them
+            if let Some(held_unit) = claimed_hold.filter(|unit_id| *unit_id != machine_id) {
+                reevaluate_waitlist(&mut client, &[held_unit]).await;
+            }
+
             return (
                 StatusCode::CREATED,
                 Json(json!({
                     "rental_id": rental_id,
                     "user_id": user_id,
+                    "total_price": total_price,
+                    "deposit": deposit,
+                    "amount_due": total_price + deposit,
+                    "payment_deadline": payment_deadline,
+                    "payment_hold_seconds": payment_hold_seconds,
                 })),
             );
         }
@@ -942,6 +1240,201 @@ pub async fn new_rental(
     );
 }
 
+pub async fn new_model_rental(
+    State(state): State<AppState>,
+    Json(payload): Json<NewModelRental>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let user_id = match get_claims_from_token(&payload.access) {
+        Some(claims) => claims.user_id,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    };
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Ingreso de información inválida"})),
+        );
+    }
+
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+        )
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let model_id = payload.model_id;
+    let location_id = payload.location_id;
+    let start_date = payload.start_date;
+    let end_date = payload.end_date;
+    let today = Local::now().date_naive();
+
+    match has_overdue_charges(&client, user_id, today).await {
+        Ok(false) => {}
+        Ok(true) => return overdue_charges_response(),
+        Err(_) => return internal_error(),
+    }
+
+    let rules = match get_model_rental_rules(&client, model_id).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    if let Err(message) = check_rental_period(&rules, start_date, end_date, today) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+    }
+
+    match client
+        .query_opt("SELECT 1 FROM users WHERE id = $1;", &[&user_id])
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "message": "No se ha encontrado al usuario. Verifique su acceso.",
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    // Frees the unit the waitlist may be holding for the client
+    let Ok(claimed_hold) = claim_waitlist_hold(
+        &transaction,
+        user_id,
+        model_id,
+        location_id,
+        start_date,
+        end_date,
+    )
+    .await
+    else {
+        return internal_error();
+    };
+
+    let machine_id = match assign_unit(&transaction, model_id, location_id, start_date, end_date)
+        .await
+    {
+        Ok(Some(unit_id)) => unit_id,
+        Ok(None) => {
+            let alternative_period = nearest_free_period(
+                &transaction,
+                model_id,
+                location_id,
+                &rules,
+                start_date,
+                end_date,
+                today,
+            )
+            .await;
+            let alternative_locations =
+                locations_with_free_unit(&transaction, model_id, location_id, start_date, end_date)
+                    .await;
+
+            let (Ok(alternative_period), Ok(alternative_locations)) =
+                (alternative_period, alternative_locations)
+            else {
+                return internal_error();
+            };
+
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "No hay ejemplares disponibles en la sucursal para las fechas indicadas",
+                    "alternative_period": alternative_period,
+                    "alternative_locations": alternative_locations,
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    let RentalPrice {
+        total_price,
+        deposit,
+    } = match resolve_rental_price(
+        &transaction,
+        &[user_id],
+        machine_id,
+        start_date,
+        end_date,
+        &payload.quote_token,
+        payload.total_price,
+    )
+    .await
+    {
+        Ok(price) => price,
+        Err(error_response) => return error_response,
+    };
+
+    let payment_hold_seconds = payment_hold().num_seconds() as i32;
+
+    let Ok((rental_id, payment_deadline)) = create_pending_rental(
+        &transaction,
+        user_id,
+        machine_id,
+        start_date,
+        end_date,
+        RentalPrice {
+            total_price,
+            deposit,
+        },
+        payment_hold_seconds,
+    )
+    .await
+    else {
+        return internal_error();
+    };
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    // Another unit was assigned than the one held for the client
+    if let Some(held_unit) = claimed_hold.filter(|unit_id| *unit_id != machine_id) {
+        reevaluate_waitlist(&mut client, &[held_unit]).await;
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "rental_id": rental_id,
+            "user_id": user_id,
+            "machine_id": machine_id,
+            "total_price": total_price,
+            "deposit": deposit,
+            "amount_due": total_price + deposit,
+            "payment_deadline": payment_deadline,
+            "payment_hold_seconds": payment_hold_seconds,
+        })),
+    )
+}
+
 pub async fn check_rental_payment(
     State(state): State<AppState>,
     query_params: Query<CheckPayment>,
@@ -961,7 +1454,7 @@ pub async fn check_rental_payment(
         }
     };
 
-    if let Ok(client) = state.pool.get().await {
+    if let Ok(mut client) = state.pool.get().await {
         let rental_id = payload.rental_id;
         let payment_id = &query_params.payment_id;
         let user_id = claims.user_id;
@@ -969,16 +1462,85 @@ pub async fn check_rental_payment(
 
         match payment_status {
             PaymentStatus::Approved => {
+                // The deposit is paid along with the rental. Lines of an order are paid with it
                 let approved_query = "
                     UPDATE rentals 
-                    SET status = 'active', payment_id = $1, updated_at = NOW()
-                    WHERE id = $2 AND status = 'pending_payment';
+                    SET payment_id = $1,
+                    deposit_status = CASE WHEN deposit_amount > 0 THEN 'held'::deposit_status END
+                    WHERE id = $2 AND order_id IS NULL;
                 ";
 
-                match client
-                    .execute(approved_query, &[&payment_id, &rental_id])
-                    .await
+                let transaction = match client.transaction().await {
+                    Ok(t) => t,
+                    Err(_) => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(json!({
+                                "message": "Se ha producido un error interno en el servidor",
+                            })),
+                        );
+                    }
+                };
+
+                // Once the hold is over the dates may already belong to another rental
+                let goes_ahead = match settle_lapsed_rental_payment(
+                    &transaction,
+                    rental_id,
+                    payment_id,
+                    RentalActor::Client(user_id),
+                )
+                .await
+                {
+                    Ok(goes_ahead) => goes_ahead,
+                    Err(_) => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(json!({
+                                "message": "Se ha producido un error al actualizar el estado del alquiler",
+                            })),
+                        );
+                    }
+                };
+
+                if !goes_ahead {
+                    if transaction.commit().await.is_err() {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(json!({
+                                "message": "Se ha producido un error al actualizar el estado del alquiler",
+                            })),
+                        );
+                    }
+
+                    reevaluate_waitlist_after_rentals(&mut client, &[rental_id]).await;
+
+                    return (
+                        StatusCode::CONFLICT,
+                        Json(json!({
+                            "message": "El plazo para pagar el alquiler venció y las fechas ya no están disponibles. Se reintegrará el pago",
+                        })),
+                    );
+                }
+
+                let updated = match transition_rental(
+                    &transaction,
+                    rental_id,
+                    RentalStatus::Active,
+                    RentalActor::Client(user_id),
+                    None,
+                )
+                .await
                 {
+                    Ok(_) => {
+                        transaction
+                            .execute(approved_query, &[&payment_id, &rental_id])
+                            .await
+                    }
+                    Err(RentalTransitionError::Db(e)) => Err(e),
+                    Err(_) => Ok(0),
+                };
+
+                match updated {
                     Ok(rows_updated) => {
                         if rows_updated == 0 {
                             return (
@@ -989,6 +1551,17 @@ pub async fn check_rental_payment(
                             );
                         }
 
+                        if issue_rental_invoice(&transaction, rental_id).await.is_err()
+                            || transaction.commit().await.is_err()
+                        {
+                            return (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                Json(json!({
+                                    "message": "Se ha producido un error al emitir la factura del alquiler",
+                                })),
+                            );
+                        }
+
                         if let Ok(user_row) = client
                             .query_one("SELECT * FROM users WHERE id = $1;", &[&user_id])
                             .await
@@ -1025,13 +1598,23 @@ pub async fn check_rental_payment(
                                     let user_email: String = user_row.get("email");
                                     let user_name: String = user_row.get("name");
 
+                                    let deposit: Decimal = rental_row.get("deposit_amount");
+                                    let deposit_line = if deposit > Decimal::ZERO {
+                                        format!(
+                                            "Depósito de garantía:\t\t\t $ {} (se devuelve con la máquina)\n",
+                                            deposit
+                                        )
+                                    } else {
+                                        String::new()
+                                    };
+
                                     let subject = format!(
                                         "Alquiler n° {} aprobado - Bob el Alquilador",
                                         rental_id
                                     );
                                     let body = format!(
                                         "Hola {},\n\n\
-                                    Tu alquiler ha sido aprobado.\n\n\
+                                    Tu alquiler ha sido aprobado. Adjuntamos el contrato y el recibo del pago.\n\n\
                                     \n\
                                     Detalles del Alquiler:\n\
                                     \n\n\
@@ -1039,7 +1622,8 @@ pub async fn check_rental_payment(
                                     Período:\t\t\t {} - {}\n\
                                     Máquina:\t\t\t {} {} {}\n\
                                     Ubicación:\t\t\t {}, {}, {}\n\
-                                    Identificador del pago:\t\t\t {}\n\n\
+                                    Identificador del pago:\t\t\t {}\n\
+                                    {}\n\
                                     \n\n\
                                     Gracias por confiar en nosotros.\n\n\
                                     Saludos cordiales,\n\
@@ -1055,9 +1639,26 @@ pub async fn check_rental_payment(
                                         rent.street,
                                         rent.number,
                                         rent.payment_id,
+                                        deposit_line,
                                     );
 
-                                    match send_mail(&user_email, &subject, &body) {
+                                    let Ok(attachments) =
+                                        rental_attachments(&client, rental_id).await
+                                    else {
+                                        return (
+                                            StatusCode::INTERNAL_SERVER_ERROR,
+                                            Json(json!({
+                                                "message": "Se ha producido un error al generar los documentos del alquiler",
+                                            })),
+                                        );
+                                    };
+
+                                    match send_mail_with_attachments(
+                                        &user_email,
+                                        &subject,
+                                        &body,
+                                        &attachments,
+                                    ) {
                                         Ok(_) => {
                                             return (
                                                 StatusCode::OK,
@@ -1087,6 +1688,7 @@ pub async fn check_rental_payment(
                             }
                         }
                     }
+                    Err(e) if is_overlapping_rental(&e) => return overlapping_rental_response(),
                     Err(_) => {
                         return (
                             StatusCode::INTERNAL_SERVER_ERROR,
@@ -1098,13 +1700,36 @@ pub async fn check_rental_payment(
                 }
             }
             PaymentStatus::Rejected => {
-                let rejected_query = "
-                    UPDATE rentals 
-                    SET status = 'failed' 
-                    WHERE id = $1 AND status = 'pending_payment';
-                ";
+                // Scoped so that the client is free again once the transaction is over
+                let updated = {
+                    let transaction = match client.transaction().await {
+                        Ok(t) => t,
+                        Err(_) => {
+                            return (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                Json(json!({
+                                    "message": "Se ha producido un error interno en el servidor",
+                                })),
+                            );
+                        }
+                    };
+
+                    match transition_rental(
+                        &transaction,
+                        rental_id,
+                        RentalStatus::Failed,
+                        RentalActor::Client(user_id),
+                        Some("El pago fue rechazado"),
+                    )
+                    .await
+                    {
+                        Ok(_) => transaction.commit().await.map(|_| 1),
+                        Err(RentalTransitionError::Db(e)) => Err(e),
+                        Err(_) => Ok(0),
+                    }
+                };
 
-                if let Ok(rows_updated) = client.execute(rejected_query, &[&rental_id]).await {
+                if let Ok(rows_updated) = updated {
                     if rows_updated == 0 {
                         return (
                             StatusCode::NOT_FOUND,
@@ -1113,6 +1738,8 @@ pub async fn check_rental_payment(
                             })),
                         );
                     } else {
+                        reevaluate_waitlist_after_rentals(&mut client, &[rental_id]).await;
+
                         return (
                             StatusCode::BAD_GATEWAY,
                             Json(json!({
@@ -1173,6 +1800,20 @@ pub async fn new_unit(State(state): State<AppState>, Json(payload): Json<NewUnit
         }
     };
 
+    if let Ok(Some(_)) = client
+        .query_opt(
+            "SELECT id FROM machinery_models WHERE id = $1 AND decommissioned_at IS NOT NULL;",
+            &[&payload.model_id],
+        )
+        .await
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The model has been decommissioned"})),
+        )
+            .into_response();
+    }
+
     match client
         .execute(
             "INSERT INTO machinery_units
@@ -1224,17 +1865,6 @@ pub async fn get_my_rentals(
     State(state): State<AppState>,
     Json(payload): Json<Access>,
 ) -> Response {
-    let nginx_url = match env::var("NGINX_URL") {
-        Ok(e) => e,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"message": "NGINX_URL must be set in the .env file"})),
-            )
-                .into_response()
-        }
-    };
-
     let claims = match validate_jwt(&payload.access) {
         Some(data) => data,
         None => {
@@ -1268,7 +1898,10 @@ pub async fn get_my_rentals(
 
     // Get rental_ids that have reviews
     let service_review_ids: HashSet<i32> = match client
-        .query("SELECT rental_id FROM service_reviews WHERE user_id = $1", &[&claims.user_id])
+        .query(
+            "SELECT rental_id FROM service_reviews WHERE user_id = $1",
+            &[&claims.user_id],
+        )
         .await
     {
         Ok(rows) => rows.iter().map(|row| row.get(0)).collect(),
@@ -1276,7 +1909,10 @@ pub async fn get_my_rentals(
     };
 
     let machine_review_ids: HashSet<i32> = match client
-        .query("SELECT rental_id FROM machine_reviews WHERE user_id = $1", &[&claims.user_id])
+        .query(
+            "SELECT rental_id FROM machine_reviews WHERE user_id = $1",
+            &[&claims.user_id],
+        )
         .await
     {
         Ok(rows) => rows.iter().map(|row| row.get(0)).collect(),
@@ -1292,9 +1928,13 @@ pub async fn get_my_rentals(
             rentals.start_date,
             rentals.end_date,
             rentals.total_price,
+            rentals.refund_amount,
+            rentals.deposit_amount,
+            rentals.deposit_status::TEXT,
             rentals.status::TEXT,
             rentals.created_at,
             rentals.updated_at,
+            rentals.order_id,
             machinery_units.id AS unit_id,
             machinery_units.serial_number AS unit_serial_number,
             machinery_models.id AS model_id,
@@ -1314,6 +1954,16 @@ pub async fn get_my_rentals(
         .await
     {
         Ok(rows) => {
+            let rental_ids: Vec<i32> = rows.iter().map(|row| row.get("rental_id")).collect();
+
+            let Ok(mut extensions) = get_rental_extensions(&client, &rental_ids).await else {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"message": "Failed to get the rentals"})),
+                )
+                    .into_response();
+            };
+
             let employees: Vec<MyRentalInfo> = rows
                 .iter()
                 .map(|row| {
@@ -1326,6 +1976,9 @@ pub async fn get_my_rentals(
                         start_date: row.get("start_date"),
                         end_date: row.get("end_date"),
                         total_price: row.get("total_price"),
+                        refund_amount: row.get("refund_amount"),
+                        deposit_amount: row.get("deposit_amount"),
+                        deposit_status: row.get("deposit_status"),
                         status: row.get("status"),
                         created_at: row.get("created_at"),
                         updated_at: row.get("updated_at"),
@@ -1338,15 +1991,16 @@ pub async fn get_my_rentals(
                         model_year: row.get("model_year"),
                         model_policy: row.get("model_policy"),
                         model_description: row.get("model_description"),
-                        model_image: format!(
-                            "{}/media/machines/{}.webp",
-                            nginx_url,
-                            row.get::<_, String>("model_image")
+                        model_image: machine_image_url(
+                            state.media.as_ref(),
+                            row.get("model_image"),
                         ),
                         days_late: None,
                         percentage_per_late_day: None,
                         has_service_review: service_review_ids.contains(&rental_id),
                         has_machine_review: machine_review_ids.contains(&rental_id),
+                        extensions: extensions.remove(&rental_id).unwrap_or_default(),
+                        order_id: row.get("order_id"),
                     }
                 })
                 .collect();
@@ -1386,6 +2040,22 @@ pub async fn load_retirement(
             .into_response();
     }
 
+    let (staged_photos, photo_names) = match &payload.inspection {
+        // Damage found when the machine leaves is only documented, it's not the client's
+        Some(inspection) if inspection.damage_charge.is_some() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "Damage can only be charged at return"})),
+            )
+                .into_response();
+        }
+        Some(inspection) => match stage_inspection_photos(inspection) {
+            Ok(staged) => staged,
+            Err(error_response) => return error_response.into_response(),
+        },
+        None => (StagedImages::default(), Vec::new()),
+    };
+
     let mut client = match state.pool.get().await {
         Ok(c) => c,
         Err(_) => {
@@ -1408,26 +2078,52 @@ pub async fn load_retirement(
         }
     };
 
-    let row = match transaction
-        .query_one(
-            "UPDATE rentals
+    match transition_rental(
+        &transaction,
+        payload.rental_id,
+        RentalStatus::Retired,
+        RentalActor::new(claims.user_id, claims.role),
+        None,
+    )
+    .await
+    {
+        Ok(_) => (),
+        Err(RentalTransitionError::NotFound) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "rental_id is invalid"})),
+            )
+                .into_response();
+        }
+        Err(RentalTransitionError::NotAllowed) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "The rental is not active"})),
+            )
+                .into_response();
+        }
+        Err(RentalTransitionError::Db(_)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to execute transaction"})),
+            )
+                .into_response();
+        }
+    }
+
+    let row = match transaction
+        .query_one(
+            "UPDATE rentals
             SET retirement_employee_id = $1,
                  retirement_date = CURRENT_DATE
             WHERE id = $2
-            RETURNING machine_id, end_date, status::TEXT;",
+            RETURNING machine_id, end_date;",
             &[&claims.user_id, &payload.rental_id],
         )
         .await
     {
         Ok(r) => r,
-        Err(e) => {
-            if e.to_string().contains("unexpected number of rows") {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"message": "rental_id is invalid"})),
-                )
-                    .into_response();
-            }
+        Err(_) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"message": "Failed to execute transaction"})),
@@ -1436,18 +2132,9 @@ pub async fn load_retirement(
         }
     };
 
-    let status: String = row.get("status");
     let end_date: NaiveDate = row.get("end_date");
     let machine_id: i32 = row.get("machine_id");
 
-    if status != "active" {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({"message": "The rental is not active"})),
-        )
-            .into_response();
-    }
-
     if Local::now().naive_local().date() >= end_date {
         return (
             StatusCode::BAD_REQUEST,
@@ -1476,13 +2163,48 @@ pub async fn load_retirement(
         }
     }
 
+    let mut inspection_id = None;
+
+    if let Some(inspection) = &payload.inspection {
+        match record_inspection(
+            &transaction,
+            payload.rental_id,
+            InspectionKind::CheckOut,
+            claims.user_id,
+            inspection,
+            &photo_names,
+        )
+        .await
+        {
+            Ok(id) => inspection_id = Some(id),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"message": "Failed to save the inspection"})),
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    // Published before committing, so the inspection never points at photos that failed to
+    // upload
+    if staged_photos.promote(state.media.as_ref()).await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to save the images"})),
+        )
+            .into_response();
+    }
+
     match transaction.commit().await {
         Ok(_) => {
             return (
                 StatusCode::CREATED,
-                Json(json!({"message": "Retirement loaded successfully"})),
+                Json(json!({"message": "Retirement loaded successfully",
+                            "inspection_id": inspection_id})),
             )
-                .into_response()
+                .into_response();
         }
         Err(_) => {
             return (
@@ -1518,6 +2240,21 @@ pub async fn load_return(
             .into_response();
     }
 
+    let (staged_photos, photo_names) = match &payload.inspection {
+        Some(inspection) if inspection.damage_charge.is_some() && !inspection.has_damage() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "A damage charge needs a damaged checklist item"})),
+            )
+                .into_response();
+        }
+        Some(inspection) => match stage_inspection_photos(inspection) {
+            Ok(staged) => staged,
+            Err(error_response) => return error_response.into_response(),
+        },
+        None => (StagedImages::default(), Vec::new()),
+    };
+
     let mut client = match state.pool.get().await {
         Ok(c) => c,
         Err(_) => {
@@ -1540,12 +2277,44 @@ pub async fn load_return(
         }
     };
 
+    match transition_rental(
+        &transaction,
+        payload.rental_id,
+        RentalStatus::Completed,
+        RentalActor::new(claims.user_id, claims.role),
+        None,
+    )
+    .await
+    {
+        Ok(_) => (),
+        Err(RentalTransitionError::NotFound) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "rental_id is invalid"})),
+            )
+                .into_response();
+        }
+        Err(RentalTransitionError::NotAllowed) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "The rental has not been retired"})),
+            )
+                .into_response();
+        }
+        Err(RentalTransitionError::Db(_)) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to update rental"})),
+            )
+                .into_response();
+        }
+    }
+
     let row = match transaction
         .query_one(
             "UPDATE rentals
              SET return_employee_id = $1,
-                 return_date = CURRENT_DATE,
-                 status = 'completed'
+                 return_date = CURRENT_DATE
              WHERE id = $2
              RETURNING machine_id;",
             &[&claims.user_id, &payload.rental_id],
@@ -1553,14 +2322,7 @@ pub async fn load_return(
         .await
     {
         Ok(r) => r,
-        Err(e) => {
-            if e.to_string().contains("unexpected number of rows") {
-                return (
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"message": "rental_id is invalid"})),
-                )
-                    .into_response();
-            }
+        Err(_) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({"message": "Failed to update rental"})),
@@ -1653,7 +2415,7 @@ pub async fn load_return(
 
     let row = match transaction
         .query_one(
-            "SELECT r.end_date, r.return_date, m.price
+            "SELECT r.user_id, r.end_date, r.return_date, m.price
         FROM rentals r
         JOIN machinery_units u ON u.id = r.machine_id
         JOIN machinery_models m ON m.id = u.model_id
@@ -1674,10 +2436,135 @@ pub async fn load_return(
 
     let end_date: NaiveDate = row.get("end_date");
     let return_date: NaiveDate = row.get("return_date");
-    let price: f32 = row.get("price");
+    let price: Decimal = row.get("price");
 
     let days_late = (return_date - end_date).num_days().max(0); // avoid negative values
-    let fine = days_late as f32 * price * LATE_RETURN_FINE;
+    let fine = round_money(Decimal::from(days_late) * price * LATE_RETURN_FINE);
+
+    let charge_id = match record_late_return_fine(
+        &transaction,
+        payload.rental_id,
+        row.get("user_id"),
+        days_late,
+        fine,
+        return_date,
+    )
+    .await
+    {
+        Ok(charge_id) => charge_id,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to record the late return fine"})),
+            )
+                .into_response();
+        }
+    };
+
+    let mut inspection_id = None;
+    let mut damage_charge_id = None;
+
+    if let Some(inspection) = &payload.inspection {
+        match (
+            inspection.hour_meter,
+            check_out_hour_meter(&transaction, payload.rental_id).await,
+        ) {
+            (_, Err(_)) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"message": "Failed to save the inspection"})),
+                )
+                    .into_response()
+            }
+            (Some(returned), Ok(Some(retired))) if returned < retired => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "The hour meter reading is lower than at retirement"})),
+                )
+                    .into_response()
+            }
+            _ => (),
+        }
+
+        let recorded = async {
+            let id = record_inspection(
+                &transaction,
+                payload.rental_id,
+                InspectionKind::CheckIn,
+                claims.user_id,
+                inspection,
+                &photo_names,
+            )
+            .await?;
+
+            if inspection.has_damage() {
+                record_damage_event(&transaction, machine_id, payload.rental_id, inspection)
+                    .await?;
+            }
+
+            let charge_id = match inspection.damage_charge {
+                Some(amount) => {
+                    let charge_id = record_damage_charge(
+                        &transaction,
+                        payload.rental_id,
+                        row.get("user_id"),
+                        amount,
+                        return_date,
+                    )
+                    .await?;
+
+                    transaction
+                        .execute(
+                            "UPDATE inspections SET damage_charge_id = $1 WHERE id = $2;",
+                            &[&charge_id, &id],
+                        )
+                        .await?;
+
+                    Some(charge_id)
+                }
+                None => None,
+            };
+
+            Ok::<_, tokio_postgres::Error>((id, charge_id))
+        }
+        .await;
+
+        match recorded {
+            Ok((id, charge_id)) => {
+                inspection_id = Some(id);
+                damage_charge_id = charge_id;
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"message": "Failed to save the inspection"})),
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    // Settled last, so that it covers the fine and the damages just recorded
+    let deposit = match settle_deposit(&transaction, payload.rental_id).await {
+        Ok(statement) => statement,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to settle the deposit"})),
+            )
+                .into_response()
+        }
+    };
+
+    // Published before committing, so the inspection never points at photos that failed to
+    // upload
+    if staged_photos.promote(state.media.as_ref()).await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to save the images"})),
+        )
+            .into_response();
+    }
 
     match transaction.commit().await {
         Ok(_) => {
@@ -1685,9 +2572,13 @@ pub async fn load_return(
                 StatusCode::CREATED,
                 Json(json!({"message": "Return loaded successfully",
                             "fine":fine,
-                            "days_late":days_late})),
+                            "days_late":days_late,
+                            "charge_id":charge_id,
+                            "inspection_id":inspection_id,
+                            "damage_charge_id":damage_charge_id,
+                            "deposit":deposit})),
             )
-                .into_response()
+                .into_response();
         }
         Err(_) => {
             return (
@@ -1714,7 +2605,7 @@ pub async fn cancel_rental(
     }
     .claims;
 
-    let client = match state.pool.get().await {
+    let mut client = match state.pool.get().await {
         Ok(c) => c,
         Err(_) => {
             return (
@@ -1726,7 +2617,7 @@ pub async fn cancel_rental(
 
     let get_rental_query = "
             SELECT * FROM rentals 
-            WHERE id = $1 AND status IN ('pending_payment', 'active');
+            WHERE id = $1 AND status IN ('pending_payment', 'active', 'retired');
         ";
 
     if let Ok(rental_row) = client
@@ -1750,56 +2641,151 @@ pub async fn cancel_rental(
             || (Local::now().date_naive() <= rental_start_date)
         {
             if claims.role == 2 {
-                let update_client_query = "
-                UPDATE rentals 
-                SET status = 'cancelled', updated_at = NOW()
-                WHERE id = $1 AND user_id = $2 AND status IN ('pending_payment', 'active');
-            ";
+                let transaction = match client.transaction().await {
+                    Ok(t) => t,
+                    Err(_) => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(
+                                json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
+                            ),
+                        );
+                    }
+                };
 
-                match client
-                    .execute(update_client_query, &[&payload.rental_id, &claims.user_id])
-                    .await
+                let refund = match compute_refund(
+                    &transaction,
+                    payload.rental_id,
+                    false,
+                    Local::now().date_naive(),
+                )
+                .await
                 {
-                    Ok(rows_updated) if rows_updated == 1 => {
+                    Ok(refund) => refund,
+                    Err(_) => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(
+                                json!({"message": "Se produjo un error interno al calcular el reembolso"}),
+                            ),
+                        );
+                    }
+                };
+
+                let cancelled = cancel_and_refund_rental(
+                    &transaction,
+                    payload.rental_id,
+                    refund,
+                    RentalActor::Client(claims.user_id),
+                    None,
+                )
+                .await;
+
+                match cancelled {
+                    Ok(CancelledRental {
+                        refund,
+                        deposit_released,
+                    }) => {
+                        if transaction.commit().await.is_err() {
+                            return (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                Json(
+                                    json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
+                                ),
+                            );
+                        }
+
+                        reevaluate_waitlist_after_rentals(&mut client, &[payload.rental_id]).await;
+
                         return (
                             StatusCode::OK,
-                            Json(json!({"message": "El alquiler ha sido cancelado exitosamente"})),
+                            Json(json!({
+                                "message": "El alquiler ha sido cancelado exitosamente",
+                                "refund_amount": refund.amount,
+                                "refund_percentage": refund.percentage,
+                                "deposit_released": deposit_released,
+                            })),
                         );
                     }
-                    Ok(_) => {
+                    Err(RentalTransitionError::Db(_)) => {
                         return (
-                            StatusCode::NOT_FOUND,
+                            StatusCode::INTERNAL_SERVER_ERROR,
                             Json(
-                                json!({"message": "El alquiler no se ha encontrado o no puede ser cancelado"}),
+                                json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
                             ),
                         );
                     }
                     Err(_) => {
                         return (
-                            StatusCode::INTERNAL_SERVER_ERROR,
+                            StatusCode::NOT_FOUND,
                             Json(
-                                json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
+                                json!({"message": "El alquiler no se ha encontrado o no puede ser cancelado"}),
                             ),
                         );
                     }
                 };
             } else {
-                let update_query = "
-                UPDATE rentals 
-                SET status = 'cancelled', notes = $1, updated_at = NOW()
-                WHERE id = $2 AND status IN ('pending_payment', 'active');
-            ";
-
                 let cancel_reason = payload
                     .reason
                     .as_deref()
                     .unwrap_or("No se indicó un motivo");
 
-                match client
-                    .execute(update_query, &[&cancel_reason, &payload.rental_id])
-                    .await
+                let transaction = match client.transaction().await {
+                    Ok(t) => t,
+                    Err(_) => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(
+                                json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
+                            ),
+                        );
+                    }
+                };
+
+                let refund = match compute_refund(
+                    &transaction,
+                    payload.rental_id,
+                    true,
+                    Local::now().date_naive(),
+                )
+                .await
                 {
-                    Ok(rows_updated) if rows_updated == 1 => {
+                    Ok(refund) => refund,
+                    Err(_) => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(
+                                json!({"message": "Se produjo un error interno al calcular el reembolso"}),
+                            ),
+                        );
+                    }
+                };
+
+                let cancelled = cancel_and_refund_rental(
+                    &transaction,
+                    payload.rental_id,
+                    refund,
+                    RentalActor::new(claims.user_id, claims.role),
+                    Some(cancel_reason),
+                )
+                .await;
+
+                match cancelled {
+                    Ok(CancelledRental {
+                        refund,
+                        deposit_released,
+                    }) => {
+                        if transaction.commit().await.is_err() {
+                            return (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                Json(
+                                    json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
+                                ),
+                            );
+                        }
+
+                        reevaluate_waitlist_after_rentals(&mut client, &[payload.rental_id]).await;
+
                         let client_id = rental_row.get::<_, i32>("user_id");
 
                         let get_client_query = "
@@ -1838,12 +2824,29 @@ pub async fn cancel_rental(
                                 let user_email: String = user_row.get("email");
                                 let user_name: String = user_row.get("name");
 
+                                let mut refund_message = if refund.amount > Decimal::ZERO {
+                                    format!(
+                                        "En la brevedad se le reintegrarán $ {} ({}% del monto abonado), según la política de cancelación del alquiler.",
+                                        refund.amount,
+                                        refund.percentage.normalize(),
+                                    )
+                                } else {
+                                    "Según la política de cancelación del alquiler, no corresponde reintegro.".to_string()
+                                };
+
+                                if let Some(deposit) = deposit_released {
+                                    refund_message.push_str(&format!(
+                                        " También se le devolverá el depósito de garantía de $ {}.",
+                                        deposit
+                                    ));
+                                }
+
                                 let subject = format!(
                                     "Alquiler n° {} cancelado - Bob el Alquilador",
                                     payload.rental_id
                                 );
                                 let body = format!(
-                                "Hola {},\n\n\
+                                    "Hola {},\n\n\
                     Se le informa que se ha cancelado su alquiler.
                     \n\n\
                     Detalles del Alquiler:
@@ -1853,30 +2856,34 @@ pub async fn cancel_rental(
                     Máquina:\t\t\t {} {} {}\n\
                     Ubicación:\t\t\t {}, {}, {}
                     \n\n\
-                    En caso de que corresponda, en la brevedad se le reintegrará la totalidad del monto abonado.\n\
+                    {}\n\
                     Nos disculpamos por las molestias ocasionadas.\n\n\
                     \n\
                     Saludos cordiales,\n\
                     El equipo de Bob el Alquilador\n",
-                                user_name,
-                                payload.rental_id,
-                                rental_start_date.format("%d/%m/%Y").to_string(),
-                                rental_end_date.format("%d/%m/%Y").to_string(),
-                                extra_info_rental_row.get::<_, String>("name"),
-                                extra_info_rental_row.get::<_, String>("brand"),
-                                extra_info_rental_row.get::<_, String>("model"),
-                                extra_info_rental_row.get::<_, String>("city"),
-                                extra_info_rental_row.get::<_, String>("street"),
-                                extra_info_rental_row.get::<_, String>("number"),
-                            );
+                                    user_name,
+                                    payload.rental_id,
+                                    rental_start_date.format("%d/%m/%Y"),
+                                    rental_end_date.format("%d/%m/%Y"),
+                                    extra_info_rental_row.get::<_, String>("name"),
+                                    extra_info_rental_row.get::<_, String>("brand"),
+                                    extra_info_rental_row.get::<_, String>("model"),
+                                    extra_info_rental_row.get::<_, String>("city"),
+                                    extra_info_rental_row.get::<_, String>("street"),
+                                    extra_info_rental_row.get::<_, String>("number"),
+                                    refund_message,
+                                );
 
                                 match send_mail(&user_email, &subject, &body) {
                                     Ok(_) => {
                                         return (
                                             StatusCode::OK,
-                                            Json(
-                                                json!({"message": "El alquiler ha sido cancelado exitosamente y el cliente ha sido notificado"}),
-                                            ),
+                                            Json(json!({
+                                                "message": "El alquiler ha sido cancelado exitosamente y el cliente ha sido notificado",
+                                                "refund_amount": refund.amount,
+                                                "refund_percentage": refund.percentage,
+                                                "deposit_released": deposit_released,
+                                            })),
                                         );
                                     }
                                     Err(_) => {
@@ -1899,19 +2906,19 @@ pub async fn cancel_rental(
                             }
                         }
                     }
-                    Ok(_) => {
+                    Err(RentalTransitionError::Db(_)) => {
                         return (
-                            StatusCode::NOT_FOUND,
+                            StatusCode::INTERNAL_SERVER_ERROR,
                             Json(
-                                json!({"message": "El alquiler no se ha encontrado o no puede ser cancelado"}),
+                                json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
                             ),
                         );
                     }
                     Err(_) => {
                         return (
-                            StatusCode::INTERNAL_SERVER_ERROR,
+                            StatusCode::NOT_FOUND,
                             Json(
-                                json!({"message": "Se produjo un error interno al cancelar el alquiler"}),
+                                json!({"message": "El alquiler no se ha encontrado o no puede ser cancelado"}),
                             ),
                         );
                     }
@@ -1933,10 +2940,9 @@ pub async fn cancel_rental(
     );
 }
 
-#[axum::debug_handler]
-pub async fn get_staff_rentals(
+pub async fn get_rental_timeline(
     State(state): State<AppState>,
-    Query(query_params): Query<GetRentalQueryParams>,
+    Path(rental_id): Path<i32>,
     Json(payload): Json<Access>,
 ) -> (StatusCode, Json<serde_json::Value>) {
     let claims = match validate_jwt(&payload.access) {
@@ -1950,15 +2956,6 @@ pub async fn get_staff_rentals(
     }
     .claims;
 
-    if (claims.role != 0) && (claims.role != 1) {
-        return (
-            StatusCode::FORBIDDEN,
-            Json(
-                json!({"message": "Solo empleados y administradores pueden acceder a esta información"}),
-            ),
-        );
-    }
-
     let client = match state.pool.get().await {
         Ok(c) => c,
         Err(_) => {
@@ -1969,14 +2966,245 @@ pub async fn get_staff_rentals(
         }
     };
 
-    let param_idx = 1;
-    let mut params: Vec<Box<dyn ToSql + Sync + Send>> = Vec::new();
-
-    let where_clause = if let Some(rental_id) = query_params.id {
-        let formatted_where_clause = format!("WHERE rentals.id = ${}", param_idx);
-
+    let rental_row = match client
+        .query_opt("SELECT user_id FROM rentals WHERE id = $1;", &[&rental_id])
+        .await
+    {
+        Ok(row) => row,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(
+                    json!({"message": "Se produjo un error al obtener el historial del alquiler"}),
+                ),
+            );
+        }
+    };
+
+    // Clients can only see the timeline of their own rentals
+    match rental_row {
+        Some(row) if claims.role != 2 || row.get::<_, i32>("user_id") == claims.user_id => (),
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "El alquiler no se ha encontrado"})),
+            );
+        }
+    }
+
+    let history_query = "
+        SELECT h.from_status::TEXT, h.to_status::TEXT, h.actor_id,
+            u.name || ' ' || u.surname AS actor_name, u.role AS actor_role,
+            h.reason, h.created_at
+        FROM rental_status_history h
+        LEFT JOIN users u ON u.id = h.actor_id
+        WHERE h.rental_id = $1
+        ORDER BY h.created_at, h.id;
+    ";
+
+    // Delivered and collected events of a job site delivery are kept with the delivery
+    match (
+        client.query(history_query, &[&rental_id]).await,
+        get_delivery(&client, rental_id).await,
+    ) {
+        (Ok(rows), Ok(delivery)) => {
+            let timeline: Vec<RentalStatusChange> = rows
+                .iter()
+                .map(RentalStatusChange::build_from_row)
+                .collect();
+
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "rental_id": rental_id,
+                    "timeline": timeline,
+                    "delivery": delivery,
+                })),
+            )
+        }
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se produjo un error al obtener el historial del alquiler"})),
+        ),
+    }
+}
+
+pub async fn get_rental_inspections(
+    State(state): State<AppState>,
+    Path(rental_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let inspections_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(
+                json!({"message": "Se produjo un error al obtener las inspecciones del alquiler"}),
+            ),
+        )
+    };
+
+    let rental_row = match client
+        .query_opt("SELECT user_id FROM rentals WHERE id = $1;", &[&rental_id])
+        .await
+    {
+        Ok(row) => row,
+        Err(_) => return inspections_error(),
+    };
+
+    match rental_row {
+        Some(row) if claims.role != 2 || row.get::<_, i32>("user_id") == claims.user_id => (),
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "El alquiler no se ha encontrado"})),
+            );
+        }
+    }
+
+    match get_inspections(&client, state.media.as_ref(), rental_id).await {
+        Ok(inspections) => (
+            StatusCode::OK,
+            Json(json!({
+                "rental_id": rental_id,
+                "inspections": inspections,
+            })),
+        ),
+        Err(_) => inspections_error(),
+    }
+}
+
+pub async fn get_rental_deposit(
+    State(state): State<AppState>,
+    Path(rental_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let deposit_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se produjo un error al obtener el depósito del alquiler"})),
+        )
+    };
+
+    let rental_row = match client
+        .query_opt("SELECT user_id FROM rentals WHERE id = $1;", &[&rental_id])
+        .await
+    {
+        Ok(row) => row,
+        Err(_) => return deposit_error(),
+    };
+
+    match rental_row {
+        Some(row) if claims.role != 2 || row.get::<_, i32>("user_id") == claims.user_id => (),
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "El alquiler no se ha encontrado"})),
+            );
+        }
+    }
+
+    match get_deposit_statement(&client, rental_id).await {
+        Ok(Some(statement)) => (StatusCode::OK, Json(json!({"deposit": statement}))),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "El alquiler no se ha encontrado"})),
+        ),
+        Err(_) => deposit_error(),
+    }
+}
+
+#[axum::debug_handler]
+pub async fn get_staff_rentals(
+    State(state): State<AppState>,
+    Query(query_params): Query<GetRentalQueryParams>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    if (claims.role != 0) && (claims.role != 1) {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(
+                json!({"message": "Solo empleados y administradores pueden acceder a esta información"}),
+            ),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let param_idx = 1;
+    let mut params: Vec<Box<dyn ToSql + Sync + Send>> = Vec::new();
+
+    let where_clause = if let Some(rental_id) = query_params.id {
+        let formatted_where_clause = format!("WHERE rentals.id = ${}", param_idx);
+
         params.push(Box::new(rental_id));
 
+        formatted_where_clause
+    } else if let Some(order_id) = query_params.order_id {
+        let formatted_where_clause = format!("WHERE rentals.order_id = ${}", param_idx);
+
+        params.push(Box::new(order_id));
+
         formatted_where_clause
     } else {
         "".to_string()
@@ -1990,9 +3218,13 @@ pub async fn get_staff_rentals(
             rentals.start_date,
             rentals.end_date,
             rentals.total_price,
+            rentals.refund_amount,
+            rentals.deposit_amount,
+            rentals.deposit_status::TEXT,
             rentals.status::TEXT,
             rentals.created_at,
             rentals.updated_at,
+            rentals.order_id,
             machinery_units.id AS unit_id,
             machinery_units.serial_number AS unit_serial_number,
             machinery_models.id AS model_id,
@@ -2021,16 +3253,6 @@ pub async fn get_staff_rentals(
 
     match client.query(&rental_query, &query_params).await {
         Ok(rows) => {
-            let nginx_url = match env::var("NGINX_URL") {
-                Ok(url) => url,
-                Err(_) => {
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({"message": "NGINX_URL must be set in the .env file"})),
-                    );
-                }
-            };
-
             if rows.is_empty() {
                 return (
                     StatusCode::NOT_FOUND,
@@ -2038,6 +3260,17 @@ pub async fn get_staff_rentals(
                 );
             }
 
+            let rental_ids: Vec<i32> = rows.iter().map(|row| row.get("rental_id")).collect();
+
+            let Ok(mut extensions) = get_rental_extensions(&client, &rental_ids).await else {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(
+                        json!({"message": "Se produjo un error interno al intentar obtener los alquileres"}),
+                    ),
+                );
+            };
+
             let rentals: Vec<MyRentalInfo> = rows
                 .iter()
                 .map(|row| MyRentalInfo {
@@ -2047,6 +3280,9 @@ pub async fn get_staff_rentals(
                     start_date: row.get("start_date"),
                     end_date: row.get("end_date"),
                     total_price: row.get("total_price"),
+                    refund_amount: row.get("refund_amount"),
+                    deposit_amount: row.get("deposit_amount"),
+                    deposit_status: row.get("deposit_status"),
                     status: row.get("status"),
                     created_at: row.get("created_at"),
                     updated_at: row.get("updated_at"),
@@ -2059,16 +3295,14 @@ pub async fn get_staff_rentals(
                     model_year: row.get("model_year"),
                     model_policy: row.get("model_policy"),
                     model_description: row.get("model_description"),
-                    model_image: format!(
-                        "{}/media/machines/{}.webp",
-                        nginx_url,
-                        row.get::<_, String>("model_image")
-                    ),
+                    model_image: machine_image_url(state.media.as_ref(), row.get("model_image")),
                     days_late: {
                         let status = row.get::<_, String>("status");
                         let today = Local::now().date_naive();
                         let end_date = row.get::<_, NaiveDate>("end_date");
-                        if ((status == "active") || (status == "pending_payment"))
+                        if ((status == "active")
+                            || (status == "pending_payment")
+                            || (status == "retired"))
                             && (end_date < today)
                         {
                             Some((today - end_date).num_days())
@@ -2080,7 +3314,9 @@ pub async fn get_staff_rentals(
                         let status = row.get::<_, String>("status");
                         let today = Local::now().date_naive();
                         let end_date = row.get::<_, NaiveDate>("end_date");
-                        if ((status == "active") || (status == "pending_payment"))
+                        if ((status == "active")
+                            || (status == "pending_payment")
+                            || (status == "retired"))
                             && (end_date < today)
                         {
                             Some("10% del precio de la máquina por día de retraso".to_string())
@@ -2090,7 +3326,10 @@ pub async fn get_staff_rentals(
                     },
                     has_service_review: false,
                     has_machine_review: false,
-
+                    extensions: extensions
+                        .remove(&row.get::<_, i32>("rental_id"))
+                        .unwrap_or_default(),
+                    order_id: row.get("order_id"),
                 })
                 .collect();
 
@@ -2171,17 +3410,6 @@ pub async fn get_locations(
 }
 
 pub async fn get_models(State(state): State<AppState>, Json(payload): Json<Access>) -> Response {
-    let nginx_url = match env::var("NGINX_URL") {
-        Ok(url) => url,
-        Err(_) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"message": "NGINX_URL must be set in the .env file"})),
-            )
-                .into_response();
-        }
-    };
-
     let claims = match validate_jwt(&payload.access) {
         Some(data) => data,
         None => {
@@ -2226,14 +3454,17 @@ pub async fn get_models(State(state): State<AppState>, Json(payload): Json<Acces
                     policy: row.get("policy"),
                     description: row.get("description"),
                     price: row.get("price"),
-                    main_image: format!(
-                        "{}/media/machines/{}.webp",
-                        nginx_url,
-                        row.get::<_, String>("image")
-                    ),
+                    deposit: row.get("deposit"),
+                    main_image: machine_image_url(state.media.as_ref(), row.get("image")),
 
                     extra_images: Vec::new(),
+                    main_image_urls: ImageUrls::new(state.media.as_ref(), row.get("image")),
+                    extra_images_urls: Vec::new(),
                     categories: Vec::new(),
+                    decommissioned_at: row.get("decommissioned_at"),
+                    decommission_reason: row.get("decommission_reason"),
+                    specs: Vec::new(),
+                    rental_rules: RentalRules::build_from_row(row),
                 })
                 .collect();
             return (StatusCode::OK, Json(json!({"models": models}))).into_response();
@@ -2248,6 +3479,254 @@ pub async fn get_models(State(state): State<AppState>, Json(payload): Json<Acces
     };
 }
 
+pub async fn update_model_rental_rules(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateModelRentalRules>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid rental rules"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let rules = &payload.rules;
+
+    match client
+        .execute(
+            "UPDATE machinery_models
+            SET min_rental_days = $1, max_rental_days = $2, turnaround_days = $3,
+            min_lead_days = $4, max_lead_days = $5
+            WHERE id = $6;",
+            &[
+                &rules.min_rental_days,
+                &rules.max_rental_days,
+                &rules.turnaround_days,
+                &rules.min_lead_days,
+                &rules.max_lead_days,
+                &payload.model_id,
+            ],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The model does not exist"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "message": "Rental rules updated successfully",
+                "rental_rules": rules,
+            })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to update the rental rules"})),
+        ),
+    }
+}
+
+pub async fn update_model_deposit(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateModelDeposit>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The deposit can't be negative"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    // Rentals already booked keep the deposit they were booked with
+    match client
+        .execute(
+            "UPDATE machinery_models SET deposit = $1 WHERE id = $2;",
+            &[&payload.deposit, &payload.model_id],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The model does not exist"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "message": "Deposit updated successfully",
+                "deposit": payload.deposit,
+            })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to update the deposit"})),
+        ),
+    }
+}
+
+pub async fn get_model_cancellation_policy(
+    State(state): State<AppState>,
+    Path(model_id): Path<i32>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+            );
+        }
+    };
+
+    match get_current_cancellation_policy(&client, model_id).await {
+        Ok(policy) => (StatusCode::OK, Json(json!({"cancellation_policy": policy}))),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+        ),
+    }
+}
+
+pub async fn update_model_cancellation_policy(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateCancellationPolicy>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid cancellation policy"})),
+        );
+    }
+
+    let mut client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to update the cancellation policy"})),
+        )
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    // Locking the model keeps concurrent updates from taking the same version
+    match transaction
+        .query_opt(
+            "SELECT id FROM machinery_models WHERE id = $1 FOR UPDATE;",
+            &[&payload.model_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "The model does not exist"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    let Ok(row) = transaction
+        .query_one(
+            "INSERT INTO cancellation_policies (model_id, version, staff_refund_percentage, created_by)
+            SELECT $1, COALESCE(MAX(version), 0) + 1, $2, $3
+            FROM cancellation_policies
+            WHERE model_id = $1
+            RETURNING id;",
+            &[
+                &payload.model_id,
+                &payload.staff_refund_percentage,
+                &claims.user_id,
+            ],
+        )
+        .await
+    else {
+        return internal_error();
+    };
+
+    let policy_id: i32 = row.get("id");
+
+    for tier in &payload.tiers {
+        if transaction
+            .execute(
+                "INSERT INTO cancellation_policy_tiers (policy_id, min_notice_days, refund_percentage)
+                VALUES ($1, $2, $3);",
+                &[&policy_id, &tier.min_notice_days, &tier.refund_percentage],
+            )
+            .await
+            .is_err()
+        {
+            return internal_error();
+        }
+    }
+
+    let Ok(Some(policy)) = get_cancellation_policy(&transaction, policy_id).await else {
+        return internal_error();
+    };
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "message": "Cancellation policy updated successfully",
+            "cancellation_policy": policy,
+        })),
+    )
+}
+
 pub async fn verify_client(
     State(state): State<AppState>,
     Json(payload): Json<VerifyClient>,
@@ -2342,7 +3821,7 @@ pub async fn get_units_by_model_and_location(
         SELECT mu.id
         FROM machinery_units mu
         INNER JOIN machinery_models mm ON mu.model_id = mm.id
-        WHERE mm.id = $1 AND mu.location_id = $2;
+        WHERE mm.id = $1 AND mu.location_id = $2 AND mu.status != 'decommissioned';
     ";
 
     if let Ok(rows) = client
@@ -2404,13 +3883,59 @@ pub async fn validate_rental_dates(
     let start_date = payload.start_date;
     let end_date = payload.end_date;
 
+    match client
+        .query_opt(
+            "SELECT id FROM machinery_units WHERE id = $1 AND status = 'decommissioned';",
+            &[&machine_id],
+        )
+        .await
+    {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+            );
+        }
+    }
+
+    // A unit that doesn't exist has no rentals, so its dates are only checked against the
+    // default rules
+    let rules = match get_rental_rules(&client, machine_id).await {
+        Ok(rules) => rules.unwrap_or_default(),
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+            );
+        }
+    };
+
+    if let Err(message) =
+        check_rental_period(&rules, start_date, end_date, Local::now().date_naive())
+    {
+        return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+    }
+
     let unavailable_dates_query = "
-            SELECT start_date, (end_date + INTERVAL '7 days')::date AS end_date
-            FROM rentals r 
-            WHERE r.status IN ('active', 'pending_payment') AND (machine_id = $1);
+            SELECT start_date, end_date + $2::INT AS end_date
+            FROM unit_bookings
+            WHERE machine_id = $1;
         ";
 
-    let unavailable_dates = match client.query(unavailable_dates_query, &[&machine_id]).await {
+    let unavailable_dates = match client
+        .query(
+            unavailable_dates_query,
+            &[&machine_id, &rules.turnaround_days],
+        )
+        .await
+    {
         Ok(rows) => rows
             .iter()
             .map(|row| DateRange {
@@ -2428,7 +3953,7 @@ pub async fn validate_rental_dates(
         }
     };
 
-    let end_date_with_maintenance_period = end_date + Duration::days(7);
+    let end_date_with_maintenance_period = end_date + Duration::days(rules.turnaround_days as i64);
 
     let overlaped_date = unavailable_dates.iter().find(|period| {
         date_is_overlap(
@@ -2439,17 +3964,6 @@ pub async fn validate_rental_dates(
         )
     });
 
-    let duration_days = (end_date - start_date).num_days();
-
-    if end_date < start_date || duration_days < 7 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": "El período indicado no es válido. Debe ser al menos 7 días y la fecha de fin no puede ser anterior a la de inicio.",
-            })),
-        );
-    }
-
     if let Some(date) = overlaped_date {
         return (
             StatusCode::CONFLICT,
@@ -2497,7 +4011,7 @@ pub async fn new_in_person_rental(
         );
     }
 
-    let client = match state.pool.get().await {
+    let mut client = match state.pool.get().await {
         Ok(c) => c,
         Err(_) => {
             return (
@@ -2511,23 +4025,32 @@ pub async fn new_in_person_rental(
     let user_id = payload.user_id;
     let start_date = payload.start_date;
     let end_date = payload.end_date;
-    let total_price = payload.total_price;
     let rental_employee_id = claims.user_id;
 
-    let unavailable_dates_query = "
-            SELECT start_date, (end_date + INTERVAL '7 days')::date AS end_date
-            FROM rentals r 
-            WHERE r.status IN ('active', 'pending_payment') AND (machine_id = $1);
-        ";
+    let rules = match get_rental_rules(&client, machine_id).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+            );
+        }
+    };
 
-    let unavailable_dates = match client.query(unavailable_dates_query, &[&machine_id]).await {
-        Ok(rows) => rows
-            .iter()
-            .map(|row| DateRange {
-                start_date: row.get("start_date"),
-                end_date: row.get("end_date"),
-            })
-            .collect::<Vec<DateRange>>(),
+    if let Err(message) =
+        check_rental_period(&rules, start_date, end_date, Local::now().date_naive())
+    {
+        return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+    }
+
+    let transaction = match client.transaction().await {
+        Ok(t) => t,
         Err(_) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -2538,86 +4061,78 @@ pub async fn new_in_person_rental(
         }
     };
 
-    let end_date_with_maintenance_period = end_date + Duration::days(7);
-
-    let is_overlap = unavailable_dates.iter().any(|period| {
-        date_is_overlap(
-            start_date,
-            end_date_with_maintenance_period,
-            period.start_date,
-            period.end_date,
-        )
-    });
-
-    let duration_days = (end_date - start_date).num_days();
-
-    if end_date < start_date || duration_days < 7 {
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "message": "El período indicado no es válido. Debe ser al menos 7 días y la fecha de fin no puede ser anterior a la de inicio.",
-            })),
-        );
-    }
-
-    if is_overlap {
-        return (
-            StatusCode::CONFLICT,
-            Json(json!({
-                "message": "Las fechas de inicio y fin se superponen con un alquiler existente, considerando el período de mantenimiento planificado",
-            })),
-        );
-    }
-
-    let price_query = "
-            SELECT price FROM machinery_models mm 
-            INNER JOIN machinery_units mu 
-            ON mm.id = mu.model_id
-            WHERE mu.id = $1;
-        ";
-
-    let price_row = match client.query_one(price_query, &[&machine_id]).await {
-        Ok(row) => row,
+    match lock_unit_period(&transaction, machine_id, start_date, end_date).await {
+        Ok(true) => (),
+        Ok(false) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "Las fechas de inicio y fin se superponen con un alquiler existente, considerando el período de mantenimiento planificado",
+                })),
+            );
+        }
         Err(_) => {
             return (
-                StatusCode::NOT_FOUND,
+                StatusCode::INTERNAL_SERVER_ERROR,
                 Json(json!({
-                    "message": "No se ha encontrado la máquina solicitada",
+                    "message": "Se ha producido un error interno en el servidor",
                 })),
             );
         }
+    }
+
+    // The client may have been quoted online, or the employee at the counter
+    let RentalPrice {
+        total_price,
+        deposit,
+    } = match resolve_rental_price(
+        &transaction,
+        &[user_id, rental_employee_id],
+        machine_id,
+        start_date,
+        end_date,
+        &payload.quote_token,
+        payload.total_price,
+    )
+    .await
+    {
+        Ok(price) => price,
+        Err(error_response) => return error_response,
     };
 
-    let machine_price: f32 = price_row.get("price");
-    let number_of_days = (end_date - start_date).num_days() as f32;
+    let user_query = "SELECT * FROM users WHERE id = $1 AND role = 2;";
 
-    if total_price != number_of_days * machine_price {
+    if transaction
+        .query_one(user_query, &[&user_id])
+        .await
+        .is_err()
+    {
         return (
-            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
             Json(json!({
-                "message": "El precio total no es correcto",
+                "message": "No se ha encontrado al usuario o no es un cliente",
             })),
         );
-    }
-
-    let user_query = "SELECT * FROM users WHERE id = $1 AND role = 2;";
+    };
 
-    if let Err(_) = client.query_one(user_query, &[&user_id]).await {
+    let Ok(cancellation_policy_id) = current_cancellation_policy_id(&transaction, machine_id).await
+    else {
         return (
-            StatusCode::NOT_FOUND,
+            StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({
-                "message": "No se ha encontrado al usuario o no es un cliente",
+                "message": "Se ha producido un error interno en el servidor",
             })),
         );
     };
 
     let insert_query = "
-            INSERT INTO rentals (user_id, rental_employee_id, machine_id, start_date, end_date, total_price, status)
-            VALUES ($1, $2, $3, $4, $5, $6, 'active')
+            INSERT INTO rentals (user_id, rental_employee_id, machine_id, start_date, end_date, total_price, status, cancellation_policy_id,
+                deposit_amount, deposit_status)
+            VALUES ($1, $2, $3, $4, $5, $6, 'active', $7, $8, CASE WHEN $8::NUMERIC > 0 THEN 'held'::deposit_status END)
             RETURNING id;
         ";
 
-    match client
+    match transaction
         .query_one(
             insert_query,
             &[
@@ -2627,6 +4142,8 @@ pub async fn new_in_person_rental(
                 &start_date,
                 &end_date,
                 &total_price,
+                &cancellation_policy_id,
+                &deposit,
             ],
         )
         .await
@@ -2641,9 +4158,10 @@ pub async fn new_in_person_rental(
                 WHERE id = $2;
                 ";
 
-            if let Err(_) = client
+            if transaction
                 .execute(update_payment_id_query, &[&payment_id, &rental_id])
                 .await
+                .is_err()
             {
                 return (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -2653,6 +4171,36 @@ pub async fn new_in_person_rental(
                 );
             }
 
+            if record_rental_status(
+                &transaction,
+                rental_id,
+                None,
+                RentalStatus::Active,
+                RentalActor::new(claims.user_id, claims.role),
+                None,
+            )
+            .await
+            .is_err()
+            {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({
+                        "message": "Se ha producido un error interno en el servidor",
+                    })),
+                );
+            }
+
+            if issue_rental_invoice(&transaction, rental_id).await.is_err()
+                || transaction.commit().await.is_err()
+            {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({
+                        "message": "Se ha producido un error al emitir la factura del alquiler",
+                    })),
+                );
+            }
+
             match client
                 .query_one("SELECT * FROM users WHERE id = $1;", &[&user_id])
                 .await
@@ -2688,6 +4236,15 @@ pub async fn new_in_person_rental(
                             let user_email: String = user_row.get("email");
                             let user_name: String = user_row.get("name");
 
+                            let deposit_line = if deposit > Decimal::ZERO {
+                                format!(
+                                    "Depósito de garantía: $ {} (se devuelve con la máquina)\n",
+                                    deposit
+                                )
+                            } else {
+                                String::new()
+                            };
+
                             let subject =
                                 format!("Alquiler n° {} aprobado - Bob el Alquilador", rental_id);
                             let body = format!(
@@ -2700,7 +4257,8 @@ pub async fn new_in_person_rental(
                                 Período: {} - {}\n\
                                 Máquina: {} {} {}\n\
                                 Ubicación: {}, {}, {}\n\
-                                Identificador del pago: {}\n\n\
+                                Identificador del pago: {}\n\
+                                {}\n\
                                 \n\n\
                                 Gracias por confiar en nosotros.\n\n\
                                 Saludos cordiales,\n\
@@ -2716,6 +4274,7 @@ pub async fn new_in_person_rental(
                                 rent.street,
                                 rent.number,
                                 rent.payment_id,
+                                deposit_line,
                             );
 
                             match send_mail(&user_email, &subject, &body) {
@@ -2724,6 +4283,10 @@ pub async fn new_in_person_rental(
                                         StatusCode::CREATED,
                                         Json(json!({
                                             "message": "El alquiler ha sido registrado exitosamente y se le ha notificado al cliente",
+                                            "rental_id": rental_id,
+                                            "total_price": total_price,
+                                            "deposit": deposit,
+                                            "amount_due": total_price + deposit,
                                         })),
                                     );
                                 }
@@ -2757,6 +4320,7 @@ pub async fn new_in_person_rental(
                 }
             }
         }
+        Err(e) if is_overlapping_rental(&e) => overlapping_rental_response(),
         Err(_) => {
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -1,7 +1,12 @@
 use crate::custom_types::enums::*;
 use crate::custom_types::structs::*;
-use crate::helpers::auth::*;
+use crate::helpers::{
+    auth::*,
+    maintenance_mgmt::*,
+    waitlist::{reevaluate_waitlist, reevaluate_waitlist_after_rentals},
+};
 use axum::{extract::Path, extract::State, http::StatusCode, Json};
+use chrono::{Local, NaiveDate};
 use serde_json::json;
 
 pub async fn get_machine_unit(
@@ -54,24 +59,15 @@ pub async fn get_machine_unit(
 
     let unit_info_query = "
     SELECT mm.id AS model_id, mm.name, mm.brand, mm.model, mm.year, mm.image,
-    mu.id AS unit_id, mu.serial_number, mu.status::TEXT, l.id AS location_id, l.city, l.street, l.number
+    mu.id AS unit_id, mu.serial_number, mu.status::TEXT, l.id AS location_id, l.city, l.street, l.number,
+    mu.decommissioned_at, mu.decommission_reason
     FROM machinery_units mu 
     INNER JOIN machinery_models mm ON mu.model_id = mm.id
     INNER JOIN locations l ON mu.location_id = l.id
     WHERE serial_number = $1;";
 
     if let Ok(row) = client.query_one(unit_info_query, &[&serial_number]).await {
-        let unit_info_result = GetMachineUnit::build_from_row(&row);
-
-        let unit_info = match unit_info_result {
-            Ok(info) => info,
-            Err(_) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"message": "Ocurrió un error al procesar la imagen de la unidad"})),
-                );
-            }
-        };
+        let unit_info = GetMachineUnit::build_from_row(&row, state.media.as_ref());
 
         return (
             StatusCode::OK,
@@ -188,7 +184,7 @@ pub async fn update_unit_history(
         );
     }
 
-    let client = match state.pool.get().await {
+    let mut client = match state.pool.get().await {
         Ok(c) => c,
         Err(_) => {
             return (
@@ -214,6 +210,22 @@ pub async fn update_unit_history(
         }
     };
 
+    if unit_row.get::<_, String>(0) == "decommissioned" {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "La unidad fue dada de baja y su estado no puede modificarse"})),
+        );
+    }
+
+    if unit_row.get::<_, String>(0) == "in_transit" {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": "La unidad está en traslado. Registre su recepción antes de modificar su estado"
+            })),
+        );
+    }
+
     let previous_status = format!("'{}'", unit_row.get::<_, String>(0));
 
     let new_status = match payload.new_status {
@@ -256,6 +268,11 @@ pub async fn update_unit_history(
         .await
     {
         Ok(_) => {
+            // A unit back from maintenance may be what someone in the waitlist is waiting for
+            if matches!(payload.new_status, UnitStatusEvents::Available) {
+                reevaluate_waitlist(&mut client, &[payload.unit_id]).await;
+            }
+
             return (
                 StatusCode::CREATED,
                 Json(json!({"message": "El evento se ha registrado correctamente"})),
@@ -271,3 +288,291 @@ pub async fn update_unit_history(
         }
     }
 }
+
+pub async fn decommission_unit(
+    State(state): State<AppState>,
+    Json(payload): Json<DecommissionUnit>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    if claims.role != 0 {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"message": "Solo administradores pueden acceder a esta funcionalidad"})),
+        );
+    }
+
+    let today = Local::now().date_naive();
+    let date = payload.date.unwrap_or(today);
+
+    if let Some(invalid_response) = validate_decommission_info(&payload.reason, date, today) {
+        return invalid_response;
+    }
+
+    let mut client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let transaction = match client.transaction().await {
+        Ok(t) => t,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to create a DB transaction"})),
+            );
+        }
+    };
+
+    match transaction
+        .query_opt(
+            "SELECT id FROM machinery_units WHERE id = $1;",
+            &[&payload.unit_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "La unidad indicada no existe"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Ocurrió un error al dar de baja la unidad"})),
+            );
+        }
+    }
+
+    let summary = match decommission_units(
+        &transaction,
+        &[payload.unit_id],
+        date,
+        payload.reason.trim(),
+        payload.future_rentals,
+        RentalActor::new(claims.user_id, claims.role),
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(error_response) => return error_response,
+    };
+
+    if transaction.commit().await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to commit transaction"})),
+        );
+    }
+
+    if !summary.cancelled_rentals.is_empty() {
+        notify_decommission_cancellations(&client, &summary.cancelled_rentals).await;
+        reevaluate_waitlist_after_rentals(&mut client, &summary.cancelled_rentals).await;
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "message": "La unidad ha sido dada de baja correctamente",
+            "reassigned_rentals": summary.reassigned_rentals,
+            "cancelled_rentals": summary.cancelled_rentals,
+        })),
+    )
+}
+
+pub async fn decommission_model(
+    State(state): State<AppState>,
+    Json(payload): Json<DecommissionModel>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    if claims.role != 0 {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({"message": "Solo administradores pueden acceder a esta funcionalidad"})),
+        );
+    }
+
+    let today = Local::now().date_naive();
+    let date = payload.date.unwrap_or(today);
+
+    if let Some(invalid_response) = validate_decommission_info(&payload.reason, date, today) {
+        return invalid_response;
+    }
+
+    // Every unit of the model leaves the fleet, so rentals can never be moved to a sibling unit
+    if payload.future_rentals == Some(FutureRentalsAction::Reassign) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": "Los alquileres de un modelo dado de baja no pueden reasignarse, solo cancelarse"
+            })),
+        );
+    }
+
+    let mut client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let transaction = match client.transaction().await {
+        Ok(t) => t,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to create a DB transaction"})),
+            );
+        }
+    };
+
+    let model_row = match transaction
+        .query_opt(
+            "SELECT decommissioned_at FROM machinery_models WHERE id = $1 FOR UPDATE;",
+            &[&payload.model_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "El modelo indicado no existe"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Ocurrió un error al dar de baja el modelo"})),
+            );
+        }
+    };
+
+    if model_row
+        .get::<_, Option<NaiveDate>>("decommissioned_at")
+        .is_some()
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "El modelo ya fue dado de baja"})),
+        );
+    }
+
+    let unit_ids: Vec<i32> = match transaction
+        .query(
+            "SELECT id FROM machinery_units WHERE model_id = $1 AND status != 'decommissioned';",
+            &[&payload.model_id],
+        )
+        .await
+    {
+        Ok(rows) => rows.iter().map(|row| row.get("id")).collect(),
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Ocurrió un error al dar de baja el modelo"})),
+            );
+        }
+    };
+
+    let summary = match decommission_units(
+        &transaction,
+        &unit_ids,
+        date,
+        payload.reason.trim(),
+        payload.future_rentals,
+        RentalActor::new(claims.user_id, claims.role),
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(error_response) => return error_response,
+    };
+
+    if transaction
+        .execute(
+            "UPDATE machinery_models
+            SET decommissioned_at = $1, decommission_reason = $2
+            WHERE id = $3;",
+            &[&date, &payload.reason.trim(), &payload.model_id],
+        )
+        .await
+        .is_err()
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Ocurrió un error al dar de baja el modelo"})),
+        );
+    }
+
+    if transaction.commit().await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to commit transaction"})),
+        );
+    }
+
+    if !summary.cancelled_rentals.is_empty() {
+        notify_decommission_cancellations(&client, &summary.cancelled_rentals).await;
+        reevaluate_waitlist_after_rentals(&mut client, &summary.cancelled_rentals).await;
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "message": "El modelo ha sido dado de baja correctamente",
+            "decommissioned_units": unit_ids,
+            "cancelled_rentals": summary.cancelled_rentals,
+        })),
+    )
+}
+
+fn validate_decommission_info(
+    reason: &str,
+    date: NaiveDate,
+    today: NaiveDate,
+) -> Option<(StatusCode, Json<serde_json::Value>)> {
+    if reason.trim().is_empty() {
+        return Some((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Debe indicarse el motivo de la baja"})),
+        ));
+    }
+
+    if date > today {
+        return Some((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "La fecha de baja no puede ser posterior a la fecha actual"})),
+        ));
+    }
+
+    None
+}
@@ -1,6 +1,18 @@
 pub mod auth;
+pub mod calendar;
+pub mod categories;
+pub mod charges;
+pub mod deliveries;
+pub mod documents;
+pub mod invoices;
 pub mod machinery_mgmt;
 pub mod maintenance_mgmt;
+pub mod orders;
+pub mod pricing;
 pub mod questions;
+pub mod rentals;
+pub mod specs;
 pub mod stats;
 pub mod reviews;
+pub mod transfers;
+pub mod waitlist;
@@ -0,0 +1,576 @@
+use crate::custom_types::enums::PaymentStatus;
+use crate::custom_types::structs::*;
+use crate::helpers::{
+    auth::{send_mail_with_attachments, validate_jwt},
+    charges::has_overdue_charges,
+    documents::rental_attachments,
+    machinery_mgmt::*,
+    orders::*,
+    pricing::resolve_rental_price,
+    rentals::*,
+    waitlist::*,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Local, NaiveDate};
+use rust_decimal::Decimal;
+use serde_json::json;
+use validator::Validate;
+
+fn internal_error() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+    )
+}
+
+/// Tells the client which line of the order a response is about.
+fn line_response(
+    (status, Json(mut body)): (StatusCode, Json<serde_json::Value>),
+    index: usize,
+) -> (StatusCode, Json<serde_json::Value>) {
+    body["line"] = json!(index);
+    (status, Json(body))
+}
+
+/// What a line of an order books.
+#[derive(Clone, Copy)]
+enum LineTarget {
+    Unit(i32),
+    /// A unit of the model at the location, assigned when the order is booked.
+    Model {
+        model_id: i32,
+        location_id: i32,
+    },
+}
+
+pub async fn new_order(
+    State(state): State<AppState>,
+    Json(payload): Json<NewOrder>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Ingreso de información inválida"})),
+        );
+    }
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let user_id = claims.user_id;
+    let today = Local::now().date_naive();
+
+    match has_overdue_charges(&client, user_id, today).await {
+        Ok(false) => {}
+        Ok(true) => return overdue_charges_response(),
+        Err(_) => return internal_error(),
+    }
+
+    // Every line is checked against the rules of its model before anything is booked
+    let mut targets = Vec::new();
+
+    for (index, line) in payload.lines.iter().enumerate() {
+        let (target, rules) = match (line.machine_id, line.model_id, line.location_id) {
+            (Some(machine_id), None, None) => (
+                LineTarget::Unit(machine_id),
+                get_rental_rules(&client, machine_id).await,
+            ),
+            (None, Some(model_id), Some(location_id)) => (
+                LineTarget::Model {
+                    model_id,
+                    location_id,
+                },
+                get_model_rental_rules(&client, model_id).await,
+            ),
+            _ => {
+                return line_response(
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({
+                            "message": "Cada línea debe indicar una máquina, o un modelo y una sucursal",
+                        })),
+                    ),
+                    index,
+                );
+            }
+        };
+
+        match rules {
+            Ok(Some(rules)) => {
+                if let Err(message) =
+                    check_rental_period(&rules, line.start_date, line.end_date, today)
+                {
+                    return line_response(
+                        (StatusCode::BAD_REQUEST, Json(json!({"message": message}))),
+                        index,
+                    );
+                }
+            }
+            Ok(None) => {
+                return line_response(
+                    (
+                        StatusCode::NOT_FOUND,
+                        Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+                    ),
+                    index,
+                );
+            }
+            Err(_) => return internal_error(),
+        }
+
+        targets.push(target);
+    }
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    let order_id: i32 = match transaction
+        .query_one(
+            "INSERT INTO orders (user_id) VALUES ($1) RETURNING id;",
+            &[&user_id],
+        )
+        .await
+    {
+        Ok(row) => row.get("id"),
+        Err(_) => return internal_error(),
+    };
+
+    let payment_hold_seconds = payment_hold().num_seconds() as i32;
+    let mut payment_deadline = None;
+    let mut lines = vec![serde_json::Value::Null; payload.lines.len()];
+    let mut released_holds = Vec::new();
+    let mut total_price = Decimal::ZERO;
+    let mut deposit = Decimal::ZERO;
+
+    // Units are locked in the same order by every booking, so orders sharing some of them wait
+    // for each other instead of deadlocking. Lines taking any unit of a model go last
+    let mut booking_order: Vec<usize> = (0..payload.lines.len()).collect();
+    booking_order.sort_by_key(|&index| match targets[index] {
+        LineTarget::Unit(machine_id) => (0, machine_id, payload.lines[index].start_date),
+        LineTarget::Model { model_id, .. } => (1, model_id, payload.lines[index].start_date),
+    });
+
+    // Still, units of a model are picked as they are found free, which can't follow that order
+    let concurrent_booking = |index: usize| {
+        line_response(
+            (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "Otra reserva de las mismas máquinas está en curso, vuelva a intentarlo",
+                })),
+            ),
+            index,
+        )
+    };
+
+    // All the lines are booked in one transaction, so either every unit is held or none is
+    for index in booking_order {
+        let line = &payload.lines[index];
+        let target = targets[index];
+        let start_date = line.start_date;
+        let end_date = line.end_date;
+
+        let (claimed_hold, machine_id) = match target {
+            LineTarget::Unit(machine_id) => {
+                let Ok(claimed_hold) = claim_waitlist_hold_of_unit(
+                    &transaction,
+                    user_id,
+                    machine_id,
+                    start_date,
+                    end_date,
+                )
+                .await
+                else {
+                    return internal_error();
+                };
+
+                match lock_unit_period(&transaction, machine_id, start_date, end_date).await {
+                    Ok(true) => (claimed_hold, machine_id),
+                    Ok(false) => {
+                        return line_response(
+                            (
+                                StatusCode::CONFLICT,
+                                Json(json!({
+                                    "message": "Las fechas de inicio y fin se superponen con un alquiler existente, considerando el período de mantenimiento planificado",
+                                })),
+                            ),
+                            index,
+                        );
+                    }
+                    Err(e) if is_deadlock(&e) => return concurrent_booking(index),
+                    Err(_) => return internal_error(),
+                }
+            }
+            LineTarget::Model {
+                model_id,
+                location_id,
+            } => {
+                let Ok(claimed_hold) = claim_waitlist_hold(
+                    &transaction,
+                    user_id,
+                    model_id,
+                    location_id,
+                    start_date,
+                    end_date,
+                )
+                .await
+                else {
+                    return internal_error();
+                };
+
+                match assign_unit(&transaction, model_id, location_id, start_date, end_date).await {
+                    Ok(Some(machine_id)) => (claimed_hold, machine_id),
+                    Ok(None) => {
+                        return line_response(
+                            (
+                                StatusCode::CONFLICT,
+                                Json(json!({
+                                    "message": "No hay ejemplares disponibles en la sucursal para las fechas indicadas",
+                                })),
+                            ),
+                            index,
+                        );
+                    }
+                    Err(e) if is_deadlock(&e) => return concurrent_booking(index),
+                    Err(_) => return internal_error(),
+                }
+            }
+        };
+
+        let price = match resolve_rental_price(
+            &transaction,
+            &[user_id],
+            machine_id,
+            start_date,
+            end_date,
+            &line.quote_token,
+            line.total_price,
+        )
+        .await
+        {
+            Ok(price) => price,
+            Err(error_response) => return line_response(error_response, index),
+        };
+
+        let Ok((rental_id, deadline)) = create_pending_rental(
+            &transaction,
+            user_id,
+            machine_id,
+            start_date,
+            end_date,
+            price,
+            payment_hold_seconds,
+        )
+        .await
+        else {
+            return internal_error();
+        };
+
+        if transaction
+            .execute(
+                "UPDATE rentals SET order_id = $1 WHERE id = $2;",
+                &[&order_id, &rental_id],
+            )
+            .await
+            .is_err()
+        {
+            return internal_error();
+        }
+
+        if let Some(held_unit) = claimed_hold.filter(|unit_id| *unit_id != machine_id) {
+            released_holds.push(held_unit);
+        }
+
+        total_price += price.total_price;
+        deposit += price.deposit;
+        payment_deadline = Some(deadline);
+
+        lines[index] = json!({
+            "rental_id": rental_id,
+            "machine_id": machine_id,
+            "start_date": start_date,
+            "end_date": end_date,
+            "total_price": price.total_price,
+            "deposit": price.deposit,
+        });
+    }
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    // The client booked other units than the ones held for them
+    if !released_holds.is_empty() {
+        reevaluate_waitlist(&mut client, &released_holds).await;
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "order_id": order_id,
+            "user_id": user_id,
+            "lines": lines,
+            "total_price": total_price,
+            "deposit": deposit,
+            "amount_due": total_price + deposit,
+            "payment_deadline": payment_deadline,
+            "payment_hold_seconds": payment_hold_seconds,
+        })),
+    )
+}
+
+pub async fn check_order_payment(
+    State(state): State<AppState>,
+    Query(query_params): Query<CheckPayment>,
+    Json(payload): Json<OrderIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let order_id = payload.order_id;
+    let user_id = claims.user_id;
+
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "No se ha encontrado el pedido pendiente de pago"})),
+        )
+    };
+
+    match query_params.status {
+        PaymentStatus::Approved => {}
+        PaymentStatus::Rejected => {
+            return match reject_order_payment(&mut client, order_id, user_id).await {
+                Ok(Some(failed)) => {
+                    reevaluate_waitlist_after_rentals(&mut client, &failed).await;
+
+                    (
+                        StatusCode::BAD_GATEWAY,
+                        Json(json!({
+                            "message": "Ha ocurrido un error en el pago por lo que no se pudo realizar el pedido.",
+                        })),
+                    )
+                }
+                Ok(None) => not_found(),
+                Err(_) => internal_error(),
+            };
+        }
+        _ => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({"message": "El pago no ha sido aprobado ni rechazado"})),
+            );
+        }
+    }
+
+    match confirm_order_payment(&mut client, order_id, user_id, &query_params.payment_id).await {
+        Ok(OrderPaymentOutcome::Paid) => {}
+        Ok(OrderPaymentOutcome::Lapsed(failed)) => {
+            reevaluate_waitlist_after_rentals(&mut client, &failed).await;
+
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "El plazo para pagar el pedido venció y las fechas ya no están disponibles. Se reintegrará el pago",
+                })),
+            );
+        }
+        Ok(OrderPaymentOutcome::NotFound) => return not_found(),
+        Err(RentalTransitionError::Db(e)) if is_overlapping_rental(&e) => {
+            return overlapping_rental_response();
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "message": "Se ha producido un error al actualizar el estado del pedido",
+                })),
+            );
+        }
+    }
+
+    // A single confirmation for the whole order
+    let (user_row, line_rows) = match (
+        client
+            .query_one("SELECT email, name FROM users WHERE id = $1;", &[&user_id])
+            .await,
+        client
+            .query(
+                "SELECT r.id, r.start_date, r.end_date, r.total_price, r.deposit_amount,
+                mm.name, mm.brand, mm.model, l.street, l.number, l.city
+                FROM rentals r
+                INNER JOIN machinery_units mu ON r.machine_id = mu.id
+                INNER JOIN machinery_models mm ON mu.model_id = mm.id
+                INNER JOIN locations l ON mu.location_id = l.id
+                WHERE r.order_id = $1 AND r.status = 'active'
+                ORDER BY r.id;",
+                &[&order_id],
+            )
+            .await,
+    ) {
+        (Ok(user_row), Ok(line_rows)) => (user_row, line_rows),
+        _ => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({
+                    "message": "Se ha producido un error al obtener los datos del pedido",
+                })),
+            );
+        }
+    };
+
+    let mut total_price = Decimal::ZERO;
+    let mut deposit = Decimal::ZERO;
+    let mut details = String::new();
+
+    for row in &line_rows {
+        let start_date: NaiveDate = row.get("start_date");
+        let end_date: NaiveDate = row.get("end_date");
+
+        total_price += row.get::<_, Decimal>("total_price");
+        deposit += row.get::<_, Decimal>("deposit_amount");
+
+        details.push_str(&format!(
+            "Alquiler n° {}:\t\t\t {} {} {}, {} - {}, {}, {}, {}\n",
+            row.get::<_, i32>("id"),
+            row.get::<_, String>("name"),
+            row.get::<_, String>("brand"),
+            row.get::<_, String>("model"),
+            start_date.format("%d/%m/%Y"),
+            end_date.format("%d/%m/%Y"),
+            row.get::<_, String>("city"),
+            row.get::<_, String>("street"),
+            row.get::<_, String>("number"),
+        ));
+    }
+
+    let deposit_line = if deposit > Decimal::ZERO {
+        format!(
+            "Depósito de garantía:\t\t\t $ {} (se devuelve con las máquinas)\n",
+            deposit
+        )
+    } else {
+        String::new()
+    };
+
+    let subject = format!("Pedido n° {} aprobado - Bob el Alquilador", order_id);
+    let body = format!(
+        "Hola {},\n\n\
+        Tu pedido ha sido aprobado. Adjuntamos el contrato y el recibo de cada alquiler.\n\n\
+        \n\
+        Detalles del Pedido:\n\
+        \n\n\
+        Número de pedido:\t\t\t {}\n\
+        {}\
+        Total:\t\t\t $ {}\n\
+        {}\
+        Identificador del pago:\t\t\t {}\n\
+        \n\n\
+        Gracias por confiar en nosotros.\n\n\
+        Saludos cordiales,\n\
+        El equipo de Bob el Alquilador\n",
+        user_row.get::<_, String>("name"),
+        order_id,
+        details,
+        total_price,
+        deposit_line,
+        query_params.payment_id,
+    );
+
+    let mut attachments = Vec::new();
+    for row in &line_rows {
+        match rental_attachments(&client, row.get("id")).await {
+            Ok(documents) => attachments.extend(documents),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({
+                        "message": "Se ha producido un error al generar los documentos del pedido",
+                    })),
+                );
+            }
+        }
+    }
+
+    match send_mail_with_attachments(
+        &user_row.get::<_, String>("email"),
+        &subject,
+        &body,
+        &attachments,
+    ) {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({
+                "message": "El pedido ha sido aprobado y el usuario ha sido notificado",
+            })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "message": "Se ha producido un error al enviar la notificación al usuario",
+            })),
+        ),
+    }
+}
+
+pub async fn get_order_detail(
+    State(state): State<AppState>,
+    Path(order_id): Path<i32>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let claims = match validate_jwt(&payload.access) {
+        Some(data) => data,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    }
+    .claims;
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    match get_order(&client, order_id).await {
+        // Clients only see their own orders
+        Ok(Some(order)) if claims.role != 2 || order.user_id == claims.user_id => {
+            (StatusCode::OK, Json(json!({"order": order})))
+        }
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "El pedido no se ha encontrado"})),
+        ),
+        Err(_) => internal_error(),
+    }
+}
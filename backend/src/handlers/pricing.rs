@@ -0,0 +1,385 @@
+use crate::custom_types::structs::*;
+use crate::helpers::{auth::validate_jwt, machinery_mgmt::*, pricing::*, rentals::*};
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Local;
+use serde_json::json;
+use tokio_postgres::error::SqlState;
+use validator::Validate;
+
+pub async fn get_rental_quote(
+    State(state): State<AppState>,
+    Json(payload): Json<RentalQuoteRequest>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let user_id = match validate_jwt(&payload.access) {
+        Some(token) => token.claims.user_id,
+        None => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            );
+        }
+    };
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let rules = match get_rental_rules(&client, payload.machine_id).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Se ha producido un error interno al calcular el precio"})),
+            );
+        }
+    };
+
+    if let Err(message) = check_rental_period(
+        &rules,
+        payload.start_date,
+        payload.end_date,
+        Local::now().date_naive(),
+    ) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+    }
+
+    let quote = match quote_rental(
+        &client,
+        payload.machine_id,
+        payload.start_date,
+        payload.end_date,
+    )
+    .await
+    {
+        Ok(q) => q,
+        Err(error_response) => return error_response,
+    };
+
+    match generate_quote_token(&quote, user_id) {
+        Ok((quote_token, expires_at)) => (
+            StatusCode::OK,
+            Json(json!({
+                "quote": quote,
+                "quote_token": quote_token,
+                "expires_at": expires_at,
+            })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno al generar el presupuesto"})),
+        ),
+    }
+}
+
+pub async fn get_pricing_rules(
+    State(state): State<AppState>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let internal_error = (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Failed to get the pricing rules"})),
+    );
+
+    let Ok(discount_rows) = client
+        .query(
+            "SELECT * FROM duration_discounts ORDER BY model_id NULLS FIRST, min_days;",
+            &[],
+        )
+        .await
+    else {
+        return internal_error;
+    };
+
+    let Ok(season_rows) = client
+        .query(
+            "SELECT * FROM seasonal_rates ORDER BY start_date, model_id NULLS FIRST;",
+            &[],
+        )
+        .await
+    else {
+        return internal_error;
+    };
+
+    let duration_discounts: Vec<DurationDiscount> = discount_rows
+        .iter()
+        .map(|row| DurationDiscount {
+            id: row.get("id"),
+            model_id: row.get("model_id"),
+            min_days: row.get("min_days"),
+            discount_percentage: row.get("discount_percentage"),
+        })
+        .collect();
+
+    let seasonal_rates: Vec<SeasonalRate> = season_rows
+        .iter()
+        .map(|row| SeasonalRate {
+            id: row.get("id"),
+            name: row.get("name"),
+            model_id: row.get("model_id"),
+            start_date: row.get("start_date"),
+            end_date: row.get("end_date"),
+            multiplier: row.get("multiplier"),
+        })
+        .collect();
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "duration_discounts": duration_discounts,
+            "seasonal_rates": seasonal_rates,
+        })),
+    )
+}
+
+pub async fn new_duration_discount(
+    State(state): State<AppState>,
+    Json(payload): Json<NewDurationDiscount>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid discount"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query_one(
+            "INSERT INTO duration_discounts (model_id, min_days, discount_percentage)
+            VALUES ($1, $2, $3) RETURNING id;",
+            &[
+                &payload.model_id,
+                &payload.min_days,
+                &payload.discount_percentage,
+            ],
+        )
+        .await
+    {
+        Ok(row) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "message": "Discount created successfully",
+                "id": row.get::<_, i32>("id"),
+            })),
+        ),
+        Err(e) => pricing_db_error(e, "A discount for this duration already exists"),
+    }
+}
+
+pub async fn new_seasonal_rate(
+    State(state): State<AppState>,
+    Json(payload): Json<NewSeasonalRate>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() || payload.end_date < payload.start_date {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid seasonal rate"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query_one(
+            "INSERT INTO seasonal_rates (name, model_id, start_date, end_date, multiplier)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id;",
+            &[
+                &payload.name.trim(),
+                &payload.model_id,
+                &payload.start_date,
+                &payload.end_date,
+                &payload.multiplier,
+            ],
+        )
+        .await
+    {
+        Ok(row) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "message": "Seasonal rate created successfully",
+                "id": row.get::<_, i32>("id"),
+            })),
+        ),
+        Err(e) => pricing_db_error(e, "Invalid seasonal rate"),
+    }
+}
+
+pub async fn delete_duration_discount(
+    State(state): State<AppState>,
+    Json(payload): Json<DeletePricingRule>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    delete_pricing_rule(
+        state,
+        payload,
+        "DELETE FROM duration_discounts WHERE id = $1;",
+    )
+    .await
+}
+
+pub async fn delete_seasonal_rate(
+    State(state): State<AppState>,
+    Json(payload): Json<DeletePricingRule>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    delete_pricing_rule(state, payload, "DELETE FROM seasonal_rates WHERE id = $1;").await
+}
+
+pub async fn update_base_price(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateBasePrice>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    if payload.validate().is_err() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid price"})),
+        );
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .execute(
+            "UPDATE machinery_models SET price = $1 WHERE id = $2;",
+            &[&payload.price, &payload.model_id],
+        )
+        .await
+    {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The model does not exist"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Price updated successfully"})),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to update the price"})),
+        ),
+    }
+}
+
+async fn delete_pricing_rule(
+    state: AppState,
+    payload: DeletePricingRule,
+    delete_query: &str,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client.execute(delete_query, &[&payload.id]).await {
+        Ok(0) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "The pricing rule does not exist"})),
+        ),
+        Ok(_) => (
+            StatusCode::OK,
+            Json(json!({"message": "Pricing rule deleted successfully"})),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to delete the pricing rule"})),
+        ),
+    }
+}
+
+fn pricing_db_error(
+    e: tokio_postgres::Error,
+    unique_violation_message: &str,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(db_err) = e.as_db_error() {
+        match *db_err.code() {
+            SqlState::UNIQUE_VIOLATION => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": unique_violation_message})),
+                );
+            }
+            SqlState::FOREIGN_KEY_VIOLATION => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "The model does not exist"})),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Failed to save the pricing rule"})),
+    )
+}
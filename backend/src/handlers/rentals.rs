@@ -0,0 +1,339 @@
+use crate::constants::INTERNAL_PAYMENT_ID_PREFIX;
+use crate::custom_types::{enums::*, structs::*};
+use crate::helpers::{auth::validate_jwt, machinery_mgmt::*, rentals::*};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::NaiveDateTime;
+use serde_json::json;
+
+fn internal_error() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+    )
+}
+
+pub async fn request_rental_extension(
+    State(state): State<AppState>,
+    Json(payload): Json<RequestExtension>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    let extension = match prepare_extension(
+        &transaction,
+        payload.rental_id,
+        payload.new_end_date,
+        RentalActor::Client(claims.user_id),
+    )
+    .await
+    {
+        Ok(extension) => extension,
+        Err(error_response) => return error_response,
+    };
+
+    let payment_hold_seconds = payment_hold().num_seconds() as i32;
+
+    let Ok(row) = transaction
+        .query_one(
+            "INSERT INTO rental_extensions (rental_id, previous_end_date, new_end_date, price,
+                status, payment_deadline)
+            VALUES ($1, $2, $3, $4, 'pending_payment', NOW() + $5::INT * INTERVAL '1 second')
+            RETURNING id, payment_deadline;",
+            &[
+                &payload.rental_id,
+                &extension.previous_end_date,
+                &payload.new_end_date,
+                &extension.price,
+                &payment_hold_seconds,
+            ],
+        )
+        .await
+    else {
+        return internal_error();
+    };
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "extension_id": row.get::<_, i32>("id"),
+            "rental_id": payload.rental_id,
+            "machine_id": extension.machine_id,
+            "new_end_date": payload.new_end_date,
+            "price": extension.price,
+            "payment_deadline": row.get::<_, NaiveDateTime>("payment_deadline"),
+            "payment_hold_seconds": payment_hold_seconds,
+        })),
+    )
+}
+
+pub async fn check_extension_payment(
+    State(state): State<AppState>,
+    Query(query_params): Query<CheckPayment>,
+    Json(payload): Json<ExtensionIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if matches!(query_params.status, PaymentStatus::Pending) {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"message": "El pago no ha sido aprobado ni rechazado"})),
+        );
+    }
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    let extension_row = match transaction
+        .query_opt(
+            "SELECT e.rental_id, e.previous_end_date, e.new_end_date, e.payment_id,
+                e.status = 'failed' OR COALESCE(e.payment_deadline <= NOW(), FALSE) AS expired,
+                r.status IN ('active', 'retired') AND r.end_date = e.previous_end_date
+                AND NOT EXISTS (
+                    SELECT 1 FROM rental_extensions o
+                    WHERE o.rental_id = e.rental_id AND o.id != e.id AND o.status = 'pending_payment'
+                ) AS extends_rental,
+                r.machine_id
+            FROM rental_extensions e
+            INNER JOIN rentals r ON e.rental_id = r.id
+            WHERE e.id = $1 AND r.user_id = $2 AND e.status IN ('pending_payment', 'failed')
+            FOR UPDATE OF e;",
+            &[&payload.extension_id, &claims.user_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la extensión pendiente de pago"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    if matches!(query_params.status, PaymentStatus::Rejected) {
+        if transaction
+            .execute(
+                "UPDATE rental_extensions SET status = 'failed' WHERE id = $1;",
+                &[&payload.extension_id],
+            )
+            .await
+            .is_err()
+            || transaction.commit().await.is_err()
+        {
+            return internal_error();
+        }
+
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({
+                "message": "Ha ocurrido un error en el pago por lo que no se pudo extender el alquiler.",
+            })),
+        );
+    }
+
+    let late_payment = || {
+        (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": "El plazo para pagar la extensión venció y las fechas ya no están disponibles. Se reintegrará el pago",
+            })),
+        )
+    };
+
+    // Already paid late once, and marked for refund then
+    if extension_row
+        .get::<_, Option<String>>("payment_id")
+        .is_some()
+    {
+        return late_payment();
+    }
+
+    // Once the hold is over the extra days are only kept if no other booking took them. They
+    // are lost for good once the rental has ended, or has been or is being extended otherwise
+    let days_kept = if !extension_row.get::<_, bool>("extends_rental") {
+        false
+    } else if extension_row.get::<_, bool>("expired") {
+        match lock_rental_period(
+            &transaction,
+            extension_row.get("rental_id"),
+            extension_row.get("machine_id"),
+            extension_row.get("previous_end_date"),
+            extension_row.get("new_end_date"),
+        )
+        .await
+        {
+            Ok(free) => free,
+            Err(_) => return internal_error(),
+        }
+    } else {
+        true
+    };
+
+    if !days_kept {
+        if transaction
+            .execute(
+                "UPDATE rental_extensions
+                SET status = 'failed', payment_id = $2, refund_amount = price
+                WHERE id = $1;",
+                &[&payload.extension_id, &query_params.payment_id],
+            )
+            .await
+            .is_err()
+            || transaction.commit().await.is_err()
+        {
+            return internal_error();
+        }
+
+        return late_payment();
+    }
+
+    let new_end_date = match settle_extension(
+        &transaction,
+        payload.extension_id,
+        &query_params.payment_id,
+    )
+    .await
+    {
+        Ok(new_end_date) => new_end_date,
+        Err(e) if is_overlapping_rental(&e) => return overlapping_rental_response(),
+        Err(_) => return internal_error(),
+    };
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "message": "La extensión ha sido pagada",
+            "new_end_date": new_end_date,
+        })),
+    )
+}
+
+pub async fn grant_rental_extension(
+    State(state): State<AppState>,
+    Json(payload): Json<RequestExtension>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let actor = RentalActor::new(token.claims.user_id, token.claims.role);
+
+    if !actor.is_staff() {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "message": "Solo empleados y administradores pueden acceder a esta funcionalidad",
+            })),
+        );
+    }
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    let extension =
+        match prepare_extension(&transaction, payload.rental_id, payload.new_end_date, actor).await
+        {
+            Ok(extension) => extension,
+            Err(error_response) => return error_response,
+        };
+
+    // Paid at the branch, so it never holds the days waiting for a payment
+    let Ok(row) = transaction
+        .query_one(
+            "INSERT INTO rental_extensions (rental_id, previous_end_date, new_end_date, price,
+                status, rental_employee_id)
+            VALUES ($1, $2, $3, $4, 'pending_payment', $5)
+            RETURNING id;",
+            &[
+                &payload.rental_id,
+                &extension.previous_end_date,
+                &payload.new_end_date,
+                &extension.price,
+                &token.claims.user_id,
+            ],
+        )
+        .await
+    else {
+        return internal_error();
+    };
+
+    let extension_id: i32 = row.get("id");
+    let payment_id = format!(
+        "{}-{}",
+        INTERNAL_PAYMENT_ID_PREFIX + (payload.rental_id as u32),
+        extension_id
+    );
+
+    match settle_extension(&transaction, extension_id, &payment_id).await {
+        Ok(_) => (),
+        Err(e) if is_overlapping_rental(&e) => return overlapping_rental_response(),
+        Err(_) => return internal_error(),
+    }
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "extension_id": extension_id,
+            "rental_id": payload.rental_id,
+            "machine_id": extension.machine_id,
+            "new_end_date": payload.new_end_date,
+            "price": extension.price,
+            "payment_id": payment_id,
+        })),
+    )
+}
@@ -0,0 +1,212 @@
+use crate::custom_types::{enums::SpecDataType, structs::*};
+use crate::helpers::{machinery_mgmt::*, specs::*};
+use axum::{extract::State, http::StatusCode, Json};
+use serde_json::json;
+use tokio_postgres::error::SqlState;
+
+pub async fn get_spec_definitions(
+    State(state): State<AppState>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query(
+            "SELECT id, category_id, key, name, unit, data_type::TEXT, allowed_values
+            FROM spec_definitions ORDER BY category_id, id;",
+            &[],
+        )
+        .await
+    {
+        Ok(rows) => {
+            let specs: Vec<SpecDefinition> = rows
+                .iter()
+                .map(|row| SpecDefinition {
+                    id: row.get("id"),
+                    category_id: row.get("category_id"),
+                    key: row.get("key"),
+                    name: row.get("name"),
+                    unit: row.get("unit"),
+                    data_type: row.get("data_type"),
+                    allowed_values: row.get("allowed_values"),
+                })
+                .collect();
+
+            (StatusCode::OK, Json(json!({"specs": specs})))
+        }
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to get the specs"})),
+        ),
+    }
+}
+
+pub async fn new_spec_definition(
+    State(state): State<AppState>,
+    Json(payload): Json<NewSpecDefinition>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let key = payload.key.trim().to_lowercase();
+    let name = payload.name.trim();
+
+    if key.is_empty() || name.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The spec key and name cannot be empty"})),
+        );
+    }
+
+    // Only enum specs have a closed set of values
+    let allowed_values = match payload.data_type {
+        SpecDataType::Enum => match payload.allowed_values {
+            Some(values) if !values.is_empty() => Some(values),
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "Enum specs must list their allowed values"})),
+                );
+            }
+        },
+        _ => None,
+    };
+
+    let client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    match client
+        .query_one(
+            "INSERT INTO spec_definitions (category_id, key, name, unit, data_type, allowed_values)
+            VALUES ($1, $2, $3, $4, $5::TEXT::spec_data_type, $6) RETURNING id;",
+            &[
+                &payload.category_id,
+                &key,
+                &name,
+                &payload.unit,
+                &payload.data_type.to_string(),
+                &allowed_values,
+            ],
+        )
+        .await
+    {
+        Ok(row) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "message": "Spec created successfully",
+                "id": row.get::<_, i32>("id"),
+            })),
+        ),
+        Err(e) => {
+            if let Some(db_err) = e.as_db_error() {
+                let message = match *db_err.code() {
+                    SqlState::UNIQUE_VIOLATION => Some("A spec with this key already exists"),
+                    SqlState::FOREIGN_KEY_VIOLATION => Some("The category does not exist"),
+                    SqlState::CHECK_VIOLATION => {
+                        Some("The key can only contain lowercase letters, numbers and underscores")
+                    }
+                    _ => None,
+                };
+
+                if let Some(message) = message {
+                    return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+                }
+            }
+
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to save the spec"})),
+            )
+        }
+    }
+}
+
+pub async fn update_model_specs(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateModelSpecs>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let mut client = match state.pool.get().await {
+        Ok(c) => c,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to connect to the DB"})),
+            );
+        }
+    };
+
+    let transaction = match client.transaction().await {
+        Ok(t) => t,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to create a DB transaction"})),
+            );
+        }
+    };
+
+    match transaction
+        .query_opt(
+            "SELECT id FROM machinery_models
+            WHERE id = $1 AND decommissioned_at IS NULL FOR UPDATE;",
+            &[&payload.model_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "The model does not exist"})),
+            );
+        }
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to save the specs"})),
+            );
+        }
+    }
+
+    if let Err(error_response) =
+        save_model_specs(&transaction, payload.model_id, &payload.specs).await
+    {
+        return error_response;
+    }
+
+    if transaction.commit().await.is_err() {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to commit transaction"})),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "Specs updated successfully"})),
+    )
+}
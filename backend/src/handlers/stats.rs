@@ -10,8 +10,27 @@ use axum::{
 use deadpool_postgres::GenericClient;
 use serde_json::json;
 use chrono::{Datelike, Local};
+use rust_decimal::Decimal;
 use std::collections::HashMap;
-
+use tokio_postgres::Row;
+
+/// Income comes from rentals when they are booked and from extensions when they are paid.
+/// Aliased as `rentals`, so the queries read the same for both kinds of stat.
+const INCOME_SOURCE: &str = "(
+        SELECT created_at, total_price, rental_employee_id, machine_id FROM rentals
+        UNION ALL
+        SELECT e.paid_at, e.price, e.rental_employee_id, r.machine_id
+        FROM rental_extensions e
+        INNER JOIN rentals r ON e.rental_id = r.id
+        WHERE e.status = 'paid'
+    ) AS rentals";
+
+fn stat_value(row: &Row, stat_type: &StatType) -> StatValue {
+    match stat_type {
+        StatType::Rentals => StatValue::Count(row.get("value")),
+        StatType::Income => StatValue::Amount(row.get("value")),
+    }
+}
 
 pub async fn get_stats_by_month(state: AppState, payload: GetStats) -> Response {
     let client = match state.pool.get().await {
@@ -27,9 +46,9 @@ pub async fn get_stats_by_month(state: AppState, payload: GetStats) -> Response
 
     let year = payload.year.unwrap_or_else(|| Local::now().year());
 
-    let select_clause = match payload.stat_type {
-        StatType::Rentals => "COUNT(*)::float",
-        StatType::Income => "SUM(total_price)::float",
+    let (select_clause, source) = match payload.stat_type {
+        StatType::Rentals => ("COUNT(*)", "rentals"),
+        StatType::Income => ("SUM(total_price)", INCOME_SOURCE),
     };
 
     let query = format!(
@@ -37,7 +56,7 @@ pub async fn get_stats_by_month(state: AppState, payload: GetStats) -> Response
         SELECT
             EXTRACT(MONTH FROM created_at)::int AS month,
             {select_clause} AS value
-        FROM rentals
+        FROM {source}
         WHERE EXTRACT(YEAR FROM created_at)::int = $1
         GROUP BY month;
         "
@@ -47,19 +66,24 @@ pub async fn get_stats_by_month(state: AppState, payload: GetStats) -> Response
 
     match result {
         Ok(rows) => {
+            let zero = match payload.stat_type {
+                StatType::Rentals => StatValue::Count(0),
+                StatType::Income => StatValue::Amount(Decimal::ZERO),
+            };
+
             let mut data = HashMap::from([
-                ("january", 0.0),
-                ("february", 0.0),
-                ("march", 0.0),
-                ("april", 0.0),
-                ("may", 0.0),
-                ("june", 0.0),
-                ("july", 0.0),
-                ("august", 0.0),
-                ("september", 0.0),
-                ("october", 0.0),
-                ("november", 0.0),
-                ("december", 0.0),
+                ("january", zero),
+                ("february", zero),
+                ("march", zero),
+                ("april", zero),
+                ("may", zero),
+                ("june", zero),
+                ("july", zero),
+                ("august", zero),
+                ("september", zero),
+                ("october", zero),
+                ("november", zero),
+                ("december", zero),
             ]);
 
             let month_names = [
@@ -79,7 +103,7 @@ pub async fn get_stats_by_month(state: AppState, payload: GetStats) -> Response
 
             for row in rows {
                 let month: i32 = row.get("month");
-                let value: f64 = row.get("value");
+                let value = stat_value(&row, &payload.stat_type);
                 if let Some(name) = month_names.get((month - 1) as usize) {
                     data.insert(name, value);
                 }
@@ -107,9 +131,9 @@ pub async fn get_stats_by_employee(state: AppState, payload: GetStats) -> Respon
         }
     };
 
-    let value_expr = match payload.stat_type {
-        StatType::Rentals => "COUNT(*)::float",
-        StatType::Income => "SUM(total_price)::float",
+    let (value_expr, source) = match payload.stat_type {
+        StatType::Rentals => ("COUNT(*)", "rentals"),
+        StatType::Income => ("SUM(total_price)", INCOME_SOURCE),
     };
 
     let order_expr = payload.order.unwrap_or(StatOrder::Desc);
@@ -119,7 +143,7 @@ pub async fn get_stats_by_employee(state: AppState, payload: GetStats) -> Respon
         SELECT
             users.name || ' ' || users.surname AS name,
             {value_expr} AS value
-        FROM rentals
+        FROM {source}
         JOIN users ON rentals.rental_employee_id = users.id
         "
     );
@@ -152,7 +176,7 @@ pub async fn get_stats_by_employee(state: AppState, payload: GetStats) -> Respon
                 .into_iter()
                 .map(|row| NameValue {
                     name: row.get("name"),
-                    value: row.get("value"),
+                    value: stat_value(&row, &payload.stat_type),
                 })
                 .collect();
 
@@ -178,9 +202,9 @@ pub async fn get_stats_by_category(state: AppState, payload: GetStats) -> Respon
         }
     };
 
-    let value_expr = match payload.stat_type {
-        StatType::Rentals => "COUNT(*)::float",
-        StatType::Income => "SUM(rentals.total_price)::float",
+    let (value_expr, source) = match payload.stat_type {
+        StatType::Rentals => ("COUNT(*)", "rentals"),
+        StatType::Income => ("SUM(rentals.total_price)", INCOME_SOURCE),
     };
 
     let order_expr = payload.order.unwrap_or(StatOrder::Desc);
@@ -190,7 +214,7 @@ pub async fn get_stats_by_category(state: AppState, payload: GetStats) -> Respon
         SELECT
             categories.name AS name,
             {value_expr} AS value
-        FROM rentals
+        FROM {source}
         JOIN machinery_units ON rentals.machine_id = machinery_units.id
         JOIN machinery_categories ON machinery_units.model_id = machinery_categories.model_id
         JOIN categories ON machinery_categories.category_id = categories.id
@@ -226,7 +250,7 @@ pub async fn get_stats_by_category(state: AppState, payload: GetStats) -> Respon
                 .into_iter()
                 .map(|row| NameValue {
                     name: row.get("name"),
-                    value: row.get("value"),
+                    value: stat_value(&row, &payload.stat_type),
                 })
                 .collect();
 
@@ -0,0 +1,480 @@
+use crate::custom_types::structs::*;
+use crate::helpers::{
+    auth::validate_jwt, machinery_mgmt::validate_admin, transfers::*, waitlist::reevaluate_waitlist,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{Local, NaiveDate};
+use serde_json::json;
+
+fn internal_error() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Failed to execute transaction"})),
+    )
+}
+
+fn transfer_not_found(status: &str) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"message": format!("There is no {} transfer with that id", status)})),
+    )
+}
+
+fn validate_staff(access_token: &str) -> Result<i32, (StatusCode, Json<serde_json::Value>)> {
+    let Some(token) = validate_jwt(access_token) else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        ));
+    };
+
+    if token.claims.role == 2 {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({"message": "Not enough permissions"})),
+        ));
+    }
+
+    Ok(token.claims.user_id)
+}
+
+pub async fn new_transfer(
+    State(state): State<AppState>,
+    Json(payload): Json<NewTransfer>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    if payload.departure_date < Local::now().date_naive()
+        || payload.expected_arrival_date < payload.departure_date
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": "The departure can't be in the past and the arrival can't be before it",
+            })),
+        );
+    }
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    let unit = match transaction
+        .query_opt(
+            "SELECT status::TEXT, location_id FROM machinery_units WHERE id = $1 FOR UPDATE;",
+            &[&payload.unit_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "The unit does not exist"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    if unit.get::<_, String>("status") == "decommissioned" {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The unit was decommissioned"})),
+        );
+    }
+
+    if unit.get::<_, i32>("location_id") == payload.to_location_id {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "The unit is already at that location"})),
+        );
+    }
+
+    match transaction
+        .query_opt(
+            "SELECT 1 FROM locations WHERE id = $1;",
+            &[&payload.to_location_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "The location does not exist"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    match transaction
+        .query_opt(
+            "SELECT id FROM unit_transfers
+            WHERE unit_id = $1 AND status IN ('requested', 'in_transit');",
+            &[&payload.unit_id],
+        )
+        .await
+    {
+        Ok(None) => (),
+        Ok(Some(row)) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "The unit already has an open transfer",
+                    "transfer_id": row.get::<_, i32>("id"),
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    // Bookings at the current branch must be over, turnaround included, before the unit leaves
+    let bookings = match transaction
+        .query(
+            "SELECT b.rental_id FROM unit_bookings b
+            INNER JOIN machinery_units mu ON b.machine_id = mu.id
+            INNER JOIN machinery_models mm ON mu.model_id = mm.id
+            WHERE b.machine_id = $1 AND b.end_date + mm.turnaround_days >= $2;",
+            &[&payload.unit_id, &payload.departure_date],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return internal_error(),
+    };
+
+    if !bookings.is_empty() {
+        let rental_ids: Vec<i32> = bookings
+            .iter()
+            .filter_map(|row| row.get::<_, Option<i32>>("rental_id"))
+            .collect();
+
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": "The unit is booked at its current location after the departure date",
+                "rental_ids": rental_ids,
+            })),
+        );
+    }
+
+    let transfer_id: i32 = match transaction
+        .query_one(
+            "INSERT INTO unit_transfers (unit_id, from_location_id, to_location_id,
+            departure_date, expected_arrival_date, notes, requested_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id;",
+            &[
+                &payload.unit_id,
+                &unit.get::<_, i32>("location_id"),
+                &payload.to_location_id,
+                &payload.departure_date,
+                &payload.expected_arrival_date,
+                &payload.notes,
+                &token.claims.user_id,
+            ],
+        )
+        .await
+    {
+        Ok(row) => row.get("id"),
+        Err(_) => return internal_error(),
+    };
+
+    let Ok(Some(transfer)) = get_transfer(&transaction, transfer_id).await else {
+        return internal_error();
+    };
+
+    if transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(json!({
+            "message": "Transfer requested successfully",
+            "transfer": transfer,
+        })),
+    )
+}
+
+pub async fn list_transfers(
+    State(state): State<AppState>,
+    Json(payload): Json<GetTransfers>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    match get_transfers(&client, payload.status, payload.location_id).await {
+        Ok(transfers) => (StatusCode::OK, Json(json!({"transfers": transfers}))),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to get the transfers"})),
+        ),
+    }
+}
+
+pub async fn ship_transfer(
+    State(state): State<AppState>,
+    Json(payload): Json<TransferIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let employee_id = match validate_staff(&payload.access) {
+        Ok(user_id) => user_id,
+        Err(invalid_response) => return invalid_response,
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let Ok(transaction) = client.transaction().await else {
+        return internal_error();
+    };
+
+    let transfer = match transaction
+        .query_opt(
+            "SELECT t.unit_id, t.departure_date, mu.status::TEXT AS unit_status, l.city
+            FROM unit_transfers t
+            INNER JOIN machinery_units mu ON mu.id = t.unit_id
+            INNER JOIN locations l ON l.id = t.to_location_id
+            WHERE t.id = $1 AND t.status = 'requested'
+            FOR UPDATE OF t, mu;",
+            &[&payload.transfer_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => return transfer_not_found("requested"),
+        Err(_) => return internal_error(),
+    };
+
+    let unit_id: i32 = transfer.get("unit_id");
+    let unit_status: String = transfer.get("unit_status");
+    let departure_date: NaiveDate = transfer.get("departure_date");
+
+    if departure_date > Local::now().date_naive() {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": format!("The transfer departs on {}", departure_date),
+            })),
+        );
+    }
+
+    if unit_status == "rented" {
+        return (
+            StatusCode::CONFLICT,
+            Json(json!({"message": "The unit hasn't been returned yet"})),
+        );
+    }
+
+    let description = format!(
+        "Traslado n° {} hacia {}",
+        payload.transfer_id,
+        transfer
+            .get::<_, Option<String>>("city")
+            .unwrap_or_default()
+    );
+
+    let shipped = async {
+        transaction
+            .execute(
+                "UPDATE unit_transfers
+                SET status = 'in_transit', shipped_by = $2, shipped_at = NOW()
+                WHERE id = $1;",
+                &[&payload.transfer_id, &employee_id],
+            )
+            .await?;
+
+        transaction
+            .execute(
+                "UPDATE machinery_units SET status = 'in_transit' WHERE id = $1;",
+                &[&unit_id],
+            )
+            .await?;
+
+        record_transfer_event(
+            &transaction,
+            unit_id,
+            &description,
+            &unit_status,
+            "in_transit",
+        )
+        .await
+    }
+    .await;
+
+    if shipped.is_err() || transaction.commit().await.is_err() {
+        return internal_error();
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "The unit is in transit"})),
+    )
+}
+
+/// Moves the unit to the destination of the transfer and closes its stay at the origin in the
+/// location history.
+pub async fn receive_transfer(
+    State(state): State<AppState>,
+    Json(payload): Json<TransferIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let employee_id = match validate_staff(&payload.access) {
+        Ok(user_id) => user_id,
+        Err(invalid_response) => return invalid_response,
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let unit_id = {
+        let Ok(transaction) = client.transaction().await else {
+            return internal_error();
+        };
+
+        let transfer = match transaction
+            .query_opt(
+                "SELECT t.unit_id, t.to_location_id, mu.location_id, mu.assigned_at, l.city
+                FROM unit_transfers t
+                INNER JOIN machinery_units mu ON mu.id = t.unit_id
+                INNER JOIN locations l ON l.id = t.to_location_id
+                WHERE t.id = $1 AND t.status = 'in_transit'
+                FOR UPDATE OF t, mu;",
+                &[&payload.transfer_id],
+            )
+            .await
+        {
+            Ok(Some(row)) => row,
+            Ok(None) => return transfer_not_found("in transit"),
+            Err(_) => return internal_error(),
+        };
+
+        let unit_id: i32 = transfer.get("unit_id");
+        let description = format!(
+            "Traslado n° {} recibido en {}",
+            payload.transfer_id,
+            transfer
+                .get::<_, Option<String>>("city")
+                .unwrap_or_default()
+        );
+
+        let received = async {
+            transaction
+                .execute(
+                    "INSERT INTO machinery_location_history
+                    (unit_id, location_id, assigned_at, unassigned_at) VALUES
+                    ($1, $2, $3, NOW());",
+                    &[
+                        &unit_id,
+                        &transfer.get::<_, i32>("location_id"),
+                        &transfer.get::<_, chrono::NaiveDateTime>("assigned_at"),
+                    ],
+                )
+                .await?;
+
+            transaction
+                .execute(
+                    "UPDATE machinery_units
+                    SET status = 'available', location_id = $2, assigned_at = NOW()
+                    WHERE id = $1;",
+                    &[&unit_id, &transfer.get::<_, i32>("to_location_id")],
+                )
+                .await?;
+
+            transaction
+                .execute(
+                    "UPDATE unit_transfers
+                    SET status = 'received', received_by = $2, received_at = NOW()
+                    WHERE id = $1;",
+                    &[&payload.transfer_id, &employee_id],
+                )
+                .await?;
+
+            record_transfer_event(
+                &transaction,
+                unit_id,
+                &description,
+                "in_transit",
+                "available",
+            )
+            .await
+        }
+        .await;
+
+        if received.is_err() || transaction.commit().await.is_err() {
+            return internal_error();
+        }
+
+        unit_id
+    };
+
+    // The unit may be what someone in the waitlist of the new branch is waiting for
+    reevaluate_waitlist(&mut client, &[unit_id]).await;
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "The unit was received at its new location"})),
+    )
+}
+
+pub async fn cancel_transfer(
+    State(state): State<AppState>,
+    Json(payload): Json<TransferIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_admin(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(token) = validate_jwt(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    // Once shipped the unit has to be received somewhere
+    let row = match client
+        .query_opt(
+            "UPDATE unit_transfers
+            SET status = 'cancelled', cancelled_by = $2, cancelled_at = NOW()
+            WHERE id = $1 AND status = 'requested'
+            RETURNING unit_id;",
+            &[&payload.transfer_id, &token.claims.user_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => return transfer_not_found("requested"),
+        Err(_) => return internal_error(),
+    };
+
+    reevaluate_waitlist(&mut client, &[row.get("unit_id")]).await;
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "Transfer cancelled successfully"})),
+    )
+}
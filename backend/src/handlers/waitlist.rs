@@ -0,0 +1,229 @@
+use crate::custom_types::structs::*;
+use crate::helpers::{machinery_mgmt::*, rentals::*, waitlist::*};
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Local;
+use serde_json::json;
+
+fn internal_error() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+    )
+}
+
+pub async fn join_waitlist(
+    State(state): State<AppState>,
+    Json(payload): Json<JoinWaitlist>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let rules = match get_model_rental_rules(&client, payload.model_id).await {
+        Ok(Some(rules)) => rules,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    if let Err(message) = check_rental_period(
+        &rules,
+        payload.start_date,
+        payload.end_date,
+        Local::now().date_naive(),
+    ) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"message": message})));
+    }
+
+    match client
+        .query_opt(
+            "SELECT 1 FROM machinery_units
+            WHERE model_id = $1 AND location_id = $2 AND status != 'decommissioned'
+            LIMIT 1;",
+            &[&payload.model_id, &payload.location_id],
+        )
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "message": "No se han encontrado unidades de la máquina en la ubicación solicitada",
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    match client
+        .query_opt(
+            "SELECT 1 FROM waitlist_entries
+            WHERE user_id = $1 AND model_id = $2 AND location_id = $3
+            AND status IN ('waiting', 'notified')
+            AND start_date <= $5 AND $4 <= end_date;",
+            &[
+                &claims.user_id,
+                &payload.model_id,
+                &payload.location_id,
+                &payload.start_date,
+                &payload.end_date,
+            ],
+        )
+        .await
+    {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "Ya se encuentra en la lista de espera de la máquina para esas fechas",
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    // Nothing to wait for when a unit can be booked right away
+    match assign_unit(
+        &client,
+        payload.model_id,
+        payload.location_id,
+        payload.start_date,
+        payload.end_date,
+    )
+    .await
+    {
+        Ok(None) => (),
+        Ok(Some(_)) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({
+                    "message": "Hay ejemplares disponibles en la sucursal para las fechas indicadas. Puede reservarlos directamente",
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    }
+
+    let entry_id: i32 = match client
+        .query_one(
+            "INSERT INTO waitlist_entries (user_id, model_id, location_id, start_date, end_date)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id;",
+            &[
+                &claims.user_id,
+                &payload.model_id,
+                &payload.location_id,
+                &payload.start_date,
+                &payload.end_date,
+            ],
+        )
+        .await
+    {
+        Ok(row) => row.get("id"),
+        Err(_) => return internal_error(),
+    };
+
+    match get_waitlist_entry(&client, entry_id).await {
+        Ok(Some(entry)) => (
+            StatusCode::CREATED,
+            Json(json!({
+                "message": "Se ha unido a la lista de espera. Le avisaremos por correo cuando se libere un ejemplar",
+                "entry": entry,
+            })),
+        ),
+        _ => internal_error(),
+    }
+}
+
+pub async fn get_waitlist(
+    State(state): State<AppState>,
+    Json(payload): Json<Access>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let Ok(client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    match get_waitlist_entries(&client, claims.user_id).await {
+        Ok(entries) => (StatusCode::OK, Json(json!({"entries": entries}))),
+        Err(_) => internal_error(),
+    }
+}
+
+pub async fn leave_waitlist(
+    State(state): State<AppState>,
+    Json(payload): Json<WaitlistEntryIdAndToken>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if let Some(invalid_response) = validate_client(&payload.access) {
+        return invalid_response;
+    }
+
+    let Some(claims) = get_claims_from_token(&payload.access) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"message": "Invalid access token"})),
+        );
+    };
+
+    let Ok(mut client) = state.pool.get().await else {
+        return internal_error();
+    };
+
+    let row = match client
+        .query_opt(
+            "UPDATE waitlist_entries SET status = 'cancelled'
+            WHERE id = $1 AND user_id = $2 AND status IN ('waiting', 'notified')
+            RETURNING unit_id;",
+            &[&payload.entry_id, &claims.user_id],
+        )
+        .await
+    {
+        Ok(Some(row)) => row,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({
+                    "message": "No se ha encontrado la inscripción en la lista de espera",
+                })),
+            );
+        }
+        Err(_) => return internal_error(),
+    };
+
+    // The unit held for the client goes to the next one in line
+    if let Some(unit_id) = row.get::<_, Option<i32>>("unit_id") {
+        reevaluate_waitlist(&mut client, &[unit_id]).await;
+    }
+
+    (
+        StatusCode::OK,
+        Json(json!({"message": "Se ha dado de baja de la lista de espera"})),
+    )
+}
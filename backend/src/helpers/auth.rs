@@ -1,6 +1,10 @@
-use chrono::{Datelike, NaiveDate, Utc, Duration};
+use crate::constants::*;
+use crate::custom_types::{enums::RunningEnv, structs::Claims};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use deadpool_postgres::{Manager, Pool};
 use hex;
-use lettre::message::{Mailbox, SinglePart};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use lettre::message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart};
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::{Message, SmtpTransport, Transport};
 use rand::distr::Alphanumeric;
@@ -8,10 +12,6 @@ use rand::{rng, Rng};
 use sha2::{Digest, Sha256};
 use std::env;
 use tokio_postgres::NoTls;
-use deadpool_postgres::{Pool, Manager};
-use jsonwebtoken::{decode, DecodingKey, Validation, TokenData, encode, Header, EncodingKey};
-use crate::custom_types::{structs::Claims, enums::RunningEnv};
-use crate::constants::*;
 
 pub fn generate_random_string(lenght: usize) -> String {
     let random_string: String = rng()
@@ -29,10 +29,13 @@ pub fn create_2fa_code() -> u32 {
 }
 
 pub async fn create_pool(running_env: RunningEnv) -> Pool {
-
     let database_url = match running_env {
-        RunningEnv::Production => env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file"),
-        RunningEnv::Testing => env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set in .env file"),
+        RunningEnv::Production => {
+            env::var("DATABASE_URL").expect("DATABASE_URL must be set in .env file")
+        }
+        RunningEnv::Testing => {
+            env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set in .env file")
+        }
     };
 
     let pg_config: tokio_postgres::Config = database_url.parse().expect("Invalid database URL");
@@ -42,7 +45,6 @@ pub async fn create_pool(running_env: RunningEnv) -> Pool {
         .max_size(10)
         .build()
         .expect("Failed to create pool")
-
 }
 
 pub fn is_adult(birth_date: NaiveDate) -> bool {
@@ -65,6 +67,16 @@ pub fn encode_password(password: &str, salt: &str) -> String {
 }
 
 pub fn send_mail(address: &str, subject: &str, body: &str) -> Result<(), String> {
+    send_mail_with_attachments(address, subject, body, &[])
+}
+
+/// Sends a plain text email with the given PDF files attached, as (file name, content) pairs.
+pub fn send_mail_with_attachments(
+    address: &str,
+    subject: &str,
+    body: &str,
+    attachments: &[(String, Vec<u8>)],
+) -> Result<(), String> {
     let email_address = env::var("EMAIL").map_err(|e| e.to_string())?;
     let app_password = env::var("APP_PASSWORD").map_err(|e| e.to_string())?;
 
@@ -75,9 +87,22 @@ pub fn send_mail(address: &str, subject: &str, body: &str) -> Result<(), String>
         ))
         .reply_to(email_address.parse().map_err(|_| "Email parsing failed")?)
         .to(address.parse().map_err(|_| "Email parsing failed")?)
-        .subject(subject)
-        .singlepart(SinglePart::plain(body.to_string()))
-        .map_err(|e| e.to_string())?;
+        .subject(subject);
+
+    let email = if attachments.is_empty() {
+        email.singlepart(SinglePart::plain(body.to_string()))
+    } else {
+        let pdf = ContentType::parse("application/pdf").map_err(|e| e.to_string())?;
+        let parts = attachments.iter().fold(
+            MultiPart::mixed().singlepart(SinglePart::plain(body.to_string())),
+            |parts, (file_name, content)| {
+                let attachment = Attachment::new(file_name.clone());
+                parts.singlepart(attachment.body(content.clone(), pdf.clone()))
+            },
+        );
+        email.multipart(parts)
+    }
+    .map_err(|e| e.to_string())?;
 
     let creds = Credentials::new(email_address.clone(), app_password);
 
@@ -92,18 +117,22 @@ pub fn send_mail(address: &str, subject: &str, body: &str) -> Result<(), String>
     }
 }
 
+/// Session tokens carry no audience, so quote and calendar feed tokens, which do, are turned down.
 pub fn validate_jwt(jwt: &str) -> Option<TokenData<Claims>> {
-    let secret_key = env::var("JWT_SECRET_KEY").expect("JTW_SECRET_KEY must be set in the .env file");
+    let secret_key =
+        env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
 
     decode::<Claims>(
         jwt,
         &DecodingKey::from_secret(secret_key.as_ref()),
         &Validation::default(),
-    ).ok()
+    )
+    .ok()
 }
 
 pub fn generate_jwt(user_id: i32, role: i16, is_refresh: bool) -> Result<String, ()> {
-    let secret_key = env::var("JWT_SECRET_KEY").expect("JTW_SECRET_KEY must be set in the .env file");
+    let secret_key =
+        env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
 
     let exp_option = if is_refresh {
         Utc::now().checked_add_signed(Duration::days(REFRESH_EXPIRATION_DAYS))
@@ -126,5 +155,10 @@ pub fn generate_jwt(user_id: i32, role: i16, is_refresh: bool) -> Result<String,
         nonce,
     };
 
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(secret_key.as_ref())).map_err(|_| ())
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret_key.as_ref()),
+    )
+    .map_err(|_| ())
 }
@@ -0,0 +1,422 @@
+use crate::constants::{
+    CALENDAR_FEED_PAST_DAYS, CALENDAR_FEED_TOKEN_AUDIENCE, CALENDAR_UID_DOMAIN, COMPANY_NAME,
+};
+use crate::custom_types::{enums::RentalStatus, structs::*};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, Utc};
+use deadpool_postgres::GenericClient;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use std::env;
+
+const RENTAL_QUERY: &str = "SELECT r.id, r.start_date, r.end_date, r.status::TEXT,
+    mm.name AS model_name, mm.brand, mm.model, mu.serial_number,
+    u.name || ' ' || u.surname AS client_name, ui.phone,
+    CONCAT_WS(', ', CONCAT_WS(' ', l.street, l.number), l.city) AS branch_address,
+    (SELECT COUNT(*) FROM rental_status_history h WHERE h.rental_id = r.id)
+    + (SELECT COUNT(*) FROM rental_extensions e WHERE e.rental_id = r.id AND e.status = 'paid')
+    AS sequence
+    FROM rentals r
+    INNER JOIN machinery_units mu ON r.machine_id = mu.id
+    INNER JOIN machinery_models mm ON mu.model_id = mm.id
+    INNER JOIN locations l ON mu.location_id = l.id
+    INNER JOIN users u ON r.user_id = u.id
+    LEFT JOIN user_info ui ON u.id = ui.id";
+
+/// Feed tokens don't expire, revoking the feed is what disables them.
+pub fn generate_feed_token(feed: &CalendarFeed) -> Result<String, ()> {
+    let secret_key =
+        env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
+
+    let claims = CalendarFeedClaims {
+        aud: CALENDAR_FEED_TOKEN_AUDIENCE.to_string(),
+        feed_id: feed.id,
+        kind: feed.kind.clone(),
+        subject_id: feed.subject_id,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret_key.as_ref()),
+    )
+    .map_err(|_| ())
+}
+
+pub fn validate_feed_token(token: &str) -> Option<CalendarFeedClaims> {
+    let secret_key =
+        env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
+
+    let mut validation = Validation::default();
+    validation.set_audience(&[CALENDAR_FEED_TOKEN_AUDIENCE]);
+    validation.set_required_spec_claims(&["aud"]);
+    validation.validate_exp = false;
+
+    decode::<CalendarFeedClaims>(
+        token,
+        &DecodingKey::from_secret(secret_key.as_ref()),
+        &validation,
+    )
+    .ok()
+    .map(|data| data.claims)
+}
+
+/// Sets the URL of a feed that hasn't been revoked.
+pub fn with_feed_url(mut feed: CalendarFeed) -> Result<CalendarFeed, ()> {
+    if feed.revoked_at.is_none() {
+        let token = generate_feed_token(&feed)?;
+        let backend_url = env::var("BACKEND_URL").unwrap_or_default();
+
+        feed.url = Some(format!("{}/calendar/{}", backend_url, token));
+    }
+
+    Ok(feed)
+}
+
+pub async fn get_calendar_feed(
+    client: &impl GenericClient,
+    feed_id: i32,
+) -> Result<Option<CalendarFeed>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT id, kind::TEXT, subject_id, created_by, created_at, revoked_at
+            FROM calendar_feeds WHERE id = $1;",
+            &[&feed_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(CalendarFeed::build_from_row))
+}
+
+fn rental_status_label(status: &str) -> &str {
+    match status {
+        "pending_payment" => "pendiente de pago",
+        "active" => "confirmado",
+        "retired" => "retirado",
+        "completed" => "devuelto",
+        "cancelled" => "cancelado",
+        _ => status,
+    }
+}
+
+fn rental_event_status(status: &str) -> &'static str {
+    match status {
+        "pending_payment" => "TENTATIVE",
+        "cancelled" => "CANCELLED",
+        _ => "CONFIRMED",
+    }
+}
+
+fn machine_label(row: &tokio_postgres::Row) -> String {
+    format!(
+        "{} {} {} ({})",
+        row.get::<_, String>("model_name"),
+        row.get::<_, String>("brand"),
+        row.get::<_, String>("model"),
+        row.get::<_, String>("serial_number"),
+    )
+}
+
+/// Whole days of a rental, from its start date to its end date.
+fn rental_event(
+    row: &tokio_postgres::Row,
+    uid: String,
+    summary: String,
+    description: String,
+) -> CalendarEvent {
+    let start_date: NaiveDate = row.get("start_date");
+    let end_date: NaiveDate = row.get("end_date");
+
+    CalendarEvent {
+        uid,
+        summary,
+        description,
+        location: Some(row.get("branch_address")),
+        start: start_date.and_hms_opt(0, 0, 0).unwrap(),
+        end: (end_date + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap(),
+        all_day: true,
+        status: rental_event_status(&row.get::<_, String>("status")),
+        sequence: row.get("sequence"),
+    }
+}
+
+/// Every rental of a client that wasn't left unpaid.
+pub async fn client_events(
+    client: &impl GenericClient,
+    user_id: i32,
+) -> Result<Vec<CalendarEvent>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            &format!(
+                "{} WHERE r.user_id = $1 AND r.status != 'failed'
+                ORDER BY r.start_date, r.id;",
+                RENTAL_QUERY
+            ),
+            &[&user_id],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let rental_id: i32 = row.get("id");
+            let status: String = row.get("status");
+
+            rental_event(
+                row,
+                format!("rental-{}@{}", rental_id, CALENDAR_UID_DOMAIN),
+                format!("Alquiler n° {}: {}", rental_id, machine_label(row)),
+                format!("Estado del alquiler: {}", rental_status_label(&status)),
+            )
+        })
+        .collect())
+}
+
+/// Rentals of a unit and the periods it spent in maintenance, according to its history.
+pub async fn unit_events(
+    client: &impl GenericClient,
+    unit_id: i32,
+) -> Result<Vec<CalendarEvent>, tokio_postgres::Error> {
+    let rental_rows = client
+        .query(
+            &format!(
+                "{} WHERE r.machine_id = $1 AND r.status != 'failed'
+                AND r.end_date >= CURRENT_DATE - $2::INT
+                ORDER BY r.start_date, r.id;",
+                RENTAL_QUERY
+            ),
+            &[&unit_id, &CALENDAR_FEED_PAST_DAYS],
+        )
+        .await?;
+
+    let mut events: Vec<CalendarEvent> = rental_rows
+        .iter()
+        .map(|row| {
+            let rental_id: i32 = row.get("id");
+            let status: String = row.get("status");
+
+            rental_event(
+                row,
+                format!("rental-{}@{}", rental_id, CALENDAR_UID_DOMAIN),
+                format!(
+                    "Alquiler n° {}: {}",
+                    rental_id,
+                    row.get::<_, String>("client_name")
+                ),
+                format!("Estado del alquiler: {}", rental_status_label(&status)),
+            )
+        })
+        .collect();
+
+    // A maintenance period lasts until the next change of status of the unit
+    let maintenance_rows = client
+        .query(
+            "SELECT id, description, started_at, ended_at FROM (
+                SELECT id, description, new_status, created_at AS started_at,
+                    LEAD(created_at) OVER (ORDER BY created_at, id) AS ended_at
+                FROM unit_history_events WHERE unit_id = $1
+            ) e
+            WHERE new_status = 'maintenance'
+            AND (ended_at IS NULL OR ended_at >= CURRENT_DATE - $2::INT)
+            ORDER BY started_at, id;",
+            &[&unit_id, &CALENDAR_FEED_PAST_DAYS],
+        )
+        .await?;
+
+    events.extend(maintenance_rows.iter().map(|row| {
+        let ended_at: Option<NaiveDateTime> = row.get("ended_at");
+
+        CalendarEvent {
+            uid: format!(
+                "maintenance-{}@{}",
+                row.get::<_, i32>("id"),
+                CALENDAR_UID_DOMAIN
+            ),
+            summary: match ended_at {
+                Some(_) => "Mantenimiento".to_string(),
+                None => "Mantenimiento (en curso)".to_string(),
+            },
+            description: row
+                .get::<_, Option<String>>("description")
+                .unwrap_or_default(),
+            location: None,
+            start: row.get("started_at"),
+            end: ended_at.unwrap_or_else(|| Local::now().naive_local()),
+            all_day: false,
+            status: "CONFIRMED",
+            sequence: i64::from(ended_at.is_some()),
+        }
+    }));
+
+    Ok(events)
+}
+
+/// Retirements of the confirmed rentals of a branch's units and returns of the ones still out.
+pub async fn branch_events(
+    client: &impl GenericClient,
+    location_id: i32,
+) -> Result<Vec<CalendarEvent>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            &format!(
+                "{} WHERE mu.location_id = $1 AND r.status IN ('active', 'retired')
+                AND r.end_date >= CURRENT_DATE - $2::INT
+                ORDER BY r.start_date, r.id;",
+                RENTAL_QUERY
+            ),
+            &[&location_id, &CALENDAR_FEED_PAST_DAYS],
+        )
+        .await?;
+
+    let mut events = Vec::new();
+
+    for row in &rows {
+        let rental_id: i32 = row.get("id");
+        let status: String = row.get("status");
+        let description = format!(
+            "Alquiler n° {}. Cliente: {}{}",
+            rental_id,
+            row.get::<_, String>("client_name"),
+            row.get::<_, Option<String>>("phone")
+                .map(|phone| format!(", teléfono {}", phone))
+                .unwrap_or_default(),
+        );
+
+        // Retired rentals only have their return left
+        let mut days: Vec<(&str, &str, NaiveDate)> = Vec::new();
+        if status == RentalStatus::Active.to_string() {
+            days.push(("retirement", "Retiro", row.get("start_date")));
+        }
+        days.push(("return", "Devolución", row.get("end_date")));
+
+        for (kind, label, date) in days {
+            events.push(CalendarEvent {
+                uid: format!("rental-{}-{}@{}", rental_id, kind, CALENDAR_UID_DOMAIN),
+                summary: format!("{}: {}", label, machine_label(row)),
+                description: description.clone(),
+                location: None,
+                start: date.and_hms_opt(0, 0, 0).unwrap(),
+                end: (date + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap(),
+                all_day: true,
+                status: "CONFIRMED",
+                sequence: row.get("sequence"),
+            });
+        }
+    }
+
+    Ok(events)
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Lines longer than 75 octets continue on the next one after a space (RFC 5545, 3.1).
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+fn format_event_time(time: NaiveDateTime, all_day: bool) -> String {
+    if all_day {
+        format!(";VALUE=DATE:{}", time.format("%Y%m%d"))
+    } else {
+        format!(":{}", time.format("%Y%m%dT%H%M%S"))
+    }
+}
+
+pub fn render_calendar(name: &str, events: &[CalendarEvent]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//{}//Alquileres//ES", COMPANY_NAME),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!(
+            "DTSTART{}",
+            format_event_time(event.start, event.all_day)
+        ));
+        lines.push(format!(
+            "DTEND{}",
+            format_event_time(event.end, event.all_day)
+        ));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if !event.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape_text(&event.description)));
+        }
+        if let Some(location) = &event.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        lines.push(format!("STATUS:{}", event.status));
+        lines.push(format!("SEQUENCE:{}", event.sequence));
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+pub async fn render_feed(
+    client: &impl GenericClient,
+    feed: &CalendarFeed,
+) -> Result<String, tokio_postgres::Error> {
+    let (name, events) = match feed.kind.as_str() {
+        "client" => (
+            "Mis alquileres".to_string(),
+            client_events(client, feed.subject_id).await?,
+        ),
+        "unit" => {
+            let row = client
+                .query_one(
+                    "SELECT serial_number FROM machinery_units WHERE id = $1;",
+                    &[&feed.subject_id],
+                )
+                .await?;
+
+            (
+                format!("Unidad {}", row.get::<_, String>("serial_number")),
+                unit_events(client, feed.subject_id).await?,
+            )
+        }
+        _ => {
+            let row = client
+                .query_one(
+                    "SELECT CONCAT_WS(', ', CONCAT_WS(' ', street, number), city) AS address
+                    FROM locations WHERE id = $1;",
+                    &[&feed.subject_id],
+                )
+                .await?;
+
+            (
+                format!("Sucursal {}", row.get::<_, String>("address")),
+                branch_events(client, feed.subject_id).await?,
+            )
+        }
+    };
+
+    Ok(render_calendar(&name, &events))
+}
@@ -0,0 +1,150 @@
+use crate::constants::CHARGE_DUE_DAYS;
+use crate::custom_types::enums::ChargeStatus;
+use crate::custom_types::structs::Charge;
+use chrono::{Duration, NaiveDate};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+
+const CHARGE_COLUMNS: &str = "id, rental_id, user_id, kind::TEXT, description, amount,
+    status::TEXT, due_date, created_at, payment_id, paid_at, collected_by, waived_by, waived_at,
+    waive_reason, deposit_applied";
+
+/// Records the fine of a late return, due `CHARGE_DUE_DAYS` after the return. Nothing is
+/// recorded for rentals returned on time.
+pub async fn record_late_return_fine(
+    client: &impl GenericClient,
+    rental_id: i32,
+    user_id: i32,
+    days_late: i64,
+    fine: Decimal,
+    return_date: NaiveDate,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    if fine <= Decimal::ZERO {
+        return Ok(None);
+    }
+
+    let description = format!("Devolución con {} días de retraso", days_late);
+    let due_date = return_date + Duration::days(CHARGE_DUE_DAYS);
+
+    let row = client
+        .query_one(
+            "INSERT INTO charges (rental_id, user_id, kind, description, amount, due_date)
+            VALUES ($1, $2, 'late_return', $3, $4, $5)
+            RETURNING id;",
+            &[&rental_id, &user_id, &description, &fine, &due_date],
+        )
+        .await?;
+
+    Ok(Some(row.get("id")))
+}
+
+/// Charges the client for the damage found when the machine was returned, due
+/// `CHARGE_DUE_DAYS` after the return.
+pub async fn record_damage_charge(
+    client: &impl GenericClient,
+    rental_id: i32,
+    user_id: i32,
+    amount: Decimal,
+    return_date: NaiveDate,
+) -> Result<i32, tokio_postgres::Error> {
+    let description = format!("Daños en la máquina del alquiler n° {}", rental_id);
+    let due_date = return_date + Duration::days(CHARGE_DUE_DAYS);
+
+    let row = client
+        .query_one(
+            "INSERT INTO charges (rental_id, user_id, kind, description, amount, due_date)
+            VALUES ($1, $2, 'damage', $3, $4, $5)
+            RETURNING id;",
+            &[&rental_id, &user_id, &description, &amount, &due_date],
+        )
+        .await?;
+
+    Ok(row.get("id"))
+}
+
+/// Charges the client for taking the machine to the job site, and bringing it back when a pickup
+/// was requested. Due on the start date of the rental.
+pub async fn record_delivery_charge(
+    client: &impl GenericClient,
+    rental_id: i32,
+    user_id: i32,
+    fee: Decimal,
+    start_date: NaiveDate,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    if fee <= Decimal::ZERO {
+        return Ok(None);
+    }
+
+    let description = format!("Envío a obra del alquiler n° {}", rental_id);
+
+    let row = client
+        .query_one(
+            "INSERT INTO charges (rental_id, user_id, kind, description, amount, due_date)
+            VALUES ($1, $2, 'delivery', $3, $4, $5)
+            RETURNING id;",
+            &[&rental_id, &user_id, &description, &fee, &start_date],
+        )
+        .await?;
+
+    Ok(Some(row.get("id")))
+}
+
+pub async fn has_overdue_charges(
+    client: &impl GenericClient,
+    user_id: i32,
+    today: NaiveDate,
+) -> Result<bool, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT EXISTS (
+                SELECT 1 FROM charges
+                WHERE user_id = $1 AND status = 'pending' AND due_date < $2
+            );",
+            &[&user_id, &today],
+        )
+        .await?;
+
+    Ok(row.get(0))
+}
+
+pub async fn get_charges(
+    client: &impl GenericClient,
+    user_id: Option<i32>,
+    status: Option<ChargeStatus>,
+) -> Result<Vec<Charge>, tokio_postgres::Error> {
+    let status = status.map(|status| status.to_string());
+
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {} FROM charges
+                WHERE ($1::INT IS NULL OR user_id = $1)
+                AND ($2::TEXT IS NULL OR status = $2::TEXT::charge_status)
+                ORDER BY created_at DESC, id DESC;",
+                CHARGE_COLUMNS
+            ),
+            &[&user_id, &status],
+        )
+        .await?;
+
+    Ok(rows.iter().map(Charge::build_from_row).collect())
+}
+
+/// Marks a pending charge as paid. `collected_by` is the employee who took the payment at the
+/// counter, `None` when it was paid online or with the deposit. Returns the number of charges
+/// updated.
+pub async fn settle_charge(
+    client: &impl GenericClient,
+    charge_id: i32,
+    payment_id: &str,
+    collected_by: Option<i32>,
+) -> Result<u64, tokio_postgres::Error> {
+    client
+        .execute(
+            "UPDATE charges
+            SET status = 'paid', payment_id = $2, paid_at = NOW(), collected_by = $3
+            WHERE id = $1 AND status = 'pending';",
+            &[&charge_id, &payment_id, &collected_by],
+        )
+        .await
+}
@@ -0,0 +1,140 @@
+use crate::custom_types::enums::DeliverySlot;
+use crate::custom_types::structs::{Delivery, DeliveryRates, DeliverySlotAvailability};
+use crate::helpers::pricing::round_money;
+use chrono::NaiveDate;
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+const DELIVERY_COLUMNS: &str = "id, rental_id, address, latitude, longitude, distance_km, fee,
+    delivery_slot::TEXT, pickup_slot::TEXT, status::TEXT, charge_id, created_at, delivered_at,
+    delivered_by, collected_at, collected_by";
+
+/// Great-circle distance between two points, rounded to 2 decimals.
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> Decimal {
+    let (from_lat, from_lon) = (from.0.to_radians(), from.1.to_radians());
+    let (to_lat, to_lon) = (to.0.to_radians(), to.1.to_radians());
+
+    let a = ((to_lat - from_lat) / 2.0).sin().powi(2)
+        + from_lat.cos() * to_lat.cos() * ((to_lon - from_lon) / 2.0).sin().powi(2);
+    let distance = 2.0 * EARTH_RADIUS_KM * a.sqrt().asin();
+
+    Decimal::from_f64_retain(distance)
+        .unwrap_or(Decimal::ZERO)
+        .round_dp(2)
+}
+
+/// Fee of a delivery, counting the trip back when the machine is also picked up.
+pub fn delivery_fee(rates: &DeliveryRates, distance_km: Decimal, pickup: bool) -> Decimal {
+    let trip = rates.base_fee + rates.fee_per_km * distance_km;
+    let trips = if pickup { Decimal::TWO } else { Decimal::ONE };
+
+    round_money(trip * trips)
+}
+
+pub async fn get_delivery_rates(
+    client: &impl GenericClient,
+    location_id: i32,
+) -> Result<Option<DeliveryRates>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT location_id, base_fee, fee_per_km, max_distance_km, slot_capacity
+            FROM delivery_rates
+            WHERE location_id = $1;",
+            &[&location_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(DeliveryRates::build_from_row))
+}
+
+/// Trips a branch already has in a slot of a day, counting deliveries on the start date of
+/// their rentals and pickups on the end date.
+pub async fn booked_trips(
+    client: &impl GenericClient,
+    location_id: i32,
+    date: NaiveDate,
+    slot: DeliverySlot,
+) -> Result<i64, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT
+                COUNT(*) FILTER (WHERE r.start_date = $2 AND d.delivery_slot = $3::TEXT::delivery_slot)
+                + COUNT(*) FILTER (WHERE r.end_date = $2 AND d.pickup_slot = $3::TEXT::delivery_slot)
+            FROM deliveries d
+            INNER JOIN rentals r ON r.id = d.rental_id
+            INNER JOIN machinery_units mu ON mu.id = r.machine_id
+            WHERE mu.location_id = $1 AND d.status != 'cancelled';",
+            &[&location_id, &date, &slot.to_string()],
+        )
+        .await?;
+
+    Ok(row.get(0))
+}
+
+pub async fn get_slot_availability(
+    client: &impl GenericClient,
+    rates: &DeliveryRates,
+    date: NaiveDate,
+) -> Result<Vec<DeliverySlotAvailability>, tokio_postgres::Error> {
+    let mut slots = Vec::new();
+
+    for slot in [DeliverySlot::Morning, DeliverySlot::Afternoon] {
+        slots.push(DeliverySlotAvailability {
+            slot,
+            capacity: rates.slot_capacity,
+            booked: booked_trips(client, rates.location_id, date, slot).await?,
+        });
+    }
+
+    Ok(slots)
+}
+
+pub async fn get_delivery(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Option<Delivery>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            &format!(
+                "SELECT {} FROM deliveries WHERE rental_id = $1;",
+                DELIVERY_COLUMNS
+            ),
+            &[&rental_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(Delivery::build_from_row))
+}
+
+/// Cancels the delivery of a cancelled rental and waives its fee if it wasn't paid yet.
+pub async fn cancel_delivery(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<(), tokio_postgres::Error> {
+    let Some(row) = client
+        .query_opt(
+            "UPDATE deliveries SET status = 'cancelled'
+            WHERE rental_id = $1 AND status = 'scheduled'
+            RETURNING charge_id;",
+            &[&rental_id],
+        )
+        .await?
+    else {
+        return Ok(());
+    };
+
+    if let Some(charge_id) = row.get::<_, Option<i32>>("charge_id") {
+        client
+            .execute(
+                "UPDATE charges
+                SET status = 'waived', waived_at = NOW(), waive_reason = 'Alquiler cancelado'
+                WHERE id = $1 AND status = 'pending';",
+                &[&charge_id],
+            )
+            .await?;
+    }
+
+    Ok(())
+}
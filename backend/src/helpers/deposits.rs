@@ -0,0 +1,169 @@
+use crate::constants::INTERNAL_PAYMENT_ID_PREFIX;
+use crate::custom_types::enums::DepositStatus;
+use crate::custom_types::structs::{DepositApplication, DepositStatement};
+use crate::helpers::charges::settle_charge;
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+
+/// Applies the deposit held by a rental to its pending charges, oldest first, and releases
+/// what's left. Charges the deposit covers in full are paid with it. Returns `None` when the
+/// rental holds no deposit.
+pub async fn settle_deposit(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Option<DepositStatement>, tokio_postgres::Error> {
+    let Some(row) = client
+        .query_opt(
+            "SELECT deposit_amount FROM rentals
+            WHERE id = $1 AND deposit_status = 'held'
+            FOR UPDATE;",
+            &[&rental_id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let deposit: Decimal = row.get("deposit_amount");
+    let mut remaining = deposit;
+
+    let payment_id = format!("D{}", INTERNAL_PAYMENT_ID_PREFIX + (rental_id as u32));
+
+    let charge_rows = client
+        .query(
+            "SELECT id, amount, deposit_applied FROM charges
+            WHERE rental_id = $1 AND status = 'pending'
+            ORDER BY created_at, id
+            FOR UPDATE;",
+            &[&rental_id],
+        )
+        .await?;
+
+    for row in charge_rows {
+        if remaining == Decimal::ZERO {
+            break;
+        }
+
+        let charge_id: i32 = row.get("id");
+        let owed = row.get::<_, Decimal>("amount") - row.get::<_, Decimal>("deposit_applied");
+        let applied = owed.min(remaining);
+        remaining -= applied;
+
+        client
+            .execute(
+                "UPDATE charges SET deposit_applied = deposit_applied + $2 WHERE id = $1;",
+                &[&charge_id, &applied],
+            )
+            .await?;
+
+        if applied == owed {
+            settle_charge(client, charge_id, &payment_id, None).await?;
+        }
+    }
+
+    let kept = deposit - remaining;
+    let status = if kept == Decimal::ZERO {
+        DepositStatus::Released
+    } else if remaining == Decimal::ZERO {
+        DepositStatus::Kept
+    } else {
+        DepositStatus::PartiallyKept
+    };
+
+    client
+        .execute(
+            "UPDATE rentals
+            SET deposit_status = $2::TEXT::deposit_status, deposit_kept = $3,
+                deposit_settled_at = NOW()
+            WHERE id = $1;",
+            &[&rental_id, &status.to_string(), &kept],
+        )
+        .await?;
+
+    get_deposit_statement(client, rental_id).await
+}
+
+/// Gives back the whole deposit held by a rental that won't be returned, such as a cancelled
+/// one. Returns the amount released, if there was one held.
+pub async fn release_deposit(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Option<Decimal>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "UPDATE rentals
+            SET deposit_status = 'released', deposit_kept = 0, deposit_settled_at = NOW()
+            WHERE id = $1 AND deposit_status = 'held'
+            RETURNING deposit_amount;",
+            &[&rental_id],
+        )
+        .await?;
+
+    Ok(row.map(|row| row.get("deposit_amount")))
+}
+
+pub async fn get_deposit_statement(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Option<DepositStatement>, tokio_postgres::Error> {
+    let Some(row) = client
+        .query_opt(
+            "SELECT deposit_amount, deposit_status::TEXT, deposit_kept, deposit_settled_at
+            FROM rentals
+            WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let deposit_amount: Decimal = row.get("deposit_amount");
+    let status: Option<String> = row.get("deposit_status");
+    let kept = row
+        .get::<_, Option<Decimal>>("deposit_kept")
+        .unwrap_or(Decimal::ZERO);
+
+    let released = match status.as_deref() {
+        None | Some("held") => Decimal::ZERO,
+        Some(_) => deposit_amount - kept,
+    };
+
+    let applications = client
+        .query(
+            "SELECT id, kind::TEXT, description, amount, deposit_applied, status::TEXT
+            FROM charges
+            WHERE rental_id = $1 AND deposit_applied > 0
+            ORDER BY created_at, id;",
+            &[&rental_id],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let amount: Decimal = row.get("amount");
+            let applied: Decimal = row.get("deposit_applied");
+
+            DepositApplication {
+                charge_id: row.get("id"),
+                kind: row.get("kind"),
+                description: row.get("description"),
+                amount,
+                applied,
+                outstanding: match row.get::<_, String>("status").as_str() {
+                    "pending" => amount - applied,
+                    _ => Decimal::ZERO,
+                },
+            }
+        })
+        .collect();
+
+    Ok(Some(DepositStatement {
+        rental_id,
+        deposit_amount,
+        status,
+        kept,
+        released,
+        settled_at: row.get("deposit_settled_at"),
+        applications,
+    }))
+}
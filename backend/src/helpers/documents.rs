@@ -0,0 +1,395 @@
+use crate::constants::{COMPANY_NAME, COMPANY_TAX_ID};
+use crate::custom_types::structs::RentalDocument;
+use crate::helpers::invoices::write_text;
+use deadpool_postgres::GenericClient;
+use printpdf::{
+    BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference,
+};
+use rust_decimal::Decimal;
+
+const PAGE_TOP: f32 = 275.0;
+const PAGE_BOTTOM: f32 = 20.0;
+/// Characters that fit in a line of 10 pt Helvetica between the margins.
+const LINE_WIDTH: usize = 95;
+
+pub async fn get_rental_document(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Option<RentalDocument>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT r.id, r.user_id, r.status::TEXT, r.start_date, r.end_date, r.total_price,
+                r.deposit_amount, r.payment_id, r.created_at,
+                u.name || ' ' || u.surname AS client_name, u.email, ui.id_card, ui.phone,
+                mm.name AS model_name, mm.brand, mm.model, mm.year, mm.policy, mu.serial_number,
+                CONCAT_WS(', ', CONCAT_WS(' ', l.street, l.number), l.city) AS branch_address,
+                (SELECT COALESCE(SUM(e.price), 0) FROM rental_extensions e
+                WHERE e.rental_id = r.id AND e.status = 'paid') AS extensions_price,
+                (SELECT d.fee FROM deliveries d
+                WHERE d.rental_id = r.id AND d.status != 'cancelled') AS delivery_fee,
+                (SELECT MIN(h.created_at) FROM rental_status_history h
+                WHERE h.rental_id = r.id AND h.to_status = 'active') AS paid_at
+            FROM rentals r
+            INNER JOIN users u ON r.user_id = u.id
+            LEFT JOIN user_info ui ON u.id = ui.id
+            INNER JOIN machinery_units mu ON r.machine_id = mu.id
+            INNER JOIN machinery_models mm ON mu.model_id = mm.id
+            INNER JOIN locations l ON mu.location_id = l.id
+            WHERE r.id = $1;",
+            &[&rental_id],
+        )
+        .await?;
+
+    Ok(row.map(|row| RentalDocument {
+        rental_id: row.get("id"),
+        user_id: row.get("user_id"),
+        status: row.get("status"),
+        client_name: row.get("client_name"),
+        client_email: row.get("email"),
+        client_id_card: row.get("id_card"),
+        client_phone: row.get("phone"),
+        model_name: row.get("model_name"),
+        brand: row.get("brand"),
+        model: row.get("model"),
+        year: row.get("year"),
+        serial_number: row.get("serial_number"),
+        branch_address: row.get("branch_address"),
+        start_date: row.get("start_date"),
+        end_date: row.get("end_date"),
+        total_price: row.get("total_price"),
+        extensions_price: row.get("extensions_price"),
+        delivery_fee: row.get("delivery_fee"),
+        deposit_amount: row.get("deposit_amount"),
+        policy: row.get("policy"),
+        payment_id: row.get("payment_id"),
+        // Rentals booked before the status history was kept have no activation entry
+        paid_at: row
+            .get::<_, Option<chrono::NaiveDateTime>>("paid_at")
+            .unwrap_or_else(|| row.get("created_at")),
+    }))
+}
+
+pub fn contract_file_name(rental_id: i32) -> String {
+    format!("contrato-alquiler-{}.pdf", rental_id)
+}
+
+pub fn receipt_file_name(rental_id: i32) -> String {
+    format!("recibo-alquiler-{}.pdf", rental_id)
+}
+
+/// The contract and receipt of a paid rental, named as they are attached to emails.
+pub async fn rental_attachments(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let document = get_rental_document(client, rental_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Rental not found")?;
+
+    Ok(vec![
+        (
+            contract_file_name(rental_id),
+            render_contract_pdf(&document).map_err(|e| e.to_string())?,
+        ),
+        (
+            receipt_file_name(rental_id),
+            render_receipt_pdf(&document).map_err(|e| e.to_string())?,
+        ),
+    ])
+}
+
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + word.chars().count() >= width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Writes rows top to bottom, moving on to a new page when the current one is full.
+struct PageWriter<'a> {
+    document: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    y: f32,
+}
+
+impl PageWriter<'_> {
+    fn row(&mut self, font: &IndirectFontRef, size: f32, columns: &[(f32, &str)]) {
+        if self.y < PAGE_BOTTOM {
+            let (page, layer) = self.document.add_page(Mm(210.0), Mm(297.0), "Layer 1");
+            self.layer = self.document.get_page(page).get_layer(layer);
+            self.y = PAGE_TOP;
+        }
+
+        for (x, text) in columns {
+            write_text(&self.layer, font, size, *x, self.y, text);
+        }
+        self.y -= size / 2.0;
+    }
+
+    fn skip(&mut self, height: f32) {
+        self.y -= height;
+    }
+}
+
+fn machine_description(document: &RentalDocument) -> String {
+    format!(
+        "{} {} {} ({})",
+        document.model_name, document.brand, document.model, document.year
+    )
+}
+
+fn write_header(
+    layer: &PdfLayerReference,
+    regular: &IndirectFontRef,
+    bold: &IndirectFontRef,
+    title: &str,
+    details: &[String],
+) {
+    write_text(layer, bold, 18.0, 15.0, PAGE_TOP, COMPANY_NAME);
+    write_text(
+        layer,
+        regular,
+        10.0,
+        15.0,
+        PAGE_TOP - 7.0,
+        &format!("CUIT: {}", COMPANY_TAX_ID),
+    );
+
+    write_text(layer, bold, 16.0, 120.0, PAGE_TOP, title);
+    let mut y = PAGE_TOP - 7.0;
+    for text in details {
+        write_text(layer, regular, 10.0, 120.0, y, text);
+        y -= 5.0;
+    }
+}
+
+pub fn render_contract_pdf(document: &RentalDocument) -> Result<Vec<u8>, printpdf::Error> {
+    let (pdf, page, layer) = PdfDocument::new(
+        format!("Contrato de alquiler n° {}", document.rental_id),
+        Mm(210.0),
+        Mm(297.0),
+        "Layer 1",
+    );
+    let regular = pdf.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = pdf.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let layer = pdf.get_page(page).get_layer(layer);
+
+    write_header(
+        &layer,
+        &regular,
+        &bold,
+        "CONTRATO DE ALQUILER",
+        &[
+            format!("Alquiler n° {}", document.rental_id),
+            format!("Fecha: {}", document.paid_at.format("%d/%m/%Y")),
+        ],
+    );
+
+    let mut writer = PageWriter {
+        document: &pdf,
+        layer,
+        y: 248.0,
+    };
+
+    let not_given = || "-".to_string();
+    let days = (document.end_date - document.start_date).num_days();
+
+    let sections = [
+        (
+            "Cliente",
+            vec![
+                ("Nombre", document.client_name.clone()),
+                (
+                    "DNI",
+                    document.client_id_card.clone().unwrap_or_else(not_given),
+                ),
+                (
+                    "Teléfono",
+                    document.client_phone.clone().unwrap_or_else(not_given),
+                ),
+                ("Email", document.client_email.clone()),
+            ],
+        ),
+        (
+            "Máquina",
+            vec![
+                ("Modelo", machine_description(document)),
+                ("N° de serie", document.serial_number.clone()),
+                ("Sucursal", document.branch_address.clone()),
+            ],
+        ),
+        (
+            "Período",
+            vec![
+                ("Desde", document.start_date.format("%d/%m/%Y").to_string()),
+                ("Hasta", document.end_date.format("%d/%m/%Y").to_string()),
+                ("Días", days.to_string()),
+            ],
+        ),
+    ];
+
+    for (title, fields) in sections {
+        writer.row(&bold, 12.0, &[(15.0, title)]);
+        writer.skip(1.0);
+        for (label, value) in fields {
+            writer.row(&regular, 10.0, &[(15.0, label), (55.0, &value)]);
+        }
+        writer.skip(6.0);
+    }
+
+    let mut prices = vec![("Alquiler", document.total_price)];
+    if document.extensions_price > Decimal::ZERO {
+        prices.push(("Extensiones", document.extensions_price));
+    }
+    if let Some(fee) = document.delivery_fee {
+        prices.push(("Envío a obra", fee));
+    }
+    let total: Decimal = prices.iter().map(|(_, amount)| amount).sum();
+
+    writer.row(&bold, 12.0, &[(15.0, "Precio")]);
+    writer.skip(1.0);
+    for (label, amount) in prices {
+        writer.row(
+            &regular,
+            10.0,
+            &[(15.0, label), (55.0, &format!("$ {}", amount))],
+        );
+    }
+    writer.row(
+        &bold,
+        10.0,
+        &[(15.0, "Total"), (55.0, &format!("$ {}", total))],
+    );
+    if document.deposit_amount > Decimal::ZERO {
+        writer.row(
+            &regular,
+            10.0,
+            &[
+                (15.0, "Depósito de garantía"),
+                (
+                    55.0,
+                    &format!("$ {} (se devuelve con la máquina)", document.deposit_amount),
+                ),
+            ],
+        );
+    }
+    writer.skip(6.0);
+
+    writer.row(&bold, 12.0, &[(15.0, "Condiciones")]);
+    writer.skip(1.0);
+    for line in wrap_text(&document.policy, LINE_WIDTH) {
+        writer.row(&regular, 10.0, &[(15.0, &line)]);
+    }
+
+    writer.skip(25.0);
+    writer.row(
+        &regular,
+        10.0,
+        &[
+            (15.0, "______________________"),
+            (120.0, "______________________"),
+        ],
+    );
+    writer.row(
+        &regular,
+        10.0,
+        &[(15.0, "Firma del cliente"), (120.0, COMPANY_NAME)],
+    );
+
+    pdf.save_to_bytes()
+}
+
+pub fn render_receipt_pdf(document: &RentalDocument) -> Result<Vec<u8>, printpdf::Error> {
+    let (pdf, page, layer) = PdfDocument::new(
+        format!("Recibo del alquiler n° {}", document.rental_id),
+        Mm(210.0),
+        Mm(297.0),
+        "Layer 1",
+    );
+    let regular = pdf.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = pdf.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let layer = pdf.get_page(page).get_layer(layer);
+
+    write_header(
+        &layer,
+        &regular,
+        &bold,
+        "RECIBO DE PAGO",
+        &[
+            format!("Alquiler n° {}", document.rental_id),
+            format!("Fecha de pago: {}", document.paid_at.format("%d/%m/%Y")),
+            format!(
+                "Identificador del pago: {}",
+                document.payment_id.as_deref().unwrap_or("-")
+            ),
+        ],
+    );
+
+    let total = document.total_price + document.deposit_amount;
+    let mut y = 243.0;
+
+    let received = format!(
+        "Recibimos de {}{} la suma de $ {} en concepto de:",
+        document.client_name,
+        document
+            .client_id_card
+            .as_ref()
+            .map(|id_card| format!(" (DNI {})", id_card))
+            .unwrap_or_default(),
+        total
+    );
+    for line in wrap_text(&received, LINE_WIDTH) {
+        write_text(&layer, &regular, 10.0, 15.0, y, &line);
+        y -= 5.0;
+    }
+    y -= 5.0;
+
+    let mut concepts = vec![(
+        format!(
+            "Alquiler de {}, n° de serie {}, del {} al {}",
+            machine_description(document),
+            document.serial_number,
+            document.start_date.format("%d/%m/%Y"),
+            document.end_date.format("%d/%m/%Y"),
+        ),
+        document.total_price,
+    )];
+    if document.deposit_amount > Decimal::ZERO {
+        concepts.push((
+            "Depósito de garantía, se devuelve con la máquina".to_string(),
+            document.deposit_amount,
+        ));
+    }
+
+    // Concepts are wrapped short of the amounts column
+    for (concept, amount) in concepts {
+        write_text(&layer, &regular, 10.0, 170.0, y, &format!("$ {}", amount));
+        for line in wrap_text(&concept, 80) {
+            write_text(&layer, &regular, 10.0, 15.0, y, &line);
+            y -= 5.0;
+        }
+        y -= 1.0;
+    }
+
+    y -= 4.0;
+    write_text(&layer, &bold, 11.0, 135.0, y, "Total");
+    write_text(&layer, &regular, 11.0, 170.0, y, &format!("$ {}", total));
+
+    pdf.save_to_bytes()
+}
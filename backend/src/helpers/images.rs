@@ -0,0 +1,267 @@
+use crate::constants::{MACHINE_IMAGES_PREFIX, STAGED_IMAGES_DIR, STAGED_IMAGE_MAX_AGE_MINUTES};
+use crate::custom_types::{enums::ImageVariant, structs::ImageSweepSummary};
+use crate::helpers::{auth::generate_random_string, media::MediaStore};
+use axum::{http::StatusCode, Json};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use deadpool_postgres::GenericClient;
+use image::{
+    imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult,
+};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Key of a variant of a machine image in the media store.
+pub fn machine_image_key(name: &str, variant: ImageVariant) -> String {
+    format!("{}{}", MACHINE_IMAGES_PREFIX, variant.file_name(name))
+}
+
+pub fn machine_image_url(media: &dyn MediaStore, name: &str) -> String {
+    media.url(&machine_image_key(name, ImageVariant::Full))
+}
+
+/// Decodes an uploaded image applying its EXIF orientation. The image is always re-encoded
+/// afterwards, so none of the original metadata reaches the disk.
+fn decode_upload(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_decoder()?;
+    let orientation = decoder.orientation()?;
+
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+/// Format of an upload told by its magic bytes, if it's one of the accepted ones.
+pub fn sniff_image_format(bytes: &[u8]) -> Option<ImageFormat> {
+    match image::guess_format(bytes) {
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP)) => Some(format),
+        _ => None,
+    }
+}
+
+fn encode_variant(image: &DynamicImage, variant: ImageVariant) -> ImageResult<Vec<u8>> {
+    let max_dimension = variant.max_dimension();
+
+    let resized = if image.width() > max_dimension || image.height() > max_dimension {
+        image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+    } else {
+        image.clone()
+    };
+
+    // The WebP encoder only takes 8-bit RGB(A)
+    let resized: DynamicImage = if resized.color().has_alpha() {
+        resized.to_rgba8().into()
+    } else {
+        resized.to_rgb8().into()
+    };
+
+    let mut bytes = Vec::new();
+    resized.write_to(&mut Cursor::new(&mut bytes), ImageFormat::WebP)?;
+
+    Ok(bytes)
+}
+
+/// Images uploaded by a request that aren't published yet. They are written to the local
+/// staging directory and uploaded to the media store by `promote`, which must run right before
+/// the transaction referencing them commits, so a failed upload rolls the request back. If the
+/// commit fails instead, the uploads are left unreferenced for `sweep_images` to remove.
+/// Whatever is still staged on drop is deleted.
+#[derive(Default)]
+pub struct StagedImages {
+    names: Vec<String>,
+}
+
+impl StagedImages {
+    /// Normalizes a base64 encoded image, stages all of its variants and returns its name.
+    pub fn stage(&mut self, b64: &str) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+        let Ok(bytes) = STANDARD.decode(b64) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "Invalid base64 in images"})),
+            ));
+        };
+
+        let Ok(image) = decode_upload(&bytes) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "Failed to decode image"})),
+            ));
+        };
+
+        self.stage_image(&image)
+    }
+
+    /// Same as `stage` for an uploaded file, naming it in the errors.
+    pub fn stage_file(
+        &mut self,
+        file_name: &str,
+        bytes: &[u8],
+    ) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+        let Ok(image) = decode_upload(bytes) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": format!("Failed to decode the image '{}'", file_name)})),
+            ));
+        };
+
+        self.stage_image(&image)
+    }
+
+    fn stage_image(
+        &mut self,
+        image: &DynamicImage,
+    ) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+        let save_error = || {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"message": "Failed to save the images"})),
+            )
+        };
+
+        let staging_dir = Path::new(STAGED_IMAGES_DIR);
+        fs::create_dir_all(staging_dir).map_err(|_| save_error())?;
+
+        let name = generate_random_string(64);
+        self.names.push(name.clone());
+
+        for variant in ImageVariant::ALL {
+            let bytes = encode_variant(image, variant).map_err(|_| save_error())?;
+            fs::write(staging_dir.join(variant.file_name(&name)), bytes)
+                .map_err(|_| save_error())?;
+        }
+
+        Ok(name)
+    }
+
+    pub async fn promote(mut self, media: &dyn MediaStore) -> Result<(), String> {
+        for name in &self.names {
+            for variant in ImageVariant::ALL {
+                let staged_path = Path::new(STAGED_IMAGES_DIR).join(variant.file_name(name));
+                let bytes = fs::read(&staged_path).map_err(|e| e.to_string())?;
+
+                media
+                    .put(&machine_image_key(name, variant), bytes, "image/webp")
+                    .await?;
+                let _ = fs::remove_file(staged_path);
+            }
+        }
+
+        self.names.clear();
+        Ok(())
+    }
+}
+
+impl Drop for StagedImages {
+    fn drop(&mut self) {
+        for name in &self.names {
+            for variant in ImageVariant::ALL {
+                let _ = fs::remove_file(Path::new(STAGED_IMAGES_DIR).join(variant.file_name(name)));
+            }
+        }
+    }
+}
+
+/// Deletes the machine images that no model or inspection references anymore and the files
+/// left in `staging_dir` by requests that never finished. Referenced images uploaded before
+/// variants existed get their missing variants generated from the full image.
+pub async fn sweep_images(
+    client: &impl GenericClient,
+    media: &dyn MediaStore,
+    staging_dir: &Path,
+) -> Result<ImageSweepSummary, String> {
+    let rows = client
+        .query(
+            "SELECT image AS name FROM machinery_models
+            UNION
+            SELECT name FROM model_extra_images
+            UNION
+            SELECT name FROM inspection_photos;",
+            &[],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let referenced: HashSet<String> = rows.iter().map(|row| row.get("name")).collect();
+    let mut summary = ImageSweepSummary::default();
+    let mut present: HashSet<(String, ImageVariant)> = HashSet::new();
+
+    for key in media.list(MACHINE_IMAGES_PREFIX).await? {
+        let Some(stem) = key
+            .strip_prefix(MACHINE_IMAGES_PREFIX)
+            .and_then(|file_name| file_name.strip_suffix(".webp"))
+        else {
+            continue;
+        };
+
+        let (name, variant) = ImageVariant::ALL
+            .into_iter()
+            .filter(|variant| *variant != ImageVariant::Full)
+            .find_map(|variant| {
+                stem.strip_suffix(variant.suffix())
+                    .map(|name| (name, variant))
+            })
+            .unwrap_or((stem, ImageVariant::Full));
+
+        if referenced.contains(name) {
+            present.insert((name.to_string(), variant));
+        } else {
+            media.delete(&key).await?;
+            summary.removed_files += 1;
+        }
+    }
+
+    for name in &referenced {
+        if !present.contains(&(name.clone(), ImageVariant::Full)) {
+            continue;
+        }
+
+        let missing_variants: Vec<ImageVariant> = ImageVariant::ALL
+            .into_iter()
+            .filter(|variant| !present.contains(&(name.clone(), *variant)))
+            .collect();
+
+        if missing_variants.is_empty() {
+            continue;
+        }
+
+        let full_image = media
+            .get(&machine_image_key(name, ImageVariant::Full))
+            .await
+            .and_then(|bytes| image::load_from_memory(&bytes).map_err(|e| e.to_string()))?;
+
+        for variant in missing_variants {
+            let bytes = encode_variant(&full_image, variant).map_err(|e| e.to_string())?;
+            media
+                .put(&machine_image_key(name, variant), bytes, "image/webp")
+                .await?;
+            summary.generated_variants += 1;
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(staging_dir) {
+        let max_age = Duration::from_secs(STAGED_IMAGE_MAX_AGE_MINUTES * 60);
+
+        for entry in entries.flatten() {
+            let is_stale = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    SystemTime::now()
+                        .duration_since(modified)
+                        .is_ok_and(|age| age > max_age)
+                });
+
+            if is_stale && fs::remove_file(entry.path()).is_ok() {
+                summary.removed_files += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
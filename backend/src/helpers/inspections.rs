@@ -0,0 +1,206 @@
+use crate::custom_types::enums::InspectionKind;
+use crate::custom_types::structs::{ChecklistItem, ImageUrls, Inspection, InspectionInput};
+use crate::helpers::{images::StagedImages, media::MediaStore};
+use axum::{http::StatusCode, Json};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::collections::HashMap;
+use validator::Validate;
+
+/// Validates an inspection and stages its photos, which must be promoted once the inspection
+/// is committed.
+pub fn stage_inspection_photos(
+    input: &InspectionInput,
+) -> Result<(StagedImages, Vec<String>), (StatusCode, Json<serde_json::Value>)> {
+    if input.validate().is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Invalid inspection"})),
+        ));
+    }
+
+    let mut staged_photos = StagedImages::default();
+    let photo_names = input
+        .photos
+        .iter()
+        .map(|photo| staged_photos.stage(photo))
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok((staged_photos, photo_names))
+}
+
+/// Saves an inspection with its checklist and the names of its already staged photos.
+pub async fn record_inspection(
+    client: &impl GenericClient,
+    rental_id: i32,
+    kind: InspectionKind,
+    employee_id: i32,
+    input: &InspectionInput,
+    photo_names: &[String],
+) -> Result<i32, tokio_postgres::Error> {
+    let kind = kind.to_string();
+
+    let row = client
+        .query_one(
+            "INSERT INTO inspections (rental_id, kind, employee_id, hour_meter, notes, has_damage)
+            VALUES ($1, $2::TEXT::inspection_kind, $3, $4, $5, $6)
+            RETURNING id;",
+            &[
+                &rental_id,
+                &kind,
+                &employee_id,
+                &input.hour_meter,
+                &input.notes,
+                &input.has_damage(),
+            ],
+        )
+        .await?;
+
+    let inspection_id: i32 = row.get("id");
+
+    for item in &input.items {
+        client
+            .execute(
+                "INSERT INTO inspection_items (inspection_id, item, ok, notes)
+                VALUES ($1, $2, $3, $4);",
+                &[&inspection_id, &item.item, &item.ok, &item.notes],
+            )
+            .await?;
+    }
+
+    for name in photo_names {
+        client
+            .execute(
+                "INSERT INTO inspection_photos (inspection_id, name) VALUES ($1, $2);",
+                &[&inspection_id, name],
+            )
+            .await?;
+    }
+
+    Ok(inspection_id)
+}
+
+/// Hour meter reading taken when the machine left, if there was one.
+pub async fn check_out_hour_meter(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Option<Decimal>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT hour_meter FROM inspections WHERE rental_id = $1 AND kind = 'check_out';",
+            &[&rental_id],
+        )
+        .await?;
+
+    Ok(row.and_then(|row| row.get("hour_meter")))
+}
+
+/// Leaves the damage found at return in the unit history. The return has already sent the
+/// unit to maintenance.
+pub async fn record_damage_event(
+    client: &impl GenericClient,
+    machine_id: i32,
+    rental_id: i32,
+    input: &InspectionInput,
+) -> Result<(), tokio_postgres::Error> {
+    let damaged_items: Vec<String> = input
+        .items
+        .iter()
+        .filter(|item| !item.ok)
+        .map(|item| match &item.notes {
+            Some(notes) => format!("{} ({})", item.item, notes),
+            None => item.item.clone(),
+        })
+        .collect();
+
+    let description = format!(
+        "Daños detectados en la devolución del alquiler n° {}: {}",
+        rental_id,
+        damaged_items.join(", ")
+    );
+
+    client
+        .execute(
+            "INSERT INTO unit_history_events (unit_id, description, previous_status, new_status)
+            VALUES ($1, $2, 'rented', 'maintenance');",
+            &[&machine_id, &description],
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_inspections(
+    client: &impl GenericClient,
+    media: &dyn MediaStore,
+    rental_id: i32,
+) -> Result<Vec<Inspection>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            "SELECT id, rental_id, kind::TEXT, employee_id, hour_meter, notes, has_damage,
+                damage_charge_id, created_at
+            FROM inspections
+            WHERE rental_id = $1
+            ORDER BY created_at, id;",
+            &[&rental_id],
+        )
+        .await?;
+
+    let inspection_ids: Vec<i32> = rows.iter().map(|row| row.get("id")).collect();
+
+    let mut items: HashMap<i32, Vec<ChecklistItem>> = HashMap::new();
+    for row in client
+        .query(
+            "SELECT inspection_id, item, ok, notes FROM inspection_items
+            WHERE inspection_id = ANY($1)
+            ORDER BY item;",
+            &[&inspection_ids],
+        )
+        .await?
+    {
+        items
+            .entry(row.get("inspection_id"))
+            .or_default()
+            .push(ChecklistItem {
+                item: row.get("item"),
+                ok: row.get("ok"),
+                notes: row.get("notes"),
+            });
+    }
+
+    let mut photos: HashMap<i32, Vec<ImageUrls>> = HashMap::new();
+    for row in client
+        .query(
+            "SELECT inspection_id, name FROM inspection_photos WHERE inspection_id = ANY($1);",
+            &[&inspection_ids],
+        )
+        .await?
+    {
+        photos
+            .entry(row.get("inspection_id"))
+            .or_default()
+            .push(ImageUrls::new(media, row.get("name")));
+    }
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let id: i32 = row.get("id");
+
+            Inspection {
+                id,
+                rental_id: row.get("rental_id"),
+                kind: row.get("kind"),
+                employee_id: row.get("employee_id"),
+                hour_meter: row.get("hour_meter"),
+                notes: row.get("notes"),
+                has_damage: row.get("has_damage"),
+                damage_charge_id: row.get("damage_charge_id"),
+                created_at: row.get("created_at"),
+                items: items.remove(&id).unwrap_or_default(),
+                photos: photos.remove(&id).unwrap_or_default(),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,608 @@
+use crate::constants::{COMPANY_NAME, COMPANY_TAX_ID, INVOICE_POINT_OF_SALE};
+use crate::custom_types::{enums::*, structs::*};
+use crate::helpers::pricing::round_money;
+use deadpool_postgres::GenericClient;
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Checks the length and the verification digit of a CUIT/CUIL written without dashes.
+pub fn is_valid_tax_id(tax_id: &str) -> bool {
+    const WEIGHTS: [u32; 10] = [5, 4, 3, 2, 7, 6, 5, 4, 3, 2];
+
+    let digits: Vec<u32> = tax_id.chars().filter_map(|c| c.to_digit(10)).collect();
+
+    if tax_id.len() != 11 || digits.len() != 11 {
+        return false;
+    }
+
+    let sum: u32 = digits
+        .iter()
+        .zip(WEIGHTS)
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+
+    let verification_digit = match 11 - sum % 11 {
+        11 => 0,
+        10 => return false,
+        digit => digit,
+    };
+
+    digits[10] == verification_digit
+}
+
+pub fn format_invoice_number(point_of_sale: i32, number: i32) -> String {
+    format!("{:04}-{:08}", point_of_sale, number)
+}
+
+fn tax_condition_label(tax_condition: &str) -> &str {
+    match tax_condition {
+        "responsable_inscripto" => "IVA Responsable Inscripto",
+        "monotributista" => "Responsable Monotributo",
+        "exento" => "IVA Sujeto Exento",
+        _ => "Consumidor Final",
+    }
+}
+
+/// Numbers are reserved inside the caller's transaction, so a rollback doesn't leave gaps.
+async fn next_invoice_number(
+    client: &impl GenericClient,
+    kind: InvoiceKind,
+    letter: &str,
+) -> Result<i32, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "INSERT INTO invoice_sequences (kind, letter, point_of_sale, last_number)
+            VALUES ($1::TEXT::invoice_kind, $2, $3, 1)
+            ON CONFLICT (kind, letter, point_of_sale)
+            DO UPDATE SET last_number = invoice_sequences.last_number + 1
+            RETURNING last_number;",
+            &[&kind.to_string(), &letter, &INVOICE_POINT_OF_SALE],
+        )
+        .await?;
+
+    Ok(row.get("last_number"))
+}
+
+/// Issues the invoice of a rental that has just become active.
+pub async fn issue_rental_invoice(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<i32, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT start_date, end_date, total_price FROM rentals WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await?;
+
+    issue_invoice(
+        client,
+        rental_id,
+        "Alquiler",
+        row.get("start_date"),
+        row.get("end_date"),
+        row.get("total_price"),
+    )
+    .await
+}
+
+/// Issues the invoice of the extra days of a rental once its extension is paid.
+pub async fn issue_extension_invoice(
+    client: &impl GenericClient,
+    extension_id: i32,
+) -> Result<i32, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT rental_id, previous_end_date, new_end_date, price
+            FROM rental_extensions WHERE id = $1;",
+            &[&extension_id],
+        )
+        .await?;
+
+    issue_invoice(
+        client,
+        row.get("rental_id"),
+        "Extensión del alquiler",
+        row.get("previous_end_date"),
+        row.get("new_end_date"),
+        row.get("price"),
+    )
+    .await
+}
+
+/// Rental prices already include taxes, so the total is split into net and tax amounts with
+/// the rate in effect for the model.
+async fn issue_invoice(
+    client: &impl GenericClient,
+    rental_id: i32,
+    concept: &str,
+    start_date: chrono::NaiveDate,
+    end_date: chrono::NaiveDate,
+    total_amount: Decimal,
+) -> Result<i32, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT r.user_id, mu.serial_number,
+                mm.name AS model_name, mm.brand, mm.model, u.name, u.surname,
+                ui.id_card, ui.tax_id, ui.tax_condition::TEXT,
+                (SELECT percentage FROM tax_rates tr
+                WHERE tr.model_id = mm.id OR tr.model_id IS NULL
+                ORDER BY tr.model_id NULLS LAST
+                LIMIT 1) AS tax_percentage
+            FROM rentals r
+            INNER JOIN machinery_units mu ON r.machine_id = mu.id
+            INNER JOIN machinery_models mm ON mu.model_id = mm.id
+            INNER JOIN users u ON r.user_id = u.id
+            INNER JOIN user_info ui ON u.id = ui.id
+            WHERE r.id = $1;",
+            &[&rental_id],
+        )
+        .await?;
+
+    let tax_percentage: Decimal = row
+        .get::<_, Option<Decimal>>("tax_percentage")
+        .unwrap_or(Decimal::ZERO);
+    let tax_condition: String = row.get("tax_condition");
+
+    let net_amount =
+        round_money(total_amount * Decimal::ONE_HUNDRED / (Decimal::ONE_HUNDRED + tax_percentage));
+    let tax_amount = total_amount - net_amount;
+    let days = (end_date - start_date).num_days().max(1) as i32;
+
+    let description = format!(
+        "{} de {} {} {} ({}) del {} al {}",
+        concept,
+        row.get::<_, String>("model_name"),
+        row.get::<_, String>("brand"),
+        row.get::<_, String>("model"),
+        row.get::<_, String>("serial_number"),
+        start_date.format("%d/%m/%Y"),
+        end_date.format("%d/%m/%Y"),
+    );
+
+    let letter = if tax_condition == TaxCondition::ResponsableInscripto.to_string() {
+        "A"
+    } else {
+        "B"
+    };
+
+    let number = next_invoice_number(client, InvoiceKind::Invoice, letter).await?;
+
+    let invoice_row = client
+        .query_one(
+            "INSERT INTO invoices (kind, letter, point_of_sale, number, rental_id, user_id,
+                billing_name, billing_id_card, billing_tax_id, billing_tax_condition,
+                net_amount, tax_amount, total_amount)
+            VALUES ('invoice', $1, $2, $3, $4, $5, $6, $7, $8, $9::TEXT::tax_condition, $10, $11, $12)
+            RETURNING id;",
+            &[
+                &letter,
+                &INVOICE_POINT_OF_SALE,
+                &number,
+                &rental_id,
+                &row.get::<_, i32>("user_id"),
+                &format!(
+                    "{} {}",
+                    row.get::<_, String>("name"),
+                    row.get::<_, String>("surname")
+                ),
+                &row.get::<_, String>("id_card"),
+                &row.get::<_, Option<String>>("tax_id"),
+                &tax_condition,
+                &net_amount,
+                &tax_amount,
+                &total_amount,
+            ],
+        )
+        .await?;
+
+    let invoice_id: i32 = invoice_row.get("id");
+
+    client
+        .execute(
+            "INSERT INTO invoice_lines (invoice_id, description, quantity, unit_price,
+                net_amount, tax_percentage, tax_amount, total_amount)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8);",
+            &[
+                &invoice_id,
+                &description,
+                &days,
+                &round_money(net_amount / Decimal::from(days)),
+                &net_amount,
+                &tax_percentage,
+                &tax_amount,
+                &total_amount,
+            ],
+        )
+        .await?;
+
+    Ok(invoice_id)
+}
+
+/// Issues the credit notes of a cancelled rental for the `amount` refunded to the client. The
+/// refund is credited against its invoices in order, the ones of its extensions included: an
+/// invoice covered in full is voided with a copy of its lines and a partly covered one gets a
+/// single line for what's given back. Nothing is refunded for rentals that were never invoiced
+/// (still pending payment) or cancelled without refund, so these don't get any.
+pub async fn issue_credit_note(
+    client: &impl GenericClient,
+    rental_id: i32,
+    amount: Decimal,
+) -> Result<Vec<i32>, tokio_postgres::Error> {
+    let invoice_rows = client
+        .query(
+            "SELECT id, letter, total_amount FROM invoices i
+            WHERE rental_id = $1 AND kind = 'invoice'
+            AND NOT EXISTS (SELECT 1 FROM invoices cn WHERE cn.related_invoice_id = i.id)
+            ORDER BY id;",
+            &[&rental_id],
+        )
+        .await?;
+
+    let mut credit_note_ids = Vec::new();
+    let mut remaining = amount;
+
+    for invoice_row in invoice_rows {
+        if remaining <= Decimal::ZERO {
+            break;
+        }
+
+        let invoice_id: i32 = invoice_row.get("id");
+        let letter: String = invoice_row.get("letter");
+        let invoice_total: Decimal = invoice_row.get("total_amount");
+
+        let credit_note_id = if remaining >= invoice_total {
+            void_invoice(client, invoice_id, &letter).await?
+        } else {
+            credit_invoice(client, invoice_id, &letter, remaining).await?
+        };
+
+        remaining -= invoice_total.min(remaining);
+        credit_note_ids.push(credit_note_id);
+    }
+
+    Ok(credit_note_ids)
+}
+
+async fn insert_credit_note(
+    client: &impl GenericClient,
+    invoice_id: i32,
+    letter: &str,
+    net_amount: Option<Decimal>,
+    tax_amount: Option<Decimal>,
+    total_amount: Option<Decimal>,
+) -> Result<i32, tokio_postgres::Error> {
+    let number = next_invoice_number(client, InvoiceKind::CreditNote, letter).await?;
+
+    let credit_note_row = client
+        .query_one(
+            "INSERT INTO invoices (kind, letter, point_of_sale, number, rental_id,
+                related_invoice_id, user_id, billing_name, billing_id_card, billing_tax_id,
+                billing_tax_condition, net_amount, tax_amount, total_amount)
+            SELECT 'credit_note', letter, $2, $3, rental_id, id, user_id, billing_name,
+                billing_id_card, billing_tax_id, billing_tax_condition,
+                COALESCE($4, net_amount), COALESCE($5, tax_amount), COALESCE($6, total_amount)
+            FROM invoices WHERE id = $1
+            RETURNING id;",
+            &[
+                &invoice_id,
+                &INVOICE_POINT_OF_SALE,
+                &number,
+                &net_amount,
+                &tax_amount,
+                &total_amount,
+            ],
+        )
+        .await?;
+
+    Ok(credit_note_row.get("id"))
+}
+
+async fn void_invoice(
+    client: &impl GenericClient,
+    invoice_id: i32,
+    letter: &str,
+) -> Result<i32, tokio_postgres::Error> {
+    let credit_note_id = insert_credit_note(client, invoice_id, letter, None, None, None).await?;
+
+    client
+        .execute(
+            "INSERT INTO invoice_lines (invoice_id, description, quantity, unit_price,
+                net_amount, tax_percentage, tax_amount, total_amount)
+            SELECT $1, description, quantity, unit_price, net_amount, tax_percentage,
+                tax_amount, total_amount
+            FROM invoice_lines WHERE invoice_id = $2
+            ORDER BY id;",
+            &[&credit_note_id, &invoice_id],
+        )
+        .await?;
+
+    Ok(credit_note_id)
+}
+
+/// Credits part of an invoice, with the tax rate it was issued with.
+async fn credit_invoice(
+    client: &impl GenericClient,
+    invoice_id: i32,
+    letter: &str,
+    total_amount: Decimal,
+) -> Result<i32, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT i.point_of_sale, i.number,
+                (SELECT tax_percentage FROM invoice_lines
+                WHERE invoice_id = i.id
+                ORDER BY id
+                LIMIT 1) AS tax_percentage
+            FROM invoices i
+            WHERE i.id = $1;",
+            &[&invoice_id],
+        )
+        .await?;
+
+    let tax_percentage: Decimal = row
+        .get::<_, Option<Decimal>>("tax_percentage")
+        .unwrap_or(Decimal::ZERO);
+
+    let net_amount =
+        round_money(total_amount * Decimal::ONE_HUNDRED / (Decimal::ONE_HUNDRED + tax_percentage));
+    let tax_amount = total_amount - net_amount;
+
+    let credit_note_id = insert_credit_note(
+        client,
+        invoice_id,
+        letter,
+        Some(net_amount),
+        Some(tax_amount),
+        Some(total_amount),
+    )
+    .await?;
+
+    let description = format!(
+        "Reintegro parcial por cancelación de la factura {}",
+        format_invoice_number(row.get("point_of_sale"), row.get("number"))
+    );
+
+    client
+        .execute(
+            "INSERT INTO invoice_lines (invoice_id, description, quantity, unit_price,
+                net_amount, tax_percentage, tax_amount, total_amount)
+            VALUES ($1, $2, 1, $3, $3, $4, $5, $6);",
+            &[
+                &credit_note_id,
+                &description,
+                &net_amount,
+                &tax_percentage,
+                &tax_amount,
+                &total_amount,
+            ],
+        )
+        .await?;
+
+    Ok(credit_note_id)
+}
+
+/// Loads the invoices and credit notes matching every given filter, with their lines.
+pub async fn find_invoices(
+    client: &impl GenericClient,
+    invoice_id: Option<i32>,
+    user_id: Option<i32>,
+    rental_id: Option<i32>,
+) -> Result<Vec<Invoice>, tokio_postgres::Error> {
+    let invoice_rows = client
+        .query(
+            "SELECT i.id, i.kind::TEXT, i.letter, i.point_of_sale, i.number, i.rental_id,
+                i.related_invoice_id, ri.point_of_sale AS related_point_of_sale,
+                ri.number AS related_number, i.user_id, i.billing_name, i.billing_id_card,
+                i.billing_tax_id, i.billing_tax_condition::TEXT, i.net_amount, i.tax_amount,
+                i.total_amount, i.issued_at
+            FROM invoices i
+            LEFT JOIN invoices ri ON i.related_invoice_id = ri.id
+            WHERE ($1::INT IS NULL OR i.id = $1)
+            AND ($2::INT IS NULL OR i.user_id = $2)
+            AND ($3::INT IS NULL OR i.rental_id = $3)
+            ORDER BY i.issued_at, i.id;",
+            &[&invoice_id, &user_id, &rental_id],
+        )
+        .await?;
+
+    let invoice_ids: Vec<i32> = invoice_rows.iter().map(|row| row.get("id")).collect();
+
+    let line_rows = client
+        .query(
+            "SELECT * FROM invoice_lines WHERE invoice_id = ANY($1) ORDER BY id;",
+            &[&invoice_ids],
+        )
+        .await?;
+
+    let mut lines: HashMap<i32, Vec<InvoiceLine>> = HashMap::new();
+
+    for row in line_rows {
+        lines
+            .entry(row.get("invoice_id"))
+            .or_default()
+            .push(InvoiceLine {
+                description: row.get("description"),
+                quantity: row.get("quantity"),
+                unit_price: row.get("unit_price"),
+                net_amount: row.get("net_amount"),
+                tax_percentage: row.get("tax_percentage"),
+                tax_amount: row.get("tax_amount"),
+                total_amount: row.get("total_amount"),
+            });
+    }
+
+    Ok(invoice_rows
+        .iter()
+        .map(|row| {
+            let id: i32 = row.get("id");
+            let related_number = row
+                .get::<_, Option<i32>>("related_number")
+                .map(|number| format_invoice_number(row.get("related_point_of_sale"), number));
+
+            Invoice {
+                id,
+                kind: row.get("kind"),
+                letter: row.get("letter"),
+                number: format_invoice_number(row.get("point_of_sale"), row.get("number")),
+                rental_id: row.get("rental_id"),
+                related_invoice_id: row.get("related_invoice_id"),
+                related_invoice_number: related_number,
+                user_id: row.get("user_id"),
+                billing_name: row.get("billing_name"),
+                billing_id_card: row.get("billing_id_card"),
+                billing_tax_id: row.get("billing_tax_id"),
+                billing_tax_condition: row.get("billing_tax_condition"),
+                net_amount: row.get("net_amount"),
+                tax_amount: row.get("tax_amount"),
+                total_amount: row.get("total_amount"),
+                issued_at: row.get("issued_at"),
+                lines: lines.remove(&id).unwrap_or_default(),
+            }
+        })
+        .collect())
+}
+
+pub fn write_text(
+    layer: &PdfLayerReference,
+    font: &IndirectFontRef,
+    size: f32,
+    x: f32,
+    y: f32,
+    text: &str,
+) {
+    layer.use_text(text, size, Mm(x), Mm(y), font);
+}
+
+pub fn render_invoice_pdf(invoice: &Invoice) -> Result<Vec<u8>, printpdf::Error> {
+    let title = if invoice.kind == InvoiceKind::CreditNote.to_string() {
+        "NOTA DE CRÉDITO"
+    } else {
+        "FACTURA"
+    };
+
+    let (document, page, layer) = PdfDocument::new(
+        format!("{} {} {}", title, invoice.letter, invoice.number),
+        Mm(210.0),
+        Mm(297.0),
+        "Layer 1",
+    );
+    let regular = document.add_builtin_font(BuiltinFont::Helvetica)?;
+    let bold = document.add_builtin_font(BuiltinFont::HelveticaBold)?;
+    let layer = document.get_page(page).get_layer(layer);
+
+    write_text(&layer, &bold, 18.0, 15.0, 275.0, COMPANY_NAME);
+    write_text(
+        &layer,
+        &regular,
+        10.0,
+        15.0,
+        268.0,
+        &format!("CUIT: {}", COMPANY_TAX_ID),
+    );
+    write_text(
+        &layer,
+        &regular,
+        10.0,
+        15.0,
+        263.0,
+        "IVA Responsable Inscripto",
+    );
+
+    write_text(
+        &layer,
+        &bold,
+        16.0,
+        120.0,
+        275.0,
+        &format!("{} {}", title, invoice.letter),
+    );
+    write_text(
+        &layer,
+        &regular,
+        10.0,
+        120.0,
+        268.0,
+        &format!("N° {}", invoice.number),
+    );
+    write_text(
+        &layer,
+        &regular,
+        10.0,
+        120.0,
+        263.0,
+        &format!("Fecha de emisión: {}", invoice.issued_at.format("%d/%m/%Y")),
+    );
+
+    let mut y = 248.0;
+    let mut client_data = vec![
+        format!("Cliente: {}", invoice.billing_name),
+        format!("DNI: {}", invoice.billing_id_card),
+    ];
+    if let Some(tax_id) = &invoice.billing_tax_id {
+        client_data.push(format!("CUIT: {}", tax_id));
+    }
+    client_data.push(format!(
+        "Condición frente al IVA: {}",
+        tax_condition_label(&invoice.billing_tax_condition)
+    ));
+    client_data.push(format!("Alquiler n° {}", invoice.rental_id));
+    if let Some(related_number) = &invoice.related_invoice_number {
+        client_data.push(format!(
+            "Anula la factura {} {}",
+            invoice.letter, related_number
+        ));
+    }
+
+    for text in client_data {
+        write_text(&layer, &regular, 10.0, 15.0, y, &text);
+        y -= 5.0;
+    }
+
+    y -= 8.0;
+    let columns = [
+        (15.0, "Cant."),
+        (35.0, "P. unitario"),
+        (75.0, "Neto"),
+        (110.0, "IVA %"),
+        (135.0, "IVA"),
+        (170.0, "Total"),
+    ];
+    for (x, header) in columns {
+        write_text(&layer, &bold, 10.0, x, y, header);
+    }
+    y -= 8.0;
+
+    for line in &invoice.lines {
+        write_text(&layer, &regular, 10.0, 15.0, y, &line.description);
+        y -= 5.0;
+
+        let values = [
+            line.quantity.to_string(),
+            format!("$ {}", line.unit_price),
+            format!("$ {}", line.net_amount),
+            format!("{}%", line.tax_percentage),
+            format!("$ {}", line.tax_amount),
+            format!("$ {}", line.total_amount),
+        ];
+        for ((x, _), value) in columns.iter().zip(values) {
+            write_text(&layer, &regular, 10.0, *x, y, &value);
+        }
+        y -= 8.0;
+    }
+
+    y -= 6.0;
+    let totals = [
+        ("Neto gravado", invoice.net_amount),
+        ("IVA", invoice.tax_amount),
+        ("Total", invoice.total_amount),
+    ];
+    for (label, amount) in totals {
+        write_text(&layer, &bold, 11.0, 135.0, y, label);
+        write_text(&layer, &regular, 11.0, 170.0, y, &format!("$ {}", amount));
+        y -= 6.0;
+    }
+
+    document.save_to_bytes()
+}
@@ -3,6 +3,15 @@ use axum::{http::StatusCode, Json};
 use chrono::NaiveDate;
 use serde_json::json;
 
+pub fn overdue_charges_response() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({
+            "message": "Tiene cargos vencidos sin pagar. Debe saldarlos antes de realizar un nuevo alquiler",
+        })),
+    )
+}
+
 pub fn validate_client(access_token: &str) -> Option<(StatusCode, Json<serde_json::Value>)> {
     let claims = match validate_jwt(&access_token) {
         Some(data) => data,
@@ -25,6 +34,28 @@ pub fn validate_client(access_token: &str) -> Option<(StatusCode, Json<serde_jso
     return None;
 }
 
+pub fn validate_admin(access_token: &str) -> Option<(StatusCode, Json<serde_json::Value>)> {
+    let claims = match validate_jwt(access_token) {
+        Some(data) => data,
+        None => {
+            return Some((
+                StatusCode::UNAUTHORIZED,
+                Json(json!({"message": "Invalid access token"})),
+            ));
+        }
+    }
+    .claims;
+
+    if claims.role != 0 {
+        return Some((
+            StatusCode::FORBIDDEN,
+            Json(json!({"message": "The user is not an admin"})),
+        ));
+    }
+
+    None
+}
+
 pub fn get_claims_from_token(access_token: &str) -> Option<Claims> {
     match validate_jwt(&access_token) {
         Some(data) => Some(data.claims),
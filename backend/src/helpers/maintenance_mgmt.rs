@@ -0,0 +1,279 @@
+use crate::custom_types::{
+    enums::{FutureRentalsAction, RentalActor},
+    structs::*,
+};
+use crate::helpers::{
+    auth::send_mail,
+    rentals::{
+        cancel_and_refund_rental, is_overlapping_rental, lock_unit_period,
+        overlapping_rental_response, paid_amount, Refund,
+    },
+};
+use axum::{http::StatusCode, Json};
+use chrono::NaiveDate;
+use deadpool_postgres::Transaction;
+use rust_decimal::Decimal;
+use serde_json::json;
+
+/// Takes the given units out of the fleet inside the caller's transaction.
+/// Rentals that are still pending or active on those units block the operation
+/// unless `action` says whether they must be moved to another unit or cancelled.
+pub async fn decommission_units(
+    transaction: &Transaction<'_>,
+    unit_ids: &[i32],
+    date: NaiveDate,
+    reason: &str,
+    action: Option<FutureRentalsAction>,
+    actor: RentalActor,
+) -> Result<DecommissionSummary, (StatusCode, Json<serde_json::Value>)> {
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Ocurrió un error al dar de baja la unidad"})),
+        )
+    };
+
+    let unit_rows = transaction
+        .query(
+            "SELECT id, status::TEXT, model_id, location_id FROM machinery_units
+            WHERE id = ANY($1) FOR UPDATE;",
+            &[&unit_ids],
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    for row in &unit_rows {
+        match row.get::<_, String>("status").as_str() {
+            "decommissioned" => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"message": "La unidad ya fue dada de baja"})),
+                ))
+            }
+            "rented" => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "message": "La unidad se encuentra alquilada. Registre su devolución antes de darla de baja"
+                    })),
+                ))
+            }
+            "in_transit" => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "message": "La unidad está en traslado. Registre su recepción antes de darla de baja"
+                    })),
+                ))
+            }
+            _ => (),
+        }
+    }
+
+    // Transfers not yet shipped are dropped along with the unit
+    transaction
+        .execute(
+            "UPDATE unit_transfers
+            SET status = 'cancelled', cancelled_by = $2, cancelled_at = NOW()
+            WHERE unit_id = ANY($1) AND status = 'requested';",
+            &[&unit_ids, &actor.user_id()],
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    let rental_rows = transaction
+        .query(
+            "SELECT r.id, r.machine_id, r.start_date, r.end_date, mu.model_id, mu.location_id
+            FROM rentals r
+            INNER JOIN machinery_units mu ON r.machine_id = mu.id
+            WHERE r.machine_id = ANY($1) AND r.status IN ('active', 'pending_payment')
+            ORDER BY r.start_date;",
+            &[&unit_ids],
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    let mut summary = DecommissionSummary::default();
+
+    if !rental_rows.is_empty() {
+        let rental_ids: Vec<i32> = rental_rows.iter().map(|row| row.get("id")).collect();
+
+        match action {
+            None => {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(json!({
+                        "message": "Existen alquileres vigentes. Indique si deben reasignarse o cancelarse",
+                        "rental_ids": rental_ids,
+                    })),
+                ));
+            }
+            Some(FutureRentalsAction::Cancel) => {
+                // The client isn't to blame, so whatever was paid is given back
+                for rental_id in &rental_ids {
+                    let refund = Refund {
+                        percentage: Decimal::ONE_HUNDRED,
+                        amount: paid_amount(transaction, *rental_id)
+                            .await
+                            .map_err(|_| internal_error())?,
+                    };
+
+                    cancel_and_refund_rental(transaction, *rental_id, refund, actor, Some(reason))
+                        .await
+                        .map_err(|_| internal_error())?;
+                }
+
+                summary.cancelled_rentals = rental_ids;
+            }
+            Some(FutureRentalsAction::Reassign) => {
+                // The replacement must be a unit of the same model at the same branch
+                // whose calendar (maintenance period included) is free for the rental.
+                let candidates_query = "
+                    SELECT id FROM machinery_units
+                    WHERE model_id = $1 AND location_id = $2
+                    AND status != 'decommissioned' AND id != ALL($3)
+                    ORDER BY id;
+                ";
+
+                for row in &rental_rows {
+                    let rental_id: i32 = row.get("id");
+                    let previous_unit_id: i32 = row.get("machine_id");
+                    let model_id: i32 = row.get("model_id");
+                    let location_id: i32 = row.get("location_id");
+                    let start_date: NaiveDate = row.get("start_date");
+                    let end_date: NaiveDate = row.get("end_date");
+
+                    let candidates = transaction
+                        .query(candidates_query, &[&model_id, &location_id, &unit_ids])
+                        .await
+                        .map_err(|_| internal_error())?;
+
+                    let mut replacement = None;
+
+                    for candidate in &candidates {
+                        let unit_id: i32 = candidate.get("id");
+
+                        if lock_unit_period(transaction, unit_id, start_date, end_date)
+                            .await
+                            .map_err(|_| internal_error())?
+                        {
+                            replacement = Some(unit_id);
+                            break;
+                        }
+                    }
+
+                    let Some(new_unit_id) = replacement else {
+                        return Err((
+                            StatusCode::CONFLICT,
+                            Json(json!({
+                                "message": "No hay otra unidad disponible en la sucursal para reasignar el alquiler",
+                                "rental_id": rental_id,
+                            })),
+                        ));
+                    };
+
+                    transaction
+                        .execute(
+                            "UPDATE rentals SET machine_id = $1, updated_at = NOW() WHERE id = $2;",
+                            &[&new_unit_id, &rental_id],
+                        )
+                        .await
+                        .map_err(|e| {
+                            if is_overlapping_rental(&e) {
+                                overlapping_rental_response()
+                            } else {
+                                internal_error()
+                            }
+                        })?;
+
+                    summary.reassigned_rentals.push(ReassignedRental {
+                        rental_id,
+                        previous_unit_id,
+                        new_unit_id,
+                    });
+                }
+            }
+        }
+    }
+
+    transaction
+        .execute(
+            "INSERT INTO unit_history_events (unit_id, description, previous_status, new_status)
+            SELECT id, $2, status, 'decommissioned' FROM machinery_units WHERE id = ANY($1);",
+            &[&unit_ids, &reason],
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    transaction
+        .execute(
+            "UPDATE machinery_units
+            SET status = 'decommissioned', decommissioned_at = $2, decommission_reason = $3
+            WHERE id = ANY($1);",
+            &[&unit_ids, &date, &reason],
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    Ok(summary)
+}
+
+/// Lets the clients of rentals cancelled by a decommission know about it.
+/// Failures are only logged since the decommission has already been committed.
+pub async fn notify_decommission_cancellations(
+    client: &deadpool_postgres::Client,
+    rental_ids: &[i32],
+) {
+    let rows = match client
+        .query(
+            "SELECT r.id, r.start_date, r.end_date, u.email, u.name AS user_name,
+            mm.name, mm.brand, mm.model
+            FROM rentals r
+            INNER JOIN users u ON r.user_id = u.id
+            INNER JOIN machinery_units mu ON r.machine_id = mu.id
+            INNER JOIN machinery_models mm ON mu.model_id = mm.id
+            WHERE r.id = ANY($1);",
+            &[&rental_ids],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Error al obtener los alquileres cancelados: {}", e);
+            return;
+        }
+    };
+
+    for row in rows {
+        let rental_id: i32 = row.get("id");
+        let start_date: NaiveDate = row.get("start_date");
+        let end_date: NaiveDate = row.get("end_date");
+
+        let subject = format!("Alquiler n° {} cancelado - Bob el Alquilador", rental_id);
+        let body = format!(
+            "Hola {},\n\n\
+            Se le informa que se ha cancelado su alquiler porque la máquina fue retirada de servicio.\n\n\
+            Número de alquiler:\t\t\t {}\n\
+            Período:\t\t\t {} - {}\n\
+            Máquina:\t\t\t {} {} {}\n\n\
+            En caso de que corresponda, en la brevedad se le reintegrará la totalidad del monto abonado.\n\
+            Nos disculpamos por las molestias ocasionadas.\n\n\
+            Saludos cordiales,\n\
+            El equipo de Bob el Alquilador\n",
+            row.get::<_, String>("user_name"),
+            rental_id,
+            start_date.format("%d/%m/%Y"),
+            end_date.format("%d/%m/%Y"),
+            row.get::<_, String>("name"),
+            row.get::<_, String>("brand"),
+            row.get::<_, String>("model"),
+        );
+
+        if let Err(e) = send_mail(&row.get::<_, String>("email"), &subject, &body) {
+            eprintln!(
+                "Error al notificar la cancelación del alquiler {}: {}",
+                rental_id, e
+            );
+        }
+    }
+}
@@ -0,0 +1,536 @@
+use crate::constants::LOCAL_MEDIA_ROOT;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use quick_xml::{events::Event, Reader};
+use reqwest::{Method, Url};
+use sha2::{Digest, Sha256};
+use std::{env, io::ErrorKind, path::PathBuf, sync::Arc, time::Duration};
+
+/// Storage of the uploaded media. Keys are relative paths such as `machines/<name>.webp`.
+#[async_trait]
+pub trait MediaStore: Send + Sync {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), String>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String>;
+
+    /// Deleting a missing key is not an error.
+    async fn delete(&self, key: &str) -> Result<(), String>;
+
+    /// Keys of the objects stored right under `prefix`, which must end with `/`.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String>;
+
+    /// URL of the object that stops working once `expires_in` has passed.
+    fn signed_url(&self, key: &str, expires_in: Duration) -> String;
+
+    /// URL handed to the clients. It's signed when the store was configured with a TTL.
+    fn url(&self, key: &str) -> String;
+}
+
+/// Builds the store selected by `MEDIA_STORE` (`local` by default, or `s3`).
+pub fn media_store_from_env() -> Result<Arc<dyn MediaStore>, String> {
+    let url_ttl = match env::var("MEDIA_URL_TTL_SECONDS") {
+        Ok(seconds) => Some(Duration::from_secs(seconds.parse().map_err(|_| {
+            "MEDIA_URL_TTL_SECONDS must be a number of seconds".to_string()
+        })?)),
+        Err(_) => None,
+    };
+
+    let required =
+        |name: &str| env::var(name).map_err(|_| format!("{} must be set in the .env file", name));
+
+    match env::var("MEDIA_STORE").as_deref() {
+        Ok("local") | Err(_) => {
+            let link_secret = env::var("MEDIA_URL_SECRET").ok();
+
+            if url_ttl.is_some() && link_secret.is_none() {
+                return Err("MEDIA_URL_SECRET is required to sign local media URLs".to_string());
+            }
+
+            Ok(Arc::new(LocalMediaStore::new(
+                LOCAL_MEDIA_ROOT,
+                &format!("{}/{}", required("NGINX_URL")?, LOCAL_MEDIA_ROOT),
+                link_secret,
+                url_ttl,
+            )))
+        }
+        Ok("s3") => Ok(Arc::new(S3MediaStore::new(
+            &required("S3_ENDPOINT")?,
+            &required("S3_BUCKET")?,
+            SigV4::new(
+                &required("S3_ACCESS_KEY")?,
+                &required("S3_SECRET_KEY")?,
+                &env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            ),
+            env::var("S3_PUBLIC_URL").ok(),
+            url_ttl,
+        )?)),
+        Ok(other) => Err(format!(
+            "Unknown MEDIA_STORE '{}': expected local or s3",
+            other
+        )),
+    }
+}
+
+/// Media kept on the API host and served by nginx. Signed URLs follow the format of nginx's
+/// `secure_link` module, so the location serving them must check them with the same secret.
+pub struct LocalMediaStore {
+    root: PathBuf,
+    base_url: String,
+    link_secret: Option<String>,
+    url_ttl: Option<Duration>,
+}
+
+impl LocalMediaStore {
+    pub fn new(
+        root: impl Into<PathBuf>,
+        base_url: &str,
+        link_secret: Option<String>,
+        url_ttl: Option<Duration>,
+    ) -> Self {
+        LocalMediaStore {
+            root: root.into(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            link_secret,
+            url_ttl,
+        }
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, key)
+    }
+}
+
+#[async_trait]
+impl MediaStore for LocalMediaStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> Result<(), String> {
+        let path = self.root.join(key);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        tokio::fs::write(path, bytes)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        tokio::fs::read(self.root.join(key))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        match tokio::fs::remove_file(self.root.join(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let mut entries = match tokio::fs::read_dir(self.root.join(prefix)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let mut keys = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            let is_file = entry
+                .file_type()
+                .await
+                .map_err(|e| e.to_string())?
+                .is_file();
+
+            if is_file {
+                keys.push(format!("{}{}", prefix, entry.file_name().to_string_lossy()));
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn signed_url(&self, key: &str, expires_in: Duration) -> String {
+        let url = self.public_url(key);
+
+        let Some(secret) = &self.link_secret else {
+            return url;
+        };
+
+        let expires = Utc::now().timestamp() + expires_in.as_secs() as i64;
+        let path = Url::parse(&url)
+            .map(|parsed| parsed.path().to_string())
+            .unwrap_or_default();
+
+        let digest = Md5::digest(format!("{}{} {}", expires, path, secret));
+
+        format!(
+            "{}?md5={}&expires={}",
+            url,
+            URL_SAFE_NO_PAD.encode(digest),
+            expires
+        )
+    }
+
+    fn url(&self, key: &str) -> String {
+        match self.url_ttl {
+            Some(ttl) => self.signed_url(key, ttl),
+            None => self.public_url(key),
+        }
+    }
+}
+
+/// Media kept in a bucket of an S3-compatible service (AWS, MinIO...), addressed path-style
+/// so it works without wildcard DNS.
+pub struct S3MediaStore {
+    http: reqwest::Client,
+    origin: String,
+    host: String,
+    bucket: String,
+    signer: SigV4,
+    public_url: Option<String>,
+    url_ttl: Option<Duration>,
+}
+
+impl S3MediaStore {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        signer: SigV4,
+        public_url: Option<String>,
+        url_ttl: Option<Duration>,
+    ) -> Result<Self, String> {
+        let endpoint = Url::parse(endpoint).map_err(|e| format!("Invalid S3 endpoint: {}", e))?;
+
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err("Invalid S3 endpoint: missing host".to_string()),
+        };
+
+        Ok(S3MediaStore {
+            http: reqwest::Client::new(),
+            origin: format!("{}://{}", endpoint.scheme(), host),
+            host,
+            bucket: bucket.to_string(),
+            signer,
+            public_url: public_url.map(|url| url.trim_end_matches('/').to_string()),
+            url_ttl,
+        })
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!("/{}/{}", self.bucket, uri_encode(key, false))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<reqwest::Response, String> {
+        let query = canonical_query(query);
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let now = Utc::now();
+
+        let authorization = self.signer.authorization(
+            method.as_str(),
+            &self.host,
+            path,
+            &query,
+            &payload_hash,
+            now,
+        );
+
+        let mut url = format!("{}{}", self.origin, path);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query);
+        }
+
+        let mut request = self
+            .http
+            .request(method.clone(), url)
+            .header("authorization", authorization)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date(now))
+            .body(body);
+
+        if let Some(content_type) = content_type {
+            request = request.header("content-type", content_type);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "S3 answered {} to {} {}",
+                response.status(),
+                method,
+                path
+            ));
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl MediaStore for S3MediaStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), String> {
+        self.send(
+            Method::PUT,
+            &self.object_path(key),
+            &[],
+            bytes,
+            Some(content_type),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        let response = self
+            .send(Method::GET, &self.object_path(key), &[], Vec::new(), None)
+            .await?;
+
+        response
+            .bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        self.send(
+            Method::DELETE,
+            &self.object_path(key),
+            &[],
+            Vec::new(),
+            None,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        let bucket_path = format!("/{}", self.bucket);
+        let mut keys = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut query = vec![("list-type", "2"), ("delimiter", "/"), ("prefix", prefix)];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token", token));
+            }
+
+            let body = self
+                .send(Method::GET, &bucket_path, &query, Vec::new(), None)
+                .await?
+                .text()
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let (page_keys, next_token) = parse_list_objects(&body)?;
+            keys.extend(page_keys);
+
+            match next_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok(keys),
+            }
+        }
+    }
+
+    fn signed_url(&self, key: &str, expires_in: Duration) -> String {
+        let path = self.object_path(key);
+        let query = self
+            .signer
+            .presign(&self.host, &path, Utc::now(), expires_in);
+
+        format!("{}{}?{}", self.origin, path, query)
+    }
+
+    fn url(&self, key: &str) -> String {
+        match (self.url_ttl, &self.public_url) {
+            (Some(ttl), _) => self.signed_url(key, ttl),
+            (None, Some(public_url)) => format!("{}/{}", public_url, uri_encode(key, false)),
+            (None, None) => format!("{}{}", self.origin, self.object_path(key)),
+        }
+    }
+}
+
+/// AWS Signature Version 4 for the S3 service.
+pub struct SigV4 {
+    access_key: String,
+    secret_key: String,
+    region: String,
+}
+
+impl SigV4 {
+    pub fn new(access_key: &str, secret_key: &str, region: &str) -> Self {
+        SigV4 {
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            region: region.to_string(),
+        }
+    }
+
+    fn credential(&self, at: DateTime<Utc>) -> String {
+        format!(
+            "{}/{}/{}/s3/aws4_request",
+            self.access_key,
+            at.format("%Y%m%d"),
+            self.region
+        )
+    }
+
+    fn signature(&self, at: DateTime<Utc>, canonical_request: &str) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}/{}/s3/aws4_request\n{}",
+            amz_date(at),
+            at.format("%Y%m%d"),
+            self.region,
+            hex::encode(Sha256::digest(canonical_request))
+        );
+
+        let mut key = hmac_sha256(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            at.format("%Y%m%d").to_string().as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+
+        hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()))
+    }
+
+    /// `Authorization` header of a request that also sends the `x-amz-content-sha256` and
+    /// `x-amz-date` headers. `path` and `query` must already be URI encoded.
+    pub fn authorization(
+        &self,
+        method: &str,
+        host: &str,
+        path: &str,
+        query: &str,
+        payload_hash: &str,
+        at: DateTime<Utc>,
+    ) -> String {
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            path,
+            query,
+            host,
+            payload_hash,
+            amz_date(at),
+            signed_headers,
+            payload_hash
+        );
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}, SignedHeaders={}, Signature={}",
+            self.credential(at),
+            signed_headers,
+            self.signature(at, &canonical_request)
+        )
+    }
+
+    /// Query string of a presigned GET of `path`.
+    pub fn presign(
+        &self,
+        host: &str,
+        path: &str,
+        at: DateTime<Utc>,
+        expires_in: Duration,
+    ) -> String {
+        let credential = self.credential(at);
+        let date = amz_date(at);
+        let expires = expires_in.as_secs().to_string();
+
+        let query = canonical_query(&[
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256"),
+            ("X-Amz-Credential", &credential),
+            ("X-Amz-Date", &date),
+            ("X-Amz-Expires", &expires),
+            ("X-Amz-SignedHeaders", "host"),
+        ]);
+
+        let canonical_request = format!(
+            "GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+            path, query, host
+        );
+
+        format!(
+            "{}&X-Amz-Signature={}",
+            query,
+            self.signature(at, &canonical_request)
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn amz_date(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if !encode_slash => "/".to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn canonical_query(params: &[(&str, &str)]) -> String {
+    let mut encoded: Vec<String> = params
+        .iter()
+        .map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true)))
+        .collect();
+    encoded.sort();
+
+    encoded.join("&")
+}
+
+/// Keys and continuation token of a ListObjectsV2 response.
+fn parse_list_objects(xml: &str) -> Result<(Vec<String>, Option<String>), String> {
+    let mut reader = Reader::from_str(xml);
+    let mut keys = Vec::new();
+    let mut next_token = None;
+    let mut current_tag: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(tag) => current_tag = Some(tag.name().as_ref().to_vec()),
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| e.to_string())?.into_owned();
+
+                match current_tag.as_deref() {
+                    Some(b"Key") => keys.push(text),
+                    Some(b"NextContinuationToken") => next_token = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => current_tag = None,
+            Event::Eof => return Ok((keys, next_token)),
+            _ => {}
+        }
+    }
+}
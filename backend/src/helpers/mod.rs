@@ -1,2 +1,18 @@
 pub mod auth;
+pub mod calendar;
+pub mod charges;
+pub mod deliveries;
+pub mod deposits;
+pub mod documents;
+pub mod images;
+pub mod inspections;
+pub mod invoices;
 pub mod machinery_mgmt;
+pub mod maintenance_mgmt;
+pub mod media;
+pub mod orders;
+pub mod pricing;
+pub mod rentals;
+pub mod specs;
+pub mod transfers;
+pub mod waitlist;
@@ -0,0 +1,299 @@
+use crate::custom_types::enums::{RentalActor, RentalStatus};
+use crate::custom_types::structs::{Order, OrderLine};
+use crate::helpers::{
+    invoices::issue_rental_invoice,
+    rentals::{lock_rental_period, transition_rental, RentalTransitionError},
+};
+use deadpool_postgres::GenericClient;
+use rust_decimal::Decimal;
+
+/// Status of an order, which isn't stored but follows from its payment and its lines.
+const ORDER_STATUS: &str = "CASE
+    WHEN o.paid_at IS NOT NULL THEN 'paid'
+    WHEN EXISTS (
+        SELECT 1 FROM rentals r
+        WHERE r.order_id = o.id AND r.status = 'pending_payment'
+        AND COALESCE(r.payment_deadline > NOW(), TRUE)
+    ) THEN 'pending_payment'
+    WHEN EXISTS (
+        SELECT 1 FROM rentals r WHERE r.order_id = o.id AND r.status = 'failed'
+    ) THEN 'failed'
+    ELSE 'cancelled'
+    END";
+
+pub enum OrderPaymentOutcome {
+    Paid,
+    /// The hold of the order ran out before the payment was confirmed and some of its dates were
+    /// taken. The payment is kept on record for the refund. Holds the lines failed.
+    Lapsed(Vec<i32>),
+    /// No order of the client is waiting for payment.
+    NotFound,
+}
+
+pub async fn get_order(
+    client: &impl GenericClient,
+    order_id: i32,
+) -> Result<Option<Order>, tokio_postgres::Error> {
+    let Some(row) = client
+        .query_opt(
+            &format!(
+                "SELECT o.id, o.user_id, o.created_at, o.payment_id, o.paid_at,
+                {} AS status,
+                (
+                    SELECT MIN(r.payment_deadline) FROM rentals r
+                    WHERE r.order_id = o.id AND r.status = 'pending_payment'
+                ) AS payment_deadline
+                FROM orders o
+                WHERE o.id = $1;",
+                ORDER_STATUS
+            ),
+            &[&order_id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let line_rows = client
+        .query(
+            "SELECT r.id AS rental_id, r.machine_id, mm.id AS model_id, mm.name AS model_name,
+            mm.brand AS model_brand, mm.model AS model_model, mu.location_id, r.start_date,
+            r.end_date, r.status::TEXT, r.total_price, r.deposit_amount, r.refund_amount
+            FROM rentals r
+            INNER JOIN machinery_units mu ON r.machine_id = mu.id
+            INNER JOIN machinery_models mm ON mu.model_id = mm.id
+            WHERE r.order_id = $1
+            ORDER BY r.id;",
+            &[&order_id],
+        )
+        .await?;
+
+    let lines: Vec<OrderLine> = line_rows.iter().map(OrderLine::build_from_row).collect();
+
+    let (total_price, deposit) = lines
+        .iter()
+        .filter(|line| line.status != "cancelled" && line.status != "failed")
+        .fold((Decimal::ZERO, Decimal::ZERO), |(total, deposit), line| {
+            (total + line.total_price, deposit + line.deposit_amount)
+        });
+
+    Ok(Some(Order {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        status: row.get("status"),
+        created_at: row.get("created_at"),
+        payment_id: row.get("payment_id"),
+        paid_at: row.get("paid_at"),
+        payment_deadline: row.get("payment_deadline"),
+        total_price,
+        deposit,
+        lines,
+    }))
+}
+
+/// Fails the lines of an order still waiting for payment and returns their ids.
+async fn fail_order_lines(
+    client: &impl GenericClient,
+    order_id: i32,
+    actor: RentalActor,
+    reason: &str,
+) -> Result<Vec<i32>, RentalTransitionError> {
+    let rows = client
+        .query(
+            "SELECT id FROM rentals
+            WHERE order_id = $1 AND status = 'pending_payment'
+            ORDER BY id
+            FOR UPDATE;",
+            &[&order_id],
+        )
+        .await?;
+
+    let mut failed = Vec::new();
+
+    for row in rows {
+        let rental_id: i32 = row.get("id");
+
+        transition_rental(client, rental_id, RentalStatus::Failed, actor, Some(reason)).await?;
+        failed.push(rental_id);
+    }
+
+    Ok(failed)
+}
+
+/// Activates every line of an unpaid order of the client with one payment. Once the hold ran
+/// out, the lines are only activated if none of them lost its dates in the meantime; otherwise
+/// the whole order fails and every line is marked to refund what was paid for it. Lines the
+/// client cancelled before paying stay cancelled.
+pub async fn confirm_order_payment(
+    client: &mut deadpool_postgres::Client,
+    order_id: i32,
+    user_id: i32,
+    payment_id: &str,
+) -> Result<OrderPaymentOutcome, RentalTransitionError> {
+    let transaction = client.transaction().await?;
+
+    let Some(order) = transaction
+        .query_opt(
+            "SELECT payment_id FROM orders
+            WHERE id = $1 AND user_id = $2 AND paid_at IS NULL
+            FOR UPDATE;",
+            &[&order_id, &user_id],
+        )
+        .await?
+    else {
+        return Ok(OrderPaymentOutcome::NotFound);
+    };
+
+    // Already paid late once, and marked for refund then
+    if order.get::<_, Option<String>>("payment_id").is_some() {
+        return Ok(OrderPaymentOutcome::Lapsed(Vec::new()));
+    }
+
+    // Sorted as bookings lock their units, so that they can't deadlock each other
+    let line_rows = transaction
+        .query(
+            "SELECT id, machine_id, start_date, end_date, status::TEXT,
+            COALESCE(payment_deadline <= NOW(), FALSE) AS lapsed
+            FROM rentals
+            WHERE order_id = $1 AND status IN ('pending_payment', 'failed')
+            ORDER BY machine_id, start_date
+            FOR UPDATE;",
+            &[&order_id],
+        )
+        .await?;
+
+    if line_rows.is_empty() {
+        return Ok(OrderPaymentOutcome::NotFound);
+    }
+
+    // Lines failed by the hold expiry may already have lost their dates to other rentals
+    let lapsed = line_rows.iter().any(|row| {
+        row.get::<_, bool>("lapsed")
+            || row.get::<_, String>("status") == RentalStatus::Failed.to_string()
+    });
+
+    if lapsed {
+        let mut dates_kept = true;
+
+        for row in &line_rows {
+            if !lock_rental_period(
+                &transaction,
+                row.get("id"),
+                row.get("machine_id"),
+                row.get("start_date"),
+                row.get("end_date"),
+            )
+            .await?
+            {
+                dates_kept = false;
+                break;
+            }
+        }
+
+        if !dates_kept {
+            let failed = fail_order_lines(
+                &transaction,
+                order_id,
+                RentalActor::Client(user_id),
+                "El pago se confirmó después del plazo de reserva",
+            )
+            .await?;
+
+            // The deposit is paid along with the rental
+            let line_ids: Vec<i32> = line_rows.iter().map(|row| row.get("id")).collect();
+
+            transaction
+                .execute(
+                    "UPDATE rentals
+                    SET payment_id = $1, refund_amount = total_price + deposit_amount,
+                    updated_at = NOW()
+                    WHERE id = ANY($2);",
+                    &[&payment_id, &line_ids],
+                )
+                .await?;
+
+            transaction
+                .execute(
+                    "UPDATE orders SET payment_id = $1 WHERE id = $2;",
+                    &[&payment_id, &order_id],
+                )
+                .await?;
+
+            transaction.commit().await?;
+
+            return Ok(OrderPaymentOutcome::Lapsed(failed));
+        }
+    }
+
+    for row in line_rows {
+        let rental_id: i32 = row.get("id");
+
+        transition_rental(
+            &transaction,
+            rental_id,
+            RentalStatus::Active,
+            RentalActor::Client(user_id),
+            None,
+        )
+        .await?;
+
+        // The deposit is paid along with the rental
+        transaction
+            .execute(
+                "UPDATE rentals
+                SET payment_id = $1,
+                deposit_status = CASE WHEN deposit_amount > 0 THEN 'held'::deposit_status END
+                WHERE id = $2;",
+                &[&payment_id, &rental_id],
+            )
+            .await?;
+
+        issue_rental_invoice(&transaction, rental_id).await?;
+    }
+
+    transaction
+        .execute(
+            "UPDATE orders SET payment_id = $1, paid_at = NOW() WHERE id = $2;",
+            &[&payment_id, &order_id],
+        )
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(OrderPaymentOutcome::Paid)
+}
+
+/// Fails the lines of an unpaid order of the client whose payment was rejected. `None` when
+/// there is no such order.
+pub async fn reject_order_payment(
+    client: &mut deadpool_postgres::Client,
+    order_id: i32,
+    user_id: i32,
+) -> Result<Option<Vec<i32>>, RentalTransitionError> {
+    let transaction = client.transaction().await?;
+
+    let order = transaction
+        .query_opt(
+            "SELECT id FROM orders
+            WHERE id = $1 AND user_id = $2 AND paid_at IS NULL
+            FOR UPDATE;",
+            &[&order_id, &user_id],
+        )
+        .await?;
+
+    if order.is_none() {
+        return Ok(None);
+    }
+
+    let failed = fail_order_lines(
+        &transaction,
+        order_id,
+        RentalActor::Client(user_id),
+        "El pago fue rechazado",
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(Some(failed))
+}
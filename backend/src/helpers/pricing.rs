@@ -0,0 +1,232 @@
+use crate::constants::{QUOTE_EXPIRATION_MINUTES, QUOTE_TOKEN_AUDIENCE};
+use crate::custom_types::structs::*;
+use axum::{http::StatusCode, Json};
+use chrono::{Duration, NaiveDate, Utc};
+use deadpool_postgres::GenericClient;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde_json::json;
+use std::env;
+
+/// Amounts are always charged in whole cents, rounding half away from zero.
+pub fn round_money(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Computes the price of renting a unit between `start_date` and `end_date`.
+///
+/// Each day is charged at the model's base price times the highest seasonal
+/// multiplier active that day, and the resulting subtotal gets the discount of the
+/// longest duration tier reached (a model's own tier wins over a global one with the same length).
+pub async fn quote_rental(
+    client: &impl GenericClient,
+    machine_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<PriceQuote, (StatusCode, Json<serde_json::Value>)> {
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno al calcular el precio"})),
+        )
+    };
+
+    let model_row = client
+        .query_opt(
+            "SELECT mm.id, mm.price, mm.deposit FROM machinery_models mm
+            INNER JOIN machinery_units mu ON mm.id = mu.model_id
+            WHERE mu.id = $1 AND mu.status != 'decommissioned';",
+            &[&machine_id],
+        )
+        .await
+        .map_err(|_| internal_error())?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "No se ha encontrado la máquina solicitada"})),
+        ))?;
+
+    let model_id: i32 = model_row.get("id");
+    let base_daily_price: Decimal = model_row.get("price");
+    let deposit: Decimal = model_row.get("deposit");
+    let days = (end_date - start_date).num_days();
+
+    let season_rows = client
+        .query(
+            "SELECT name, start_date, end_date, multiplier FROM seasonal_rates
+            WHERE (model_id = $1 OR model_id IS NULL)
+            AND start_date < $3 AND end_date >= $2
+            ORDER BY multiplier DESC;",
+            &[&model_id, &start_date, &end_date],
+        )
+        .await
+        .map_err(|_| internal_error())?;
+
+    // Consecutive days charged under the same rule are grouped into a single line
+    let mut lines: Vec<QuoteLine> = Vec::new();
+
+    for day in start_date.iter_days().take(days as usize) {
+        let season = season_rows
+            .iter()
+            .find(|row| row.get::<_, NaiveDate>("start_date") <= day && day <= row.get("end_date"));
+
+        let (description, multiplier) = match season {
+            Some(row) => (row.get("name"), row.get("multiplier")),
+            None => ("Tarifa base".to_string(), Decimal::ONE),
+        };
+
+        match lines.last_mut() {
+            Some(line) if line.description == description && line.multiplier == multiplier => {
+                line.end_date = day + Duration::days(1);
+                line.days += 1;
+            }
+            _ => lines.push(QuoteLine {
+                description,
+                start_date: day,
+                end_date: day + Duration::days(1),
+                days: 1,
+                daily_price: round_money(base_daily_price * multiplier),
+                multiplier,
+                amount: Decimal::ZERO,
+            }),
+        }
+    }
+
+    for line in lines.iter_mut() {
+        line.amount = round_money(line.daily_price * Decimal::from(line.days));
+    }
+
+    let discount_percentage = client
+        .query_opt(
+            "SELECT discount_percentage FROM duration_discounts
+            WHERE (model_id = $1 OR model_id IS NULL) AND min_days <= $2
+            ORDER BY min_days DESC, model_id NULLS LAST
+            LIMIT 1;",
+            &[&model_id, &(days as i32)],
+        )
+        .await
+        .map_err(|_| internal_error())?
+        .map(|row| row.get("discount_percentage"))
+        .unwrap_or(Decimal::ZERO);
+
+    let subtotal = round_money(lines.iter().map(|line| line.amount).sum());
+    let discount = round_money(subtotal * discount_percentage / Decimal::ONE_HUNDRED);
+    let total_price = round_money(subtotal - discount);
+
+    Ok(PriceQuote {
+        machine_id,
+        model_id,
+        start_date,
+        end_date,
+        days,
+        base_daily_price,
+        lines,
+        subtotal,
+        discount_percentage,
+        discount,
+        total_price,
+        deposit,
+        amount_due: total_price + deposit,
+    })
+}
+
+pub fn generate_quote_token(quote: &PriceQuote, user_id: i32) -> Result<(String, usize), ()> {
+    let secret_key =
+        env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
+
+    let exp = Utc::now()
+        .checked_add_signed(Duration::minutes(QUOTE_EXPIRATION_MINUTES))
+        .ok_or(())?
+        .timestamp() as usize;
+
+    let claims = QuoteClaims {
+        aud: QUOTE_TOKEN_AUDIENCE.to_string(),
+        user_id,
+        machine_id: quote.machine_id,
+        model_id: quote.model_id,
+        start_date: quote.start_date,
+        end_date: quote.end_date,
+        total_price: quote.total_price,
+        deposit: quote.deposit,
+        exp,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret_key.as_ref()),
+    )
+    .map(|token| (token, exp))
+    .map_err(|_| ())
+}
+
+pub fn validate_quote_token(token: &str) -> Option<QuoteClaims> {
+    let secret_key =
+        env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
+
+    let mut validation = Validation::default();
+    validation.set_audience(&[QUOTE_TOKEN_AUDIENCE]);
+    validation.set_required_spec_claims(&["exp", "aud"]);
+
+    decode::<QuoteClaims>(
+        token,
+        &DecodingKey::from_secret(secret_key.as_ref()),
+        &validation,
+    )
+    .ok()
+    .map(|data| data.claims)
+}
+
+/// Final price and deposit of a new rental. A quote token fixes the ones that were quoted to
+/// one of `user_ids`; otherwise the informed total must match the current quote.
+pub async fn resolve_rental_price(
+    client: &impl GenericClient,
+    user_ids: &[i32],
+    machine_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    quote_token: &Option<String>,
+    total_price: Option<Decimal>,
+) -> Result<RentalPrice, (StatusCode, Json<serde_json::Value>)> {
+    let quote = quote_rental(client, machine_id, start_date, end_date).await?;
+
+    if let Some(token) = quote_token {
+        let Some(claims) = validate_quote_token(token) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "El presupuesto no es válido o ha expirado"})),
+            ));
+        };
+
+        // The price only depends on the model, so the quote of any of its units is valid
+        if !user_ids.contains(&claims.user_id)
+            || claims.model_id != quote.model_id
+            || claims.start_date != start_date
+            || claims.end_date != end_date
+        {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": "El presupuesto no corresponde al alquiler solicitado"})),
+            ));
+        }
+
+        return Ok(RentalPrice {
+            total_price: claims.total_price,
+            deposit: claims.deposit,
+        });
+    }
+
+    match total_price {
+        Some(total_price) if total_price == quote.total_price => Ok(RentalPrice {
+            total_price: quote.total_price,
+            deposit: quote.deposit,
+        }),
+        Some(_) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "El precio total no es correcto"})),
+        )),
+        None => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"message": "Debe indicarse el presupuesto del alquiler"})),
+        )),
+    }
+}
@@ -0,0 +1,1051 @@
+use crate::constants::{DEFAULT_PAYMENT_HOLD_MINUTES, PAYMENT_HOLD_SWEEP_SECONDS};
+use crate::custom_types::enums::{RentalActor, RentalStatus};
+use crate::custom_types::structs::{
+    CancellationPolicy, DateRange, Location, RefundTier, RentalExtension, RentalPrice, RentalRules,
+};
+use crate::helpers::{
+    deliveries::cancel_delivery,
+    deposits::release_deposit,
+    invoices::{issue_credit_note, issue_extension_invoice},
+    pricing::quote_rental,
+    waitlist::{lapsed_waitlist_holds, reevaluate_waitlist, reevaluate_waitlist_after_rentals},
+};
+use axum::{http::StatusCode, Json};
+use chrono::{Duration as DateDuration, NaiveDate, NaiveDateTime};
+use deadpool_postgres::{GenericClient, Pool};
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::{collections::HashMap, env, sync::Arc, time::Duration};
+use tokio_postgres::error::SqlState;
+
+/// Condition on `machinery_units mu` for the units that can be handed a booking: units under
+/// maintenance or decommissioned can't.
+pub const BOOKABLE_UNIT: &str = "mu.status NOT IN ('maintenance', 'decommissioned')";
+
+#[derive(Debug)]
+pub enum RentalTransitionError {
+    /// The rental doesn't exist or belongs to another client.
+    NotFound,
+    /// The rental is in a status from which the actor can't reach the requested one.
+    NotAllowed,
+    Db(tokio_postgres::Error),
+}
+
+impl From<tokio_postgres::Error> for RentalTransitionError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        RentalTransitionError::Db(e)
+    }
+}
+
+/// Edges of the rental lifecycle and who may take each of them:
+///
+/// - pending_payment -> active: the paying client.
+/// - pending_payment -> failed: the paying client, or the system when the hold runs out.
+/// - failed -> active: the paying client, when the payment is approved after the hold ran out
+///   and the dates are still free.
+/// - pending_payment | active -> cancelled: the client or the staff.
+/// - active -> retired -> completed: the staff, when the machine leaves and comes back.
+pub fn can_transition(from: RentalStatus, to: RentalStatus, actor: RentalActor) -> bool {
+    use RentalStatus::*;
+
+    match (from, to) {
+        (PendingPayment, Active) | (Failed, Active) => matches!(actor, RentalActor::Client(_)),
+        (PendingPayment, Failed) => {
+            matches!(actor, RentalActor::Client(_) | RentalActor::System)
+        }
+        (PendingPayment, Cancelled) | (Active, Cancelled) => true,
+        (Active, Retired) | (Retired, Completed) => actor.is_staff(),
+        _ => false,
+    }
+}
+
+/// Appends an entry to the status history of a rental. `from` is `None` when the rental is created.
+pub async fn record_rental_status(
+    client: &impl GenericClient,
+    rental_id: i32,
+    from: Option<RentalStatus>,
+    to: RentalStatus,
+    actor: RentalActor,
+    reason: Option<&str>,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "INSERT INTO rental_status_history (rental_id, from_status, to_status, actor_id, reason)
+            VALUES ($1, $2::TEXT::rental_status, $3::TEXT::rental_status, $4, $5);",
+            &[
+                &rental_id,
+                &from.map(|status| status.to_string()),
+                &to.to_string(),
+                &actor.user_id(),
+                &reason,
+            ],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// The only way the status of an existing rental changes. It locks the rental, so it must
+/// run inside the caller's transaction, and returns the status the rental had before.
+/// Clients can only move their own rentals.
+pub async fn transition_rental(
+    client: &impl GenericClient,
+    rental_id: i32,
+    to: RentalStatus,
+    actor: RentalActor,
+    reason: Option<&str>,
+) -> Result<RentalStatus, RentalTransitionError> {
+    let row = client
+        .query_opt(
+            "SELECT user_id, status::TEXT FROM rentals WHERE id = $1 FOR UPDATE;",
+            &[&rental_id],
+        )
+        .await?
+        .ok_or(RentalTransitionError::NotFound)?;
+
+    if let RentalActor::Client(user_id) = actor {
+        if row.get::<_, i32>("user_id") != user_id {
+            return Err(RentalTransitionError::NotFound);
+        }
+    }
+
+    let from: RentalStatus = row
+        .get::<_, String>("status")
+        .parse()
+        .expect("rental_status values must match RentalStatus");
+
+    if !can_transition(from, to, actor) {
+        return Err(RentalTransitionError::NotAllowed);
+    }
+
+    client
+        .execute(
+            "UPDATE rentals SET status = $1::TEXT::rental_status, updated_at = NOW() WHERE id = $2;",
+            &[&to.to_string(), &rental_id],
+        )
+        .await?;
+
+    record_rental_status(client, rental_id, Some(from), to, actor, reason).await?;
+
+    Ok(from)
+}
+
+/// Rental rules of the model the unit belongs to, or `None` when the unit doesn't exist.
+pub async fn get_rental_rules(
+    client: &impl GenericClient,
+    machine_id: i32,
+) -> Result<Option<RentalRules>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT mm.min_rental_days, mm.max_rental_days, mm.turnaround_days,
+            mm.min_lead_days, mm.max_lead_days
+            FROM machinery_models mm
+            INNER JOIN machinery_units mu ON mm.id = mu.model_id
+            WHERE mu.id = $1;",
+            &[&machine_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(RentalRules::build_from_row))
+}
+
+/// Rental rules of a model that is still offered, or `None` when it isn't.
+pub async fn get_model_rental_rules(
+    client: &impl GenericClient,
+    model_id: i32,
+) -> Result<Option<RentalRules>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT min_rental_days, max_rental_days, turnaround_days, min_lead_days, max_lead_days
+            FROM machinery_models
+            WHERE id = $1 AND decommissioned_at IS NULL;",
+            &[&model_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(RentalRules::build_from_row))
+}
+
+/// Checks the requested period against the rules of the model. The error is the message
+/// shown to the client.
+pub fn check_rental_period(
+    rules: &RentalRules,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    today: NaiveDate,
+) -> Result<(), String> {
+    let days = (end_date - start_date).num_days();
+
+    if end_date < start_date || days < rules.min_rental_days as i64 {
+        return Err(format!(
+            "El período indicado no es válido. Debe ser al menos {} días y la fecha de fin no puede ser anterior a la de inicio.",
+            rules.min_rental_days
+        ));
+    }
+
+    if let Some(max_days) = rules.max_rental_days {
+        if days > max_days as i64 {
+            return Err(format!(
+                "El período indicado no es válido. Esta máquina se alquila por un máximo de {} días.",
+                max_days
+            ));
+        }
+    }
+
+    let lead_days = (start_date - today).num_days();
+
+    if let Some(min_lead) = rules.min_lead_days {
+        if lead_days < min_lead as i64 {
+            return Err(format!(
+                "El alquiler de esta máquina debe reservarse con al menos {} días de anticipación.",
+                min_lead
+            ));
+        }
+    }
+
+    if let Some(max_lead) = rules.max_lead_days {
+        if lead_days > max_lead as i64 {
+            return Err(format!(
+                "El alquiler de esta máquina no puede reservarse con más de {} días de anticipación.",
+                max_lead
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+async fn period_is_free(
+    client: &impl GenericClient,
+    machine_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    excluded_rental_id: Option<i32>,
+) -> Result<bool, tokio_postgres::Error> {
+    // A unit leaving for another branch can't be booked here once it departs
+    let row = client
+        .query_one(
+            "SELECT NOT EXISTS (
+                SELECT 1 FROM unit_bookings b
+                INNER JOIN machinery_units mu ON b.machine_id = mu.id
+                INNER JOIN machinery_models mm ON mu.model_id = mm.id
+                WHERE b.machine_id = $1 AND COALESCE(b.rental_id != $4, TRUE)
+                AND b.start_date <= $3::date + mm.turnaround_days
+                AND $2 <= b.end_date + mm.turnaround_days
+            ) AND NOT EXISTS (
+                SELECT 1 FROM unit_transfers t
+                INNER JOIN machinery_units mu ON t.unit_id = mu.id
+                INNER JOIN machinery_models mm ON mu.model_id = mm.id
+                WHERE t.unit_id = $1 AND t.status IN ('requested', 'in_transit')
+                AND t.departure_date <= $3::date + mm.turnaround_days
+            );",
+            &[&machine_id, &start_date, &end_date, &excluded_rental_id],
+        )
+        .await?;
+
+    Ok(row.get(0))
+}
+
+/// Whether the period, plus the maintenance days of the model after it, is free in the unit.
+pub async fn unit_period_is_free(
+    client: &impl GenericClient,
+    machine_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<bool, tokio_postgres::Error> {
+    period_is_free(client, machine_id, start_date, end_date, None).await
+}
+
+/// Locks the unit until the caller's transaction ends and tells whether the period is still
+/// free. Bookings of the same unit wait for each other here, so two of them can never both
+/// find the same dates free.
+pub async fn lock_unit_period(
+    client: &impl GenericClient,
+    machine_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<bool, tokio_postgres::Error> {
+    client
+        .execute(
+            "SELECT 1 FROM machinery_units WHERE id = $1 FOR UPDATE;",
+            &[&machine_id],
+        )
+        .await?;
+
+    unit_period_is_free(client, machine_id, start_date, end_date).await
+}
+
+/// Same as `lock_unit_period` for days already tied to a rental, which only have to be free of
+/// the other bookings of the unit: the days it is extended by, or its own dates when it is paid
+/// after its hold ran out.
+pub async fn lock_rental_period(
+    client: &impl GenericClient,
+    rental_id: i32,
+    machine_id: i32,
+    end_date: NaiveDate,
+    new_end_date: NaiveDate,
+) -> Result<bool, tokio_postgres::Error> {
+    client
+        .execute(
+            "SELECT 1 FROM machinery_units WHERE id = $1 FOR UPDATE;",
+            &[&machine_id],
+        )
+        .await?;
+
+    period_is_free(client, machine_id, end_date, new_end_date, Some(rental_id)).await
+}
+
+/// Whether the database turned a write down because two rentals would hold the same unit on
+/// overlapping days, which the `rentals_no_overlap` constraint forbids. It only happens when
+/// the dates were taken in between, so callers answer it with `overlapping_rental_response`.
+pub fn is_overlapping_rental(e: &tokio_postgres::Error) -> bool {
+    e.code() == Some(&SqlState::EXCLUSION_VIOLATION)
+}
+
+pub fn overlapping_rental_response() -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({"message": "La unidad ya está reservada para esas fechas"})),
+    )
+}
+
+/// Whether the database aborted the transaction because it was waiting for locks held by
+/// another one that was waiting for it in turn.
+pub fn is_deadlock(e: &tokio_postgres::Error) -> bool {
+    e.code() == Some(&SqlState::T_R_DEADLOCK_DETECTED)
+}
+
+/// Picks the unit of a model at a location that gets a booking. Among the units free for the
+/// period it takes the one with the fewest rented days so far, so wear spreads evenly across
+/// the fleet, breaking ties by id, and skips the units that aren't `BOOKABLE_UNIT`. The unit
+/// stays locked as in `lock_unit_period`. `None` when every unit is taken.
+pub async fn assign_unit(
+    client: &impl GenericClient,
+    model_id: i32,
+    location_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    let candidates = client
+        .query(
+            &format!(
+                "SELECT mu.id FROM machinery_units mu
+                LEFT JOIN rentals r ON r.machine_id = mu.id
+                    AND r.status IN ('active', 'retired', 'completed')
+                WHERE mu.model_id = $1 AND mu.location_id = $2 AND {}
+                GROUP BY mu.id
+                ORDER BY COALESCE(SUM(r.end_date - r.start_date), 0), mu.id;",
+                BOOKABLE_UNIT
+            ),
+            &[&model_id, &location_id],
+        )
+        .await?;
+
+    for row in candidates {
+        let unit_id: i32 = row.get("id");
+
+        if lock_unit_period(client, unit_id, start_date, end_date).await? {
+            return Ok(Some(unit_id));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The free period with the requested length closest to the requested one in any
+/// `BOOKABLE_UNIT` of the model at the location, among the ones the rules of the model allow.
+pub async fn nearest_free_period(
+    client: &impl GenericClient,
+    model_id: i32,
+    location_id: i32,
+    rules: &RentalRules,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    today: NaiveDate,
+) -> Result<Option<DateRange>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            &format!(
+                "SELECT mu.id, b.start_date, b.end_date FROM machinery_units mu
+                LEFT JOIN unit_bookings b ON b.machine_id = mu.id
+                WHERE mu.model_id = $1 AND mu.location_id = $2 AND {}
+                ORDER BY mu.id;",
+                BOOKABLE_UNIT
+            ),
+            &[&model_id, &location_id],
+        )
+        .await?;
+
+    let mut units: Vec<(i32, Vec<(NaiveDate, NaiveDate)>)> = Vec::new();
+
+    for row in rows {
+        let unit_id: i32 = row.get("id");
+
+        if units.last().is_none_or(|(id, _)| *id != unit_id) {
+            units.push((unit_id, Vec::new()));
+        }
+
+        if let (Some(start), Some(end)) = (row.get("start_date"), row.get("end_date")) {
+            units.last_mut().unwrap().1.push((start, end));
+        }
+    }
+
+    let length = end_date - start_date;
+    let turnaround = DateDuration::days(rules.turnaround_days as i64);
+    let mut nearest: Option<NaiveDate> = None;
+
+    for (_, rentals) in &units {
+        // A free period either starts on the requested date or right before or after a rental
+        let candidates = std::iter::once(start_date).chain(rentals.iter().flat_map(
+            |(rental_start, rental_end)| {
+                [
+                    *rental_end + turnaround + DateDuration::days(1),
+                    *rental_start - turnaround - DateDuration::days(1) - length,
+                ]
+            },
+        ));
+
+        for candidate in candidates {
+            let candidate_end = candidate + length;
+
+            let is_free = rentals.iter().all(|(rental_start, rental_end)| {
+                candidate > *rental_end + turnaround || candidate_end + turnaround < *rental_start
+            });
+
+            if !is_free || check_rental_period(rules, candidate, candidate_end, today).is_err() {
+                continue;
+            }
+
+            let distance = |date: NaiveDate| (date - start_date).num_days().abs();
+
+            if nearest.is_none_or(|best| (distance(candidate), candidate) < (distance(best), best))
+            {
+                nearest = Some(candidate);
+            }
+        }
+    }
+
+    Ok(nearest.map(|start| DateRange {
+        start_date: start,
+        end_date: start + length,
+    }))
+}
+
+/// Locations, other than the given one, with a `BOOKABLE_UNIT` of the model free for the period.
+pub async fn locations_with_free_unit(
+    client: &impl GenericClient,
+    model_id: i32,
+    excluded_location_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Vec<Location>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            &format!(
+                "SELECT mu.id AS unit_id, l.* FROM machinery_units mu
+                INNER JOIN locations l ON mu.location_id = l.id
+                WHERE mu.model_id = $1 AND mu.location_id != $2 AND {}
+                ORDER BY l.id, mu.id;",
+                BOOKABLE_UNIT
+            ),
+            &[&model_id, &excluded_location_id],
+        )
+        .await?;
+
+    let mut locations: Vec<Location> = Vec::new();
+
+    for row in rows {
+        let location_id: i32 = row.get("id");
+
+        if locations
+            .last()
+            .is_some_and(|location| location.id == location_id)
+        {
+            continue;
+        }
+
+        if unit_period_is_free(client, row.get("unit_id"), start_date, end_date).await? {
+            locations.push(Location::build_from_row(&row));
+        }
+    }
+
+    Ok(locations)
+}
+
+/// Inserts a rental waiting for the client's payment, which holds its dates until the
+/// payment deadline, and records its creation. Returns its id and deadline.
+pub async fn create_pending_rental(
+    client: &impl GenericClient,
+    user_id: i32,
+    machine_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    price: RentalPrice,
+    payment_hold_seconds: i32,
+) -> Result<(i32, NaiveDateTime), tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status,
+                payment_deadline, cancellation_policy_id, deposit_amount)
+            VALUES ($1, $2, $3, $4, $5, 'pending_payment', NOW() + $6::INT * INTERVAL '1 second', $7, $8)
+            RETURNING id, payment_deadline;",
+            &[
+                &user_id,
+                &machine_id,
+                &start_date,
+                &end_date,
+                &price.total_price,
+                &payment_hold_seconds,
+                &current_cancellation_policy_id(client, machine_id).await?,
+                &price.deposit,
+            ],
+        )
+        .await?;
+
+    let rental_id: i32 = row.get("id");
+
+    record_rental_status(
+        client,
+        rental_id,
+        None,
+        RentalStatus::PendingPayment,
+        RentalActor::Client(user_id),
+        None,
+    )
+    .await?;
+
+    Ok((rental_id, row.get("payment_deadline")))
+}
+
+/// The latest version of the cancellation policy of the unit's model, which is the one a new
+/// rental is booked with.
+pub async fn current_cancellation_policy_id(
+    client: &impl GenericClient,
+    machine_id: i32,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT cp.id FROM cancellation_policies cp
+            INNER JOIN machinery_units mu ON cp.model_id = mu.model_id
+            WHERE mu.id = $1
+            ORDER BY cp.version DESC
+            LIMIT 1;",
+            &[&machine_id],
+        )
+        .await?;
+
+    Ok(row.map(|row| row.get("id")))
+}
+
+async fn load_cancellation_policy(
+    client: &impl GenericClient,
+    row: Option<tokio_postgres::Row>,
+) -> Result<Option<CancellationPolicy>, tokio_postgres::Error> {
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let id: i32 = row.get("id");
+    let tiers = client
+        .query(
+            "SELECT min_notice_days, refund_percentage FROM cancellation_policy_tiers
+            WHERE policy_id = $1
+            ORDER BY min_notice_days DESC;",
+            &[&id],
+        )
+        .await?
+        .iter()
+        .map(|tier| RefundTier {
+            min_notice_days: tier.get("min_notice_days"),
+            refund_percentage: tier.get("refund_percentage"),
+        })
+        .collect();
+
+    Ok(Some(CancellationPolicy {
+        id,
+        model_id: row.get("model_id"),
+        version: row.get("version"),
+        staff_refund_percentage: row.get("staff_refund_percentage"),
+        tiers,
+        created_at: row.get("created_at"),
+    }))
+}
+
+pub async fn get_cancellation_policy(
+    client: &impl GenericClient,
+    policy_id: i32,
+) -> Result<Option<CancellationPolicy>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT * FROM cancellation_policies WHERE id = $1;",
+            &[&policy_id],
+        )
+        .await?;
+
+    load_cancellation_policy(client, row).await
+}
+
+pub async fn get_current_cancellation_policy(
+    client: &impl GenericClient,
+    model_id: i32,
+) -> Result<Option<CancellationPolicy>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT * FROM cancellation_policies
+            WHERE model_id = $1
+            ORDER BY version DESC
+            LIMIT 1;",
+            &[&model_id],
+        )
+        .await?;
+
+    load_cancellation_policy(client, row).await
+}
+
+pub struct Refund {
+    pub percentage: Decimal,
+    pub amount: Decimal,
+}
+
+/// What the client has paid for a rental: nothing while it waits for its payment, and the
+/// rental price plus its paid extensions otherwise.
+pub async fn paid_amount(
+    client: &impl GenericClient,
+    rental_id: i32,
+) -> Result<Decimal, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT r.status::TEXT,
+                r.total_price + COALESCE((
+                    SELECT SUM(e.price) FROM rental_extensions e
+                    WHERE e.rental_id = r.id AND e.status = 'paid'
+                ), 0) AS paid_amount
+            FROM rentals r
+            WHERE r.id = $1;",
+            &[&rental_id],
+        )
+        .await?;
+
+    Ok(match row.get::<_, String>("status").as_str() {
+        "pending_payment" => Decimal::ZERO,
+        _ => row.get("paid_amount"),
+    })
+}
+
+/// Refund for cancelling a rental on `today`, following the policy it was booked with. Rentals
+/// without one refund in full. Only what was paid counts, see `paid_amount`.
+pub async fn compute_refund(
+    client: &impl GenericClient,
+    rental_id: i32,
+    staff_initiated: bool,
+    today: NaiveDate,
+) -> Result<Refund, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT start_date, cancellation_policy_id FROM rentals WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await?;
+
+    let paid_amount = paid_amount(client, rental_id).await?;
+
+    let percentage = match row.get::<_, Option<i32>>("cancellation_policy_id") {
+        Some(policy_id) => {
+            let notice_days = (row.get::<_, NaiveDate>("start_date") - today).num_days();
+
+            get_cancellation_policy(client, policy_id)
+                .await?
+                .map_or(Decimal::ONE_HUNDRED, |policy| {
+                    policy.refund_percentage(notice_days, staff_initiated)
+                })
+        }
+        None => Decimal::ONE_HUNDRED,
+    };
+
+    Ok(Refund {
+        percentage,
+        amount: (paid_amount * percentage / Decimal::ONE_HUNDRED).round_dp(2),
+    })
+}
+
+/// What a cancelled rental gave back to the client.
+pub struct CancelledRental {
+    pub refund: Refund,
+    pub deposit_released: Option<Decimal>,
+}
+
+/// Cancels a rental inside the caller's transaction and undoes what it involved: the client
+/// gets `refund` back with a credit note, the deposit held is released and a scheduled
+/// delivery is called off. The waitlist of the unit must be re-evaluated once the caller
+/// commits, since the dates are only free from then on.
+pub async fn cancel_and_refund_rental(
+    client: &impl GenericClient,
+    rental_id: i32,
+    refund: Refund,
+    actor: RentalActor,
+    reason: Option<&str>,
+) -> Result<CancelledRental, RentalTransitionError> {
+    transition_rental(client, rental_id, RentalStatus::Cancelled, actor, reason).await?;
+
+    client
+        .execute(
+            "UPDATE rentals SET refund_amount = $2, notes = COALESCE($3, notes) WHERE id = $1;",
+            &[&rental_id, &refund.amount, &reason],
+        )
+        .await?;
+
+    let deposit_released = release_deposit(client, rental_id).await?;
+    cancel_delivery(client, rental_id).await?;
+    issue_credit_note(client, rental_id, refund.amount).await?;
+
+    Ok(CancelledRental {
+        refund,
+        deposit_released,
+    })
+}
+
+/// What a rental extension changes, as checked by `prepare_extension`.
+pub struct ExtensionQuote {
+    pub machine_id: i32,
+    pub previous_end_date: NaiveDate,
+    pub price: Decimal,
+}
+
+/// Checks that a rental can be extended until `new_end_date` and prices the extra days as a
+/// rental of their own. The rental and its unit stay locked until the caller's transaction
+/// ends, so the extra days can't be taken in the meantime. Clients can only extend their own
+/// rentals.
+pub async fn prepare_extension(
+    client: &impl GenericClient,
+    rental_id: i32,
+    new_end_date: NaiveDate,
+    actor: RentalActor,
+) -> Result<ExtensionQuote, (StatusCode, Json<serde_json::Value>)> {
+    let internal_error = |_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Se ha producido un error interno en el servidor"})),
+        )
+    };
+
+    let rental_row = client
+        .query_opt(
+            "SELECT r.user_id, r.machine_id, r.start_date, r.end_date, r.status::TEXT,
+                mm.max_rental_days
+            FROM rentals r
+            INNER JOIN machinery_units mu ON r.machine_id = mu.id
+            INNER JOIN machinery_models mm ON mu.model_id = mm.id
+            WHERE r.id = $1
+            FOR UPDATE OF r;",
+            &[&rental_id],
+        )
+        .await
+        .map_err(internal_error)?
+        .filter(|row| match actor {
+            RentalActor::Client(user_id) => row.get::<_, i32>("user_id") == user_id,
+            _ => true,
+        })
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(json!({"message": "No se ha encontrado el alquiler"})),
+        ))?;
+
+    let status: String = rental_row.get("status");
+    let machine_id: i32 = rental_row.get("machine_id");
+    let start_date: NaiveDate = rental_row.get("start_date");
+    let end_date: NaiveDate = rental_row.get("end_date");
+
+    if status != RentalStatus::Active.to_string() && status != RentalStatus::Retired.to_string() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"message": "Solo se pueden extender alquileres en curso"})),
+        ));
+    }
+
+    if new_end_date <= end_date {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "message": "La nueva fecha de fin debe ser posterior a la fecha de fin actual",
+            })),
+        ));
+    }
+
+    if let Some(max_days) = rental_row.get::<_, Option<i32>>("max_rental_days") {
+        if (new_end_date - start_date).num_days() > max_days as i64 {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "message": format!(
+                        "El período indicado no es válido. Esta máquina se alquila por un máximo de {} días.",
+                        max_days
+                    ),
+                })),
+            ));
+        }
+    }
+
+    let pending_extension = client
+        .query_opt(
+            "SELECT 1 FROM rental_extensions
+            WHERE rental_id = $1 AND status = 'pending_payment' AND payment_deadline > NOW();",
+            &[&rental_id],
+        )
+        .await
+        .map_err(internal_error)?;
+
+    if pending_extension.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"message": "El alquiler ya tiene una extensión pendiente de pago"})),
+        ));
+    }
+
+    if !lock_rental_period(client, rental_id, machine_id, end_date, new_end_date)
+        .await
+        .map_err(internal_error)?
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "message": "La máquina no está disponible para las fechas de la extensión, considerando el período de mantenimiento planificado",
+            })),
+        ));
+    }
+
+    let quote = quote_rental(client, machine_id, end_date, new_end_date).await?;
+
+    Ok(ExtensionQuote {
+        machine_id,
+        previous_end_date: end_date,
+        price: quote.total_price,
+    })
+}
+
+/// Marks an extension as paid and moves the end of its rental to the new date, invoicing the
+/// extra days. Returns the new end date.
+pub async fn settle_extension(
+    client: &impl GenericClient,
+    extension_id: i32,
+    payment_id: &str,
+) -> Result<NaiveDate, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "UPDATE rental_extensions
+            SET status = 'paid', payment_id = $1, paid_at = NOW()
+            WHERE id = $2
+            RETURNING rental_id, new_end_date;",
+            &[&payment_id, &extension_id],
+        )
+        .await?;
+
+    let rental_id: i32 = row.get("rental_id");
+    let new_end_date: NaiveDate = row.get("new_end_date");
+
+    client
+        .execute(
+            "UPDATE rentals SET end_date = $1, updated_at = NOW() WHERE id = $2;",
+            &[&new_end_date, &rental_id],
+        )
+        .await?;
+
+    issue_extension_invoice(client, extension_id).await?;
+
+    Ok(new_end_date)
+}
+
+/// Extensions of each of the rentals, oldest first.
+pub async fn get_rental_extensions(
+    client: &impl GenericClient,
+    rental_ids: &[i32],
+) -> Result<HashMap<i32, Vec<RentalExtension>>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            "SELECT id, rental_id, previous_end_date, new_end_date, price, status::TEXT,
+                payment_deadline, rental_employee_id, created_at, paid_at, refund_amount
+            FROM rental_extensions
+            WHERE rental_id = ANY($1)
+            ORDER BY id;",
+            &[&rental_ids],
+        )
+        .await?;
+
+    let mut extensions: HashMap<i32, Vec<RentalExtension>> = HashMap::new();
+
+    for row in rows {
+        let extension = RentalExtension::build_from_row(&row);
+        extensions
+            .entry(extension.rental_id)
+            .or_default()
+            .push(extension);
+    }
+
+    Ok(extensions)
+}
+
+/// How long a `pending_payment` rental holds its dates, set through `PAYMENT_HOLD_MINUTES`.
+pub fn payment_hold() -> chrono::Duration {
+    let minutes = env::var("PAYMENT_HOLD_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .unwrap_or(DEFAULT_PAYMENT_HOLD_MINUTES);
+
+    chrono::Duration::minutes(minutes)
+}
+
+/// Checks an approved payment of a rental booked on its own against its hold. Once the hold ran
+/// out the dates are only kept if no other booking took them; otherwise the rental fails, and
+/// the payment stays on record with the whole amount paid to be refunded. Returns whether the
+/// rental can be activated as usual.
+pub async fn settle_lapsed_rental_payment(
+    client: &impl GenericClient,
+    rental_id: i32,
+    payment_id: &str,
+    actor: RentalActor,
+) -> Result<bool, RentalTransitionError> {
+    let Some(row) = client
+        .query_opt(
+            "SELECT user_id, machine_id, start_date, end_date, status::TEXT, payment_id
+            FROM rentals
+            WHERE id = $1 AND order_id IS NULL
+            AND (status = 'failed' OR (status = 'pending_payment' AND payment_deadline <= NOW()))
+            FOR UPDATE;",
+            &[&rental_id],
+        )
+        .await?
+    else {
+        return Ok(true);
+    };
+
+    // Rentals of other clients are turned down by the transition itself
+    if let RentalActor::Client(user_id) = actor {
+        if row.get::<_, i32>("user_id") != user_id {
+            return Ok(true);
+        }
+    }
+
+    // Already paid late once, and marked for refund then
+    if row.get::<_, Option<String>>("payment_id").is_some() {
+        return Ok(false);
+    }
+
+    let machine_id: i32 = row.get("machine_id");
+    let start_date: NaiveDate = row.get("start_date");
+    let end_date: NaiveDate = row.get("end_date");
+
+    if lock_rental_period(client, rental_id, machine_id, start_date, end_date).await? {
+        return Ok(true);
+    }
+
+    if row.get::<_, String>("status") == RentalStatus::PendingPayment.to_string() {
+        transition_rental(
+            client,
+            rental_id,
+            RentalStatus::Failed,
+            actor,
+            Some("El pago se confirmó después del plazo de reserva"),
+        )
+        .await?;
+    }
+
+    // The deposit is paid along with the rental
+    client
+        .execute(
+            "UPDATE rentals
+            SET payment_id = $1, refund_amount = total_price + deposit_amount, updated_at = NOW()
+            WHERE id = $2;",
+            &[&payment_id, &rental_id],
+        )
+        .await?;
+
+    Ok(false)
+}
+
+/// Fails the pending payments of rentals and extensions whose deadline has passed and returns
+/// the ids of the failed rentals. The ones locked by a payment being confirmed right now are
+/// left for the next run.
+pub async fn expire_payment_holds(
+    client: &mut deadpool_postgres::Client,
+) -> Result<Vec<i32>, RentalTransitionError> {
+    let transaction = client.transaction().await?;
+
+    let rows = transaction
+        .query(
+            "SELECT id FROM rentals
+            WHERE status = 'pending_payment' AND payment_deadline <= NOW()
+            FOR UPDATE SKIP LOCKED;",
+            &[],
+        )
+        .await?;
+
+    let mut expired = Vec::new();
+
+    for row in rows {
+        let rental_id: i32 = row.get("id");
+
+        transition_rental(
+            &transaction,
+            rental_id,
+            RentalStatus::Failed,
+            RentalActor::System,
+            Some("No se recibió el pago dentro del plazo de reserva"),
+        )
+        .await?;
+
+        expired.push(rental_id);
+    }
+
+    // Extensions only stop holding their extra days, the rental itself is unaffected
+    transaction
+        .execute(
+            "UPDATE rental_extensions SET status = 'failed'
+            WHERE id IN (
+                SELECT id FROM rental_extensions
+                WHERE status = 'pending_payment' AND payment_deadline <= NOW()
+                FOR UPDATE SKIP LOCKED
+            );",
+            &[],
+        )
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(expired)
+}
+
+/// Runs `expire_payment_holds` periodically for as long as the server is up, and passes the
+/// units freed by it or by lapsed waitlist holds to the next clients in the waitlist.
+pub fn spawn_payment_hold_expiry(pool: Arc<Pool>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(PAYMENT_HOLD_SWEEP_SECONDS));
+
+        loop {
+            interval.tick().await;
+
+            let mut client = match pool.get().await {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Failed to connect to the DB to expire payment holds: {}", e);
+                    continue;
+                }
+            };
+
+            match expire_payment_holds(&mut client).await {
+                Ok(expired) if !expired.is_empty() => {
+                    println!("Expired the payment hold of the rentals {:?}", expired);
+                    reevaluate_waitlist_after_rentals(&mut client, &expired).await;
+                }
+                Ok(_) => (),
+                Err(e) => eprintln!("Failed to expire payment holds: {:?}", e),
+            }
+
+            match lapsed_waitlist_holds(&client).await {
+                Ok(unit_ids) if !unit_ids.is_empty() => {
+                    reevaluate_waitlist(&mut client, &unit_ids).await;
+                }
+                Ok(_) => (),
+                Err(e) => eprintln!("Failed to get the lapsed waitlist holds: {}", e),
+            }
+        }
+    });
+}
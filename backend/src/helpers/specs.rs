@@ -0,0 +1,145 @@
+use crate::custom_types::{enums::SpecOperator, structs::SpecFilter};
+use axum::{http::StatusCode, Json};
+use deadpool_postgres::Transaction;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Typed columns of a `model_specs` row: numeric, enum and bool values respectively.
+pub type SpecColumns = (Option<f64>, Option<String>, Option<bool>);
+
+/// Parses a catalog filter such as `weight_kg<=5000` or `has_cabin=true`.
+pub fn parse_spec_filter(filter: &str) -> Option<SpecFilter> {
+    let operator_start = filter.find(['<', '>', '=', '!'])?;
+    let (key, rest) = filter.split_at(operator_start);
+
+    // Two-character operators must be checked first
+    let (operator, value) = [
+        ("<=", SpecOperator::LtEq),
+        (">=", SpecOperator::GtEq),
+        ("!=", SpecOperator::NotEq),
+        ("<", SpecOperator::Lt),
+        (">", SpecOperator::Gt),
+        ("=", SpecOperator::Eq),
+    ]
+    .into_iter()
+    .find_map(|(symbol, operator)| rest.strip_prefix(symbol).map(|value| (operator, value)))?;
+
+    let key = key.trim();
+    let value = value.trim();
+
+    if key.is_empty() || value.is_empty() {
+        return None;
+    }
+
+    Some(SpecFilter {
+        key: key.to_string(),
+        operator,
+        value: value.to_string(),
+    })
+}
+
+/// Checks a value against its definition and maps it to the column it is stored in.
+pub fn spec_value_to_columns(
+    data_type: &str,
+    allowed_values: &Option<Vec<String>>,
+    value: &serde_json::Value,
+) -> Option<SpecColumns> {
+    match data_type {
+        "numeric" => value.as_f64().map(|n| (Some(n), None, None)),
+        "enum" => {
+            let text = value.as_str()?;
+            allowed_values
+                .as_ref()?
+                .iter()
+                .any(|allowed| allowed == text)
+                .then(|| (None, Some(text.to_string()), None))
+        }
+        "bool" => value.as_bool().map(|b| (None, None, Some(b))),
+        _ => None,
+    }
+}
+
+/// Replaces the spec values of a model inside the caller's transaction.
+/// Only specs defined for the model's categories (or any of their parents) are accepted
+/// and null values are treated as not informed.
+pub async fn save_model_specs(
+    transaction: &Transaction<'_>,
+    model_id: i32,
+    specs: &HashMap<String, serde_json::Value>,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let internal_error = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"message": "Failed to save the specs"})),
+        )
+    };
+
+    let definitions_query = "
+        WITH RECURSIVE model_categories AS (
+            SELECT category_id AS id FROM machinery_categories WHERE model_id = $1
+            UNION
+            SELECT c.parent_id FROM categories c
+            INNER JOIN model_categories mc ON c.id = mc.id
+            WHERE c.parent_id IS NOT NULL
+        )
+        SELECT sd.id, sd.key, sd.data_type::TEXT, sd.allowed_values
+        FROM spec_definitions sd
+        WHERE sd.category_id IN (SELECT id FROM model_categories);
+    ";
+
+    let definition_rows = transaction
+        .query(definitions_query, &[&model_id])
+        .await
+        .map_err(|_| internal_error())?;
+
+    transaction
+        .execute("DELETE FROM model_specs WHERE model_id = $1;", &[&model_id])
+        .await
+        .map_err(|_| internal_error())?;
+
+    for (key, value) in specs {
+        if value.is_null() {
+            continue;
+        }
+
+        let Some(definition) = definition_rows
+            .iter()
+            .find(|row| row.get::<_, String>("key") == *key)
+        else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "message": format!("The spec {} does not apply to the model categories", key)
+                })),
+            ));
+        };
+
+        let Some((numeric_value, text_value, bool_value)) = spec_value_to_columns(
+            &definition.get::<_, String>("data_type"),
+            &definition.get("allowed_values"),
+            value,
+        ) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"message": format!("Invalid value for the spec {}", key)})),
+            ));
+        };
+
+        transaction
+            .execute(
+                "INSERT INTO model_specs (model_id, spec_id, numeric_value, text_value, bool_value)
+                VALUES ($1, $2, $3, $4, $5);",
+                &[
+                    &model_id,
+                    &definition.get::<_, i32>("id"),
+                    &numeric_value,
+                    &text_value,
+                    &bool_value,
+                ],
+            )
+            .await
+            .map_err(|_| internal_error())?;
+    }
+
+    Ok(())
+}
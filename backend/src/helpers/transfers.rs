@@ -0,0 +1,75 @@
+use crate::custom_types::{enums::TransferStatus, structs::UnitTransfer};
+use deadpool_postgres::GenericClient;
+
+const TRANSFER_QUERY: &str = "SELECT t.id, t.unit_id, mu.serial_number, mm.id AS model_id,
+    mm.name AS model_name, t.from_location_id, lf.city AS from_city, t.to_location_id,
+    lt.city AS to_city, t.departure_date, t.expected_arrival_date, t.status::TEXT, t.notes,
+    t.requested_by, t.requested_at, t.shipped_by, t.shipped_at, t.received_by, t.received_at,
+    t.cancelled_by, t.cancelled_at
+    FROM unit_transfers t
+    INNER JOIN machinery_units mu ON mu.id = t.unit_id
+    INNER JOIN machinery_models mm ON mm.id = mu.model_id
+    INNER JOIN locations lf ON lf.id = t.from_location_id
+    INNER JOIN locations lt ON lt.id = t.to_location_id";
+
+pub async fn get_transfer(
+    client: &impl GenericClient,
+    transfer_id: i32,
+) -> Result<Option<UnitTransfer>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            &format!("{} WHERE t.id = $1;", TRANSFER_QUERY),
+            &[&transfer_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(UnitTransfer::build_from_row))
+}
+
+/// Transfers with the given status, or the ones still requested or in transit, soonest first.
+pub async fn get_transfers(
+    client: &impl GenericClient,
+    status: Option<TransferStatus>,
+    location_id: Option<i32>,
+) -> Result<Vec<UnitTransfer>, tokio_postgres::Error> {
+    let statuses = match status {
+        Some(status) => vec![status.to_string()],
+        None => vec![
+            TransferStatus::Requested.to_string(),
+            TransferStatus::InTransit.to_string(),
+        ],
+    };
+
+    let rows = client
+        .query(
+            &format!(
+                "{} WHERE t.status::TEXT = ANY($1)
+                AND ($2::INT IS NULL OR $2 IN (t.from_location_id, t.to_location_id))
+                ORDER BY t.departure_date, t.id;",
+                TRANSFER_QUERY
+            ),
+            &[&statuses, &location_id],
+        )
+        .await?;
+
+    Ok(rows.iter().map(UnitTransfer::build_from_row).collect())
+}
+
+/// Records a change of status of a transferred unit in its history.
+pub async fn record_transfer_event(
+    client: &impl GenericClient,
+    unit_id: i32,
+    description: &str,
+    previous_status: &str,
+    new_status: &str,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "INSERT INTO unit_history_events (unit_id, description, previous_status, new_status)
+            VALUES ($1, $2, $3::TEXT::machine_status, $4::TEXT::machine_status);",
+            &[&unit_id, &description, &previous_status, &new_status],
+        )
+        .await?;
+
+    Ok(())
+}
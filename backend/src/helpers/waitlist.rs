@@ -0,0 +1,370 @@
+use crate::constants::DEFAULT_WAITLIST_PRIORITY_MINUTES;
+use crate::custom_types::structs::WaitlistEntry;
+use crate::helpers::{
+    auth::send_mail,
+    rentals::{check_rental_period, get_model_rental_rules, lock_unit_period, BOOKABLE_UNIT},
+};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use deadpool_postgres::GenericClient;
+use std::env;
+
+const WAITLIST_ENTRY_COLUMNS: &str = "w.id, w.model_id, w.location_id, w.start_date,
+    w.end_date, w.status::TEXT, w.created_at,
+    CASE WHEN w.status = 'waiting' THEN (
+        SELECT COUNT(*) FROM waitlist_entries o
+        WHERE o.model_id = w.model_id AND o.location_id = w.location_id
+        AND o.status = 'waiting' AND (o.created_at, o.id) <= (w.created_at, w.id)
+    ) END AS position,
+    w.unit_id, w.notified_at, w.priority_until";
+
+/// How long a notified client has the freed unit to themselves, set through
+/// `WAITLIST_PRIORITY_MINUTES`.
+pub fn waitlist_priority_window() -> chrono::Duration {
+    let minutes = env::var("WAITLIST_PRIORITY_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .unwrap_or(DEFAULT_WAITLIST_PRIORITY_MINUTES);
+
+    chrono::Duration::minutes(minutes)
+}
+
+pub async fn get_waitlist_entry(
+    client: &impl GenericClient,
+    entry_id: i32,
+) -> Result<Option<WaitlistEntry>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            &format!(
+                "SELECT {} FROM waitlist_entries w WHERE w.id = $1;",
+                WAITLIST_ENTRY_COLUMNS
+            ),
+            &[&entry_id],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(WaitlistEntry::build_from_row))
+}
+
+/// Entries of a client, newest first.
+pub async fn get_waitlist_entries(
+    client: &impl GenericClient,
+    user_id: i32,
+) -> Result<Vec<WaitlistEntry>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {} FROM waitlist_entries w
+                WHERE w.user_id = $1
+                ORDER BY w.created_at DESC, w.id DESC;",
+                WAITLIST_ENTRY_COLUMNS
+            ),
+            &[&user_id],
+        )
+        .await?;
+
+    Ok(rows.iter().map(WaitlistEntry::build_from_row).collect())
+}
+
+/// Locks the first `BOOKABLE_UNIT` of the model at the location that is free for the period.
+async fn offer_unit(
+    client: &impl GenericClient,
+    model_id: i32,
+    location_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    let candidates = client
+        .query(
+            &format!(
+                "SELECT mu.id FROM machinery_units mu
+                WHERE mu.model_id = $1 AND mu.location_id = $2 AND {}
+                ORDER BY mu.id;",
+                BOOKABLE_UNIT
+            ),
+            &[&model_id, &location_id],
+        )
+        .await?;
+
+    for row in candidates {
+        let unit_id: i32 = row.get("id");
+
+        if lock_unit_period(client, unit_id, start_date, end_date).await? {
+            return Ok(Some(unit_id));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Moves the waitlist of a model forward. Holds whose priority window ran out and entries the
+/// rules of the model no longer allow expire, then the waiting entries are walked in the order
+/// they joined and each one a unit is free for gets that unit held. Must run inside the
+/// caller's transaction. Returns the ids of the entries notified.
+async fn process_waitlist(
+    client: &impl GenericClient,
+    model_id: i32,
+) -> Result<Vec<i32>, tokio_postgres::Error> {
+    client
+        .execute(
+            "UPDATE waitlist_entries SET status = 'expired'
+            WHERE model_id = $1 AND status = 'notified' AND priority_until <= NOW();",
+            &[&model_id],
+        )
+        .await?;
+
+    let rules = get_model_rental_rules(client, model_id).await?;
+
+    let rows = client
+        .query(
+            "SELECT id, location_id, start_date, end_date FROM waitlist_entries
+            WHERE model_id = $1 AND status = 'waiting'
+            ORDER BY created_at, id
+            FOR UPDATE SKIP LOCKED;",
+            &[&model_id],
+        )
+        .await?;
+
+    let today = Local::now().date_naive();
+    let priority_seconds = waitlist_priority_window().num_seconds() as i32;
+    let mut notified = Vec::new();
+
+    for row in rows {
+        let entry_id: i32 = row.get("id");
+        let location_id: i32 = row.get("location_id");
+        let start_date: NaiveDate = row.get("start_date");
+        let end_date: NaiveDate = row.get("end_date");
+
+        let still_allowed = rules
+            .as_ref()
+            .is_some_and(|rules| check_rental_period(rules, start_date, end_date, today).is_ok());
+
+        if !still_allowed {
+            client
+                .execute(
+                    "UPDATE waitlist_entries SET status = 'expired' WHERE id = $1;",
+                    &[&entry_id],
+                )
+                .await?;
+            continue;
+        }
+
+        if let Some(unit_id) =
+            offer_unit(client, model_id, location_id, start_date, end_date).await?
+        {
+            client
+                .execute(
+                    "UPDATE waitlist_entries
+                    SET status = 'notified', unit_id = $2, notified_at = NOW(),
+                    priority_until = NOW() + $3::INT * INTERVAL '1 second'
+                    WHERE id = $1;",
+                    &[&entry_id, &unit_id, &priority_seconds],
+                )
+                .await?;
+
+            notified.push(entry_id);
+        }
+    }
+
+    Ok(notified)
+}
+
+async fn notify_waitlist_entries(client: &impl GenericClient, entry_ids: &[i32]) {
+    let rows = match client
+        .query(
+            "SELECT w.start_date, w.end_date, w.priority_until, u.email, u.name,
+            mm.name AS model_name, mm.brand, mm.model, l.street, l.number, l.city
+            FROM waitlist_entries w
+            INNER JOIN users u ON w.user_id = u.id
+            INNER JOIN machinery_models mm ON w.model_id = mm.id
+            INNER JOIN locations l ON w.location_id = l.id
+            WHERE w.id = ANY($1);",
+            &[&entry_ids],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!("Failed to get the notified waitlist entries: {}", e);
+            return;
+        }
+    };
+
+    for row in rows {
+        let start_date: NaiveDate = row.get("start_date");
+        let end_date: NaiveDate = row.get("end_date");
+        let priority_until: NaiveDateTime = row.get("priority_until");
+        let email: String = row.get("email");
+
+        let subject = "Hay un ejemplar disponible para usted - Bob el Alquilador";
+        let body = format!(
+            "Hola {},\n\nSe liberó un ejemplar de la máquina {} {} {} en la sucursal de {}, {} {} para el período del {} al {} que esperaba.\n\nLo reservamos para usted hasta el {}. Pasado ese plazo se ofrecerá a la siguiente persona en la lista de espera.\n\nSaludos cordiales,\nEl equipo de Bob el Alquilador.",
+            row.get::<_, String>("name"),
+            row.get::<_, String>("model_name"),
+            row.get::<_, String>("brand"),
+            row.get::<_, String>("model"),
+            row.get::<_, String>("city"),
+            row.get::<_, String>("street"),
+            row.get::<_, String>("number"),
+            start_date.format("%d/%m/%Y"),
+            end_date.format("%d/%m/%Y"),
+            priority_until.format("%d/%m/%Y %H:%M"),
+        );
+
+        if let Err(e) = send_mail(&email, subject, &body) {
+            eprintln!("Failed to notify the waitlist entry of {}: {}", email, e);
+        }
+    }
+}
+
+/// Moves the waitlists of the models the units belong to and emails the clients notified.
+/// Called whenever units may have become free; failures are only logged, since whatever freed
+/// the units already happened. Returns the ids of the entries notified.
+pub async fn reevaluate_waitlist(
+    client: &mut deadpool_postgres::Client,
+    unit_ids: &[i32],
+) -> Vec<i32> {
+    let model_rows = match client
+        .query(
+            "SELECT DISTINCT model_id FROM machinery_units WHERE id = ANY($1);",
+            &[&unit_ids],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            eprintln!(
+                "Failed to re-evaluate the waitlist of the units {:?}: {}",
+                unit_ids, e
+            );
+            return Vec::new();
+        }
+    };
+
+    let mut notified = Vec::new();
+
+    for row in model_rows {
+        let model_id: i32 = row.get("model_id");
+
+        let result = async {
+            let transaction = client.transaction().await?;
+            let entry_ids = process_waitlist(&transaction, model_id).await?;
+            transaction.commit().await?;
+            Ok::<_, tokio_postgres::Error>(entry_ids)
+        }
+        .await;
+
+        match result {
+            Ok(entry_ids) => notified.extend(entry_ids),
+            Err(e) => eprintln!(
+                "Failed to re-evaluate the waitlist of the model {}: {}",
+                model_id, e
+            ),
+        }
+    }
+
+    if !notified.is_empty() {
+        notify_waitlist_entries(&*client, &notified).await;
+    }
+
+    notified
+}
+
+/// Same as `reevaluate_waitlist` for the units of the given rentals.
+pub async fn reevaluate_waitlist_after_rentals(
+    client: &mut deadpool_postgres::Client,
+    rental_ids: &[i32],
+) -> Vec<i32> {
+    let unit_ids: Vec<i32> = match client
+        .query(
+            "SELECT DISTINCT machine_id FROM rentals WHERE id = ANY($1);",
+            &[&rental_ids],
+        )
+        .await
+    {
+        Ok(rows) => rows.iter().map(|row| row.get("machine_id")).collect(),
+        Err(e) => {
+            eprintln!(
+                "Failed to get the units of the rentals {:?}: {}",
+                rental_ids, e
+            );
+            return Vec::new();
+        }
+    };
+
+    reevaluate_waitlist(client, &unit_ids).await
+}
+
+/// Units held for notified entries whose priority window has run out.
+pub async fn lapsed_waitlist_holds(
+    client: &impl GenericClient,
+) -> Result<Vec<i32>, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            "SELECT DISTINCT unit_id FROM waitlist_entries
+            WHERE status = 'notified' AND priority_until <= NOW();",
+            &[],
+        )
+        .await?;
+
+    Ok(rows.iter().map(|row| row.get("unit_id")).collect())
+}
+
+/// Turns the hold a client got from the waitlist of the model at the location into the
+/// booking being made for overlapping dates, so that the held unit is free for it. Must run
+/// inside the booking's transaction, before the unit is locked. Returns the unit that was held.
+pub async fn claim_waitlist_hold(
+    client: &impl GenericClient,
+    user_id: i32,
+    model_id: i32,
+    location_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "UPDATE waitlist_entries SET status = 'booked'
+            WHERE id = (
+                SELECT id FROM waitlist_entries
+                WHERE user_id = $1 AND model_id = $2 AND location_id = $3
+                AND status = 'notified' AND priority_until > NOW()
+                AND start_date <= $5 AND $4 <= end_date
+                ORDER BY created_at, id
+                LIMIT 1
+                FOR UPDATE
+            )
+            RETURNING unit_id;",
+            &[&user_id, &model_id, &location_id, &start_date, &end_date],
+        )
+        .await?;
+
+    Ok(row.map(|row| row.get("unit_id")))
+}
+
+/// Same as `claim_waitlist_hold` for the model and location of the unit being booked.
+pub async fn claim_waitlist_hold_of_unit(
+    client: &impl GenericClient,
+    user_id: i32,
+    unit_id: i32,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Option<i32>, tokio_postgres::Error> {
+    let Some(row) = client
+        .query_opt(
+            "SELECT model_id, location_id FROM machinery_units WHERE id = $1;",
+            &[&unit_id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    claim_waitlist_hold(
+        client,
+        user_id,
+        row.get("model_id"),
+        row.get("location_id"),
+        start_date,
+        end_date,
+    )
+    .await
+}
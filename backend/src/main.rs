@@ -1,3 +1,4 @@
+use crate::constants::{MAX_EXTRA_IMAGES, MAX_IMAGE_UPLOAD_BYTES, STAGED_IMAGES_DIR};
 use crate::custom_types::enums::RunningEnv;
 use crate::custom_types::structs::AppState;
 use axum::{
@@ -7,10 +8,15 @@ use axum::{
 };
 use dotenvy::dotenv;
 use handlers::{
-    auth::*, machinery_mgmt::*, maintenance_mgmt::*, questions::*, reviews::*, stats::*,
+    auth::*, calendar::*, categories::*, charges::*, deliveries::*, documents::*, invoices::*,
+    machinery_mgmt::*, maintenance_mgmt::*, orders::*, pricing::*, questions::*, rentals::*,
+    reviews::*, specs::*, stats::*, transfers::*, waitlist::*,
 };
-use helpers::auth::create_pool;
-use std::{env, sync::Arc};
+use helpers::{
+    auth::create_pool, images::sweep_images, media::media_store_from_env,
+    rentals::spawn_payment_hold_expiry,
+};
+use std::{env, path::Path, sync::Arc};
 use tower_http::cors::CorsLayer;
 
 mod constants;
@@ -28,7 +34,7 @@ async fn main() {
     // Get the first CLI argument (after the executable name)
     let db_env = env::args()
         .nth(1)
-        .expect("Missing environment parameter: Usage cargo run -- <prod|test>");
+        .expect("Missing environment parameter: Usage cargo run -- <prod|test> [sweep-images]");
 
     // Create the pool
     let pool = match db_env.as_str() {
@@ -36,16 +42,36 @@ async fn main() {
         "prod" => create_pool(RunningEnv::Production).await,
         other => {
             panic!(
-                "Invalid environment parameter '{}': Usage cargo run -- <prod|test>",
+                "Invalid environment parameter '{}': Usage cargo run -- <prod|test> [sweep-images]",
                 other
             );
         }
     };
 
+    let media = media_store_from_env().unwrap_or_else(|e| panic!("{}", e));
+
+    // Maintenance commands run against the selected DB and exit
+    if env::args().nth(2).as_deref() == Some("sweep-images") {
+        let client = pool.get().await.expect("Failed to connect to the DB");
+
+        match sweep_images(&client, media.as_ref(), Path::new(STAGED_IMAGES_DIR)).await {
+            Ok(summary) => println!(
+                "Removed {} unreferenced files and generated {} missing variants",
+                summary.removed_files, summary.generated_variants
+            ),
+            Err(e) => eprintln!("Failed to sweep the images: {}", e),
+        }
+
+        return;
+    }
+
     let shared_state = AppState {
         pool: Arc::new(pool),
+        media,
     };
 
+    spawn_payment_hold_expiry(shared_state.pool.clone());
+
     // initialize tracing
     tracing_subscriber::fmt::init();
 
@@ -59,6 +85,7 @@ async fn main() {
         .route("/requestpswchange", post(request_psw_change))
         .route("/changepsw", post(change_password))
         .route("/changephone", post(change_phone))
+        .route("/changebilling", post(change_billing_info))
         .route("/checkchangepswcode", post(check_changepsw_code))
         .route("/getemployees", post(get_employees))
         .route("/deletemployee", post(delete_employee))
@@ -66,21 +93,86 @@ async fn main() {
         .route("/explore", get(explore_catalog))
         .route("/explore/{id}", get(select_machine))
         .route("/explore/{id}/locations", post(get_machine_locations))
+        .route(
+            "/explore/{id}/cancellationpolicy",
+            get(get_model_cancellation_policy),
+        )
+        .route("/calendar/{token}", get(get_calendar))
         .route("/rental/availability", post(get_units_unavailable_dates))
+        .route("/rental/quote", post(get_rental_quote))
         .route("/rental/new", post(new_rental))
+        .route("/rental/new/model", post(new_model_rental))
         .route("/newunit", post(new_unit))
         .route("/myrentals", post(get_my_rentals))
-        .route("/loadretirement", post(load_retirement))
-        .route("/loadreturn", post(load_return))
+        .route(
+            "/loadretirement",
+            post(load_retirement).layer(DefaultBodyLimit::max(20 * 1024 * 1024)),
+        ) //20MB for inspection photos
+        .route(
+            "/loadreturn",
+            post(load_return).layer(DefaultBodyLimit::max(20 * 1024 * 1024)),
+        )
         .route("/getmodels", post(get_models))
         .route(
             "/newmodel",
             post(new_model).layer(DefaultBodyLimit::max(20 * 1024 * 1024)),
         ) //20MB for images
+        .route(
+            "/newmodel/multipart",
+            post(new_model_multipart).layer(DefaultBodyLimit::max(
+                (MAX_EXTRA_IMAGES + 1) * MAX_IMAGE_UPLOAD_BYTES + 1024 * 1024,
+            )),
+        ) //Every image at its maximum size plus the model data
         .route("/payment/check", post(check_rental_payment))
         .route("/rental/cancel", post(cancel_rental))
+        .route("/rental/{id}/timeline", post(get_rental_timeline))
+        .route("/rental/{id}/inspections", post(get_rental_inspections))
+        .route("/rental/{id}/deposit", post(get_rental_deposit))
+        .route("/rental/{id}/contract", post(get_rental_contract))
+        .route("/rental/{id}/receipt", post(get_rental_receipt))
+        .route("/order/new", post(new_order))
+        .route("/order/payment/check", post(check_order_payment))
+        .route("/order/{id}", post(get_order_detail))
+        .route("/rental/extension/request", post(request_rental_extension))
+        .route("/rental/extension/payment", post(check_extension_payment))
+        .route("/charges", post(list_charges))
+        .route("/charge/payment", post(check_charge_payment))
+        .route("/charge/waive", post(waive_charge))
+        .route("/delivery/quote", post(get_delivery_quote))
+        .route("/delivery/request", post(request_delivery))
+        .route("/delivery/slots", post(get_delivery_slots))
+        .route("/delivery/rates/update", post(update_delivery_rates))
+        .route("/waitlist", post(get_waitlist))
+        .route("/waitlist/join", post(join_waitlist))
+        .route("/waitlist/leave", post(leave_waitlist))
         .route("/staff/rentals", post(get_staff_rentals))
         .route("/locations", post(get_locations))
+        .route("/categories", post(get_categories))
+        .route("/categories/new", post(new_category))
+        .route("/categories/update", post(update_category))
+        .route("/categories/merge", post(merge_categories))
+        .route("/categories/delete", post(delete_category))
+        .route("/specs", post(get_spec_definitions))
+        .route("/specs/new", post(new_spec_definition))
+        .route("/model/specs/update", post(update_model_specs))
+        .route("/model/rentalrules/update", post(update_model_rental_rules))
+        .route(
+            "/model/cancellationpolicy/update",
+            post(update_model_cancellation_policy),
+        )
+        .route("/model/deposit/update", post(update_model_deposit))
+        .route("/pricing", post(get_pricing_rules))
+        .route("/pricing/baseprice", post(update_base_price))
+        .route("/pricing/discounts/new", post(new_duration_discount))
+        .route("/pricing/discounts/delete", post(delete_duration_discount))
+        .route("/pricing/seasons/new", post(new_seasonal_rate))
+        .route("/pricing/seasons/delete", post(delete_seasonal_rate))
+        .route("/taxes", post(get_tax_rates))
+        .route("/taxes/set", post(set_tax_rate))
+        .route("/taxes/delete", post(delete_tax_rate))
+        .route("/invoices", post(get_invoices))
+        .route("/invoice/{id}", post(get_invoice))
+        .route("/invoice/{id}/pdf", post(get_invoice_pdf))
         .route("/newquestion", post(new_question))
         .route("/newanswer", post(new_answer))
         .route("/votequestion", post(vote_question))
@@ -89,6 +181,14 @@ async fn main() {
         .route("/unit/{serial_number}", post(get_machine_unit))
         .route("/unit/{id}/history", post(get_unit_history))
         .route("/unit/history/update", post(update_unit_history))
+        .route("/unit/decommission", post(decommission_unit))
+        .route("/transfers", post(list_transfers))
+        .route("/transfer/new", post(new_transfer))
+        .route("/transfer/cancel", post(cancel_transfer))
+        .route("/calendar/feeds", post(get_calendar_feeds))
+        .route("/calendar/feed/new", post(new_calendar_feed))
+        .route("/calendar/feed/revoke", post(revoke_calendar_feed))
+        .route("/model/decommission", post(decommission_model))
         .route("/staff/rental/verifyclient", post(verify_client))
         .route(
             "/staff/rental/getunits",
@@ -96,6 +196,13 @@ async fn main() {
         )
         .route("/staff/rental/validatedates", post(validate_rental_dates))
         .route("/staff/rental/new", post(new_in_person_rental))
+        .route("/staff/rental/extension", post(grant_rental_extension))
+        .route("/staff/charge/collect", post(collect_charge))
+        .route("/staff/transfer/ship", post(ship_transfer))
+        .route("/staff/transfer/receive", post(receive_transfer))
+        .route("/staff/deliveries", post(get_dispatch_list))
+        .route("/staff/delivery/delivered", post(mark_delivered))
+        .route("/staff/delivery/collected", post(mark_collected))
         .route("/reviews/machines/new", post(new_machine_review))
         .route("/reviews/service/new", post(new_service_review))
         .route("/reviews/service/get", post(get_service_reviews))
@@ -0,0 +1,265 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+
+#[tokio::test]
+async fn test_calendar_feeds() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testcalendar', 'model23', 'CL-1', 2024, 'Sin reembolsos.', 'Agenda', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('CALENDAR-001', 'available', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+
+    // calendar@example.com has id 37
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (37, $1, $2, $3, 7000.00, 'active')
+            RETURNING id;",
+            &[
+                &unit_id,
+                &(today + Duration::days(10)),
+                &(today + Duration::days(17)),
+            ],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let jwt = get_test_jwt("calendar@example.com", false).await;
+    let other_jwt = get_test_jwt("documents@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+
+    let new_feed = |access: &str, kind: &str, subject_id: Option<i32>| {
+        http_client
+            .post(backend_url("/calendar/feed/new"))
+            .json(&serde_json::json!({
+                "access": access,
+                "kind": kind,
+                "subject_id": subject_id
+            }))
+            .send()
+    };
+
+    let fetch = |url: String| {
+        let http_client = &http_client;
+        async move {
+            let response = http_client.get(url).send().await.unwrap();
+            let status = response.status();
+            (status, response.text().await.unwrap())
+        }
+    };
+
+    // ---------- Feed of the client's rentals
+
+    let response = new_feed(&staff_jwt, "client", Some(37)).await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = new_feed(&jwt, "unit", Some(unit_id)).await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = new_feed(&jwt, "client", None).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let feed_id = body["feed"]["id"].as_i64().unwrap() as i32;
+    let url = body["feed"]["url"].as_str().unwrap().to_string();
+    assert!(url.contains("/calendar/"));
+
+    // A client only has one feed
+    let response = new_feed(&jwt, "client", None).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["feed"]["id"], feed_id);
+
+    // Feed tokens don't open anything else, and no other token opens a feed
+    let feed_token = url.rsplit('/').next().unwrap().to_string();
+    let response = new_feed(&feed_token, "client", None).await.unwrap();
+
+    assert_eq!(response.status(), 401);
+
+    let (status, _) = fetch(backend_url(&format!("/calendar/{}", jwt))).await;
+
+    assert_eq!(status, 404);
+
+    let response = http_client.get(&url).send().await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/calendar; charset=utf-8"
+    );
+    let calendar = response.text().await.unwrap();
+    assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(calendar.contains(&format!("UID:rental-{}@", rental_id)));
+    assert!(calendar.contains(&format!(
+        "DTSTART;VALUE=DATE:{}",
+        (today + Duration::days(10)).format("%Y%m%d")
+    )));
+    assert!(calendar.contains(&format!(
+        "DTEND;VALUE=DATE:{}",
+        (today + Duration::days(18)).format("%Y%m%d")
+    )));
+    assert!(calendar.contains("STATUS:CONFIRMED"));
+
+    // The feed follows the status of the rental
+    db_client
+        .execute(
+            "UPDATE rentals SET status = 'cancelled' WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await
+        .unwrap();
+
+    let (status, calendar) = fetch(url.clone()).await;
+
+    assert_eq!(status, 200);
+    assert!(calendar.contains("STATUS:CANCELLED"));
+
+    db_client
+        .execute(
+            "UPDATE rentals SET status = 'active' WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await
+        .unwrap();
+
+    // ---------- Schedule of a unit
+
+    db_client
+        .execute(
+            "INSERT INTO unit_history_events (unit_id, description, previous_status, new_status, created_at)
+            VALUES ($1, 'Cambio de aceite', 'available', 'maintenance', NOW() - INTERVAL '3 days'),
+            ($1, NULL, 'maintenance', 'available', NOW() - INTERVAL '1 day');",
+            &[&unit_id],
+        )
+        .await
+        .unwrap();
+
+    let response = new_feed(&staff_jwt, "unit", None).await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = new_feed(&staff_jwt, "unit", Some(999999)).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = new_feed(&staff_jwt, "unit", Some(unit_id)).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+
+    let (status, calendar) = fetch(body["feed"]["url"].as_str().unwrap().to_string()).await;
+
+    assert_eq!(status, 200);
+    assert!(calendar.contains("X-WR-CALNAME:Unidad CALENDAR-001"));
+    assert!(calendar.contains(&format!("UID:rental-{}@", rental_id)));
+    assert!(calendar.contains("SUMMARY:Mantenimiento\r\n"));
+    assert!(calendar.contains("DESCRIPTION:Cambio de aceite"));
+
+    // ---------- Agenda of a branch
+
+    let response = new_feed(&staff_jwt, "branch", Some(1)).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let branch_url = body["feed"]["url"].as_str().unwrap().to_string();
+
+    let (status, calendar) = fetch(branch_url.clone()).await;
+
+    assert_eq!(status, 200);
+    assert!(calendar.contains(&format!("UID:rental-{}-retirement@", rental_id)));
+    assert!(calendar.contains(&format!("UID:rental-{}-return@", rental_id)));
+
+    // Once the machine is retired only its return is due
+    db_client
+        .execute(
+            "UPDATE rentals SET status = 'retired' WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await
+        .unwrap();
+
+    let (_, calendar) = fetch(branch_url).await;
+
+    assert!(!calendar.contains(&format!("UID:rental-{}-retirement@", rental_id)));
+    assert!(calendar.contains(&format!("UID:rental-{}-return@", rental_id)));
+
+    let response = http_client
+        .post(backend_url("/calendar/feeds"))
+        .json(&serde_json::json!({"access": staff_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let feeds = body["feeds"].as_array().unwrap();
+    assert_eq!(feeds.len(), 2);
+    assert!(feeds.iter().all(|feed| feed["kind"] != "client"));
+
+    // ---------- Revoking a feed
+
+    let revoke = |access: &str| {
+        http_client
+            .post(backend_url("/calendar/feed/revoke"))
+            .json(&serde_json::json!({"access": access, "feed_id": feed_id}))
+            .send()
+    };
+
+    let response = revoke(&other_jwt).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = revoke(&jwt).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let (status, _) = fetch(url.clone()).await;
+
+    assert_eq!(status, 404);
+
+    // A tampered token isn't accepted either
+    let (status, _) = fetch(format!("{}x", url)).await;
+
+    assert_eq!(status, 404);
+
+    let response = new_feed(&jwt, "client", None).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_ne!(body["feed"]["id"], feed_id);
+    assert_ne!(body["feed"]["url"], url);
+}
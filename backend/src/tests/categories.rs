@@ -0,0 +1,355 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use reqwest::Client;
+
+#[tokio::test]
+async fn test_manage_categories() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("admin@example.com", true).await;
+
+    // ---------- A parent category matches the models of its subcategories
+
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("category", "excavadoras")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 2);
+    assert_eq!(body["items"][0]["id"], 11);
+    assert_eq!(body["items"][1]["id"], 12);
+
+    // ---------- Employee tries to create a category
+
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+
+    let response = http_client
+        .post(backend_url("/categories/new"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "name": "Excavadoras compactas",
+            "parent_id": 6
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // ---------- Admin creates a subcategory
+
+    let response = http_client
+        .post(backend_url("/categories/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "  Excavadoras Compactas ",
+            "parent_id": 6
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let new_category_id = body["id"].as_i64().unwrap() as i32;
+
+    let row = db_client
+        .query_one(
+            "SELECT name, parent_id FROM categories WHERE id = $1;",
+            &[&new_category_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("name"), "excavadoras compactas");
+    assert_eq!(row.get::<_, Option<i32>>("parent_id"), Some(6));
+
+    // ---------- Admin creates a category with an existing name
+
+    let response = http_client
+        .post(backend_url("/categories/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "Excavadoras",
+            "parent_id": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin creates a category with a non-existing parent
+
+    let response = http_client
+        .post(backend_url("/categories/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "sin padre",
+            "parent_id": 99999
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin renames and moves the category
+
+    let response = http_client
+        .post(backend_url("/categories/update"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": new_category_id,
+            "name": "Miniexcavadoras compactas",
+            "parent_id": 7
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let row = db_client
+        .query_one(
+            "SELECT name, parent_id FROM categories WHERE id = $1;",
+            &[&new_category_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("name"), "miniexcavadoras compactas");
+    assert_eq!(row.get::<_, Option<i32>>("parent_id"), Some(7));
+
+    // ---------- Admin moves a category under one of its subcategories
+
+    let response = http_client
+        .post(backend_url("/categories/update"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": 6,
+            "name": "excavadoras",
+            "parent_id": new_category_id
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin updates a non-existing category
+
+    let response = http_client
+        .post(backend_url("/categories/update"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": 99999,
+            "name": "no existe",
+            "parent_id": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Admin merges the misspelled category into the new one
+
+    let response = http_client
+        .post(backend_url("/categories/merge"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "source_id": 8,
+            "target_id": new_category_id
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let rows = db_client
+        .query("SELECT * FROM categories WHERE id = 8;", &[])
+        .await
+        .unwrap();
+
+    assert!(rows.is_empty());
+
+    let row = db_client
+        .query_one(
+            "SELECT category_id FROM machinery_categories WHERE model_id = 13;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, i32>("category_id"), new_category_id);
+
+    // The merged model is now found through the whole tree
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("category", "excavadoras")])
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 3);
+
+    // ---------- Admin merges a category with itself
+
+    let response = http_client
+        .post(backend_url("/categories/merge"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "source_id": 6,
+            "target_id": 6
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin merges a category into one of its subcategories
+
+    let response = http_client
+        .post(backend_url("/categories/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "miniexcavadora sobre orugas",
+            "parent_id": 7
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let grandchild_id = body["id"].as_i64().unwrap();
+
+    for target_id in [7, grandchild_id] {
+        let response = http_client
+            .post(backend_url("/categories/merge"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "source_id": 6,
+                "target_id": target_id
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400, "target {}", target_id);
+    }
+
+    let rows = db_client
+        .query("SELECT id FROM categories WHERE id = 6;", &[])
+        .await
+        .unwrap();
+
+    assert_eq!(rows.len(), 1);
+
+    let response = http_client
+        .post(backend_url("/categories/delete"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": grandchild_id
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Admin deletes a category that is in use
+
+    let response = http_client
+        .post(backend_url("/categories/delete"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": 7
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin deletes an unused category
+
+    let response = http_client
+        .post(backend_url("/categories/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "categoria temporal",
+            "parent_id": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let temporal_category_id = body["id"].as_i64().unwrap();
+
+    let response = http_client
+        .post(backend_url("/categories/delete"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": temporal_category_id
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Admin deletes a non-existing category
+
+    let response = http_client
+        .post(backend_url("/categories/delete"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "id": 99999
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Admin lists the categories
+
+    let response = http_client
+        .post(backend_url("/categories"))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let category = body["categories"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["name"] == "miniexcavadora")
+        .unwrap();
+
+    assert_eq!(category["parent_id"], 6);
+    assert_eq!(category["model_count"], 1);
+}
@@ -0,0 +1,287 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[tokio::test]
+async fn test_charges() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testcharges', 'model15', 'CH-1', 2024, 'Sin reembolsos.', 'Compactadora', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('CHARGE-001', 'rented', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+
+    // charges@example.com has id 28
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status, retirement_date)
+            VALUES (28, $1, $2, $3, 15000.00, 'retired', $2)
+            RETURNING id;",
+            &[&unit_id, &(today - Duration::days(20)), &(today - Duration::days(5))],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let jwt = get_test_jwt("charges@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+    let admin_jwt = get_test_jwt("admin@example.com", false).await;
+
+    let list_charges = |access: &str, body: serde_json::Value| {
+        let mut body = body;
+        body["access"] = serde_json::json!(access);
+        http_client.post(backend_url("/charges")).json(&body).send()
+    };
+
+    let book = || {
+        http_client
+            .post(backend_url("/rental/new"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "machine_id": unit_id,
+                "start_date": today + Duration::days(300),
+                "end_date": today + Duration::days(307),
+                "total_price": null
+            }))
+            .send()
+    };
+
+    // ---------- Late return fine is recorded
+
+    let response = http_client
+        .post(backend_url("/loadreturn"))
+        .json(&serde_json::json!({
+            "access": staff_jwt,
+            "rental_id": rental_id,
+            "location_id": 1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["days_late"], 5);
+    let charge_id = body["charge_id"].as_i64().unwrap() as i32;
+
+    let response = list_charges(&jwt, serde_json::json!({})).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let charges = body["charges"].as_array().unwrap();
+    assert_eq!(charges.len(), 1);
+    assert_eq!(charges[0]["kind"], "late_return");
+    assert_eq!(charges[0]["status"], "pending");
+    assert_eq!(charges[0]["rental_id"], rental_id);
+    let pending_amount: Decimal = serde_json::from_value(body["pending_amount"].clone()).unwrap();
+    assert_eq!(pending_amount, dec!(500));
+
+    // Clients can't see other clients' charges
+    let response = list_charges(&jwt, serde_json::json!({"user_id": 8}))
+        .await
+        .unwrap();
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert!(body["charges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|charge| charge["user_id"] == 28));
+
+    // ---------- Overdue charges block new rentals
+
+    db_client
+        .execute(
+            "UPDATE charges SET due_date = $1 WHERE id = $2;",
+            &[&(today - Duration::days(1)), &charge_id],
+        )
+        .await
+        .unwrap();
+
+    let response = book().await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = http_client
+        .post(backend_url("/rental/new/model"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": model_id,
+            "location_id": 1,
+            "start_date": today + Duration::days(300),
+            "end_date": today + Duration::days(307),
+            "total_price": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // ---------- Online payment
+
+    let pay = |status: &str| {
+        http_client
+            .post(backend_url("/charge/payment"))
+            .query(&[("payment_id", "CH-2424235352"), ("status", status)])
+            .json(&serde_json::json!({
+                "access": jwt,
+                "charge_id": charge_id
+            }))
+            .send()
+    };
+
+    let response = pay("rejected").await.unwrap();
+
+    assert_eq!(response.status(), 502);
+
+    let response = pay("approved").await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = pay("approved").await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT, payment_id, collected_by FROM charges WHERE id = $1;",
+            &[&charge_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "paid");
+    assert_eq!(row.get::<_, String>("payment_id"), "CH-2424235352");
+    assert_eq!(row.get::<_, Option<i32>>("collected_by"), None);
+
+    let response = book().await.unwrap();
+
+    assert_ne!(response.status(), 403);
+
+    // ---------- Paid at the counter and waived
+
+    let insert_charge = "
+        INSERT INTO charges (rental_id, user_id, kind, description, amount, due_date)
+        VALUES ($1, 28, 'late_return', 'Devolución con 2 días de retraso', 200.00, $2)
+        RETURNING id;
+    ";
+
+    let counter_charge_id: i32 = db_client
+        .query_one(insert_charge, &[&rental_id, &(today - Duration::days(1))])
+        .await
+        .unwrap()
+        .get("id");
+
+    let collect = |access: &str| {
+        http_client
+            .post(backend_url("/staff/charge/collect"))
+            .json(&serde_json::json!({
+                "access": access,
+                "charge_id": counter_charge_id
+            }))
+            .send()
+    };
+
+    let response = collect(&jwt).await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = collect(&staff_jwt).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let collected_by: Option<i32> = db_client
+        .query_one(
+            "SELECT collected_by FROM charges WHERE id = $1;",
+            &[&counter_charge_id],
+        )
+        .await
+        .unwrap()
+        .get("collected_by");
+
+    assert_eq!(collected_by, Some(2));
+
+    let waived_charge_id: i32 = db_client
+        .query_one(insert_charge, &[&rental_id, &(today - Duration::days(1))])
+        .await
+        .unwrap()
+        .get("id");
+
+    let waive = |access: &str, charge_id: i32, reason: &str| {
+        http_client
+            .post(backend_url("/charge/waive"))
+            .json(&serde_json::json!({
+                "access": access,
+                "charge_id": charge_id,
+                "reason": reason
+            }))
+            .send()
+    };
+
+    let response = waive(&staff_jwt, waived_charge_id, "Cliente frecuente")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = waive(&admin_jwt, waived_charge_id, "").await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = waive(&admin_jwt, counter_charge_id, "Cliente frecuente")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = waive(&admin_jwt, waived_charge_id, "Cliente frecuente")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = list_charges(
+        &staff_jwt,
+        serde_json::json!({"user_id": 28, "status": "waived"}),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let charges = body["charges"].as_array().unwrap();
+    assert_eq!(charges.len(), 1);
+    assert_eq!(charges[0]["id"], waived_charge_id);
+    assert_eq!(charges[0]["waive_reason"], "Cliente frecuente");
+    assert_eq!(charges[0]["waived_by"], 11);
+}
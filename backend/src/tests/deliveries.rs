@@ -0,0 +1,351 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local, NaiveDate};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[tokio::test]
+async fn test_deliveries() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testdelivery', 'model20', 'DL-1', 2024, 'Sin reembolsos.', 'Envío', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let mut unit_ids = Vec::new();
+    for serial_number in ["DELIVERY-001", "DELIVERY-002", "DELIVERY-003"] {
+        let unit_id: i32 = db_client
+            .query_one(
+                "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+                VALUES ($1, 'available', $2, 1)
+                RETURNING id;",
+                &[&serial_number, &model_id],
+            )
+            .await
+            .unwrap()
+            .get("id");
+        unit_ids.push(unit_id);
+    }
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + Duration::days(n);
+
+    // deliveries@example.com has id 34
+    let paid_rental = |unit_id: i32, start: i64| {
+        let db_client = &db_client;
+        async move {
+            db_client
+                .query_one(
+                    "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+                    VALUES (34, $1, $2, $3, 7000.00, 'active')
+                    RETURNING id;",
+                    &[&unit_id, &days(start), &days(start + 7)],
+                )
+                .await
+                .unwrap()
+                .get::<_, i32>("id")
+        }
+    };
+
+    let jwt = get_test_jwt("deliveries@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+    let admin_jwt = get_test_jwt("admin@example.com", false).await;
+
+    // A job site about 10 km from the branch in Buenos Aires, and one in La Plata
+    let (site_latitude, site_longitude) = (-34.545, -58.46);
+    let (far_latitude, far_longitude) = (-34.920495, -57.953566);
+
+    // ---------- Rates of the branch
+
+    let update_rates = |access: &str, base_fee: &str| {
+        http_client
+            .post(backend_url("/delivery/rates/update"))
+            .json(&serde_json::json!({
+                "access": access,
+                "location_id": 1,
+                "base_fee": base_fee,
+                "fee_per_km": "1000.00",
+                "max_distance_km": "50.00",
+                "slot_capacity": 1
+            }))
+            .send()
+    };
+
+    let response = update_rates(&staff_jwt, "10000.00").await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = update_rates(&admin_jwt, "-1.00").await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = update_rates(&admin_jwt, "10000.00").await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Fee from the distance to the branch
+
+    let quote = |latitude: f64, longitude: f64, pickup: bool| {
+        http_client
+            .post(backend_url("/delivery/quote"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "machine_id": unit_ids[0],
+                "latitude": latitude,
+                "longitude": longitude,
+                "pickup": pickup
+            }))
+            .send()
+    };
+
+    let response = quote(site_latitude, site_longitude, false).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let distance = decimal(&body["distance_km"]);
+    assert!(distance > dec!(5) && distance < dec!(15));
+    let fee = decimal(&body["fee"]);
+    assert_eq!(fee, dec!(10000) + dec!(1000) * distance);
+
+    let response = quote(site_latitude, site_longitude, true).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["fee"]), fee * dec!(2));
+
+    let response = quote(far_latitude, far_longitude, false).await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Scheduling deliveries in the slots of the branch
+
+    let request = |rental_id: i32, delivery_slot: &str, pickup_slot: Option<&str>| {
+        http_client
+            .post(backend_url("/delivery/request"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "rental_id": rental_id,
+                "address": "Av. del Libertador 6000",
+                "latitude": site_latitude,
+                "longitude": site_longitude,
+                "delivery_slot": delivery_slot,
+                "pickup_slot": pickup_slot
+            }))
+            .send()
+    };
+
+    let rental_id = paid_rental(unit_ids[0], 30).await;
+    let other_rental_id = paid_rental(unit_ids[1], 30).await;
+
+    let response = request(rental_id, "morning", Some("afternoon"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["delivery"]["fee"]), fee * dec!(2));
+    assert_eq!(body["delivery"]["status"], "scheduled");
+    let charge_id = body["delivery"]["charge_id"].as_i64().unwrap() as i32;
+
+    let charge = db_client
+        .query_one(
+            "SELECT kind::TEXT, amount, due_date FROM charges WHERE id = $1;",
+            &[&charge_id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(charge.get::<_, String>("kind"), "delivery");
+    assert_eq!(charge.get::<_, Decimal>("amount"), fee * dec!(2));
+    assert_eq!(charge.get::<_, NaiveDate>("due_date"), days(30));
+
+    let response = request(rental_id, "afternoon", None).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    // The only morning trip of the day is taken
+    let response = request(other_rental_id, "morning", None).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = request(other_rental_id, "afternoon", None).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let response = http_client
+        .post(backend_url("/delivery/slots"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "location_id": 1,
+            "date": days(30)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["slots"][0]["slot"], "morning");
+    assert_eq!(body["slots"][0]["booked"], 1);
+    assert_eq!(body["slots"][1]["slot"], "afternoon");
+    assert_eq!(body["slots"][1]["booked"], 1);
+
+    // ---------- Daily dispatch list
+
+    let dispatch_list = |access: &str, date: NaiveDate| {
+        http_client
+            .post(backend_url("/staff/deliveries"))
+            .json(&serde_json::json!({
+                "access": access,
+                "location_id": 1,
+                "date": date
+            }))
+            .send()
+    };
+
+    let response = dispatch_list(&jwt, days(30)).await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = dispatch_list(&staff_jwt, days(30)).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let stops = body["stops"].as_array().unwrap();
+    assert_eq!(stops.len(), 2);
+    assert_eq!(stops[0]["rental_id"], rental_id);
+    assert_eq!(stops[0]["slot"], "morning");
+    assert_eq!(stops[0]["client_phone"], "1145678901");
+    assert_eq!(stops[1]["rental_id"], other_rental_id);
+
+    let response = dispatch_list(&staff_jwt, days(37)).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let stops = body["stops"].as_array().unwrap();
+    assert_eq!(stops.len(), 1);
+    assert_eq!(stops[0]["kind"], "pickup");
+    assert_eq!(stops[0]["rental_id"], rental_id);
+
+    // ---------- Delivered and collected events
+
+    let record = |event: &str, rental_id: i32| {
+        http_client
+            .post(backend_url(&format!("/staff/delivery/{}", event)))
+            .json(&serde_json::json!({
+                "access": staff_jwt,
+                "rental_id": rental_id
+            }))
+            .send()
+    };
+
+    // The machine hasn't left the branch yet
+    let response = record("delivered", rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    db_client
+        .execute(
+            "UPDATE rentals SET status = 'retired', retirement_date = $2
+            WHERE id = ANY($1);",
+            &[&vec![rental_id, other_rental_id], &today],
+        )
+        .await
+        .unwrap();
+
+    let response = record("collected", rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = record("delivered", rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = record("collected", rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = record("collected", rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    // The client returns this one to the branch
+    let response = record("delivered", other_rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = record("collected", other_rental_id).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = http_client
+        .post(backend_url(&format!("/rental/{}/timeline", rental_id)))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["delivery"]["status"], "collected");
+    assert!(!body["delivery"]["delivered_at"].is_null());
+    assert!(!body["delivery"]["collected_at"].is_null());
+
+    // ---------- Cancelling the rental cancels the delivery
+
+    let cancelled_rental_id = paid_rental(unit_ids[2], 60).await;
+
+    let response = request(cancelled_rental_id, "morning", None).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let charge_id = body["delivery"]["charge_id"].as_i64().unwrap() as i32;
+
+    let response = http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": cancelled_rental_id,
+            "reason": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let status: String = db_client
+        .query_one(
+            "SELECT status::TEXT FROM deliveries WHERE rental_id = $1;",
+            &[&cancelled_rental_id],
+        )
+        .await
+        .unwrap()
+        .get("status");
+    assert_eq!(status, "cancelled");
+
+    let status: String = db_client
+        .query_one(
+            "SELECT status::TEXT FROM charges WHERE id = $1;",
+            &[&charge_id],
+        )
+        .await
+        .unwrap()
+        .get("status");
+    assert_eq!(status, "waived");
+}
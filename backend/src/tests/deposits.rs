@@ -0,0 +1,330 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[tokio::test]
+async fn test_deposits() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testdeposits', 'model17', 'DP-1', 2024, 'Sin reembolsos.', 'Grúa', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let mut unit_ids = Vec::new();
+    for (serial_number, status) in [
+        ("DEPOSIT-001", "available"),
+        ("DEPOSIT-002", "rented"),
+        ("DEPOSIT-003", "rented"),
+    ] {
+        let unit_id: i32 = db_client
+            .query_one(
+                "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+                VALUES ($1, $2::TEXT::machine_status, $3, 1)
+                RETURNING id;",
+                &[&serial_number, &status, &model_id],
+            )
+            .await
+            .unwrap()
+            .get("id");
+        unit_ids.push(unit_id);
+    }
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + Duration::days(n);
+
+    let jwt = get_test_jwt("deposits@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+    let admin_jwt = get_test_jwt("admin@example.com", false).await;
+
+    let deposit_of = |rental_id: i32| {
+        let db_client = &db_client;
+        async move {
+            let row = db_client
+                .query_one(
+                    "SELECT deposit_amount, deposit_status::TEXT FROM rentals WHERE id = $1;",
+                    &[&rental_id],
+                )
+                .await
+                .unwrap();
+            (
+                row.get::<_, Decimal>("deposit_amount"),
+                row.get::<_, Option<String>>("deposit_status"),
+            )
+        }
+    };
+
+    // ---------- Per-model deposit
+
+    let update_deposit = |access: &str, deposit: &str| {
+        http_client
+            .post(backend_url("/model/deposit/update"))
+            .json(&serde_json::json!({
+                "access": access,
+                "model_id": model_id,
+                "deposit": deposit
+            }))
+            .send()
+    };
+
+    let response = update_deposit(&staff_jwt, "5000.00").await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = update_deposit(&admin_jwt, "-1.00").await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = update_deposit(&admin_jwt, "5000.00").await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = http_client
+        .get(backend_url(&format!("/explore/{}", model_id)))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["machine"]["deposit"]), dec!(5000));
+
+    let quote = |start: i64| {
+        http_client
+            .post(backend_url("/rental/quote"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "machine_id": unit_ids[0],
+                "start_date": days(start),
+                "end_date": days(start + 7)
+            }))
+            .send()
+    };
+
+    let response = quote(100).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["quote"]["total_price"]), dec!(7000));
+    assert_eq!(decimal(&body["quote"]["deposit"]), dec!(5000));
+    assert_eq!(decimal(&body["quote"]["amount_due"]), dec!(12000));
+    let quote_token = body["quote_token"].as_str().unwrap().to_string();
+
+    // ---------- Paid online with the rental, released on cancellation
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": unit_ids[0],
+            "start_date": days(100),
+            "end_date": days(107),
+            "quote_token": quote_token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["deposit"]), dec!(5000));
+    assert_eq!(decimal(&body["amount_due"]), dec!(12000));
+    let online_rental_id = body["rental_id"].as_i64().unwrap() as i32;
+
+    assert_eq!(deposit_of(online_rental_id).await, (dec!(5000), None));
+
+    http_client
+        .post(backend_url("/payment/check"))
+        .query(&[("payment_id", "DP-2424235352"), ("status", "approved")])
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": online_rental_id
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        deposit_of(online_rental_id).await,
+        (dec!(5000), Some("held".to_string()))
+    );
+
+    let response = http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": online_rental_id,
+            "reason": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["deposit_released"]), dec!(5000));
+    assert_eq!(
+        deposit_of(online_rental_id).await,
+        (dec!(5000), Some("released".to_string()))
+    );
+
+    // ---------- Collected at the counter
+
+    let response = quote(120).await.unwrap();
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let quote_token = body["quote_token"].as_str().unwrap().to_string();
+
+    http_client
+        .post(backend_url("/staff/rental/new"))
+        .json(&serde_json::json!({
+            "access": staff_jwt,
+            "machine_id": unit_ids[0],
+            "user_id": 30,
+            "start_date": days(120),
+            "end_date": days(127),
+            "quote_token": quote_token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let row = db_client
+        .query_one(
+            "SELECT deposit_amount, deposit_status::TEXT FROM rentals
+            WHERE user_id = 30 AND start_date = $1;",
+            &[&days(120)],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, Decimal>("deposit_amount"), dec!(5000));
+    assert_eq!(
+        row.get::<_, Option<String>>("deposit_status").as_deref(),
+        Some("held")
+    );
+
+    // ---------- Settled against the charges at return
+
+    // deposits@example.com has id 30
+    let retired_rental = |unit_id: i32, end: i64| {
+        let db_client = &db_client;
+        async move {
+            db_client
+                .query_one(
+                    "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status,
+                        retirement_date, deposit_amount, deposit_status)
+                    VALUES (30, $1, $2, $3, 7000.00, 'retired', $2, 5000.00, 'held')
+                    RETURNING id;",
+                    &[&unit_id, &days(end - 7), &days(end)],
+                )
+                .await
+                .unwrap()
+                .get::<_, i32>("id")
+        }
+    };
+
+    let give_back = |rental_id: i32, damage_charge: &str| {
+        http_client
+            .post(backend_url("/loadreturn"))
+            .json(&serde_json::json!({
+                "access": staff_jwt,
+                "rental_id": rental_id,
+                "location_id": 1,
+                "inspection": {
+                    "items": [{"item": "Pluma", "ok": false, "notes": "Abollada"}],
+                    "damage_charge": damage_charge
+                }
+            }))
+            .send()
+    };
+
+    // Returned on time, the deposit covers the damages and the rest is released
+    let rental_id = retired_rental(unit_ids[1], 0).await;
+
+    let response = give_back(rental_id, "2000.00").await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let damage_charge_id = body["damage_charge_id"].as_i64().unwrap() as i32;
+    let statement = &body["deposit"];
+    assert_eq!(statement["status"], "partially_kept");
+    assert_eq!(decimal(&statement["kept"]), dec!(2000));
+    assert_eq!(decimal(&statement["released"]), dec!(3000));
+    assert_eq!(statement["applications"].as_array().unwrap().len(), 1);
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT, payment_id FROM charges WHERE id = $1;",
+            &[&damage_charge_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "paid");
+    assert!(row.get::<_, String>("payment_id").starts_with('D'));
+
+    // Returned late, the fine and the damages take the whole deposit
+    let late_rental_id = retired_rental(unit_ids[2], -5).await;
+
+    let response = give_back(late_rental_id, "8000.00").await.unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let statement_of = |access: &str| {
+        http_client
+            .post(backend_url(&format!("/rental/{}/deposit", late_rental_id)))
+            .json(&serde_json::json!({"access": access}))
+            .send()
+    };
+
+    let hank_jwt = get_test_jwt("hank@example.com", false).await;
+    let response = statement_of(&hank_jwt).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = statement_of(&jwt).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let statement = &body["deposit"];
+    assert_eq!(statement["status"], "kept");
+    assert_eq!(decimal(&statement["kept"]), dec!(5000));
+    assert_eq!(decimal(&statement["released"]), dec!(0));
+
+    let applications = statement["applications"].as_array().unwrap();
+    assert_eq!(applications.len(), 2);
+    assert_eq!(applications[0]["kind"], "late_return");
+    assert_eq!(decimal(&applications[0]["applied"]), dec!(500));
+    assert_eq!(decimal(&applications[0]["outstanding"]), dec!(0));
+    assert_eq!(applications[1]["kind"], "damage");
+    assert_eq!(decimal(&applications[1]["applied"]), dec!(4500));
+    assert_eq!(decimal(&applications[1]["outstanding"]), dec!(3500));
+
+    // The client only owes what the deposit didn't cover
+    let response = http_client
+        .post(backend_url("/charges"))
+        .json(&serde_json::json!({"access": jwt, "status": "pending"}))
+        .send()
+        .await
+        .unwrap();
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(decimal(&body["pending_amount"]), dec!(3500));
+}
@@ -0,0 +1,124 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+
+#[tokio::test]
+async fn test_rental_documents() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    // A long policy so that the contract takes more than one page
+    let policy =
+        "El cliente se compromete a devolver la máquina limpia y con el tanque lleno. ".repeat(80);
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testdocument', 'model22', 'DC-1', 2024, $1, 'Contrato', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[&policy],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('DOCUMENT-001', 'available', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+
+    // documents@example.com has id 36
+    let rental = |status: &str, payment_id: Option<&str>, start: i64| {
+        let db_client = &db_client;
+        let status = status.to_string();
+        let payment_id = payment_id.map(str::to_string);
+        async move {
+            db_client
+                .query_one(
+                    "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price,
+                        status, payment_id, deposit_amount)
+                    VALUES (36, $1, $2, $3, 7000.00, $4::TEXT::rental_status, $5, 2000.00)
+                    RETURNING id;",
+                    &[
+                        &unit_id,
+                        &(today + Duration::days(start)),
+                        &(today + Duration::days(start + 7)),
+                        &status,
+                        &payment_id,
+                    ],
+                )
+                .await
+                .unwrap()
+                .get::<_, i32>("id")
+        }
+    };
+
+    let paid_rental_id = rental("active", Some("123456789"), 10).await;
+    let pending_rental_id = rental("pending_payment", None, 30).await;
+
+    let jwt = get_test_jwt("documents@example.com", false).await;
+    let other_jwt = get_test_jwt("transfers@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+
+    let download = |access: &str, rental_id: i32, document: &str| {
+        http_client
+            .post(backend_url(&format!("/rental/{}/{}", rental_id, document)))
+            .json(&serde_json::json!({"access": access}))
+            .send()
+    };
+
+    // ---------- Client and staff download the documents of a paid rental
+
+    for access in [&jwt, &staff_jwt] {
+        for document in ["contract", "receipt"] {
+            let response = download(access, paid_rental_id, document).await.unwrap();
+
+            assert_eq!(response.status(), 200);
+            assert_eq!(response.headers()["content-type"], "application/pdf");
+            let pdf = response.bytes().await.unwrap();
+            assert!(pdf.starts_with(b"%PDF"));
+        }
+    }
+
+    let response = download(&jwt, paid_rental_id, "contract").await.unwrap();
+    assert!(response.headers()["content-disposition"]
+        .to_str()
+        .unwrap()
+        .contains(&format!("contrato-alquiler-{}.pdf", paid_rental_id)));
+
+    // ---------- Rentals of other clients and unpaid ones
+
+    let response = download(&other_jwt, paid_rental_id, "contract")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = download(&jwt, pending_rental_id, "contract").await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = download(&jwt, pending_rental_id, "receipt").await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = download(&jwt, 999999, "receipt").await.unwrap();
+
+    assert_eq!(response.status(), 404);
+}
@@ -5,6 +5,7 @@ use tokio_postgres::NoTls;
 use jsonwebtoken::{encode, EncodingKey, Header};
 use crate::helpers::auth::create_2fa_code;
 use crate::custom_types::structs::Claims;
+use rust_decimal::Decimal;
 
 static INIT: OnceCell<()> = OnceCell::const_new();
 
@@ -50,7 +51,7 @@ pub async fn get_test_jwt(email: &str, is_refresh: bool) -> String {
     let user_id = row.get("id");
     let role = row.get("role");
 
-    let secret_key = env::var("JWT_SECRET_KEY").expect("JTW_SECRET_KEY must be set in the .env file");
+    let secret_key = env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set in the .env file");
 
     let nonce = create_2fa_code();
 
@@ -64,3 +65,8 @@ pub async fn get_test_jwt(email: &str, is_refresh: bool) -> String {
 
     encode(&Header::default(), &claims, &EncodingKey::from_secret(secret_key.as_ref())).unwrap()
 }
+
+/// Amount of a JSON response, which the API sends as a string.
+pub fn decimal(value: &serde_json::Value) -> Decimal {
+    serde_json::from_value(value.clone()).unwrap()
+}
@@ -0,0 +1,229 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Local};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::fs;
+
+#[tokio::test]
+async fn test_inspections() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testinspections', 'model16', 'IN-1', 2024, 'Sin reembolsos.', 'Retroexcavadora', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('INSPECT-001', 'available', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+
+    // inspections@example.com has id 29
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (29, $1, $2, $3, 7000.00, 'active')
+            RETURNING id;",
+            &[&unit_id, &today, &(today + Duration::days(7))],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let jwt = get_test_jwt("inspections@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+
+    let photo = STANDARD.encode(fs::read("media/test/test_image1.png").unwrap());
+
+    // ---------- Check-out inspection at retirement
+
+    let retire = |inspection: serde_json::Value| {
+        http_client
+            .post(backend_url("/loadretirement"))
+            .json(&serde_json::json!({
+                "access": staff_jwt,
+                "rental_id": rental_id,
+                "inspection": inspection
+            }))
+            .send()
+    };
+
+    // Damage can't be charged when the machine leaves
+    let response = retire(serde_json::json!({
+        "items": [{"item": "Balde", "ok": false, "notes": "Rayado"}],
+        "damage_charge": "1000.00"
+    }))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = retire(serde_json::json!({
+        "items": [{"item": "", "ok": true}]
+    }))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = retire(serde_json::json!({
+        "items": [
+            {"item": "Balde", "ok": true},
+            {"item": "Neumáticos", "ok": true},
+            {"item": "Cabina", "ok": false, "notes": "Vidrio lateral rayado"}
+        ],
+        "notes": "Sale con el tanque lleno",
+        "hour_meter": "1200.5",
+        "photos": [photo]
+    }))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert!(body["inspection_id"].is_i64());
+
+    // ---------- Check-in inspection at return
+
+    let give_back = |inspection: serde_json::Value| {
+        http_client
+            .post(backend_url("/loadreturn"))
+            .json(&serde_json::json!({
+                "access": staff_jwt,
+                "rental_id": rental_id,
+                "location_id": 1,
+                "inspection": inspection
+            }))
+            .send()
+    };
+
+    let response = give_back(serde_json::json!({
+        "items": [{"item": "Balde", "ok": true}],
+        "hour_meter": "1100.0"
+    }))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // A charge needs something to have been damaged
+    let response = give_back(serde_json::json!({
+        "items": [{"item": "Balde", "ok": true}],
+        "damage_charge": "25000.00"
+    }))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = give_back(serde_json::json!({
+        "items": [
+            {"item": "Balde", "ok": false, "notes": "Diente roto"},
+            {"item": "Neumáticos", "ok": true},
+            {"item": "Cabina", "ok": false, "notes": "Vidrio lateral rayado"}
+        ],
+        "hour_meter": "1248.0",
+        "damage_charge": "25000.00"
+    }))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let damage_charge_id = body["damage_charge_id"].as_i64().unwrap() as i32;
+
+    let row = db_client
+        .query_one(
+            "SELECT user_id, kind::TEXT, amount, status::TEXT FROM charges WHERE id = $1;",
+            &[&damage_charge_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, i32>("user_id"), 29);
+    assert_eq!(row.get::<_, String>("kind"), "damage");
+    assert_eq!(row.get::<_, Decimal>("amount"), dec!(25000));
+    assert_eq!(row.get::<_, String>("status"), "pending");
+
+    let status: String = db_client
+        .query_one(
+            "SELECT status::TEXT FROM machinery_units WHERE id = $1;",
+            &[&unit_id],
+        )
+        .await
+        .unwrap()
+        .get("status");
+
+    assert_eq!(status, "maintenance");
+
+    let description: String = db_client
+        .query_one(
+            "SELECT description FROM unit_history_events
+            WHERE unit_id = $1 AND new_status = 'maintenance';",
+            &[&unit_id],
+        )
+        .await
+        .unwrap()
+        .get("description");
+
+    assert!(description.contains("Balde (Diente roto)"));
+    assert!(description.contains(&rental_id.to_string()));
+
+    // ---------- Listing the inspections
+
+    let list = |access: &str| {
+        http_client
+            .post(backend_url(&format!("/rental/{}/inspections", rental_id)))
+            .json(&serde_json::json!({"access": access}))
+            .send()
+    };
+
+    let hank_jwt = get_test_jwt("hank@example.com", false).await;
+    let response = list(&hank_jwt).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = list(&jwt).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let inspections = body["inspections"].as_array().unwrap();
+    assert_eq!(inspections.len(), 2);
+
+    assert_eq!(inspections[0]["kind"], "check_out");
+    assert_eq!(inspections[0]["has_damage"], true);
+    assert_eq!(inspections[0]["items"].as_array().unwrap().len(), 3);
+    assert_eq!(inspections[0]["photos"].as_array().unwrap().len(), 1);
+    assert_eq!(inspections[0]["damage_charge_id"], serde_json::Value::Null);
+
+    assert_eq!(inspections[1]["kind"], "check_in");
+    assert_eq!(inspections[1]["employee_id"], 2);
+    assert_eq!(inspections[1]["damage_charge_id"], damage_charge_id);
+    let hour_meter: Decimal = serde_json::from_value(inspections[1]["hour_meter"].clone()).unwrap();
+    assert_eq!(hour_meter, dec!(1248));
+}
@@ -0,0 +1,289 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[tokio::test]
+async fn test_invoices() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("invoices@example.com", false).await;
+    let admin_jwt = get_test_jwt("admin@example.com", true).await;
+    let today = Local::now().date_naive();
+
+    // ---------- Client sets invalid billing data
+
+    for billing in [
+        serde_json::json!({"tax_id": "20-12345678-9", "tax_condition": "responsable_inscripto"}),
+        serde_json::json!({"tax_id": "2012345678", "tax_condition": "monotributista"}),
+        serde_json::json!({"tax_id": null, "tax_condition": "responsable_inscripto"}),
+    ] {
+        let mut payload = billing.clone();
+        payload["access"] = serde_json::json!(jwt);
+
+        let response = http_client
+            .post(backend_url("/changebilling"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400, "billing {}", billing);
+    }
+
+    // ---------- Client sets valid billing data
+
+    let response = http_client
+        .post(backend_url("/changebilling"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "tax_id": "20-12345678-6",
+            "tax_condition": "responsable_inscripto"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Admin manages the tax rates
+
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+
+    let response = http_client
+        .post(backend_url("/taxes/set"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "model_id": 14,
+            "name": "IVA 10,5%",
+            "percentage": 10.5
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = http_client
+        .post(backend_url("/taxes/set"))
+        .json(&serde_json::json!({
+            "access": admin_jwt,
+            "model_id": 14,
+            "name": "IVA 10,5%",
+            "percentage": 100
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = http_client
+        .post(backend_url("/taxes/set"))
+        .json(&serde_json::json!({
+            "access": admin_jwt,
+            "model_id": 14,
+            "name": "IVA 10,5%",
+            "percentage": 10.5
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let tax_rate_id = body["id"].as_i64().unwrap();
+
+    let response = http_client
+        .post(backend_url("/taxes"))
+        .json(&serde_json::json!({"access": admin_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["tax_rates"].as_array().unwrap().len(), 2);
+    assert_eq!(body["tax_rates"][0]["percentage"], "21.00");
+    assert_eq!(body["tax_rates"][1]["percentage"], "10.50");
+
+    // ---------- Paid rental gets an invoice
+
+    let row = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (25, 28, $1, $2, 10000, 'pending_payment') RETURNING id;",
+            &[
+                &(today + Duration::days(400)),
+                &(today + Duration::days(410)),
+            ],
+        )
+        .await
+        .unwrap();
+
+    let rental_id: i32 = row.get("id");
+
+    // The invoice is issued before notifying the client, so it exists even if the mail fails
+    http_client
+        .post(backend_url("/payment/check"))
+        .query(&[("payment_id", "98765"), ("status", "approved")])
+        .json(&serde_json::json!({
+            "rental_id": rental_id,
+            "access": jwt
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let response = http_client
+        .post(backend_url("/invoices"))
+        .json(&serde_json::json!({"access": jwt, "rental_id": rental_id}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let invoice = &body["invoices"][0];
+
+    assert_eq!(body["invoices"].as_array().unwrap().len(), 1);
+    assert_eq!(invoice["kind"], "invoice");
+    assert_eq!(invoice["letter"], "A");
+    assert_eq!(invoice["number"], "0001-00000001");
+    assert_eq!(invoice["billing_tax_id"], "20123456786");
+    assert_eq!(invoice["net_amount"], "9049.77");
+    assert_eq!(invoice["tax_amount"], "950.23");
+    assert_eq!(invoice["total_amount"], "10000.00");
+    assert_eq!(invoice["lines"][0]["quantity"], 10);
+    assert_eq!(invoice["lines"][0]["unit_price"], "904.98");
+    assert_eq!(invoice["lines"][0]["tax_percentage"], "10.50");
+
+    let invoice_id = invoice["id"].as_i64().unwrap();
+
+    // ---------- Invoice download
+
+    let response = http_client
+        .post(backend_url(&format!("/invoice/{}", invoice_id)))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["total_amount"]
+            .as_str()
+            .unwrap()
+            .parse::<Decimal>()
+            .unwrap(),
+        dec!(10000)
+    );
+
+    let response = http_client
+        .post(backend_url(&format!("/invoice/{}/pdf", invoice_id)))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-type"].to_str().unwrap(),
+        "application/pdf"
+    );
+
+    let pdf = response.bytes().await.unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+
+    // ---------- Other client can't download the invoice
+
+    let other_jwt = get_test_jwt("ivy@example.com", false).await;
+
+    let response = http_client
+        .post(backend_url(&format!("/invoice/{}/pdf", invoice_id)))
+        .json(&serde_json::json!({"access": other_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Cancelled rental gets a credit note
+
+    let response = http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({"access": jwt, "rental_id": rental_id}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = http_client
+        .post(backend_url("/invoices"))
+        .json(&serde_json::json!({"access": admin_jwt, "rental_id": rental_id}))
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let credit_note = &body["invoices"][1];
+
+    assert_eq!(body["invoices"].as_array().unwrap().len(), 2);
+    assert_eq!(credit_note["kind"], "credit_note");
+    assert_eq!(credit_note["number"], "0001-00000001");
+    assert_eq!(credit_note["related_invoice_id"], invoice_id);
+    assert_eq!(credit_note["related_invoice_number"], "0001-00000001");
+    assert_eq!(credit_note["total_amount"], "10000.00");
+    assert_eq!(credit_note["lines"].as_array().unwrap().len(), 1);
+
+    // ---------- Admin deletes the tax rates
+
+    let response = http_client
+        .post(backend_url("/taxes/delete"))
+        .json(&serde_json::json!({"access": admin_jwt, "id": 1}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = http_client
+        .post(backend_url("/taxes/delete"))
+        .json(&serde_json::json!({"access": admin_jwt, "id": tax_rate_id}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Client goes back to final consumer
+
+    let response = http_client
+        .post(backend_url("/changebilling"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "tax_id": null,
+            "tax_condition": "consumidor_final"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+}
@@ -1,13 +1,52 @@
+use crate::constants::{LOCAL_MEDIA_ROOT, STAGED_IMAGES_DIR};
 use crate::custom_types::structs::MachineModel;
-use crate::custom_types::{enums::RunningEnv, structs::MyRentalInfo};
-use crate::helpers::auth::create_pool;
+use crate::custom_types::{
+    enums::{ImageVariant, RunningEnv},
+    structs::MyRentalInfo,
+};
+use crate::helpers::{
+    auth::create_pool,
+    images::{machine_image_key, sweep_images},
+    media::LocalMediaStore,
+};
 use crate::tests::helpers::*;
 use base64::{engine::general_purpose::STANDARD, Engine};
 use chrono::{NaiveDate, Utc};
-use reqwest::Client;
-use std::{env, fs::File, io::Read};
+use image::{DynamicImage, ImageFormat};
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::{collections::HashSet, env, ffi::OsString, fs, fs::File, io::Read, path::Path};
+use tokio::time::{sleep, Duration};
 use validator::ValidateLength;
 
+/// Files in the staging directory, which the server shares between the requests of every test.
+fn staged_files() -> HashSet<OsString> {
+    fs::read_dir(STAGED_IMAGES_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(entry.ok()?.file_name()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks that the files staged since `before` are removed. Requests of other tests may keep
+/// theirs for a moment, but the ones a request of this test leaked would never go away.
+async fn assert_staged_files_removed(before: &HashSet<OsString>) {
+    for _ in 0..50 {
+        if staged_files().is_subset(before) {
+            return;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+
+    panic!("Staged images were left behind");
+}
+
 #[tokio::test]
 async fn test_explore_catalog() {
     setup().await;
@@ -28,7 +67,7 @@ async fn test_explore_catalog() {
 
     assert_eq!(response_body["items"].as_array().length(), Some(2));
 
-    assert_eq!(response_body["all_categories"].as_array().unwrap().len(), 5);
+    assert_eq!(response_body["all_categories"].as_array().unwrap().len(), 8);
 
     let machine1 = &response_body["items"][0];
     let machine2 = &response_body["items"][1];
@@ -137,7 +176,9 @@ async fn test_explore_catalog() {
         .unwrap()
         .get("price")
         .unwrap()
-        .as_f64()
+        .as_str()
+        .unwrap()
+        .parse::<Decimal>()
         .unwrap();
 
     assert_eq!(machine7.as_object().unwrap().get("id").unwrap(), 2);
@@ -145,7 +186,7 @@ async fn test_explore_catalog() {
         machine8.as_object().unwrap().get("brand").unwrap(),
         "Komatsu"
     );
-    assert_eq!(machine9_price, 75000.0);
+    assert_eq!(machine9_price, dec!(75000));
 
     assert!(machines_categories.contains(
         &machine9
@@ -571,6 +612,55 @@ async fn test_get_units_unavailable_dates() {
     assert_eq!(missing_params_response.status(), 400);
 }
 
+#[tokio::test]
+async fn test_sweep_images() {
+    setup().await;
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let media_root = env::temp_dir().join("saga_sweep_images");
+    let images_dir = media_root.join("machines");
+    let staging_dir = media_root.join("staging");
+    let _ = fs::remove_dir_all(&media_root);
+    fs::create_dir_all(&images_dir).unwrap();
+    fs::create_dir_all(&staging_dir).unwrap();
+
+    let media = LocalMediaStore::new(&media_root, "http://localhost/media", None, None);
+
+    // 'imagecode' is used by the seeded models and only has the full image,
+    // like the ones uploaded before variants existed
+    let image = DynamicImage::new_rgb8(800, 400);
+    for file_name in ["imagecode.webp", "orphan.webp", "orphan_thumb.webp"] {
+        image
+            .save_with_format(images_dir.join(file_name), ImageFormat::WebP)
+            .unwrap();
+    }
+    fs::write(staging_dir.join("recent.webp"), b"").unwrap();
+
+    let summary = sweep_images(&db_client, &media, &staging_dir)
+        .await
+        .unwrap();
+
+    assert_eq!(summary.removed_files, 2);
+    assert_eq!(summary.generated_variants, 2);
+    assert!(!images_dir.join("orphan.webp").exists());
+    assert!(!images_dir.join("orphan_thumb.webp").exists());
+    assert!(images_dir.join("imagecode.webp").exists());
+    assert_eq!(
+        image::image_dimensions(images_dir.join("imagecode_thumb.webp")).unwrap(),
+        (200, 100)
+    );
+    assert!(images_dir.join("imagecode_card.webp").exists());
+    // Staged files of requests that may still be running are kept
+    assert!(staging_dir.join("recent.webp").exists());
+
+    fs::remove_dir_all(&media_root).unwrap();
+}
+
 #[tokio::test]
 async fn test_new_model() {
     setup().await;
@@ -655,6 +745,52 @@ async fn test_new_model() {
         .unwrap();
     assert_eq!(imgs.len(), 2);
 
+    // Check that every variant of the main image was published
+    let image_name: String = row.get("image");
+    for variant in ImageVariant::ALL {
+        let (width, height) = image::image_dimensions(
+            Path::new(LOCAL_MEDIA_ROOT).join(machine_image_key(&image_name, variant)),
+        )
+        .unwrap();
+        assert!(width.max(height) <= variant.max_dimension());
+    }
+
+    //Try to create a model with an invalid extra image
+    let staged_before = staged_files();
+
+    let res = client
+        .post(backend_url("/newmodel"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "Bulldozer X2",
+            "brand": "Caterpillar",
+            "model": "X2 2024",
+            "year": 2024,
+            "policy": "Basic Warranty",
+            "description": "Powerful bulldozer for rough terrain",
+            "price": 99000,
+            "categories": ["Heavy"],
+            "extra_images": [img2, "not an image"],
+            "image": img1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), 400);
+
+    // Neither the model nor its already processed images are kept
+    let rows = db_client
+        .query(
+            "SELECT * FROM machinery_models WHERE name = $1",
+            &[&"Bulldozer X2"],
+        )
+        .await
+        .unwrap();
+    assert!(rows.is_empty());
+
+    assert_staged_files_removed(&staged_before).await;
+
     //Try to create a model with the same brand, model and year
     let res = client
         .post(backend_url("/newmodel"))
@@ -683,6 +819,63 @@ async fn test_new_model() {
         "A model with this same name, brand, model and year already exists"
     );
 
+    //Create a model with specs of its category
+    let res = client
+        .post(backend_url("/newmodel"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "Excavadora E8",
+            "brand": "Caterpillar",
+            "model": "E8",
+            "year": 2024,
+            "policy": "Basic Warranty",
+            "description": "Excavadora compacta",
+            "price": 50000,
+            "categories": ["Excavadoras"],
+            "specs": {"weight_kg": 8000, "has_cabin": true},
+            "extra_images": [],
+            "image": img1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), 201);
+
+    let specs = db_client
+        .query(
+            "SELECT ms.* FROM model_specs ms
+            INNER JOIN machinery_models mm ON ms.model_id = mm.id
+            WHERE mm.name = 'Excavadora E8'",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(specs.len(), 2);
+
+    //Try to create a model with a spec that does not belong to its categories
+    let res = client
+        .post(backend_url("/newmodel"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "Bulldozer X2",
+            "brand": "Caterpillar",
+            "model": "X2 2024",
+            "year": 2024,
+            "policy": "Basic Warranty",
+            "description": "Powerful bulldozer for rough terrain",
+            "price": 99000,
+            "categories": ["Heavy"],
+            "specs": {"weight_kg": 8000},
+            "extra_images": [],
+            "image": img1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), 400);
+
     //Try to send 12 images
     let res = client
         .post(backend_url("/newmodel"))
@@ -768,6 +961,148 @@ async fn test_new_model() {
             .unwrap(),
         "Not enough permissions"
     );
+
+    // ---------- Multipart upload
+
+    let admin_jwt = get_test_jwt("admin@example.com", true).await;
+    let image1 = fs::read("media/test/test_image1.png").unwrap();
+    let image2 = fs::read("media/test/test_image2.png").unwrap();
+
+    let model_data = |access: &str, model: &str| {
+        Part::text(
+            serde_json::json!({
+                "access": access,
+                "name": "Excavator M1",
+                "brand": "Komatsu",
+                "model": model,
+                "year": 2023,
+                "policy": "Basic Warranty",
+                "description": "Compact excavator",
+                "price": 45000,
+                "categories": ["Heavy"],
+            })
+            .to_string(),
+        )
+    };
+    let file = |name: &str, bytes: &[u8]| Part::bytes(bytes.to_vec()).file_name(name.to_string());
+
+    let res = client
+        .post(backend_url("/newmodel/multipart"))
+        .multipart(
+            Form::new()
+                .part("data", model_data(&admin_jwt, "M1 2023"))
+                .part("image", file("front.png", &image1))
+                .part("extra_images", file("side.png", &image2))
+                .part("extra_images", file("back.png", &image2)),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), 201);
+
+    let row = db_client
+        .query_one(
+            "SELECT id, image FROM machinery_models WHERE model = $1",
+            &[&"M1 2023"],
+        )
+        .await
+        .unwrap();
+    let model_id: i32 = row.get("id");
+    let image_name: String = row.get("image");
+
+    let extra_images = db_client
+        .query(
+            "SELECT * FROM model_extra_images WHERE id = $1",
+            &[&model_id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(extra_images.len(), 2);
+    assert!(Path::new(LOCAL_MEDIA_ROOT)
+        .join(machine_image_key(&image_name, ImageVariant::Thumbnail))
+        .exists());
+
+    // ---------- Multipart upload with invalid parts
+
+    let too_large = [image2.as_slice(), &vec![0; 8 * 1024 * 1024]].concat();
+    let staged_before = staged_files();
+
+    let invalid_forms = [
+        (
+            Form::new()
+                .part("image", file("front.png", &image1))
+                .part("data", model_data(&admin_jwt, "M2 2023")),
+            400,
+            "The data part must be sent before the images",
+        ),
+        (
+            Form::new()
+                .part("data", model_data("hello", "M2 2023"))
+                .part("image", file("front.png", &image1)),
+            401,
+            "Invalid access token",
+        ),
+        (
+            Form::new()
+                .part("data", model_data(&admin_jwt, "M2 2023"))
+                .part("image", file("front.png", &image1))
+                .part(
+                    "extra_images",
+                    file("notes.txt", b"these are not the images you are looking for"),
+                ),
+            415,
+            "The file 'notes.txt' is not a PNG, JPEG or WebP image",
+        ),
+        (
+            Form::new()
+                .part("data", model_data(&admin_jwt, "M2 2023"))
+                .part("image", file("huge.png", &too_large)),
+            413,
+            "The file 'huge.png' is larger than 8 MB",
+        ),
+        (
+            (0..11).fold(
+                Form::new()
+                    .part("data", model_data(&admin_jwt, "M2 2023"))
+                    .part("image", file("front.png", &image2)),
+                |form, _| form.part("extra_images", file("side.png", &image2)),
+            ),
+            400,
+            "Cannot upload more than 10 images",
+        ),
+        (
+            Form::new().part("data", model_data(&admin_jwt, "M2 2023")),
+            400,
+            "The main image is required",
+        ),
+    ];
+
+    for (form, status, message) in invalid_forms {
+        let res = client
+            .post(backend_url("/newmodel/multipart"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(res.status(), status, "{}", message);
+        assert_eq!(
+            res.json::<serde_json::Value>().await.unwrap()["message"],
+            message
+        );
+    }
+
+    let rows = db_client
+        .query(
+            "SELECT * FROM machinery_models WHERE model = $1",
+            &[&"M2 2023"],
+        )
+        .await
+        .unwrap();
+    assert!(rows.is_empty());
+
+    assert_staged_files_removed(&staged_before).await;
 }
 
 #[tokio::test]
@@ -813,15 +1148,20 @@ async fn test_new_rental() {
     let response_body = valid_response.json::<serde_json::Value>().await.unwrap();
 
     let rental_id = response_body["rental_id"].as_i64().unwrap() as i32;
+    assert!(response_body["payment_hold_seconds"].as_i64().unwrap() > 0);
+    assert!(response_body["payment_deadline"].is_string());
 
     match db_client
         .query_one("SELECT * FROM rentals r WHERE r.id = $1;", &[&rental_id])
         .await
     {
         Ok(row) => {
+            assert!(row
+                .get::<_, Option<chrono::NaiveDateTime>>("payment_deadline")
+                .is_some());
             assert_eq!(row.get::<_, i32>("machine_id"), 1);
             assert_eq!(row.get::<_, i32>("user_id"), 8);
-            assert_eq!(row.get::<_, f32>("total_price"), 1_050_000.0);
+            assert_eq!(row.get::<_, Decimal>("total_price"), dec!(1_050_000));
             assert_eq!(row.get::<_, chrono::NaiveDate>("start_date"), start_date);
             assert_eq!(row.get::<_, chrono::NaiveDate>("end_date"), end_date);
         }
@@ -865,7 +1205,7 @@ async fn test_new_rental() {
         Ok(row) => {
             assert_eq!(row.get::<_, i32>("machine_id"), 4);
             assert_eq!(row.get::<_, i32>("user_id"), 9);
-            assert_eq!(row.get::<_, f32>("total_price"), 665_000.0);
+            assert_eq!(row.get::<_, Decimal>("total_price"), dec!(665_000));
             assert_eq!(row.get::<_, chrono::NaiveDate>("start_date"), start_date);
             assert_eq!(row.get::<_, chrono::NaiveDate>("end_date"), end_date);
         }
@@ -878,9 +1218,9 @@ async fn test_new_rental() {
 
     let missing_params_rental = serde_json::json!({
         "machine_id": 1,
-        "start_date": start_date,
-        "end_date": end_date,
-        // "total_price" is missing
+        "start_date": start_date + chrono::Duration::days(30),
+        "end_date": end_date + chrono::Duration::days(30),
+        // "total_price" and "quote_token" are missing
         "access": jwt
     });
 
@@ -891,7 +1231,7 @@ async fn test_new_rental() {
         .await
         .unwrap();
 
-    assert_eq!(missing_params_response.status(), 422);
+    assert_eq!(missing_params_response.status(), 400);
 
     // ----------- Create a new rental with invalid data (end date before start date)
 
@@ -1080,7 +1420,8 @@ async fn test_check_rental_payment() {
     setup().await;
     let http_client = Client::new();
 
-    let jwt = get_test_jwt("hank@example.com", false).await;
+    // Rentals 7 and 8 belong to ivy@example.com
+    let jwt = get_test_jwt("ivy@example.com", false).await;
 
     // ----------- Check payment for a valid rental ID
 
@@ -1347,7 +1688,7 @@ async fn test_get_my_rentals() {
         rental.end_date,
         NaiveDate::from_ymd_opt(2025, 1, 12).unwrap()
     );
-    assert_eq!(rental.total_price, 1000.0);
+    assert_eq!(rental.total_price, dec!(1000));
     assert_eq!(rental.status, "completed");
     assert_eq!(rental.unit_id, 5);
     assert_eq!(rental.unit_serial_number, "JD-002");
@@ -1567,11 +1908,24 @@ async fn test_load_return() {
     // Get an admin token
     let jwt = get_test_jwt("admin@example.com", true).await;
 
+    // A rental of unit 16 that was retired and should have come back long ago
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status,
+                retirement_employee_id, retirement_date)
+            VALUES (17, 16, '2025-01-03', '2025-01-12', 1000.00, 'retired', 2, '2025-01-03')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
     let res = client
         .post(backend_url("/loadreturn"))
         .json(&serde_json::json!({
             "access": jwt,
-            "rental_id": 3,
+            "rental_id": rental_id,
             "location_id": 3,
         }))
         .send()
@@ -1585,7 +1939,7 @@ async fn test_load_return() {
         "Return loaded successfully"
     );
     assert!(json["days_late"].as_u64().unwrap() > 0);
-    assert!(json["fine"].as_f64().unwrap() > 0.0);
+    assert!(json["fine"].as_str().unwrap().parse::<Decimal>().unwrap() > Decimal::ZERO);
 
     // Check that the rental was updated
     db_client
@@ -1595,7 +1949,7 @@ async fn test_load_return() {
             return_employee_id = $3 AND
             status = 'completed' AND
             return_date = CURRENT_DATE;",
-            &[&&3, &&16, &&11],
+            &[&rental_id, &&16, &&11],
         )
         .await
         .unwrap();
@@ -1641,12 +1995,44 @@ async fn test_load_return() {
         "rental_id is invalid"
     );
 
-    //Invalid location_id
+    //Rental not retired
     let res = client
         .post(backend_url("/loadreturn"))
         .json(&serde_json::json!({
             "access": jwt,
             "rental_id": 4,
+            "location_id": 3
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(res.status(), 400);
+    assert_eq!(
+        res.json::<serde_json::Value>().await.unwrap()["message"]
+            .as_str()
+            .unwrap(),
+        "The rental has not been retired"
+    );
+
+    //Invalid location_id
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status,
+                retirement_employee_id, retirement_date)
+            VALUES (17, 17, '2025-02-03', '2025-02-12', 1000.00, 'retired', 2, '2025-02-03')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let res = client
+        .post(backend_url("/loadreturn"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": rental_id,
             "location_id": 10000
         }))
         .send()
@@ -2566,7 +2952,7 @@ async fn test_new_in_person_rental() {
         Ok(row) => {
             assert_eq!(row.get::<_, i32>("machine_id"), valid_machine_id);
             assert_eq!(row.get::<_, i32>("user_id"), valid_user_id);
-            assert_eq!(row.get::<_, f32>("total_price"), 665_000.0);
+            assert_eq!(row.get::<_, Decimal>("total_price"), dec!(665_000));
             assert_eq!(
                 row.get::<_, chrono::NaiveDate>("start_date"),
                 valid_start_date
@@ -2,6 +2,7 @@ use crate::custom_types::enums::RunningEnv;
 use crate::helpers::auth::create_pool;
 use crate::tests::helpers::*;
 use reqwest::Client;
+use rust_decimal::Decimal;
 
 #[tokio::test]
 async fn test_get_machine_unit() {
@@ -452,3 +453,435 @@ async fn test_update_unit_history() {
 
     assert_eq!(client_update_response.status(), 403);
 }
+
+#[tokio::test]
+async fn test_decommission_unit() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("admin@example.com", true).await;
+
+    // ---------- Employee tries to decommission a unit
+
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "unit_id": 21,
+            "reason": "Motor fundido"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // ---------- Admin decommissions a unit without a reason
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 21,
+            "reason": "   "
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin decommissions a unit without rentals
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 21,
+            "reason": "Motor fundido"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT, decommission_reason, decommissioned_at FROM machinery_units WHERE id = 21;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "decommissioned");
+    assert_eq!(row.get::<_, String>("decommission_reason"), "Motor fundido");
+    assert!(row
+        .get::<_, Option<chrono::NaiveDate>>("decommissioned_at")
+        .is_some());
+
+    // The decommission is part of the unit history
+    let history = db_client
+        .query(
+            "SELECT * FROM unit_history_events WHERE unit_id = 21 AND new_status = 'decommissioned';",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(history.len(), 1);
+
+    // ---------- Admin decommissions the same unit again
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 21,
+            "reason": "Motor fundido"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Employee tries to change the status of a decommissioned unit
+
+    let response = http_client
+        .post(backend_url("/unit/history/update"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "unit_id": 21,
+            "new_status": "available",
+            "description": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin decommissions a unit with a future rental without choosing an action
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 22,
+            "reason": "Vuelco"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["rental_ids"], serde_json::json!([61]));
+
+    // ---------- Admin decommissions a unit reassigning its future rental
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 22,
+            "reason": "Vuelco",
+            "future_rentals": "reassign"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["reassigned_rentals"][0]["rental_id"], 61);
+    assert_eq!(body["reassigned_rentals"][0]["new_unit_id"], 23);
+
+    let row = db_client
+        .query_one(
+            "SELECT machine_id, status::TEXT FROM rentals WHERE id = 61;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, i32>("machine_id"), 23);
+    assert_eq!(row.get::<_, String>("status"), "active");
+
+    // ---------- Admin decommissions the last unit at the branch reassigning its rental
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 23,
+            "reason": "Vuelco",
+            "future_rentals": "reassign"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT FROM machinery_units WHERE id = 23;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "available");
+
+    // ---------- Decommissioned units are not offered for new rentals
+
+    let response = http_client
+        .post(backend_url("/staff/rental/getunits"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "model_id": 9,
+            "location_id": 1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["units_id"], serde_json::json!([23]));
+
+    // ---------- Admin decommissions a non-existing unit
+
+    let response = http_client
+        .post(backend_url("/unit/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "unit_id": 99999,
+            "reason": "Vuelco"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_decommission_model() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("admin@example.com", true).await;
+
+    // ---------- The model is listed in the catalog before being decommissioned
+
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("search", "testdecommissionmodel")])
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 1);
+
+    // ---------- Admin decommissions a model with a future rental without choosing an action
+
+    let response = http_client
+        .post(backend_url("/model/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 10,
+            "reason": "Fuera de catálogo"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    // ---------- Admin tries to reassign the rentals of a decommissioned model
+
+    let response = http_client
+        .post(backend_url("/model/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 10,
+            "reason": "Fuera de catálogo",
+            "future_rentals": "reassign"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin decommissions a model with a date in the future
+
+    let response = http_client
+        .post(backend_url("/model/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 10,
+            "reason": "Fuera de catálogo",
+            "date": "2999-01-01",
+            "future_rentals": "cancel"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin decommissions a model cancelling its future rentals
+
+    db_client
+        .execute(
+            "UPDATE rentals
+            SET status = 'active', payment_deadline = NULL, deposit_amount = 300.00,
+                deposit_status = 'held'
+            WHERE id = 62;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let response = http_client
+        .post(backend_url("/model/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 10,
+            "reason": "Fuera de catálogo",
+            "date": "2025-01-01",
+            "future_rentals": "cancel"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["cancelled_rentals"], serde_json::json!([62]));
+    assert_eq!(body["decommissioned_units"], serde_json::json!([24]));
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT, notes, refund_amount, deposit_status::TEXT
+            FROM rentals WHERE id = 62;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "cancelled");
+    assert_eq!(row.get::<_, String>("notes"), "Fuera de catálogo");
+    // Rentals cancelled by a decommission are refunded in full and give the deposit back
+    assert_eq!(
+        row.get::<_, Option<Decimal>>("refund_amount"),
+        Some(Decimal::from(1000))
+    );
+    assert_eq!(row.get::<_, String>("deposit_status"), "released");
+
+    let row = db_client
+        .query_one(
+            "SELECT decommissioned_at FROM machinery_models WHERE id = 10;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        row.get::<_, Option<chrono::NaiveDate>>("decommissioned_at"),
+        chrono::NaiveDate::from_ymd_opt(2025, 1, 1)
+    );
+
+    // ---------- The model disappears from the catalog
+
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("search", "testdecommissionmodel")])
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 0);
+
+    let response = http_client
+        .get(backend_url("/explore/10"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- New units cannot be added to a decommissioned model
+
+    let response = http_client
+        .post(backend_url("/newunit"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "serial_number": "DECM-002",
+            "model_id": 10,
+            "location_id": 1
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- The model is still listed for admins
+
+    let response = http_client
+        .post(backend_url("/getmodels"))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let model = body["models"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["id"] == 10)
+        .unwrap();
+
+    assert_eq!(model["decommission_reason"], "Fuera de catálogo");
+
+    // ---------- Admin decommissions the same model again
+
+    let response = http_client
+        .post(backend_url("/model/decommission"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 10,
+            "reason": "Fuera de catálogo"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
@@ -1,14 +1,44 @@
 #[cfg(test)]
 pub mod auth;
 #[cfg(test)]
+pub mod calendar;
+#[cfg(test)]
+pub mod categories;
+#[cfg(test)]
+pub mod charges;
+#[cfg(test)]
+pub mod deliveries;
+#[cfg(test)]
+pub mod deposits;
+#[cfg(test)]
+pub mod documents;
+#[cfg(test)]
 pub mod helpers;
 #[cfg(test)]
+pub mod inspections;
+#[cfg(test)]
+pub mod invoices;
+#[cfg(test)]
 pub mod machinery_mgmt;
 #[cfg(test)]
 pub mod maintenance_mgmt;
 #[cfg(test)]
+pub mod media;
+#[cfg(test)]
+pub mod orders;
+#[cfg(test)]
+pub mod pricing;
+#[cfg(test)]
 pub mod questions;
 #[cfg(test)]
+pub mod rentals;
+#[cfg(test)]
+pub mod specs;
+#[cfg(test)]
 pub mod stats;
 #[cfg(test)]
 pub mod reviews;
+#[cfg(test)]
+pub mod transfers;
+#[cfg(test)]
+pub mod waitlist;
@@ -0,0 +1,458 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+use rust_decimal_macros::dec;
+
+#[tokio::test]
+async fn test_orders() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let mut model_ids = Vec::new();
+    for (name, price, deposit) in [
+        ("testorderloader", dec!(1000), dec!(500)),
+        ("testordercrane", dec!(2000), dec!(0)),
+    ] {
+        let model_id: i32 = db_client
+            .query_one(
+                "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image, deposit)
+                VALUES ($1, 'model19', 'OR-1', 2024, 'Sin reembolsos.', 'Pedido', $2, 'imagecode', $3)
+                RETURNING id;",
+                &[&name, &price, &deposit],
+            )
+            .await
+            .unwrap()
+            .get("id");
+        model_ids.push(model_id);
+    }
+
+    let mut unit_ids = Vec::new();
+    for (serial_number, model_id) in [
+        ("ORDER-001", model_ids[0]),
+        ("ORDER-002", model_ids[1]),
+        ("ORDER-003", model_ids[1]),
+    ] {
+        let unit_id: i32 = db_client
+            .query_one(
+                "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+                VALUES ($1, 'available', $2, 1)
+                RETURNING id;",
+                &[&serial_number, &model_id],
+            )
+            .await
+            .unwrap()
+            .get("id");
+        unit_ids.push(unit_id);
+    }
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + Duration::days(n);
+
+    let jwt = get_test_jwt("orders@example.com", false).await;
+    let other_jwt = get_test_jwt("deposits@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+
+    let quote = |machine_id: i32, start: i64| {
+        let request = http_client
+            .post(backend_url("/rental/quote"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "machine_id": machine_id,
+                "start_date": days(start),
+                "end_date": days(start + 7)
+            }))
+            .send();
+        async move {
+            let response = request.await.unwrap();
+            assert_eq!(response.status(), 200);
+            let body = response.json::<serde_json::Value>().await.unwrap();
+            body["quote_token"].as_str().unwrap().to_string()
+        }
+    };
+
+    let new_order = |lines: serde_json::Value| {
+        http_client
+            .post(backend_url("/order/new"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "lines": lines
+            }))
+            .send()
+    };
+
+    let get_order = |access: &str, order_id: i32| {
+        http_client
+            .post(backend_url(&format!("/order/{}", order_id)))
+            .json(&serde_json::json!({"access": access}))
+            .send()
+    };
+
+    let check_payment = |order_id: i32, payment_id: &str, status: &str| {
+        http_client
+            .post(backend_url("/order/payment/check"))
+            .query(&[("payment_id", payment_id), ("status", status)])
+            .json(&serde_json::json!({
+                "access": jwt,
+                "order_id": order_id
+            }))
+            .send()
+    };
+
+    let rental_status = |rental_id: i32| {
+        let db_client = &db_client;
+        async move {
+            let row = db_client
+                .query_one(
+                    "SELECT status::TEXT, payment_id, deposit_status::TEXT FROM rentals
+                    WHERE id = $1;",
+                    &[&rental_id],
+                )
+                .await
+                .unwrap();
+            (
+                row.get::<_, String>("status"),
+                row.get::<_, Option<String>>("payment_id"),
+                row.get::<_, Option<String>>("deposit_status"),
+            )
+        }
+    };
+
+    // ---------- Invalid orders
+
+    let response = new_order(serde_json::json!([])).await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = new_order(serde_json::json!([{
+        "machine_id": unit_ids[0],
+        "model_id": model_ids[0],
+        "location_id": 1,
+        "start_date": days(100),
+        "end_date": days(107),
+        "quote_token": quote(unit_ids[0], 100).await
+    }]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["line"], 0);
+
+    // ---------- Availability is checked for every line at once
+
+    let response = new_order(serde_json::json!([
+        {
+            "machine_id": unit_ids[0],
+            "start_date": days(100),
+            "end_date": days(107),
+            "quote_token": quote(unit_ids[0], 100).await
+        },
+        {
+            "machine_id": unit_ids[0],
+            "start_date": days(103),
+            "end_date": days(110),
+            "quote_token": quote(unit_ids[0], 103).await
+        }
+    ]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 409);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["line"], 1);
+
+    // Lines of a unit are booked by date, whatever their place in the order
+    let response = new_order(serde_json::json!([
+        {
+            "machine_id": unit_ids[0],
+            "start_date": days(103),
+            "end_date": days(110),
+            "quote_token": quote(unit_ids[0], 103).await
+        },
+        {
+            "machine_id": unit_ids[0],
+            "start_date": days(100),
+            "end_date": days(107),
+            "quote_token": quote(unit_ids[0], 100).await
+        }
+    ]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 409);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["line"], 0);
+
+    let booked: i64 = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM rentals WHERE machine_id = $1;",
+            &[&unit_ids[0]],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(booked, 0);
+
+    // ---------- A unit and a model in one order
+
+    let response = new_order(serde_json::json!([
+        {
+            "machine_id": unit_ids[0],
+            "start_date": days(100),
+            "end_date": days(107),
+            "quote_token": quote(unit_ids[0], 100).await
+        },
+        {
+            "model_id": model_ids[1],
+            "location_id": 1,
+            "start_date": days(100),
+            "end_date": days(107),
+            "quote_token": quote(unit_ids[1], 100).await
+        }
+    ]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let order_id = body["order_id"].as_i64().unwrap() as i32;
+    assert_eq!(decimal(&body["total_price"]), dec!(21000));
+    assert_eq!(decimal(&body["deposit"]), dec!(500));
+    assert_eq!(decimal(&body["amount_due"]), dec!(21500));
+    assert_eq!(body["lines"].as_array().unwrap().len(), 2);
+    let first_line = body["lines"][0]["rental_id"].as_i64().unwrap() as i32;
+    let second_line = body["lines"][1]["rental_id"].as_i64().unwrap() as i32;
+
+    let response = get_order(&jwt, order_id).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["order"]["status"], "pending_payment");
+    assert!(!body["order"]["payment_deadline"].is_null());
+
+    let response = get_order(&other_jwt, order_id).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = http_client
+        .post(backend_url("/myrentals"))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let rentals = body["rentals"].as_array().unwrap();
+    assert_eq!(rentals.len(), 2);
+    assert!(rentals.iter().all(|rental| rental["order_id"] == order_id));
+
+    let response = http_client
+        .post(backend_url("/staff/rentals"))
+        .query(&[("order_id", order_id)])
+        .json(&serde_json::json!({"access": staff_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["rentals"].as_array().unwrap().len(), 2);
+
+    // Lines are paid through the order, not on their own
+    let response = http_client
+        .post(backend_url("/payment/check"))
+        .query(&[("payment_id", "OR-1111"), ("status", "approved")])
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": first_line
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+    assert_eq!(rental_status(first_line).await.0, "pending_payment");
+
+    // ---------- Cancelling one line keeps the rest of the order
+
+    let response = http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": second_line,
+            "reason": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = get_order(&jwt, order_id).await.unwrap();
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["order"]["status"], "pending_payment");
+    assert_eq!(decimal(&body["order"]["total_price"]), dec!(7000));
+
+    // ---------- One payment for the order
+
+    // The confirmation email can't be sent in the test environment
+    check_payment(order_id, "OR-2222", "approved")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        rental_status(first_line).await,
+        (
+            "active".to_string(),
+            Some("OR-2222".to_string()),
+            Some("held".to_string())
+        )
+    );
+    assert_eq!(rental_status(second_line).await.0, "cancelled");
+
+    let response = get_order(&staff_jwt, order_id).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["order"]["status"], "paid");
+    assert_eq!(body["order"]["payment_id"], "OR-2222");
+
+    let response = check_payment(order_id, "OR-2222", "approved")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Rejected payments fail every line
+
+    let response = new_order(serde_json::json!([{
+        "machine_id": unit_ids[0],
+        "start_date": days(200),
+        "end_date": days(207),
+        "quote_token": quote(unit_ids[0], 200).await
+    }]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let rejected_order_id = body["order_id"].as_i64().unwrap() as i32;
+    let rejected_line = body["lines"][0]["rental_id"].as_i64().unwrap() as i32;
+
+    let response = check_payment(rejected_order_id, "OR-3333", "rejected")
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 502);
+    assert_eq!(rental_status(rejected_line).await.0, "failed");
+
+    let response = get_order(&jwt, rejected_order_id).await.unwrap();
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["order"]["status"], "failed");
+
+    let response = new_order(serde_json::json!([{
+        "machine_id": unit_ids[0],
+        "start_date": days(200),
+        "end_date": days(207),
+        "quote_token": quote(unit_ids[0], 200).await
+    }]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let late_order_id = body["order_id"].as_i64().unwrap() as i32;
+    let late_line = body["lines"][0]["rental_id"].as_i64().unwrap() as i32;
+
+    db_client
+        .execute(
+            "UPDATE rentals SET payment_deadline = NOW() - INTERVAL '1 minute' WHERE id = $1;",
+            &[&late_line],
+        )
+        .await
+        .unwrap();
+
+    // ---------- Late payments keep the dates while nobody took them
+
+    check_payment(late_order_id, "OR-4444", "approved")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        rental_status(late_line).await,
+        (
+            "active".to_string(),
+            Some("OR-4444".to_string()),
+            Some("held".to_string())
+        )
+    );
+
+    // ---------- Otherwise the order fails and what was paid is refunded
+
+    let response = new_order(serde_json::json!([{
+        "machine_id": unit_ids[0],
+        "start_date": days(230),
+        "end_date": days(237),
+        "quote_token": quote(unit_ids[0], 230).await
+    }]))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let overtaken_order_id = body["order_id"].as_i64().unwrap() as i32;
+    let overtaken_line = body["lines"][0]["rental_id"].as_i64().unwrap() as i32;
+
+    db_client
+        .execute(
+            "UPDATE rentals SET payment_deadline = NOW() - INTERVAL '1 minute' WHERE id = $1;",
+            &[&overtaken_line],
+        )
+        .await
+        .unwrap();
+
+    db_client
+        .execute(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (8, $1, $2, $3, 7000.00, 'active');",
+            &[&unit_ids[0], &days(233), &days(240)],
+        )
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        let response = check_payment(overtaken_order_id, "OR-5555", "approved")
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 409);
+    }
+
+    assert_eq!(
+        rental_status(overtaken_line).await,
+        ("failed".to_string(), Some("OR-5555".to_string()), None)
+    );
+
+    let response = get_order(&jwt, overtaken_order_id).await.unwrap();
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["order"]["status"], "failed");
+    assert_eq!(body["order"]["payment_id"], "OR-5555");
+
+    let line = &body["order"]["lines"][0];
+    assert_eq!(
+        decimal(&line["refund_amount"]),
+        decimal(&line["total_price"]) + decimal(&line["deposit_amount"])
+    );
+}
@@ -0,0 +1,441 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[tokio::test]
+async fn test_rental_quote() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("hank@example.com", false).await;
+    let today = Local::now().date_naive();
+
+    // ---------- Quote crossing into a season with the weekly discount
+
+    let start_date = today + Duration::days(95);
+    let end_date = today + Duration::days(105);
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let quote = &body["quote"];
+
+    assert_eq!(quote["days"], 10);
+    assert_eq!(quote["lines"].as_array().unwrap().len(), 2);
+    assert_eq!(quote["lines"][0]["days"], 5);
+    assert_eq!(quote["lines"][0]["amount"], "5000.00");
+    assert_eq!(quote["lines"][1]["description"], "Temporada alta");
+    assert_eq!(quote["lines"][1]["daily_price"], "1500.00");
+    assert_eq!(quote["lines"][1]["amount"], "7500.00");
+    assert_eq!(quote["subtotal"], "12500.00");
+    assert_eq!(quote["discount_percentage"], "5.00");
+    assert_eq!(quote["discount"], "625.00");
+    assert_eq!(quote["total_price"], "11875.00");
+
+    let quote_token = body["quote_token"].as_str().unwrap().to_string();
+
+    // ---------- Invalid quote requests
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": start_date + Duration::days(3)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 99999,
+            "start_date": start_date,
+            "end_date": end_date
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": "invalid",
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+
+    // ---------- Rental with a quote token for other dates
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date + Duration::days(1),
+            "quote_token": quote_token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Rental with a forged quote token
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date,
+            "quote_token": jwt
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Rental with a quote token given to another client
+
+    let other_jwt = get_test_jwt("calendar@example.com", false).await;
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": other_jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date,
+            "quote_token": quote_token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- A quote token isn't a session token
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": quote_token,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+
+    // ---------- Rental with the quote token
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date,
+            "quote_token": quote_token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let rental_id = body["rental_id"].as_i64().unwrap() as i32;
+
+    let row = db_client
+        .query_one(
+            "SELECT total_price FROM rentals WHERE id = $1;",
+            &[&rental_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, Decimal>("total_price"), dec!(11875));
+
+    // ---------- Rental with a total that includes the monthly discount
+
+    let start_date = today + Duration::days(200);
+    let end_date = today + Duration::days(230);
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date,
+            "total_price": 30000.0
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = http_client
+        .post(backend_url("/rental/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": start_date,
+            "end_date": end_date,
+            "total_price": 25500.0
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+}
+
+#[tokio::test]
+async fn test_manage_pricing_rules() {
+    setup().await;
+    let http_client = Client::new();
+
+    let jwt = get_test_jwt("admin@example.com", true).await;
+    let today = Local::now().date_naive();
+
+    // ---------- Employee tries to create a discount
+
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+
+    let response = http_client
+        .post(backend_url("/pricing/discounts/new"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "model_id": null,
+            "min_days": 365,
+            "discount_percentage": 30
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // ---------- Admin creates a global discount
+
+    let response = http_client
+        .post(backend_url("/pricing/discounts/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": null,
+            "min_days": 365,
+            "discount_percentage": 30
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let discount_id = body["id"].as_i64().unwrap();
+
+    // ---------- Admin creates invalid discounts
+
+    for discount in [
+        serde_json::json!({"model_id": null, "min_days": 365, "discount_percentage": 10}),
+        serde_json::json!({"model_id": 14, "min_days": 0, "discount_percentage": 10}),
+        serde_json::json!({"model_id": 14, "min_days": 90, "discount_percentage": 100}),
+        serde_json::json!({"model_id": 99999, "min_days": 90, "discount_percentage": 10}),
+    ] {
+        let mut payload = discount.clone();
+        payload["access"] = serde_json::json!(jwt);
+
+        let response = http_client
+            .post(backend_url("/pricing/discounts/new"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400, "discount {}", discount);
+    }
+
+    // ---------- Admin creates a seasonal rate
+
+    let response = http_client
+        .post(backend_url("/pricing/seasons/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "Temporada baja",
+            "model_id": 14,
+            "start_date": today + Duration::days(300),
+            "end_date": today + Duration::days(330),
+            "multiplier": 0.8
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let season_id = body["id"].as_i64().unwrap();
+
+    let response = http_client
+        .post(backend_url("/pricing/seasons/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "name": "Temporada invertida",
+            "model_id": 14,
+            "start_date": today + Duration::days(330),
+            "end_date": today + Duration::days(300),
+            "multiplier": 1.2
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin lists the rules
+
+    let response = http_client
+        .post(backend_url("/pricing"))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["duration_discounts"].as_array().unwrap().len(), 3);
+    assert_eq!(
+        body["duration_discounts"][0]["model_id"],
+        serde_json::Value::Null
+    );
+    assert_eq!(body["seasonal_rates"].as_array().unwrap().len(), 2);
+
+    // ---------- Admin changes the base price and the quote follows it
+
+    let response = http_client
+        .post(backend_url("/pricing/baseprice"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 14,
+            "price": 2000
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 28,
+            "start_date": today + Duration::days(310),
+            "end_date": today + Duration::days(320)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["quote"]["lines"][0]["daily_price"], "1600.00");
+    assert_eq!(body["quote"]["total_price"], "15200.00");
+
+    let response = http_client
+        .post(backend_url("/pricing/baseprice"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 99999,
+            "price": 2000
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Admin deletes the rules
+
+    for (route, id) in [
+        ("/pricing/discounts/delete", discount_id),
+        ("/pricing/seasons/delete", season_id),
+    ] {
+        let response = http_client
+            .post(backend_url(route))
+            .json(&serde_json::json!({"access": jwt, "id": id}))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+
+        let response = http_client
+            .post(backend_url(route))
+            .json(&serde_json::json!({"access": jwt, "id": id}))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 404);
+    }
+
+    let response = http_client
+        .post(backend_url("/pricing/baseprice"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 14,
+            "price": 1000
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+}
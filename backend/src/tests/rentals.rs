@@ -0,0 +1,1482 @@
+use crate::custom_types::enums::{RentalActor, RentalStatus, RunningEnv};
+use crate::custom_types::structs::RentalPrice;
+use crate::helpers::auth::create_pool;
+use crate::helpers::invoices::issue_rental_invoice;
+use crate::helpers::pricing::quote_rental;
+use crate::helpers::rentals::*;
+use crate::tests::helpers::*;
+use chrono::{Local, NaiveDate};
+use reqwest::Client;
+use rust_decimal::Decimal;
+
+#[test]
+fn test_rental_transitions() {
+    use RentalStatus::*;
+
+    let client = RentalActor::Client(8);
+    let employee = RentalActor::Employee(2);
+    let admin = RentalActor::Admin(11);
+
+    // ---------- Allowed edges
+
+    assert!(can_transition(PendingPayment, Active, client));
+    assert!(can_transition(PendingPayment, Failed, client));
+    assert!(can_transition(PendingPayment, Cancelled, client));
+    assert!(can_transition(Failed, Active, client));
+    assert!(can_transition(Active, Cancelled, employee));
+    assert!(can_transition(Active, Retired, employee));
+    assert!(can_transition(Retired, Completed, admin));
+
+    // ---------- Edges the actor can't take
+
+    assert!(!can_transition(PendingPayment, Active, employee));
+    assert!(!can_transition(Failed, Active, admin));
+    assert!(!can_transition(Active, Retired, client));
+    assert!(!can_transition(Retired, Completed, client));
+
+    // ---------- Edges that don't exist
+
+    assert!(!can_transition(PendingPayment, Retired, admin));
+    assert!(!can_transition(Active, Completed, admin));
+    assert!(!can_transition(Retired, Cancelled, admin));
+    assert!(!can_transition(Completed, Active, admin));
+    assert!(!can_transition(Cancelled, Active, client));
+    assert!(!can_transition(Failed, PendingPayment, client));
+}
+
+#[tokio::test]
+async fn test_get_rental_timeline() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    // hank@example.com (id 8) books a rental far away in time
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (8, 1, '2099-01-01', '2099-01-05', 1000.00, 'pending_payment')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    record_rental_status(
+        &db_client,
+        rental_id,
+        None,
+        RentalStatus::PendingPayment,
+        RentalActor::Client(8),
+        None,
+    )
+    .await
+    .unwrap();
+
+    let jwt = get_test_jwt("hank@example.com", false).await;
+
+    let response = http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({
+            "rental_id": rental_id,
+            "access": jwt,
+            "reason": null,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Transitions outside the lifecycle are rejected
+
+    assert!(matches!(
+        transition_rental(
+            &db_client,
+            rental_id,
+            RentalStatus::Active,
+            RentalActor::Client(8),
+            None
+        )
+        .await,
+        Err(RentalTransitionError::NotAllowed)
+    ));
+
+    assert!(matches!(
+        transition_rental(
+            &db_client,
+            rental_id,
+            RentalStatus::Cancelled,
+            RentalActor::Client(9),
+            None
+        )
+        .await,
+        Err(RentalTransitionError::NotFound)
+    ));
+
+    // ---------- The client sees the timeline of their rental
+
+    let response = http_client
+        .post(backend_url(&format!("/rental/{}/timeline", rental_id)))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let timeline = body["timeline"].as_array().unwrap();
+
+    assert_eq!(timeline.len(), 2);
+    assert!(timeline[0]["from_status"].is_null());
+    assert_eq!(timeline[0]["to_status"], "pending_payment");
+    assert_eq!(timeline[1]["from_status"], "pending_payment");
+    assert_eq!(timeline[1]["to_status"], "cancelled");
+    assert_eq!(timeline[1]["actor_id"], 8);
+    assert_eq!(timeline[1]["actor_role"], 2);
+
+    // ---------- Staff sees every timeline
+
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+
+    let response = http_client
+        .post(backend_url(&format!("/rental/{}/timeline", rental_id)))
+        .json(&serde_json::json!({"access": employee_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Other clients can't see it
+
+    let other_jwt = get_test_jwt("login@example.com", false).await;
+
+    let response = http_client
+        .post(backend_url(&format!("/rental/{}/timeline", rental_id)))
+        .json(&serde_json::json!({"access": other_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Rental that doesn't exist
+
+    let response = http_client
+        .post(backend_url("/rental/99999/timeline"))
+        .json(&serde_json::json!({"access": employee_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Invalid token
+
+    let response = http_client
+        .post(backend_url(&format!("/rental/{}/timeline", rental_id)))
+        .json(&serde_json::json!({"access": "invalidtoken"}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn test_payment_hold_expiry() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let mut db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let unit_row = db_client
+        .query_one(
+            "SELECT model_id, location_id FROM machinery_units WHERE id = 1;",
+            &[],
+        )
+        .await
+        .unwrap();
+    let model_id: i32 = unit_row.get("model_id");
+    let location_id: i32 = unit_row.get("location_id");
+
+    let insert_query = "
+        INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status, payment_deadline)
+        VALUES (8, 1, $1, $2, 1000.00, 'pending_payment', NOW() + $3::INT * INTERVAL '1 minute')
+        RETURNING id;
+    ";
+
+    let abandoned_start = NaiveDate::from_ymd_opt(2098, 3, 1).unwrap();
+    let abandoned_id: i32 = db_client
+        .query_one(
+            insert_query,
+            &[
+                &abandoned_start,
+                &NaiveDate::from_ymd_opt(2098, 3, 5).unwrap(),
+                &-1,
+            ],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let held_start = NaiveDate::from_ymd_opt(2098, 6, 1).unwrap();
+    let held_id: i32 = db_client
+        .query_one(
+            insert_query,
+            &[
+                &held_start,
+                &NaiveDate::from_ymd_opt(2098, 6, 5).unwrap(),
+                &30,
+            ],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    // ---------- Expired holds no longer block the dates, even before the job runs
+
+    let jwt = get_test_jwt("hank@example.com", false).await;
+
+    let response = http_client
+        .post(backend_url("/rental/availability"))
+        .query(&[
+            ("model_id", &model_id.to_string()),
+            ("location_id", &location_id.to_string()),
+        ])
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let unit = body["units_and_their_unavailable_dates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|unit| unit["unit_id"] == 1)
+        .unwrap();
+    let starts: Vec<String> = unit["periods"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|period| period["start_date"].as_str().unwrap().to_string())
+        .collect();
+
+    assert!(starts.contains(&held_start.to_string()));
+    assert!(!starts.contains(&abandoned_start.to_string()));
+
+    // ---------- The job fails the expired holds only
+
+    // The server runs the same job, so the abandoned rental may already be gone from the result
+    let expired = expire_payment_holds(&mut db_client).await.unwrap();
+    assert!(!expired.contains(&held_id));
+
+    let status_query = "SELECT status::TEXT FROM rentals WHERE id = $1;";
+    let status = |row: tokio_postgres::Row| row.get::<_, String>("status");
+
+    assert_eq!(
+        status(
+            db_client
+                .query_one(status_query, &[&abandoned_id])
+                .await
+                .unwrap()
+        ),
+        "failed"
+    );
+    assert_eq!(
+        status(
+            db_client
+                .query_one(status_query, &[&held_id])
+                .await
+                .unwrap()
+        ),
+        "pending_payment"
+    );
+
+    let history_row = db_client
+        .query_one(
+            "SELECT from_status::TEXT, actor_id FROM rental_status_history
+            WHERE rental_id = $1 AND to_status = 'failed';",
+            &[&abandoned_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        history_row.get::<_, String>("from_status"),
+        "pending_payment"
+    );
+    assert_eq!(history_row.get::<_, Option<i32>>("actor_id"), None);
+
+    // ---------- A late payment keeps the dates while nobody took them
+
+    let client = RentalActor::Client(8);
+
+    assert!(
+        settle_lapsed_rental_payment(&db_client, abandoned_id, "LATE-0001", client)
+            .await
+            .unwrap()
+    );
+    transition_rental(&db_client, abandoned_id, RentalStatus::Active, client, None)
+        .await
+        .unwrap();
+
+    // ---------- Otherwise the payment is kept to be refunded
+
+    let overtaken_start = NaiveDate::from_ymd_opt(2098, 9, 1).unwrap();
+    let overtaken_id: i32 = db_client
+        .query_one(
+            insert_query,
+            &[
+                &overtaken_start,
+                &NaiveDate::from_ymd_opt(2098, 9, 5).unwrap(),
+                &-1,
+            ],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    db_client
+        .execute(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (4, 1, '2098-09-04', '2098-09-10', 1000.00, 'active');",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        assert!(
+            !settle_lapsed_rental_payment(&db_client, overtaken_id, "LATE-0002", client)
+                .await
+                .unwrap()
+        );
+    }
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT, payment_id, refund_amount FROM rentals WHERE id = $1;",
+            &[&overtaken_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "failed");
+    assert_eq!(
+        row.get::<_, Option<String>>("payment_id").as_deref(),
+        Some("LATE-0002")
+    );
+    assert_eq!(
+        row.get::<_, Option<Decimal>>("refund_amount"),
+        Some(Decimal::from(1000))
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_bookings() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("hank@example.com", false).await;
+    let start_date = NaiveDate::from_ymd_opt(2097, 1, 10).unwrap();
+    let end_date = NaiveDate::from_ymd_opt(2097, 1, 20).unwrap();
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": 1,
+            "start_date": start_date,
+            "end_date": end_date
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let quote_token = response.json::<serde_json::Value>().await.unwrap()["quote_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // ---------- Parallel bookings of the same unit and dates
+
+    let requests: Vec<_> = (0..8)
+        .map(|_| {
+            let http_client = http_client.clone();
+            let payload = serde_json::json!({
+                "machine_id": 1,
+                "start_date": start_date,
+                "end_date": end_date,
+                "quote_token": quote_token,
+                "access": jwt
+            });
+
+            tokio::spawn(async move {
+                http_client
+                    .post(backend_url("/rental/new"))
+                    .json(&payload)
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+            })
+        })
+        .collect();
+
+    let mut statuses = Vec::new();
+    for request in requests {
+        statuses.push(request.await.unwrap());
+    }
+
+    assert_eq!(statuses.iter().filter(|status| **status == 201).count(), 1);
+    assert_eq!(statuses.iter().filter(|status| **status == 409).count(), 7);
+
+    let booked: i64 = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM rentals
+            WHERE machine_id = 1 AND start_date = $1 AND end_date = $2;",
+            &[&start_date, &end_date],
+        )
+        .await
+        .unwrap()
+        .get(0);
+
+    assert_eq!(booked, 1);
+}
+
+#[tokio::test]
+async fn test_overlapping_rentals_rejected() {
+    setup().await;
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let date = |day: u32| NaiveDate::from_ymd_opt(2096, 1, day).unwrap();
+
+    let insert = |status: &'static str, start_date: NaiveDate, end_date: NaiveDate| {
+        let db_client = &db_client;
+        async move {
+            db_client
+                .query_one(
+                    "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+                    VALUES (4, 1, $1, $2, 1000.00, $3::TEXT::rental_status)
+                    RETURNING id;",
+                    &[&start_date, &end_date, &status],
+                )
+                .await
+                .map(|row| row.get::<_, i32>("id"))
+        }
+    };
+
+    let rental_id = insert("active", date(10), date(20)).await.unwrap();
+    insert("retired", date(25), date(30)).await.unwrap();
+
+    // ---------- A second rental holding the same days is turned down by the database
+
+    let error = insert("active", date(20), date(22)).await.unwrap_err();
+    assert!(is_overlapping_rental(&error));
+
+    // Pending rentals don't hold their dates for good, so they aren't checked
+    insert("pending_payment", date(15), date(17)).await.unwrap();
+
+    // ---------- So is an extension paid after its extra days were taken
+
+    let extension_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rental_extensions (rental_id, previous_end_date, new_end_date, price, status)
+            VALUES ($1, $2, $3, 700.00, 'pending_payment')
+            RETURNING id;",
+            &[&rental_id, &date(20), &date(26)],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let error = settle_extension(&db_client, extension_id, "123456789")
+        .await
+        .unwrap_err();
+    assert!(is_overlapping_rental(&error));
+}
+
+#[tokio::test]
+async fn test_model_rental_rules() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    // A model of its own, so the rules don't affect the rest of the tests
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testrentalrules', 'model11', 'RR-1', 2024, 'Sin reembolsos.', 'Hormigonera', 50000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('RULES-001', 'available', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let admin_jwt = get_test_jwt("admin@example.com", true).await;
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+    let client_jwt = get_test_jwt("hank@example.com", false).await;
+
+    let rules = serde_json::json!({
+        "model_id": model_id,
+        "min_rental_days": 3,
+        "max_rental_days": 10,
+        "turnaround_days": 2,
+        "min_lead_days": 5,
+        "max_lead_days": 400
+    });
+
+    // ---------- Employee tries to update the rules
+
+    let mut payload = rules.clone();
+    payload["access"] = serde_json::json!(employee_jwt);
+
+    let response = http_client
+        .post(backend_url("/model/rentalrules/update"))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // ---------- Admin sets invalid rules
+
+    for (field, value) in [
+        ("min_rental_days", 0),
+        ("max_rental_days", 2),
+        ("turnaround_days", -1),
+        ("max_lead_days", 4),
+    ] {
+        let mut payload = rules.clone();
+        payload[field] = serde_json::json!(value);
+        payload["access"] = serde_json::json!(admin_jwt);
+
+        let response = http_client
+            .post(backend_url("/model/rentalrules/update"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    let mut payload = rules.clone();
+    payload["model_id"] = serde_json::json!(99999);
+    payload["access"] = serde_json::json!(admin_jwt);
+
+    let response = http_client
+        .post(backend_url("/model/rentalrules/update"))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Admin updates the rules
+
+    let mut payload = rules.clone();
+    payload["access"] = serde_json::json!(admin_jwt);
+
+    let response = http_client
+        .post(backend_url("/model/rentalrules/update"))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- The rules are shown with the machine
+
+    let response = http_client
+        .get(backend_url(&format!("/explore/{}", model_id)))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let machine_rules = &body["machine"]["rental_rules"];
+
+    assert_eq!(machine_rules["min_rental_days"], 3);
+    assert_eq!(machine_rules["max_rental_days"], 10);
+    assert_eq!(machine_rules["turnaround_days"], 2);
+    assert_eq!(machine_rules["min_lead_days"], 5);
+    assert_eq!(machine_rules["max_lead_days"], 400);
+
+    // ---------- Quotes outside the rules are rejected
+
+    let today = Local::now().date_naive();
+
+    for (start, end, message) in [
+        (50, 52, "al menos 3 días"),
+        (50, 61, "máximo de 10 días"),
+        (2, 6, "al menos 5 días de anticipación"),
+        (500, 504, "más de 400 días de anticipación"),
+    ] {
+        let response = http_client
+            .post(backend_url("/rental/quote"))
+            .json(&serde_json::json!({
+                "access": client_jwt,
+                "machine_id": unit_id,
+                "start_date": today + chrono::Duration::days(start),
+                "end_date": today + chrono::Duration::days(end)
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400);
+
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert!(body["message"].as_str().unwrap().contains(message));
+    }
+
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": client_jwt,
+            "machine_id": unit_id,
+            "start_date": today + chrono::Duration::days(50),
+            "end_date": today + chrono::Duration::days(53)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    // ---------- Availability uses the maintenance days of the model
+
+    let rental_start = today + chrono::Duration::days(30);
+    let rental_end = today + chrono::Duration::days(35);
+
+    db_client
+        .execute(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (8, $1, $2, $3, 250000.00, 'active');",
+            &[&unit_id, &rental_start, &rental_end],
+        )
+        .await
+        .unwrap();
+
+    let response = http_client
+        .post(backend_url("/rental/availability"))
+        .query(&[("model_id", model_id), ("location_id", 1)])
+        .json(&serde_json::json!({"access": client_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let periods = &body["units_and_their_unavailable_dates"][0]["periods"];
+
+    assert_eq!(periods[0]["start_date"], rental_start.to_string());
+    assert_eq!(
+        periods[0]["end_date"],
+        (rental_end + chrono::Duration::days(2)).to_string()
+    );
+
+    let validate_dates = |start: NaiveDate, end: NaiveDate| {
+        http_client
+            .post(backend_url("/staff/rental/validatedates"))
+            .json(&serde_json::json!({
+                "unit_id": unit_id,
+                "start_date": start,
+                "end_date": end,
+                "access": employee_jwt
+            }))
+            .send()
+    };
+
+    let response = validate_dates(
+        rental_end + chrono::Duration::days(2),
+        rental_end + chrono::Duration::days(6),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = validate_dates(
+        rental_end + chrono::Duration::days(3),
+        rental_end + chrono::Duration::days(6),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn test_new_model_rental() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testassignment', 'model12', 'AS-1', 2024, 'Sin reembolsos.', 'Autoelevador', 40000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let insert_unit = "
+        INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+        VALUES ($1, 'available', $2, $3)
+        RETURNING id;
+    ";
+
+    let mut unit_ids = Vec::new();
+    for (serial_number, location_id) in [("ASSIGN-001", 1), ("ASSIGN-002", 1), ("ASSIGN-003", 2)] {
+        let unit_id: i32 = db_client
+            .query_one(insert_unit, &[&serial_number, &model_id, &location_id])
+            .await
+            .unwrap()
+            .get("id");
+        unit_ids.push(unit_id);
+    }
+
+    // The first unit has already been used, so the second one is assigned first
+    db_client
+        .execute(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (8, $1, '2024-01-01', '2024-01-11', 400000.00, 'completed');",
+            &[&unit_ids[0]],
+        )
+        .await
+        .unwrap();
+
+    let jwt = get_test_jwt("hank@example.com", false).await;
+    let today = Local::now().date_naive();
+    let start_date = today + chrono::Duration::days(100);
+    let end_date = today + chrono::Duration::days(107);
+
+    // A quote of any unit of the model is valid for the booking
+    let response = http_client
+        .post(backend_url("/rental/quote"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "machine_id": unit_ids[2],
+            "start_date": start_date,
+            "end_date": end_date
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let quote_token = response.json::<serde_json::Value>().await.unwrap()["quote_token"].clone();
+
+    let book = |start_date: NaiveDate, end_date: NaiveDate, model_id: i32| {
+        http_client
+            .post(backend_url("/rental/new/model"))
+            .json(&serde_json::json!({
+                "model_id": model_id,
+                "location_id": 1,
+                "start_date": start_date,
+                "end_date": end_date,
+                "quote_token": quote_token,
+                "access": jwt
+            }))
+            .send()
+    };
+
+    // ---------- Units are assigned by least usage
+
+    for expected_unit in [unit_ids[1], unit_ids[0]] {
+        let response = book(start_date, end_date, model_id).await.unwrap();
+
+        assert_eq!(response.status(), 201);
+
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["machine_id"], expected_unit);
+    }
+
+    // ---------- Every unit at the branch is taken
+
+    let response = book(start_date, end_date, model_id).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let body = response.json::<serde_json::Value>().await.unwrap();
+
+    // Free periods start a week of maintenance before or after the existing rentals
+    assert_eq!(
+        body["alternative_period"]["start_date"],
+        (start_date - chrono::Duration::days(15)).to_string()
+    );
+    assert_eq!(
+        body["alternative_period"]["end_date"],
+        (end_date - chrono::Duration::days(15)).to_string()
+    );
+
+    let alternative_locations = body["alternative_locations"].as_array().unwrap();
+    assert_eq!(alternative_locations.len(), 1);
+    assert_eq!(alternative_locations[0]["id"], 2);
+
+    // ---------- Invalid bookings
+
+    let response = book(start_date, start_date + chrono::Duration::days(3), model_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = book(start_date, end_date, 99999).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let booked: i64 = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM rentals WHERE machine_id = ANY($1) AND start_date = $2;",
+            &[&unit_ids, &start_date],
+        )
+        .await
+        .unwrap()
+        .get(0);
+
+    assert_eq!(booked, 2);
+}
+
+#[tokio::test]
+async fn test_rental_extensions() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testextension', 'model13', 'EX-1', 2024, 'Sin reembolsos.', 'Retroexcavadora', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('EXTEND-001', 'rented', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + chrono::Duration::days(n);
+
+    // extensions@example.com has id 26
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (26, $1, $2, $3, 7000.00, 'active')
+            RETURNING id;",
+            &[&unit_id, &days(-3), &days(4)],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let jwt = get_test_jwt("extensions@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+
+    let request = |access: &str, new_end_date: NaiveDate| {
+        http_client
+            .post(backend_url("/rental/extension/request"))
+            .json(&serde_json::json!({
+                "access": access,
+                "rental_id": rental_id,
+                "new_end_date": new_end_date
+            }))
+            .send()
+    };
+
+    let pay = |extension_id: i64, status: &str| {
+        http_client
+            .post(backend_url("/rental/extension/payment"))
+            .query(&[("payment_id", "EXT-2424235352"), ("status", status)])
+            .json(&serde_json::json!({
+                "access": jwt,
+                "extension_id": extension_id
+            }))
+            .send()
+    };
+
+    // ---------- Request and pay an extension
+
+    let response = request(&jwt, days(10)).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let extension_id = body["extension_id"].as_i64().unwrap();
+    assert_eq!(body["machine_id"], unit_id);
+    assert!(body["payment_deadline"].is_string());
+
+    // Only the added days are charged, at the prices of those days
+    let quote = quote_rental(&db_client, unit_id, days(4), days(10))
+        .await
+        .unwrap();
+    let price: Decimal = serde_json::from_value(body["price"].clone()).unwrap();
+
+    assert_eq!(price, quote.total_price);
+    assert_eq!(quote.days, 6);
+
+    // Only one extension can wait for payment at a time
+    let response = request(&jwt, days(12)).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    // The extra days are held while the payment is pending
+    let held: i64 = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM unit_bookings WHERE machine_id = $1 AND end_date = $2;",
+            &[&unit_id, &days(10)],
+        )
+        .await
+        .unwrap()
+        .get(0);
+
+    assert_eq!(held, 1);
+
+    let response = pay(extension_id, "pending").await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = pay(extension_id, "approved").await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap()["new_end_date"],
+        days(10).to_string()
+    );
+
+    let row = db_client
+        .query_one(
+            "SELECT r.end_date, e.status::TEXT, e.paid_at IS NOT NULL AS paid,
+                (SELECT COUNT(*) FROM invoices i WHERE i.rental_id = r.id) AS invoices
+            FROM rentals r
+            INNER JOIN rental_extensions e ON e.rental_id = r.id
+            WHERE e.id = $1;",
+            &[&(extension_id as i32)],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, NaiveDate>("end_date"), days(10));
+    assert_eq!(row.get::<_, String>("status"), "paid");
+    assert!(row.get::<_, bool>("paid"));
+    assert_eq!(row.get::<_, i64>("invoices"), 1);
+
+    let response = pay(extension_id, "approved").await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = http_client
+        .post(backend_url("/myrentals"))
+        .json(&serde_json::json!({
+            "access": jwt
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let extensions = body["rentals"][0]["extensions"].as_array().unwrap();
+    assert_eq!(extensions.len(), 1);
+    assert_eq!(extensions[0]["previous_end_date"], days(4).to_string());
+    assert_eq!(extensions[0]["status"], "paid");
+
+    // ---------- Conflicts with the next booking, considering the turnaround
+
+    db_client
+        .execute(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (8, $1, $2, $3, 7000.00, 'active');",
+            &[&unit_id, &days(25), &days(32)],
+        )
+        .await
+        .unwrap();
+
+    let response = request(&jwt, days(20)).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = request(&jwt, days(10)).await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Rejected payment releases the days
+
+    let response = request(&jwt, days(14)).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let extension_id = response.json::<serde_json::Value>().await.unwrap()["extension_id"]
+        .as_i64()
+        .unwrap();
+
+    let response = pay(extension_id, "rejected").await.unwrap();
+
+    assert_eq!(response.status(), 502);
+
+    let row = db_client
+        .query_one(
+            "SELECT r.end_date, e.status::TEXT
+            FROM rentals r
+            INNER JOIN rental_extensions e ON e.rental_id = r.id
+            WHERE e.id = $1;",
+            &[&(extension_id as i32)],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, NaiveDate>("end_date"), days(10));
+    assert_eq!(row.get::<_, String>("status"), "failed");
+
+    // ---------- Extension granted at the branch
+
+    let response = http_client
+        .post(backend_url("/staff/rental/extension"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "rental_id": rental_id,
+            "new_end_date": days(12)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = http_client
+        .post(backend_url("/staff/rental/extension"))
+        .json(&serde_json::json!({
+            "access": staff_jwt,
+            "rental_id": rental_id,
+            "new_end_date": days(12)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let row = db_client
+        .query_one(
+            "SELECT r.end_date, e.status::TEXT, e.rental_employee_id
+            FROM rentals r
+            INNER JOIN rental_extensions e ON e.rental_id = r.id
+            WHERE r.id = $1
+            ORDER BY e.id DESC
+            LIMIT 1;",
+            &[&rental_id],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, NaiveDate>("end_date"), days(12));
+    assert_eq!(row.get::<_, String>("status"), "paid");
+    assert_eq!(row.get::<_, Option<i32>>("rental_employee_id"), Some(2));
+
+    // ---------- Late payments
+
+    // The rejected extension no longer follows the end of the rental
+    let response = pay(extension_id, "approved").await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let row = db_client
+        .query_one(
+            "SELECT status::TEXT, payment_id, refund_amount, price
+            FROM rental_extensions
+            WHERE id = $1;",
+            &[&(extension_id as i32)],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(row.get::<_, String>("status"), "failed");
+    assert_eq!(
+        row.get::<_, Option<String>>("payment_id").as_deref(),
+        Some("EXT-2424235352")
+    );
+    assert_eq!(
+        row.get::<_, Option<Decimal>>("refund_amount"),
+        Some(row.get::<_, Decimal>("price"))
+    );
+
+    // Days still free are kept after the hold ran out
+    let response = request(&jwt, days(14)).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let extension_id = response.json::<serde_json::Value>().await.unwrap()["extension_id"]
+        .as_i64()
+        .unwrap();
+
+    db_client
+        .execute(
+            "UPDATE rental_extensions SET payment_deadline = NOW() - INTERVAL '1 minute'
+            WHERE id = $1;",
+            &[&(extension_id as i32)],
+        )
+        .await
+        .unwrap();
+
+    let response = pay(extension_id, "approved").await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap()["new_end_date"],
+        days(14).to_string()
+    );
+
+    // ---------- Someone else's rental
+
+    let other_jwt = get_test_jwt("hank@example.com", false).await;
+    let response = request(&other_jwt, days(15)).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_cancellation_refunds() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testcancellation', 'model14', 'CP-1', 2024, 'Reembolso según aviso previo.', 'Minicargadora', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('CANCEL-001', 'available', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + chrono::Duration::days(n);
+
+    let admin_jwt = get_test_jwt("admin@example.com", false).await;
+    let jwt = get_test_jwt("cancellations@example.com", false).await;
+
+    let update_policy = |access: &str, body: serde_json::Value| {
+        let mut body = body;
+        body["access"] = serde_json::json!(access);
+        body["model_id"] = serde_json::json!(model_id);
+        http_client
+            .post(backend_url("/model/cancellationpolicy/update"))
+            .json(&body)
+            .send()
+    };
+
+    // cancellations@example.com has id 27
+    let book = |start: NaiveDate| {
+        let db_client = &db_client;
+        async move {
+            let (rental_id, _) = create_pending_rental(
+                db_client,
+                27,
+                unit_id,
+                start,
+                start + chrono::Duration::days(7),
+                RentalPrice {
+                    total_price: Decimal::from(7000),
+                    deposit: Decimal::ZERO,
+                },
+                600,
+            )
+            .await
+            .unwrap();
+            rental_id
+        }
+    };
+
+    let refund_of = |rental_id: i32| {
+        let db_client = &db_client;
+        async move {
+            db_client
+                .query_one(
+                    "SELECT refund_amount FROM rentals WHERE id = $1;",
+                    &[&rental_id],
+                )
+                .await
+                .unwrap()
+                .get::<_, Option<Decimal>>("refund_amount")
+        }
+    };
+
+    let cancel = |rental_id: i32| {
+        http_client
+            .post(backend_url("/rental/cancel"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "rental_id": rental_id,
+                "reason": null
+            }))
+            .send()
+    };
+
+    // Rentals booked before the model had a policy refund in full
+    let unversioned = book(days(200)).await;
+
+    // ---------- Policy versions
+
+    let response = update_policy(
+        &jwt,
+        serde_json::json!({"staff_refund_percentage": 100, "tiers": []}),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    let response = update_policy(
+        &admin_jwt,
+        serde_json::json!({
+            "staff_refund_percentage": 100,
+            "tiers": [{"min_notice_days": 7, "refund_percentage": 150}]
+        }),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = update_policy(
+        &admin_jwt,
+        serde_json::json!({
+            "staff_refund_percentage": 100,
+            "tiers": [
+                {"min_notice_days": 7, "refund_percentage": 50},
+                {"min_notice_days": 7, "refund_percentage": 20}
+            ]
+        }),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = update_policy(
+        &admin_jwt,
+        serde_json::json!({
+            "staff_refund_percentage": 90,
+            "tiers": [
+                {"min_notice_days": 30, "refund_percentage": 100},
+                {"min_notice_days": 7, "refund_percentage": 50}
+            ]
+        }),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let policy = response.json::<serde_json::Value>().await.unwrap()["cancellation_policy"].clone();
+    assert_eq!(policy["version"], 1);
+    assert_eq!(policy["tiers"].as_array().unwrap().len(), 2);
+
+    let early = book(days(60)).await;
+    let late = book(days(10)).await;
+    let last_minute = book(days(2)).await;
+    let unpaid = book(days(100)).await;
+    let staff_cancelled = book(days(120)).await;
+
+    db_client
+        .execute(
+            "UPDATE rentals SET status = 'active', payment_deadline = NULL WHERE id = ANY($1);",
+            &[&vec![
+                unversioned,
+                early,
+                late,
+                last_minute,
+                staff_cancelled,
+            ]],
+        )
+        .await
+        .unwrap();
+
+    for rental_id in [late, last_minute] {
+        issue_rental_invoice(&db_client, rental_id).await.unwrap();
+    }
+
+    let credit_notes_of = |rental_id: i32| {
+        let db_client = &db_client;
+        async move {
+            db_client
+                .query(
+                    "SELECT total_amount FROM invoices
+                    WHERE rental_id = $1 AND kind = 'credit_note';",
+                    &[&rental_id],
+                )
+                .await
+                .unwrap()
+                .iter()
+                .map(|row| row.get::<_, Decimal>("total_amount"))
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // A newer version only applies to rentals booked from then on
+    let response = update_policy(
+        &admin_jwt,
+        serde_json::json!({
+            "staff_refund_percentage": 80,
+            "tiers": [{"min_notice_days": 0, "refund_percentage": 0}]
+        }),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = http_client
+        .get(backend_url(&format!(
+            "/explore/{}/cancellationpolicy",
+            model_id
+        )))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let policy = response.json::<serde_json::Value>().await.unwrap()["cancellation_policy"].clone();
+    assert_eq!(policy["version"], 2);
+
+    // ---------- Refunds by notice
+
+    let response = cancel(early).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let refund_amount: Decimal = serde_json::from_value(body["refund_amount"].clone()).unwrap();
+    assert_eq!(refund_amount, Decimal::from(7000));
+    assert_eq!(refund_of(early).await, Some(Decimal::from(7000)));
+
+    let response = cancel(late).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(refund_of(late).await, Some(Decimal::from(3500)));
+    // The credit note gives back the refund, not the whole invoice
+    assert_eq!(credit_notes_of(late).await, vec![Decimal::from(3500)]);
+
+    let response = cancel(last_minute).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(refund_of(last_minute).await, Some(Decimal::ZERO));
+    assert!(credit_notes_of(last_minute).await.is_empty());
+
+    let response = cancel(unpaid).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(refund_of(unpaid).await, Some(Decimal::ZERO));
+
+    let response = cancel(unversioned).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(refund_of(unversioned).await, Some(Decimal::from(7000)));
+
+    // ---------- Staff cancellations use the staff percentage, whatever the notice
+
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+    http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({
+            "access": staff_jwt,
+            "rental_id": staff_cancelled,
+            "reason": "Máquina averiada"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(refund_of(staff_cancelled).await, Some(Decimal::from(6300)));
+}
@@ -0,0 +1,282 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use reqwest::Client;
+
+#[tokio::test]
+async fn test_filter_catalog_by_specs() {
+    setup().await;
+    let http_client = Client::new();
+
+    // ---------- Numeric range filter
+
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("spec", "weight_kg<=5000")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 1);
+    assert_eq!(body["items"][0]["id"], 12);
+
+    // ---------- Several filters are combined
+
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("spec", "weight_kg>5000"), ("spec", "has_cabin=true")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 1);
+    assert_eq!(body["items"][0]["id"], 11);
+
+    // ---------- Enum equality filter
+
+    let response = http_client
+        .get(backend_url("/explore"))
+        .query(&[("spec", "track_type!=orugas")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["total_items"], 1);
+    assert_eq!(body["items"][0]["id"], 12);
+
+    // ---------- Invalid filters
+
+    for filter in [
+        "track_type>orugas",
+        "weight_kg<=mucho",
+        "has_cabin=quizas",
+        "unknown_spec=3",
+        "weight_kg",
+        "<=5000",
+    ] {
+        let response = http_client
+            .get(backend_url("/explore"))
+            .query(&[("spec", filter)])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400, "filter {}", filter);
+    }
+
+    // ---------- Specs are returned with the machine
+
+    let response = http_client
+        .get(backend_url("/explore/11"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let specs = body["machine"]["specs"].as_array().unwrap();
+
+    assert_eq!(specs.len(), 4);
+    assert_eq!(specs[0]["key"], "weight_kg");
+    assert_eq!(specs[0]["unit"], "kg");
+    assert_eq!(specs[0]["value"], 20000.0);
+    assert_eq!(specs[2]["value"], "orugas");
+    assert_eq!(specs[3]["value"], true);
+}
+
+#[tokio::test]
+async fn test_manage_specs() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let jwt = get_test_jwt("admin@example.com", true).await;
+
+    // ---------- Employee tries to create a spec
+
+    let employee_jwt = get_test_jwt("bob@example.com", true).await;
+
+    let response = http_client
+        .post(backend_url("/specs/new"))
+        .json(&serde_json::json!({
+            "access": employee_jwt,
+            "category_id": 7,
+            "key": "bucket_capacity_m3",
+            "name": "Capacidad del balde",
+            "unit": "m3",
+            "data_type": "numeric"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // ---------- Admin creates a spec for a subcategory
+
+    let response = http_client
+        .post(backend_url("/specs/new"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "category_id": 7,
+            "key": "bucket_capacity_m3",
+            "name": "Capacidad del balde",
+            "unit": "m3",
+            "data_type": "numeric"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    // ---------- Admin creates invalid specs
+
+    for spec in [
+        serde_json::json!({"key": "bucket_capacity_m3", "data_type": "numeric", "category_id": 7}),
+        serde_json::json!({"key": "max_lift", "data_type": "numeric", "category_id": 99999}),
+        serde_json::json!({"key": "altura maxima", "data_type": "numeric", "category_id": 7}),
+        serde_json::json!({"key": "fuel", "data_type": "enum", "category_id": 7}),
+    ] {
+        let mut payload = spec.clone();
+        payload["access"] = serde_json::json!(jwt);
+        payload["name"] = serde_json::json!("Especificación");
+
+        let response = http_client
+            .post(backend_url("/specs/new"))
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400, "spec {}", spec);
+    }
+
+    // ---------- Admin lists the specs
+
+    let response = http_client
+        .post(backend_url("/specs"))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let specs = body["specs"].as_array().unwrap();
+    assert_eq!(specs.len(), 5);
+    assert_eq!(specs[2]["allowed_values"][0], "orugas");
+
+    // ---------- Admin edits the specs of a model
+
+    let response = http_client
+        .post(backend_url("/model/specs/update"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 12,
+            "specs": {
+                "weight_kg": 3600,
+                "track_type": "orugas",
+                "bucket_capacity_m3": 0.1,
+                "has_cabin": null
+            }
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let rows = db_client
+        .query(
+            "SELECT sd.key, ms.numeric_value, ms.text_value FROM model_specs ms
+            INNER JOIN spec_definitions sd ON ms.spec_id = sd.id
+            WHERE ms.model_id = 12 ORDER BY sd.id;",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0].get::<_, Option<f64>>("numeric_value"), Some(3600.0));
+    assert_eq!(
+        rows[1].get::<_, Option<String>>("text_value"),
+        Some("orugas".to_string())
+    );
+    assert_eq!(rows[2].get::<_, String>("key"), "bucket_capacity_m3");
+
+    // ---------- Admin sets a spec of a subcategory on the parent's model
+
+    let response = http_client
+        .post(backend_url("/model/specs/update"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 11,
+            "specs": {"bucket_capacity_m3": 0.5}
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // ---------- Admin sets values of the wrong type
+
+    for specs in [
+        serde_json::json!({"track_type": "ruedas"}),
+        serde_json::json!({"weight_kg": "3600"}),
+        serde_json::json!({"has_cabin": 1}),
+    ] {
+        let response = http_client
+            .post(backend_url("/model/specs/update"))
+            .json(&serde_json::json!({
+                "access": jwt,
+                "model_id": 11,
+                "specs": specs
+            }))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400, "specs {}", specs);
+    }
+
+    // The failed updates did not remove the model's values
+    let rows = db_client
+        .query("SELECT * FROM model_specs WHERE model_id = 11;", &[])
+        .await
+        .unwrap();
+
+    assert_eq!(rows.len(), 4);
+
+    // ---------- Admin edits the specs of a non-existing model
+
+    let response = http_client
+        .post(backend_url("/model/specs/update"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": 99999,
+            "specs": {}
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+}
@@ -1,6 +1,8 @@
 use crate::custom_types::structs::*;
 use crate::tests::helpers::*;
 use reqwest::Client;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
 use std::collections::HashMap;
 
 #[tokio::test]
@@ -24,13 +26,13 @@ async fn test_get_stats_by_month() {
     assert_eq!(res.status(), 200);
 
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
-    let stats: HashMap<String, f64> = serde_json::from_value(value).unwrap();
-    assert!(*stats.get("january").unwrap() >= 23.0);
-    assert!(*stats.get("february").unwrap() >= 5.0);
-    assert!(*stats.get("december").unwrap() >= 5.0);
-    assert!(*stats.get("march").unwrap() <= 5.0);
-    assert!(*stats.get("may").unwrap() <= 5.0);
-    assert!(*stats.get("october").unwrap() <= 5.0);
+    let stats: HashMap<String, i64> = serde_json::from_value(value).unwrap();
+    assert!(*stats.get("january").unwrap() >= 23);
+    assert!(*stats.get("february").unwrap() >= 5);
+    assert!(*stats.get("december").unwrap() >= 5);
+    assert!(*stats.get("march").unwrap() <= 5);
+    assert!(*stats.get("may").unwrap() <= 5);
+    assert!(*stats.get("october").unwrap() <= 5);
 
     //Get rentals by month in 2024
     let res = client
@@ -47,13 +49,13 @@ async fn test_get_stats_by_month() {
     assert_eq!(res.status(), 200);
 
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
-    let stats: HashMap<String, f64> = serde_json::from_value(value).unwrap();
-    assert_eq!(*stats.get("january").unwrap(), 0.0);
-    assert_eq!(*stats.get("february").unwrap(), 0.0);
-    assert_eq!(*stats.get("december").unwrap(), 0.0);
-    assert_eq!(*stats.get("march").unwrap(), 5.0);
-    assert_eq!(*stats.get("may").unwrap(), 0.0);
-    assert_eq!(*stats.get("april").unwrap(), 5.0);
+    let stats: HashMap<String, i64> = serde_json::from_value(value).unwrap();
+    assert_eq!(*stats.get("january").unwrap(), 0);
+    assert_eq!(*stats.get("february").unwrap(), 0);
+    assert_eq!(*stats.get("december").unwrap(), 0);
+    assert_eq!(*stats.get("march").unwrap(), 5);
+    assert_eq!(*stats.get("may").unwrap(), 0);
+    assert_eq!(*stats.get("april").unwrap(), 5);
 
     //Get income by month in the current year
     let res = client
@@ -69,13 +71,13 @@ async fn test_get_stats_by_month() {
     assert_eq!(res.status(), 200);
 
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
-    let stats: HashMap<String, f64> = serde_json::from_value(value).unwrap();
-    assert!(*stats.get("january").unwrap() >= 23000.0);
-    assert!(*stats.get("february").unwrap() >= 5000.0);
-    assert!(*stats.get("december").unwrap() >= 5000.0);
-    assert!(*stats.get("march").unwrap() <= 5000.0);
-    assert!(*stats.get("may").unwrap() <= 5000.0);
-    assert!(*stats.get("october").unwrap() <= 5000.0);
+    let stats: HashMap<String, Decimal> = serde_json::from_value(value).unwrap();
+    assert!(*stats.get("january").unwrap() >= dec!(23000));
+    assert!(*stats.get("february").unwrap() >= dec!(5000));
+    assert!(*stats.get("december").unwrap() >= dec!(5000));
+    assert!(*stats.get("march").unwrap() <= dec!(5000));
+    assert!(*stats.get("may").unwrap() <= dec!(5000));
+    assert!(*stats.get("october").unwrap() <= dec!(5000));
 
     //Get income by month in 2024
     let res = client
@@ -92,13 +94,13 @@ async fn test_get_stats_by_month() {
     assert_eq!(res.status(), 200);
 
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
-    let stats: HashMap<String, f64> = serde_json::from_value(value).unwrap();
-    assert_eq!(*stats.get("january").unwrap(), 0.0);
-    assert_eq!(*stats.get("february").unwrap(), 0.0);
-    assert_eq!(*stats.get("december").unwrap(), 0.0);
-    assert_eq!(*stats.get("march").unwrap(), 5000.0);
-    assert_eq!(*stats.get("may").unwrap(), 0.0);
-    assert_eq!(*stats.get("april").unwrap(), 5000.0);
+    let stats: HashMap<String, Decimal> = serde_json::from_value(value).unwrap();
+    assert_eq!(*stats.get("january").unwrap(), dec!(0));
+    assert_eq!(*stats.get("february").unwrap(), dec!(0));
+    assert_eq!(*stats.get("december").unwrap(), dec!(0));
+    assert_eq!(*stats.get("march").unwrap(), dec!(5000));
+    assert_eq!(*stats.get("may").unwrap(), dec!(0));
+    assert_eq!(*stats.get("april").unwrap(), dec!(5000));
 
     //Invalid role
     let jwt = get_test_jwt("user@example.com", false).await;
@@ -163,9 +165,9 @@ async fn test_get_stats_by_employee() {
     assert!(stats.len() >= 2);
     assert!(stats[0].value >= stats[1].value);
     //assert_eq!(stats[0].name, "user22 u22");
-    //assert_eq!(stats[0].value, 6.0);
+    //assert_eq!(stats[0].value, StatValue::Count(6));
     //assert_eq!(stats[1].name, "user23 u23");
-    //assert_eq!(stats[1].value, 3.0);
+    //assert_eq!(stats[1].value, StatValue::Count(3));
 
     //Get rentals by employee - all time - asc order
     let res = client
@@ -186,9 +188,9 @@ async fn test_get_stats_by_employee() {
     assert!(stats.len() >= 2);
     //assert_eq!(stats[0].name, "user23 u23");
     assert!(stats[0].value <= stats[1].value);
-    //assert_eq!(stats[0].value, 3.0);
+    //assert_eq!(stats[0].value, StatValue::Count(3));
     //assert_eq!(stats[1].name, "user22 u22");
-    //assert_eq!(stats[1].value, 6.0);
+    //assert_eq!(stats[1].value, StatValue::Count(6));
 
     //Get rentals by employee - 2024 - explicit desc order
     let res = client
@@ -209,9 +211,9 @@ async fn test_get_stats_by_employee() {
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert!(stats.len() >= 2);
     assert_eq!(stats[0].name, "user22 u22");
-    assert_eq!(stats[0].value, 2.0);
+    assert_eq!(stats[0].value, StatValue::Count(2));
     assert_eq!(stats[1].name, "user23 u23");
-    assert_eq!(stats[1].value, 1.0);
+    assert_eq!(stats[1].value, StatValue::Count(1));
 
     //Get income by employee - all time
     let res = client
@@ -231,9 +233,9 @@ async fn test_get_stats_by_employee() {
     assert!(stats.len() >= 2);
     assert!(stats[0].value >= stats[1].value);
     //assert_eq!(stats[0].name, "user22 u22");
-    //assert_eq!(stats[0].value, 6000.0);
+    //assert_eq!(stats[0].value, StatValue::Amount(dec!(6000)));
     //assert_eq!(stats[1].name, "user23 u23");
-    //assert_eq!(stats[1].value, 3000.0);
+    //assert_eq!(stats[1].value, StatValue::Amount(dec!(3000)));
 
     //Get income by employee - all time - asc order
     let res = client
@@ -254,9 +256,9 @@ async fn test_get_stats_by_employee() {
     assert!(stats.len() >= 2);
     assert!(stats[0].value <= stats[1].value);
     //assert_eq!(stats[0].name, "user23 u23");
-    //assert_eq!(stats[0].value, 3000.0);
+    //assert_eq!(stats[0].value, StatValue::Amount(dec!(3000)));
     //assert_eq!(stats[1].name, "user22 u22");
-    //assert_eq!(stats[1].value, 6000.0);
+    //assert_eq!(stats[1].value, StatValue::Amount(dec!(6000)));
 
     //Get income by employee - 2024 - explicit desc order
     let res = client
@@ -277,9 +279,9 @@ async fn test_get_stats_by_employee() {
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert!(stats.len() >= 2);
     assert_eq!(stats[0].name, "user22 u22");
-    assert_eq!(stats[0].value, 2000.0);
+    assert_eq!(stats[0].value, StatValue::Amount(dec!(2000)));
     assert_eq!(stats[1].name, "user23 u23");
-    assert_eq!(stats[1].value, 1000.0);
+    assert_eq!(stats[1].value, StatValue::Amount(dec!(1000)));
 }
 
 #[tokio::test]
@@ -305,12 +307,12 @@ async fn test_get_stats_by_category() {
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert!(stats.len() >= 5);
-    let mut previous = 0.0;
+    let mut previous = Decimal::ZERO;
     for nv in stats {
-        if previous == 0.0 {
-            previous = nv.value;
+        if previous.is_zero() {
+            previous = nv.value.into();
         } else {
-            assert!(nv.value <= previous)
+            assert!(Decimal::from(nv.value) <= previous)
         }
     }
 
@@ -331,12 +333,12 @@ async fn test_get_stats_by_category() {
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert!(stats.len() >= 5);
-    let mut previous = 0.0;
+    let mut previous = Decimal::ZERO;
     for nv in stats {
-        if previous == 0.0 {
-            previous = nv.value;
+        if previous.is_zero() {
+            previous = nv.value.into();
         } else {
-            assert!(nv.value >= previous)
+            assert!(Decimal::from(nv.value) >= previous)
         }
     }
 
@@ -359,9 +361,9 @@ async fn test_get_stats_by_category() {
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert_eq!(stats.len(), 2);
     assert_eq!(stats[0].name, "obras urbanas");
-    assert_eq!(stats[0].value, 4.0);
+    assert_eq!(stats[0].value, StatValue::Count(4));
     assert_eq!(stats[1].name, "construccion pesada");
-    assert_eq!(stats[1].value, 2.0);
+    assert_eq!(stats[1].value, StatValue::Count(2));
 
     //Get income by category - all time
     let res = client
@@ -379,12 +381,12 @@ async fn test_get_stats_by_category() {
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert!(stats.len() >= 5);
-    let mut previous = 0.0;
+    let mut previous = Decimal::ZERO;
     for nv in stats {
-        if previous == 0.0 {
-            previous = nv.value;
+        if previous.is_zero() {
+            previous = nv.value.into();
         } else {
-            assert!(nv.value <= previous)
+            assert!(Decimal::from(nv.value) <= previous)
         }
     }
 
@@ -405,12 +407,12 @@ async fn test_get_stats_by_category() {
     let value = res.json::<serde_json::Value>().await.unwrap()["stats"].clone();
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert!(stats.len() >= 5);
-    let mut previous = 0.0;
+    let mut previous = Decimal::ZERO;
     for nv in stats {
-        if previous == 0.0 {
-            previous = nv.value;
+        if previous.is_zero() {
+            previous = nv.value.into();
         } else {
-            assert!(nv.value >= previous)
+            assert!(Decimal::from(nv.value) >= previous)
         }
     }
 
@@ -433,7 +435,7 @@ async fn test_get_stats_by_category() {
     let stats: Vec<NameValue> = serde_json::from_value(value).unwrap();
     assert_eq!(stats.len(), 2);
     assert_eq!(stats[0].name, "obras urbanas");
-    assert_eq!(stats[0].value, 4000.0);
+    assert_eq!(stats[0].value, StatValue::Amount(dec!(4000)));
     assert_eq!(stats[1].name, "construccion pesada");
-    assert_eq!(stats[1].value, 2000.0);
+    assert_eq!(stats[1].value, StatValue::Amount(dec!(2000)));
 }
@@ -0,0 +1,294 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local};
+use reqwest::Client;
+
+#[tokio::test]
+async fn test_unit_transfers() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testtransfer', 'model21', 'TR-1', 2024, 'Sin reembolsos.', 'Traslado', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let mut unit_ids = Vec::new();
+    for serial_number in ["TRANSFER-001", "TRANSFER-002"] {
+        let unit_id: i32 = db_client
+            .query_one(
+                "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+                VALUES ($1, 'available', $2, 1)
+                RETURNING id;",
+                &[&serial_number, &model_id],
+            )
+            .await
+            .unwrap()
+            .get("id");
+        unit_ids.push(unit_id);
+    }
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + Duration::days(n);
+
+    // transfers@example.com has id 35
+    let rental_id: i32 = db_client
+        .query_one(
+            "INSERT INTO rentals (user_id, machine_id, start_date, end_date, total_price, status)
+            VALUES (35, $1, $2, $3, 7000.00, 'active')
+            RETURNING id;",
+            &[&unit_ids[0], &days(10), &days(17)],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let jwt = get_test_jwt("transfers@example.com", false).await;
+    let staff_jwt = get_test_jwt("bob@example.com", false).await;
+    let admin_jwt = get_test_jwt("admin@example.com", false).await;
+
+    let new_transfer = |access: &str, unit_id: i32, departure: i64| {
+        http_client
+            .post(backend_url("/transfer/new"))
+            .json(&serde_json::json!({
+                "access": access,
+                "unit_id": unit_id,
+                "to_location_id": 2,
+                "departure_date": days(departure),
+                "expected_arrival_date": days(departure + 2),
+                "notes": "Refuerzo de flota"
+            }))
+            .send()
+    };
+
+    let transfer_action = |access: &str, action: &str, transfer_id: i32| {
+        http_client
+            .post(backend_url(action))
+            .json(&serde_json::json!({
+                "access": access,
+                "transfer_id": transfer_id
+            }))
+            .send()
+    };
+
+    let book = |start: i64| {
+        let http_client = &http_client;
+        let jwt = &jwt;
+        let unit_id = unit_ids[0];
+        async move {
+            let response = http_client
+                .post(backend_url("/rental/quote"))
+                .json(&serde_json::json!({
+                    "access": jwt,
+                    "machine_id": unit_id,
+                    "start_date": days(start),
+                    "end_date": days(start + 7)
+                }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            let body = response.json::<serde_json::Value>().await.unwrap();
+
+            http_client
+                .post(backend_url("/rental/new"))
+                .json(&serde_json::json!({
+                    "access": jwt,
+                    "machine_id": unit_id,
+                    "start_date": days(start),
+                    "end_date": days(start + 7),
+                    "quote_token": body["quote_token"]
+                }))
+                .send()
+                .await
+                .unwrap()
+        }
+    };
+
+    // ---------- Requesting a transfer
+
+    let response = new_transfer(&staff_jwt, unit_ids[0], 30).await.unwrap();
+
+    assert_eq!(response.status(), 403);
+
+    // The unit is rented at its branch on those days
+    let response = new_transfer(&admin_jwt, unit_ids[0], 12).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["rental_ids"][0], rental_id);
+
+    let response = new_transfer(&admin_jwt, unit_ids[0], 30).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let transfer_id = body["transfer"]["id"].as_i64().unwrap() as i32;
+    assert_eq!(body["transfer"]["status"], "requested");
+    assert_eq!(body["transfer"]["from_location_id"], 1);
+
+    let response = new_transfer(&admin_jwt, unit_ids[0], 40).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    // ---------- The unit can't be booked at its branch once it leaves
+
+    let response = book(40).await;
+
+    assert_eq!(response.status(), 409);
+
+    let response = http_client
+        .post(backend_url("/transfers"))
+        .json(&serde_json::json!({"access": admin_jwt, "location_id": 2}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let transfers = body["transfers"].as_array().unwrap();
+    assert!(transfers
+        .iter()
+        .any(|transfer| transfer["id"] == transfer_id));
+
+    // ---------- Cancelling frees the unit
+
+    let response = transfer_action(&staff_jwt, "/staff/transfer/ship", transfer_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = transfer_action(&admin_jwt, "/transfer/cancel", transfer_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = book(40).await;
+
+    assert_eq!(response.status(), 201);
+
+    let response = transfer_action(&admin_jwt, "/transfer/cancel", transfer_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    // ---------- Shipping and receiving a unit
+
+    let response = new_transfer(&admin_jwt, unit_ids[1], 0).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let transfer_id = body["transfer"]["id"].as_i64().unwrap() as i32;
+
+    let response = transfer_action(&staff_jwt, "/staff/transfer/receive", transfer_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = transfer_action(&staff_jwt, "/staff/transfer/ship", transfer_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let unit_status = |unit_id: i32| {
+        let db_client = &db_client;
+        async move {
+            let row = db_client
+                .query_one(
+                    "SELECT status::TEXT, location_id FROM machinery_units WHERE id = $1;",
+                    &[&unit_id],
+                )
+                .await
+                .unwrap();
+            (
+                row.get::<_, String>("status"),
+                row.get::<_, i32>("location_id"),
+            )
+        }
+    };
+
+    assert_eq!(
+        unit_status(unit_ids[1]).await,
+        ("in_transit".to_string(), 1)
+    );
+
+    let response = http_client
+        .post(backend_url("/unit/history/update"))
+        .json(&serde_json::json!({
+            "access": staff_jwt,
+            "unit_id": unit_ids[1],
+            "new_status": "available",
+            "description": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = transfer_action(&staff_jwt, "/staff/transfer/receive", transfer_id)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    assert_eq!(unit_status(unit_ids[1]).await, ("available".to_string(), 2));
+
+    let history: i64 = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM machinery_location_history
+            WHERE unit_id = $1 AND location_id = 1;",
+            &[&unit_ids[1]],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(history, 1);
+
+    let events: Vec<String> = db_client
+        .query(
+            "SELECT new_status::TEXT FROM unit_history_events WHERE unit_id = $1 ORDER BY id;",
+            &[&unit_ids[1]],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    assert_eq!(events, vec!["in_transit", "available"]);
+
+    let response = http_client
+        .post(backend_url("/transfers"))
+        .json(&serde_json::json!({"access": admin_jwt, "status": "received"}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let received = body["transfers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|transfer| transfer["id"] == transfer_id)
+        .unwrap();
+    assert_eq!(received["status"], "received");
+    assert!(!received["received_at"].is_null());
+}
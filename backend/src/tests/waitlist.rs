@@ -0,0 +1,249 @@
+use crate::custom_types::enums::RunningEnv;
+use crate::helpers::auth::create_pool;
+use crate::tests::helpers::*;
+use chrono::{Duration, Local, NaiveDateTime};
+use reqwest::Client;
+
+#[tokio::test]
+async fn test_waitlist() {
+    setup().await;
+    let http_client = Client::new();
+
+    let pool = create_pool(RunningEnv::Testing);
+    let db_client = match pool.await.get().await {
+        Ok(c) => c,
+        Err(e) => panic!("Failed to connect to the database: {}", e),
+    };
+
+    let model_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_models (name, brand, model, year, policy, description, price, image)
+            VALUES ('testwaitlist', 'model18', 'WL-1', 2024, 'Sin reembolsos.', 'Autoelevador', 1000.00, 'imagecode')
+            RETURNING id;",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let unit_id: i32 = db_client
+        .query_one(
+            "INSERT INTO machinery_units (serial_number, status, model_id, location_id)
+            VALUES ('WAITLIST-001', 'available', $1, 1)
+            RETURNING id;",
+            &[&model_id],
+        )
+        .await
+        .unwrap()
+        .get("id");
+
+    let today = Local::now().date_naive();
+    let days = |n: i64| today + Duration::days(n);
+
+    let jwt = get_test_jwt("waitlist@example.com", false).await;
+    let other_jwt = get_test_jwt("waitlist2@example.com", false).await;
+
+    let quote = |access: &str, start: i64| {
+        let request = http_client
+            .post(backend_url("/rental/quote"))
+            .json(&serde_json::json!({
+                "access": access,
+                "machine_id": unit_id,
+                "start_date": days(start),
+                "end_date": days(start + 7)
+            }))
+            .send();
+        async move {
+            let response = request.await.unwrap();
+            assert_eq!(response.status(), 200);
+            let body = response.json::<serde_json::Value>().await.unwrap();
+            body["quote_token"].as_str().unwrap().to_string()
+        }
+    };
+
+    let book_unit = |access: &str, start: i64, quote_token: &str| {
+        http_client
+            .post(backend_url("/rental/new"))
+            .json(&serde_json::json!({
+                "access": access,
+                "machine_id": unit_id,
+                "start_date": days(start),
+                "end_date": days(start + 7),
+                "quote_token": quote_token
+            }))
+            .send()
+    };
+
+    let join = |access: &str, start: i64, end: i64| {
+        http_client
+            .post(backend_url("/waitlist/join"))
+            .json(&serde_json::json!({
+                "access": access,
+                "model_id": model_id,
+                "location_id": 1,
+                "start_date": days(start),
+                "end_date": days(end)
+            }))
+            .send()
+    };
+
+    let entry_status = |entry_id: i32| {
+        let db_client = &db_client;
+        async move {
+            let row = db_client
+                .query_one(
+                    "SELECT status::TEXT, unit_id, priority_until FROM waitlist_entries
+                    WHERE id = $1;",
+                    &[&entry_id],
+                )
+                .await
+                .unwrap();
+            (
+                row.get::<_, String>("status"),
+                row.get::<_, Option<i32>>("unit_id"),
+                row.get::<_, Option<NaiveDateTime>>("priority_until"),
+            )
+        }
+    };
+
+    // ---------- Joining only when the model is booked out
+
+    let token = quote(&other_jwt, 100).await;
+    let response = book_unit(&other_jwt, 100, &token).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let booked_rental_id = body["rental_id"].as_i64().unwrap() as i32;
+
+    let response = join(&jwt, 200, 207).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = join(&jwt, 101, 103).await.unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    let response = join(&jwt, 101, 108).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let entry_id = body["entry"]["id"].as_i64().unwrap() as i32;
+    assert_eq!(body["entry"]["status"], "waiting");
+    assert_eq!(body["entry"]["position"], 1);
+
+    let response = join(&jwt, 102, 109).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    let response = join(&other_jwt, 101, 108).await.unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    let other_entry_id = body["entry"]["id"].as_i64().unwrap() as i32;
+    assert_eq!(body["entry"]["position"], 2);
+
+    // ---------- A cancellation notifies the first in line and holds the unit
+
+    let response = http_client
+        .post(backend_url("/rental/cancel"))
+        .json(&serde_json::json!({
+            "access": other_jwt,
+            "rental_id": booked_rental_id,
+            "reason": null
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let (status, held_unit, priority_until) = entry_status(entry_id).await;
+    assert_eq!(status, "notified");
+    assert_eq!(held_unit, Some(unit_id));
+    assert!(priority_until.unwrap() > Local::now().naive_local());
+
+    let (status, held_unit, _) = entry_status(other_entry_id).await;
+    assert_eq!(status, "waiting");
+    assert_eq!(held_unit, None);
+
+    let response = http_client
+        .post(backend_url("/waitlist"))
+        .json(&serde_json::json!({"access": other_jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["entries"][0]["id"], other_entry_id);
+    assert_eq!(body["entries"][0]["position"], 1);
+
+    // Nobody else can take the unit during the priority window
+    let token = quote(&other_jwt, 101).await;
+    let response = book_unit(&other_jwt, 101, &token).await.unwrap();
+
+    assert_eq!(response.status(), 409);
+
+    // ---------- The notified client books the held unit
+
+    let token = quote(&jwt, 101).await;
+    let response = http_client
+        .post(backend_url("/rental/new/model"))
+        .json(&serde_json::json!({
+            "access": jwt,
+            "model_id": model_id,
+            "location_id": 1,
+            "start_date": days(101),
+            "end_date": days(108),
+            "quote_token": token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["machine_id"], unit_id);
+
+    let (status, _, _) = entry_status(entry_id).await;
+    assert_eq!(status, "booked");
+
+    let response = http_client
+        .post(backend_url("/waitlist"))
+        .json(&serde_json::json!({"access": jwt}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["entries"][0]["status"], "booked");
+    assert!(body["entries"][0]["position"].is_null());
+
+    // ---------- Leaving the waitlist
+
+    let leave = |access: &str, entry_id: i32| {
+        http_client
+            .post(backend_url("/waitlist/leave"))
+            .json(&serde_json::json!({
+                "access": access,
+                "entry_id": entry_id
+            }))
+            .send()
+    };
+
+    let response = leave(&jwt, other_entry_id).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+
+    let response = leave(&other_jwt, other_entry_id).await.unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let (status, _, _) = entry_status(other_entry_id).await;
+    assert_eq!(status, "cancelled");
+
+    let response = leave(&other_jwt, other_entry_id).await.unwrap();
+
+    assert_eq!(response.status(), 404);
+}
@@ -32,6 +32,8 @@ const translateStatus = (status) => {
       return "Pago pendiente";
     case "active":
       return "Activo";
+    case "retired":
+      return "Retirado";
     case "cancelled":
       return "Cancelado";
     case "completed":
@@ -158,8 +160,7 @@ export default function RentalCard({
         {returnDate ? (
           <Typography>Devuelto el: {parseDate(returnDate)}</Typography>
         ) : (
-          retirementDate &&
-          status == "active" && (
+          status == "retired" && (
             <Stack direction="row" spacing={1}>
               <Typography>Devolucion pendiente</Typography>
               <Button
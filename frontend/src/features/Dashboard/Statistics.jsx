@@ -65,12 +65,22 @@ const Statistics = () => {
       return statsData.length === 0;
     }
     if (statsData && typeof statsData === "object") {
-      // Para estadisticas por mes, que retorna "mes" : 0
-      return Object.values(statsData).every((val) => !val || val === 0);
+      // Para estadisticas por mes, que retorna "mes" : 0 (o "0" para ingresos)
+      return Object.values(statsData).every((val) => !Number(val));
     }
     return true;
   }
 
+  // Los ingresos llegan como decimales en texto, los graficos necesitan numeros
+  function parseStats(stats) {
+    if (Array.isArray(stats)) {
+      return stats.map((item) => ({ ...item, value: Number(item.value) }));
+    }
+    return Object.fromEntries(
+      Object.entries(stats).map(([month, value]) => [month, Number(value)])
+    );
+  }
+
   const [loading, setLoading] = React.useState(false);
 
   const [openSnack, setOpenSnack] = React.useState(false);
@@ -121,7 +131,7 @@ const Statistics = () => {
       console.log("getStats parameters", parameters.period);
       const { data } = await post("/stats", parameters);
       console.log("getStats data", data.stats);
-      setStatsData(data.stats);
+      setStatsData(parseStats(data.stats));
     } catch (error) {
       setStatsData([]); // Por si mete cualquier cosa ns
       let errorMsg = "Ocurrio un error al intentar obtener las estadisticas";
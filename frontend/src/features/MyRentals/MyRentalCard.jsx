@@ -25,6 +25,9 @@ const MyRentalCard = ({
   startDate,
   endDate,
   amountPaid,
+  depositAmount,
+  depositStatus,
+  orderID,
   days,
   status,
 }) => {
@@ -39,12 +42,29 @@ const MyRentalCard = ({
     color: '', // o "danger"
   });
   
+  const translateDepositStatus = (status) => {
+    switch (status) {
+      case "held":
+        return "Retenido";
+      case "released":
+        return "Devuelto";
+      case "partially_kept":
+        return "Aplicado en parte a cargos";
+      case "kept":
+        return "Aplicado a cargos";
+      default:
+        return "Pendiente de pago";
+    }
+  };
+
   const translateStatus = (status) => {
   switch (status) {
     case "pending_payment":
       return "Pago pendiente";
     case "active":
       return "Activo";
+    case "retired":
+      return "Retirado";
     case "completed":
       return "Completado";
     case "cancelled":
@@ -72,7 +92,7 @@ const response = await post("/rental/cancel", data);
     if (response.status === 200) {
       setSnackbar({
         open: true,
-        message: "Alquiler cancelado exitosamente.",
+        message: `Alquiler cancelado exitosamente. Reembolso: $ ${response.data.refund_amount}.`,
         color: "success",
       });
     }
@@ -164,6 +184,11 @@ const response = await post("/rental/cancel", data);
               </AspectRatio>
               <Box>
                 <Typography level="title-lg">{modelName}</Typography>
+                {orderID && (
+                  <Typography level="body-sm" color="neutral">
+                    Pedido n° {orderID}
+                  </Typography>
+                )}
                 <Typography level="body-md" color="neutral">
                   {withdrawnDate
                     ? `Retirado el ${withdrawnDate}`
@@ -183,6 +208,12 @@ const response = await post("/rental/cancel", data);
                     ({days} días)
                   </Typography>
                 </Typography>
+                {Number(depositAmount) > 0 && (
+                  <Typography level="body-md" color="neutral">
+                    Depósito de garantía: ${depositAmount} (
+                    {translateDepositStatus(depositStatus)})
+                  </Typography>
+                )}
               </Box>
             </Stack>
             <Stack
@@ -301,6 +301,9 @@ const handleSubmitServiceReview = async () => {
                   startDate={rental.start_date}
                   endDate={rental.end_date}
                   amountPaid={rental.total_price}
+                  depositAmount={rental.deposit_amount}
+                  depositStatus={rental.deposit_status}
+                  orderID={rental.order_id}
                   days={
                     rental.start_date && rental.end_date
                       ? Math.round(
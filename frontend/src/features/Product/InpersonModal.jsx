@@ -85,6 +85,7 @@ const InpersonModal = ({
     start_date: "",
     end_date: "",
   });
+  const [quote, setQuote] = useState(null);
   const [error, setError] = useState("");
 
   const steps = [
@@ -108,6 +109,7 @@ const InpersonModal = ({
       component: (
         <SelectPeriod
           unitId={unitId}
+          rules={machine.rental_rules}
           setDisable={setDisable}
           setValidPeriod={setValidPeriod}
         />
@@ -122,6 +124,7 @@ const InpersonModal = ({
           validPeriod={validPeriod}
           days={getDaysBetween(validPeriod)}
           machine={machine}
+          quote={quote?.quote}
         />
       ),
     },
@@ -139,6 +142,30 @@ const InpersonModal = ({
   }
 
   async function handleNext() {
+    if (activeStep === 2) {
+      setloading(true);
+      try {
+        // The backend prices the rental and the quote fixes that price for the booking
+        const { data } = await post("/rental/quote", {
+          machine_id: unitId,
+          start_date: validPeriod.start_date,
+          end_date: validPeriod.end_date,
+        });
+        setQuote(data);
+      } catch (error) {
+        console.error("Error al calcular el precio del alquiler:", error);
+        setStatus({
+          isError: true,
+          message:
+            error.response?.data?.message ??
+            "Hubo un error al calcular el precio. Intentalo mas tarde.",
+        });
+        setOpenSnack(true);
+        setloading(false);
+        return;
+      }
+      setloading(false);
+    }
     setActiveStep((prev) => prev + 1);
   }
 
@@ -150,7 +177,7 @@ const InpersonModal = ({
         user_id: userId,
         start_date: validPeriod.start_date,
         end_date: validPeriod.end_date,
-        total_price: getDaysBetween(validPeriod) * machine.price,
+        quote_token: quote.quote_token,
       });
       setStatus({
         isError: false,
@@ -163,6 +190,7 @@ const InpersonModal = ({
       setSelectedCity(null);
       setUnitId(null);
       setValidPeriod({ start_date: "", end_date: "" });
+      setQuote(null);
       setError("");
       setDisable(true);
     } catch (error) {
@@ -183,7 +211,7 @@ const InpersonModal = ({
           break;
         case 400:
           errorMessage =
-            "El precio es incorrecto o el periodo indicado no es valido.";
+            "El presupuesto expiro o el periodo indicado no es valido.";
           break;
       }
       setStatus({
@@ -32,7 +32,13 @@ function formatDays(amount) {
   })}`;
 }
 
-const InpersonSummary = ({ selectedCity, unitId, validPeriod, machine }) => {
+const InpersonSummary = ({
+  selectedCity,
+  unitId,
+  validPeriod,
+  machine,
+  quote,
+}) => {
   const days = getDaysBetween(validPeriod);
   return (
     <Box>
@@ -66,10 +72,22 @@ const InpersonSummary = ({ selectedCity, unitId, validPeriod, machine }) => {
           <tr>
             <td>Duracion total</td> <td>{formatDays(days)} dias</td>
           </tr>
+          {Number(quote.discount) > 0 && (
+            <tr>
+              <td>Descuento ({Number(quote.discount_percentage)}%)</td>
+              <td>-{formatARS(Number(quote.discount))}</td>
+            </tr>
+          )}
           <tr>
             <td>Precio total</td>
-            <td>{formatARS(machine.price * days)}</td>
+            <td>{formatARS(Number(quote.total_price))}</td>
           </tr>
+          {Number(quote.deposit) > 0 && (
+            <tr>
+              <td>Deposito de garantia</td>
+              <td>{formatARS(Number(quote.deposit))}</td>
+            </tr>
+          )}
         </tbody>
       </Table>
     </Box>
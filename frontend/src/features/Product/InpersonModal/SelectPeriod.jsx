@@ -13,7 +13,7 @@ import {
 import { useEffect, useState } from "react";
 import useAuth from "../../utils/useAuth";
 
-const SelectPeriod = ({ unitId, setDisable, setValidPeriod }) => {
+const SelectPeriod = ({ unitId, rules, setDisable, setValidPeriod }) => {
   const { post } = useAuth();
 
   function isBeforeToday(dateStr) {
@@ -74,7 +74,10 @@ const SelectPeriod = ({ unitId, setDisable, setValidPeriod }) => {
       console.error("Error validating dates:", error);
       switch (error.response?.status) {
         case 400:
-          setError("Las fechas no son válidas o el periodo es menor a 7 dias.");
+          setError(
+            error.response.data?.message ??
+              "Las fechas no son válidas para esta máquina."
+          );
           break;
         case 409:
           if (error.response.data) {
@@ -124,8 +127,17 @@ const SelectPeriod = ({ unitId, setDisable, setValidPeriod }) => {
     const diffDays =
       (new Date(fechaFin) - new Date(fechaInicio)) / (1000 * 60 * 60 * 24);
 
-    if (diffDays < 7) {
-      setError("El periodo debe ser de al menos 7 días.");
+    if (diffDays < rules.min_rental_days) {
+      setError(
+        `El periodo debe ser de al menos ${rules.min_rental_days} días.`
+      );
+      return;
+    }
+
+    if (rules.max_rental_days !== null && diffDays > rules.max_rental_days) {
+      setError(
+        `El periodo no puede superar los ${rules.max_rental_days} días.`
+      );
       return;
     }
 
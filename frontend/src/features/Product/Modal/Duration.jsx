@@ -1,6 +1,7 @@
 import { Box, List, ListItem, ListItemButton, Sheet } from "@mui/joy";
 import {
   areIntervalsOverlapping,
+  differenceInCalendarDays,
   formatISO,
   interval,
   isSameDay,
@@ -10,7 +11,10 @@ import { useEffect, useState } from "react";
 import { DateRange } from "@iroomit/react-date-range";
 import ArrowRightRoundedIcon from "@mui/icons-material/ArrowRightRounded";
 
-function Duration({ availability, setDisable, dispatch, loading }) {
+function Duration({ availability, rules, setDisable, dispatch, loading }) {
+  const minDays = rules.min_rental_days;
+  const turnaround = rules.turnaround_days;
+
   const [selectedRange, setSelectedRange] = useState([
     {
       startDate: new Date(),
@@ -36,7 +40,7 @@ function Duration({ availability, setDisable, dispatch, loading }) {
       const selectedInterval = interval(range.startDate, range.endDate);
       const maintentanceInterval = interval(
         addDays(selectedInterval.end, 1),
-        addDays(range.endDate, 7)
+        addDays(range.endDate, Math.max(turnaround, 1))
       );
       const disabledIntervals = selected.periods.map((x) =>
         interval(x.start_date, x.end_date)
@@ -53,25 +57,32 @@ function Duration({ availability, setDisable, dispatch, loading }) {
           })
         ) {
           selectedInterval.start = addDays(d.end, 1);
-          selectedInterval.end = addDays(selectedInterval.start, 7);
+          selectedInterval.end = addDays(selectedInterval.start, minDays);
 
           maintentanceInterval.start = addDays(selectedInterval.end, 1);
-          maintentanceInterval.end = addDays(selectedInterval.end, 7);
+          maintentanceInterval.end = addDays(
+            selectedInterval.end,
+            Math.max(turnaround, 1)
+          );
         }
       }
 
-      setSelectedRange([
+      const ranges = [
         {
           startDate: selectedInterval.start,
           endDate: selectedInterval.end,
           key: "selection",
         },
-        {
+      ];
+      // Models without maintenance between rentals don't show the second range
+      if (turnaround > 0) {
+        ranges.push({
           startDate: maintentanceInterval.start,
           endDate: maintentanceInterval.end,
           key: "maintenance",
-        },
-      ]);
+        });
+      }
+      setSelectedRange(ranges);
 
       setDisable(false);
       dispatch({
@@ -175,11 +186,23 @@ function Duration({ availability, setDisable, dispatch, loading }) {
                   setClickCount(1);
                 } else {
                   // End date was selected
-                  // If the selected range is only one day, or less than 7 days, make it 7 days
+                  // If the selected range is only one day, or shorter than the model allows, extend it to the minimum
                   if (isSameDay(startDate, endDate)) {
-                    selection.endDate = addDays(endDate, 7);
-                  } else if (duration.days < 7) {
-                    selection.endDate = addDays(endDate, 7 - duration.days);
+                    selection.endDate = addDays(endDate, minDays);
+                  } else if (duration.days < minDays) {
+                    selection.endDate = addDays(
+                      endDate,
+                      minDays - duration.days
+                    );
+                  } else if (
+                    rules.max_rental_days !== null &&
+                    differenceInCalendarDays(endDate, startDate) >
+                      rules.max_rental_days
+                  ) {
+                    selection.endDate = addDays(
+                      startDate,
+                      rules.max_rental_days
+                    );
                   }
                   setClickCount(0);
                 }
@@ -188,7 +211,12 @@ function Duration({ availability, setDisable, dispatch, loading }) {
               }}
               showSelectionPreview={true}
               months={2}
-              minDate={new Date()}
+              minDate={addDays(new Date(), rules.min_lead_days ?? 0)}
+              maxDate={
+                rules.max_lead_days !== null
+                  ? addDays(new Date(), rules.max_lead_days)
+                  : undefined
+              }
               ranges={selectedRange}
               direction="horizontal"
               showDateDisplay={false}
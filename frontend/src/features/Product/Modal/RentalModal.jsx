@@ -47,6 +47,13 @@ function reducer(state, action) {
         unitId: action.value,
       };
 
+    case "setQuote":
+      return {
+        ...state,
+        quote: action.value.quote,
+        quoteToken: action.value.quote_token,
+      };
+
     case "clear":
       return {
         machine: state.machine,
@@ -54,6 +61,8 @@ function reducer(state, action) {
         dates: [],
         days: 0,
         unitId: 0,
+        quote: null,
+        quoteToken: "",
       };
   }
 }
@@ -72,6 +81,8 @@ function RentalModal({ setOpen, open, machine, locations }) {
     dates: [],
     days: 0,
     unitId: 0,
+    quote: null,
+    quoteToken: "",
   });
 
   const steps = [
@@ -84,6 +95,7 @@ function RentalModal({ setOpen, open, machine, locations }) {
       component: (
         <Duration
           availability={availability}
+          rules={state.machine.rental_rules}
           setDisable={setDisable}
           dispatch={dispatch}
           loading={loadingMl}
@@ -114,7 +126,18 @@ function RentalModal({ setOpen, open, machine, locations }) {
       setAvailability(units_and_their_unavailable_dates);
     } else if (activeStep === 1) {
       setLoadingMl(true);
-      const { data } = await axios.post("http://localhost:3000/pago", state);
+      // The backend prices the rental, so the payment charges exactly what it quoted
+      const { data: quote } = await post("/rental/quote", {
+        machine_id: state.unitId,
+        start_date: state.dates[0],
+        end_date: state.dates[1],
+      });
+      dispatch({ type: "setQuote", value: quote });
+
+      const { data } = await axios.post("http://localhost:3000/pago", {
+        machine: state.machine,
+        quote: quote.quote,
+      });
 
       setMlId(data.id);
       setLoadingMl(false);
@@ -203,7 +226,7 @@ function RentalModal({ setOpen, open, machine, locations }) {
                   machine_id: state.unitId,
                   start_date: state.dates[0],
                   end_date: state.dates[1],
-                  total_price: state.days * state.machine.price,
+                  quote_token: state.quoteToken,
                 };
                 const { data } = await post("/rental/new", body);
 
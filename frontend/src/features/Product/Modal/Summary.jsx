@@ -1,8 +1,10 @@
 import { Table, Typography } from "@mui/joy";
 
 function Summary({ info }) {
-  const { machine, selectedLocation, dates, days } = info;
-  const { name, model, price } = machine;
+  const { machine, selectedLocation, dates, days, quote } = info;
+  const { name, model } = machine;
+  const hasDiscount = Number(quote.discount) > 0;
+  const hasDeposit = Number(quote.deposit) > 0;
 
   return (
     <>
@@ -32,10 +34,24 @@ function Summary({ info }) {
           <tr>
             <td>Duracion total</td> <td>{days} dias</td>
           </tr>
+          {hasDiscount && (
+            <tr>
+              <td>Descuento ({Number(quote.discount_percentage)}%)</td>
+              <td>-${Number(quote.discount)}</td>
+            </tr>
+          )}
+          <tr>
+            <td>Precio del alquiler</td> <td>${Number(quote.total_price)}</td>
+          </tr>
+          {hasDeposit && (
+            <tr>
+              <td>Deposito de garantia</td> <td>${Number(quote.deposit)}</td>
+            </tr>
+          )}
         </tbody>
       </Table>
       <Typography sx={{ textAlign: "right", mt: 2 }} level="h3">
-        Precio total: ${price * days}
+        Precio total: ${Number(quote.amount_due)}
       </Typography>
     </>
   );
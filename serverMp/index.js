@@ -24,21 +24,73 @@ app.post("/pago", (req, res) => {
   const preference = new Preference(client);
   const {
     body: {
-      machine: { name, model, price },
-      days,
+      machine: { name, model },
+      quote: { total_price, deposit },
     },
   } = req;
 
+  // The quoted price and deposit add up to the amount due the backend records and invoices
+  const items = [
+    {
+      title: `${name} ${model}`,
+      quantity: 1,
+      unit_price: Number(total_price),
+    },
+  ];
+
+  // The deposit is paid along with the rental and given back at return
+  if (Number(deposit) > 0) {
+    items.push({
+      title: `Depósito de garantía ${name} ${model}`,
+      quantity: 1,
+      unit_price: Number(deposit),
+    });
+  }
+
+  preference
+    .create({
+      body: {
+        items,
+        back_urls: {
+          success: `${process.env.NGROK}/payment`,
+          failure: `${process.env.NGROK}/payment`,
+        },
+      },
+    })
+    .then((p) => res.send(p))
+    .catch((e) => res.status(e.status).send(e));
+});
+
+// An order is paid with a single preference holding every line
+app.post("/pago/pedido", (req, res) => {
+  const preference = new Preference(client);
+  const {
+    body: { lines },
+  } = req;
+
+  const items = [];
+
+  // Each line carries the price and deposit the backend booked it with
+  lines.forEach(({ machine: { name, model }, total_price, deposit }) => {
+    items.push({
+      title: `${name} ${model}`,
+      quantity: 1,
+      unit_price: Number(total_price),
+    });
+
+    if (Number(deposit) > 0) {
+      items.push({
+        title: `Depósito de garantía ${name} ${model}`,
+        quantity: 1,
+        unit_price: Number(deposit),
+      });
+    }
+  });
+
   preference
     .create({
       body: {
-        items: [
-          {
-            title: `${name} ${model}`,
-            quantity: 1,
-            unit_price: price * days,
-          },
-        ],
+        items,
         back_urls: {
           success: `${process.env.NGROK}/payment`,
           failure: `${process.env.NGROK}/payment`,